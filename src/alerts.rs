@@ -0,0 +1,161 @@
+use crate::error::{Error, ErrorContext, Result};
+use crate::redact::redact;
+use reqwest::Client;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tracing::{debug, warn};
+use url::Url;
+
+/// Reports failed notebook syncs to Sentry and/or a webhook, so a daemon
+/// running unattended gets noticed when notebooks keep failing.
+///
+/// Only the notebook's name, the run ID and the error (with API keys and
+/// tokens removed) are sent; never any of the notebook's text.
+pub struct ErrorReporter {
+    client: Client,
+    webhook_url: Option<String>,
+    sentry: Option<SentryTarget>,
+}
+
+struct SentryTarget {
+    store_url: String,
+    auth: String,
+}
+
+impl ErrorReporter {
+    /// `None` unless a webhook URL or Sentry DSN is given
+    pub fn new(webhook_url: Option<String>, sentry_dsn: Option<&str>) -> Result<Option<Self>> {
+        let sentry = sentry_dsn.map(SentryTarget::from_dsn).transpose()?;
+        if webhook_url.is_none() && sentry.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            client: Client::new(),
+            webhook_url,
+            sentry,
+        }))
+    }
+
+    /// Report a failed notebook; delivery problems are only logged
+    pub async fn report(&self, run_id: &str, notebook: &str, error: &Error) {
+        let message = redact(&error.to_string());
+        let context = error.context();
+
+        if let Some(ref url) = self.webhook_url {
+            let payload = json!({
+                "event": "notebook_failed",
+                "run_id": run_id,
+                "notebook": notebook,
+                "error": message,
+                "context": context,
+                "version": env!("CARGO_PKG_VERSION"),
+                "timestamp": chrono::Utc::now().to_rfc3339(),
+            });
+            self.send("error webhook", self.client.post(url).json(&payload))
+                .await;
+        }
+
+        if let Some(ref sentry) = self.sentry {
+            let event = sentry_event(run_id, notebook, &message, &context);
+            let request = self
+                .client
+                .post(&sentry.store_url)
+                .header("X-Sentry-Auth", &sentry.auth)
+                .json(&event);
+            self.send("Sentry", request).await;
+        }
+    }
+
+    async fn send(&self, target: &str, request: reqwest::RequestBuilder) {
+        match request.send().await {
+            Ok(response) if response.status().is_success() => {
+                debug!("Reported failure to {}", target);
+            }
+            Ok(response) => warn!(
+                "Failed to report failure to {}: {}",
+                target,
+                response.status()
+            ),
+            Err(e) => warn!(
+                "Failed to report failure to {}: {}",
+                target,
+                redact(&e.to_string())
+            ),
+        }
+    }
+}
+
+impl SentryTarget {
+    /// Parse a DSN like `https://<key>@o123.ingest.sentry.io/<project>`
+    fn from_dsn(dsn: &str) -> Result<Self> {
+        let invalid = || Error::Config(format!("Invalid SENTRY_DSN: {}", redact(dsn)));
+        let url = Url::parse(dsn).map_err(|_| invalid())?;
+        let key = url.username();
+        let host = url.host_str().ok_or_else(invalid)?;
+        let path = url.path().trim_matches('/');
+        let (prefix, project) = match path.rsplit_once('/') {
+            Some((prefix, project)) => (format!("/{}", prefix), project),
+            None => (String::new(), path),
+        };
+        if key.is_empty() || project.is_empty() {
+            return Err(invalid());
+        }
+
+        let port = url
+            .port()
+            .map(|port| format!(":{}", port))
+            .unwrap_or_default();
+        Ok(Self {
+            store_url: format!(
+                "{}://{}{}{}/api/{}/store/",
+                url.scheme(),
+                host,
+                port,
+                prefix,
+                project
+            ),
+            auth: format!(
+                "Sentry sentry_version=7, sentry_client=remarkable2notion/{}, sentry_key={}",
+                env!("CARGO_PKG_VERSION"),
+                key
+            ),
+        })
+    }
+}
+
+/// A Sentry event grouped by stage and error code, so a notebook failing
+/// every run shows up as one recurring issue
+fn sentry_event(
+    run_id: &str,
+    notebook: &str,
+    message: &str,
+    context: &ErrorContext,
+) -> serde_json::Value {
+    let timestamp = chrono::Utc::now();
+    let digest = Sha256::digest(format!("{}{}{}", run_id, notebook, timestamp).as_bytes());
+    let event_id: String = digest[..16].iter().map(|b| format!("{:02x}", b)).collect();
+
+    let stage = context.stage.unwrap_or("unknown");
+    let code = context.code.clone().unwrap_or_default();
+    json!({
+        "event_id": event_id,
+        "timestamp": timestamp.to_rfc3339(),
+        "platform": "other",
+        "level": "error",
+        "logger": "remarkable2notion",
+        "release": concat!("remarkable2notion@", env!("CARGO_PKG_VERSION")),
+        "message": { "formatted": format!("{} - {}", notebook, message) },
+        "fingerprint": [stage, context.service.unwrap_or_default(), code],
+        "tags": {
+            "stage": stage,
+            "service": context.service,
+            "http_status": context.http_status,
+            "code": context.code,
+        },
+        "extra": {
+            "notebook": notebook,
+            "run_id": run_id,
+            "request_id": context.request_id,
+        },
+    })
+}
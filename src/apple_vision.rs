@@ -0,0 +1,144 @@
+use crate::error::{Error, Result};
+use crate::ocr::{OcrBackend, PageText};
+use crate::process;
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+use tracing::debug;
+
+/// How long the helper may take for one page
+const RECOGNIZE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Swift helper that runs VNRecognizeTextRequest on one image and prints JSON
+const HELPER_SOURCE: &str = r#"import Foundation
+import Vision
+
+guard CommandLine.arguments.count > 1 else {
+    FileHandle.standardError.write("usage: apple_ocr <image>\n".data(using: .utf8)!)
+    exit(2)
+}
+
+let request = VNRecognizeTextRequest()
+request.recognitionLevel = .accurate
+request.usesLanguageCorrection = true
+
+let handler = VNImageRequestHandler(url: URL(fileURLWithPath: CommandLine.arguments[1]), options: [:])
+do {
+    try handler.perform([request])
+} catch {
+    FileHandle.standardError.write("\(error)\n".data(using: .utf8)!)
+    exit(1)
+}
+
+var lines: [String] = []
+var total: Float = 0
+let observations = request.results ?? []
+for observation in observations {
+    if let candidate = observation.topCandidates(1).first {
+        lines.append(candidate.string)
+        total += candidate.confidence
+    }
+}
+
+let output: [String: Any] = [
+    "text": lines.joined(separator: "\n"),
+    "confidence": observations.isEmpty ? 0 : total / Float(observations.count),
+]
+let data = try! JSONSerialization.data(withJSONObject: output)
+print(String(data: data, encoding: .utf8)!)
+"#;
+
+#[derive(Debug, Deserialize)]
+struct HelperOutput {
+    text: String,
+    confidence: f32,
+}
+
+/// Offline OCR using macOS's Vision framework through a compiled Swift helper
+pub struct AppleVisionClient {
+    helper: PathBuf,
+}
+
+impl AppleVisionClient {
+    /// Compile the helper into the cache dir if it's missing or outdated
+    pub fn new() -> Result<Self> {
+        if !cfg!(target_os = "macos") {
+            return Err(Error::Config(
+                "The apple OCR backend is only available on macOS".to_string(),
+            ));
+        }
+
+        let mut helper_dir = dirs::cache_dir().unwrap_or_else(std::env::temp_dir);
+        helper_dir.push("remarkable2notion");
+        std::fs::create_dir_all(&helper_dir)?;
+
+        let source_path = helper_dir.join("apple_ocr.swift");
+        let helper = helper_dir.join("apple_ocr");
+
+        let up_to_date = helper.exists()
+            && std::fs::read_to_string(&source_path)
+                .map(|source| source == HELPER_SOURCE)
+                .unwrap_or(false);
+
+        if !up_to_date {
+            debug!("Compiling Apple Vision helper to {:?}", helper);
+            std::fs::write(&source_path, HELPER_SOURCE)?;
+
+            let output = Command::new("swiftc")
+                .arg("-O")
+                .arg("-o")
+                .arg(&helper)
+                .arg(&source_path)
+                .output()
+                .map_err(|e| {
+                    Error::Ocr(format!(
+                        "swiftc not found: {}. Install the Xcode command line tools: xcode-select --install",
+                        e
+                    ))
+                })?;
+
+            if !output.status.success() {
+                // Don't leave a source file behind that claims the helper is current
+                std::fs::remove_file(&source_path).ok();
+                return Err(Error::Ocr(format!(
+                    "Failed to compile Apple Vision helper: {}",
+                    String::from_utf8_lossy(&output.stderr)
+                )));
+            }
+        }
+
+        Ok(Self { helper })
+    }
+}
+
+#[async_trait]
+impl OcrBackend for AppleVisionClient {
+    fn name(&self) -> &'static str {
+        "Apple Vision"
+    }
+
+    async fn recognize(&self, image_path: &Path) -> Result<PageText> {
+        let mut cmd = tokio::process::Command::new(&self.helper);
+        cmd.arg(image_path);
+        let output = process::output(&mut cmd, RECOGNIZE_TIMEOUT)
+            .await
+            .map_err(|e| Error::Ocr(format!("Failed to run Apple Vision helper: {}", e)))?;
+
+        if !output.status.success() {
+            return Err(Error::Ocr(format!(
+                "Apple Vision failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+
+        let result: HelperOutput = serde_json::from_slice(&output.stdout)
+            .map_err(|e| Error::Ocr(format!("Invalid Apple Vision helper output: {}", e)))?;
+
+        Ok(PageText {
+            text: result.text,
+            confidence: Some(result.confidence),
+        })
+    }
+}
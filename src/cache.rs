@@ -0,0 +1,129 @@
+use crate::error::Result;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+/// File in a cache entry holding the stage output
+const ENTRY_FILE: &str = "entry.json";
+
+/// Output of a notebook's render and OCR stages, kept until the notebook is
+/// synced so a failure in a later stage (Drive or Notion) doesn't mean
+/// OCR'ing it again on the next run.
+///
+/// Each notebook has a directory holding the output and the page images it
+/// refers to. An entry is only used when its key, which should cover the PDF
+/// and everything else that affects the output, matches.
+pub struct StageCache {
+    dir: PathBuf,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry<T> {
+    key: String,
+    /// File names of the page images in the entry's directory, in order
+    images: Vec<String>,
+    output: T,
+}
+
+impl StageCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// The notebook's cached output and page images, if made with `key`
+    pub fn load<T: DeserializeOwned>(
+        &self,
+        notebook_id: &str,
+        key: &str,
+    ) -> Option<(T, Vec<PathBuf>)> {
+        let dir = self.entry_dir(notebook_id);
+        let text = std::fs::read_to_string(dir.join(ENTRY_FILE)).ok()?;
+        let entry: Entry<T> = match serde_json::from_str(&text) {
+            Ok(entry) => entry,
+            Err(e) => {
+                debug!("Ignoring unreadable cache entry {:?}: {}", dir, e);
+                return None;
+            }
+        };
+        if entry.key != key {
+            debug!("Cache entry {:?} is out of date", dir);
+            return None;
+        }
+
+        let images: Vec<PathBuf> = entry.images.iter().map(|name| dir.join(name)).collect();
+        if !images.iter().all(|image| image.exists()) {
+            debug!("Cache entry {:?} is missing page images", dir);
+            return None;
+        }
+        Some((entry.output, images))
+    }
+
+    /// Cache the notebook's output, moving its page images into the entry;
+    /// `images` is updated to point at them there
+    pub fn store<T: Serialize>(
+        &self,
+        notebook_id: &str,
+        key: &str,
+        output: &T,
+        images: &mut [PathBuf],
+    ) -> Result<()> {
+        let dir = self.entry_dir(notebook_id);
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir)?;
+        }
+        std::fs::create_dir_all(&dir)?;
+
+        let mut names = Vec::with_capacity(images.len());
+        for image in images.iter_mut() {
+            let name = image
+                .file_name()
+                .ok_or_else(|| std::io::Error::other(format!("Invalid image path {:?}", image)))?
+                .to_owned();
+            let cached = dir.join(&name);
+            move_file(image, &cached)?;
+            *image = cached;
+            names.push(name.to_string_lossy().into_owned());
+        }
+
+        let entry = Entry {
+            key: key.to_string(),
+            images: names,
+            output,
+        };
+        std::fs::write(dir.join(ENTRY_FILE), serde_json::to_string(&entry)?)?;
+        debug!("Cached stage output in {:?}", dir);
+        Ok(())
+    }
+
+    /// Drop the notebook's entry once it's no longer needed
+    pub fn remove(&self, notebook_id: &str) {
+        let dir = self.entry_dir(notebook_id);
+        if dir.exists() {
+            if let Err(e) = std::fs::remove_dir_all(&dir) {
+                warn!("Failed to remove cache entry {:?}: {}", dir, e);
+            }
+        }
+    }
+
+    /// Notebook IDs can be paths, so entries are named by their hash
+    fn entry_dir(&self, notebook_id: &str) -> PathBuf {
+        let hash: String = Sha256::digest(notebook_id.as_bytes())
+            .iter()
+            .take(16)
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        self.dir.join(hash)
+    }
+}
+
+/// Rename, falling back to copying when the cache is on another filesystem
+/// than the work directory
+fn move_file(from: &Path, to: &Path) -> Result<()> {
+    if std::fs::rename(from, to).is_err() {
+        std::fs::copy(from, to)?;
+        std::fs::remove_file(from)?;
+    }
+    Ok(())
+}
@@ -1,4 +1,8 @@
+use crate::config::{DeleteMode, PrivacyMode, Since};
+use crate::glob::Glob;
+use crate::schedule::Schedule;
 use clap::{Parser, Subcommand};
+use std::path::PathBuf;
 
 #[derive(Parser)]
 #[command(name = "remarkable2notion")]
@@ -24,6 +28,293 @@ pub enum Commands {
 
         #[arg(short, long, help = "Enable verbose logging")]
         verbose: bool,
+
+        #[arg(long, help = "Print a JSON report with per-notebook timings to stdout")]
+        json: bool,
+
+        #[arg(
+            long,
+            help = "Read pages back after writing and flag truncated or partial writes"
+        )]
+        verify: bool,
+
+        #[arg(long, help = "Only process notebooks RemarkableSync exported this run")]
+        changed_only: bool,
+
+        #[arg(
+            long,
+            help = "Also process notebooks whose PDF didn't change since the last sync"
+        )]
+        force: bool,
+
+        #[arg(
+            long,
+            default_value_t = 1,
+            value_name = "N",
+            help = "Number of notebooks to process at the same time"
+        )]
+        concurrency: usize,
+
+        #[arg(long, help = "Stop at the first notebook that fails")]
+        fail_fast: bool,
+
+        #[arg(
+            long,
+            value_name = "N",
+            conflicts_with = "fail_fast",
+            value_parser = clap::value_parser!(u64).range(1..),
+            help = "Stop once N notebooks failed"
+        )]
+        max_errors: Option<u64>,
+
+        #[arg(
+            long,
+            value_name = "N",
+            help = "Process at most N notebooks this run, never synced ones first"
+        )]
+        limit: Option<usize>,
+
+        #[arg(
+            long,
+            help = "Write pages with their properties now and queue the OCR for --ocr-backlog"
+        )]
+        defer_ocr: bool,
+
+        #[arg(
+            long,
+            conflicts_with = "defer_ocr",
+            help = "Only OCR the notebooks queued by --defer-ocr, without syncing the tablet"
+        )]
+        ocr_backlog: bool,
+
+        #[arg(
+            long,
+            value_name = "WHEN",
+            help = "Only process notebooks modified after a date (2024-01-01) or within an age (7d, 12h)"
+        )]
+        since: Option<Since>,
+
+        #[arg(long, help = "Only process notebooks starred on the tablet")]
+        favorites_only: bool,
+
+        #[arg(
+            long,
+            value_name = "PATTERN",
+            help = "Only process notebooks with this name, or matching this glob, e.g. \"Journal*\" (repeatable)"
+        )]
+        only: Vec<Glob>,
+
+        #[arg(
+            long,
+            help = "Only process the notebooks named on stdin, one name, path or UUID per line"
+        )]
+        stdin: bool,
+
+        #[arg(
+            long = "include-tag",
+            value_name = "TAG",
+            help = "Only process notebooks with this tag (repeatable)"
+        )]
+        include_tags: Vec<String>,
+
+        #[arg(
+            long = "exclude-tag",
+            value_name = "TAG",
+            help = "Skip notebooks with this tag (repeatable)"
+        )]
+        exclude_tags: Vec<String>,
+
+        #[arg(
+            long,
+            value_name = "MODE",
+            help = "What to do with pages of notebooks in the tablet's trash: ignore, archive or delete"
+        )]
+        delete_mode: Option<DeleteMode>,
+
+        #[arg(
+            long,
+            conflicts_with = "delete_mode",
+            help = "Also sync notebooks in the tablet's trash, tagged \"Trash\" in Notion"
+        )]
+        include_trash: bool,
+
+        #[arg(
+            long,
+            value_name = "MODE",
+            help = "Keep OCR text (images) or page images (text) on this machine, OCR'ing locally"
+        )]
+        privacy_mode: Option<PrivacyMode>,
+
+        #[arg(
+            long,
+            value_name = "DIR",
+            help = "Write the pages that would be sent to Notion as markdown to DIR, without changing anything"
+        )]
+        review_dir: Option<PathBuf>,
+
+        #[arg(long, help = "Print progress events as JSON lines to stdout")]
+        events: bool,
+    },
+
+    #[command(about = "Keep running and sync notebooks when new exports appear")]
+    Daemon {
+        #[arg(long, help = "Notion API integration token")]
+        notion_token: Option<String>,
+
+        #[arg(long, help = "Notion database ID to sync to")]
+        notion_database_id: Option<String>,
+
+        #[arg(long, help = "Google Drive folder ID the tablet exports PDFs to")]
+        watch_drive_folder: Option<String>,
+
+        #[arg(
+            long,
+            conflicts_with = "watch_drive_folder",
+            help = "Watch the backup directory instead, for when RemarkableSync runs on its own"
+        )]
+        watch_backup: bool,
+
+        #[arg(
+            long,
+            value_name = "SCHEDULE",
+            conflicts_with_all = ["watch_drive_folder", "watch_backup"],
+            help = "Run a full sync on a schedule instead: a cron expression (\"0 */2 * * *\") or an interval (30m, 2h)"
+        )]
+        schedule: Option<Schedule>,
+
+        #[arg(
+            long,
+            default_value_t = 60,
+            help = "Seconds between checks for new Drive exports"
+        )]
+        interval: u64,
+
+        #[arg(
+            long,
+            default_value_t = 10,
+            help = "Seconds the backup directory must be unchanged before syncing"
+        )]
+        settle: u64,
+
+        #[arg(
+            long,
+            help = "Also serve the PDF archive (PDF_ARCHIVE_DIR) over HTTP on PDF_SERVER_BIND"
+        )]
+        serve_pdfs: bool,
+
+        #[arg(short, long, help = "Enable verbose logging")]
+        verbose: bool,
+    },
+
+    #[command(about = "Inspect the Notion workspace")]
+    Notion {
+        #[command(subcommand)]
+        command: NotionCommands,
+    },
+
+    #[command(about = "Compare the database with the properties the sync writes")]
+    Schema {
+        #[command(subcommand)]
+        command: SchemaCommands,
+    },
+
+    #[command(about = "Describe the supported settings")]
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+
+    #[command(about = "Manage the Google authorization")]
+    Auth {
+        #[command(subcommand)]
+        command: AuthCommands,
+    },
+
+    #[command(about = "Clean up artifacts of interrupted or buggy runs")]
+    Repair {
+        #[arg(long, help = "Notion API integration token")]
+        notion_token: Option<String>,
+
+        #[arg(long, help = "Notion database ID to repair")]
+        notion_database_id: Option<String>,
+
+        #[arg(
+            long,
+            help = "Remove duplicated text sections and image sets from synced pages"
+        )]
+        dedupe: bool,
+
+        #[arg(
+            long,
+            help = "Merge pages with the same title into the most recently edited one and archive the rest"
+        )]
+        merge_duplicates: bool,
+
+        #[arg(
+            long,
+            help = "Delete orphaned temp files and Google Drive uploads of interrupted runs"
+        )]
+        storage: bool,
+
+        #[arg(long, help = "Report what would be removed without changing anything")]
+        dry_run: bool,
+
+        #[arg(short, long, help = "Enable verbose logging")]
+        verbose: bool,
+    },
+
+    #[command(about = "Add properties introduced by newer versions to existing pages")]
+    Backfill {
+        #[arg(long, help = "Notion API integration token")]
+        notion_token: Option<String>,
+
+        #[arg(long, help = "Notion database ID to backfill")]
+        notion_database_id: Option<String>,
+
+        #[arg(long, help = "Report what would be set without changing anything")]
+        dry_run: bool,
+
+        #[arg(short, long, help = "Enable verbose logging")]
+        verbose: bool,
+    },
+
+    #[command(about = "Check the database for pages the sync can't keep up to date")]
+    Verify {
+        #[arg(long, help = "Notion API integration token")]
+        notion_token: Option<String>,
+
+        #[arg(long, help = "Notion database ID to check")]
+        notion_database_id: Option<String>,
+
+        #[arg(
+            long,
+            help = "Report notebooks with more than one page (same Source UUID)"
+        )]
+        unique: bool,
+
+        #[arg(
+            long,
+            help = "Merge the pages found into the one the sync updates and archive the rest"
+        )]
+        fix: bool,
+
+        #[arg(short, long, help = "Enable verbose logging")]
+        verbose: bool,
+    },
+
+    #[command(about = "Serve the PDF archive over HTTP, for PDF_BASE_URL links")]
+    Serve {
+        #[arg(long, help = "Directory to serve (default: PDF_ARCHIVE_DIR)")]
+        dir: Option<PathBuf>,
+
+        #[arg(
+            long,
+            help = "Address to listen on (default: PDF_SERVER_BIND or 127.0.0.1:8086)"
+        )]
+        bind: Option<String>,
+
+        #[arg(short, long, help = "Enable verbose logging")]
+        verbose: bool,
     },
 
     #[command(about = "Test individual components")]
@@ -47,3 +338,68 @@ pub enum Commands {
         verbose: bool,
     },
 }
+
+#[derive(Subcommand)]
+pub enum SchemaCommands {
+    #[command(about = "Report missing properties and options, wrong types and conflicting names")]
+    Check {
+        #[arg(long, help = "Notion API integration token")]
+        notion_token: Option<String>,
+
+        #[arg(long, help = "Notion database ID to check")]
+        notion_database_id: Option<String>,
+
+        #[arg(long, help = "Apply the fixes found, after confirmation")]
+        fix: bool,
+
+        #[arg(long, requires = "fix", help = "Don't ask for confirmation")]
+        yes: bool,
+
+        #[arg(short, long, help = "Enable verbose logging")]
+        verbose: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommands {
+    #[command(about = "List every setting with its type, default and feature")]
+    Docs,
+
+    #[command(about = "Print every setting as JSON")]
+    Schema,
+}
+
+#[derive(Subcommand)]
+pub enum AuthCommands {
+    #[command(
+        about = "Show the scopes granted to the app, asking for the ones enabled features need"
+    )]
+    Scopes {
+        #[arg(short, long, help = "Enable verbose logging")]
+        verbose: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum NotionCommands {
+    #[command(about = "List the databases shared with the integration and their IDs")]
+    ListDatabases {
+        #[arg(long, help = "Notion API integration token")]
+        notion_token: Option<String>,
+
+        #[arg(short, long, help = "Enable verbose logging")]
+        verbose: bool,
+    },
+
+    #[command(about = "Check the database for the default views and explain how to add them")]
+    BootstrapViews {
+        #[arg(long, help = "Notion API integration token")]
+        notion_token: Option<String>,
+
+        #[arg(long, help = "Notion database ID to prepare")]
+        notion_database_id: Option<String>,
+
+        #[arg(short, long, help = "Enable verbose logging")]
+        verbose: bool,
+    },
+}
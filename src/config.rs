@@ -1,18 +1,804 @@
 use crate::error::{Error, Result};
+use crate::exporter::{ExporterKind, PageFormat};
+use crate::file_server;
+use crate::glob::Glob;
+use crate::notion;
+use crate::oauth;
+use crate::ocr::{AdaptiveDpi, OcrBackendKind, DEFAULT_DPI};
+use crate::rate_limit;
+use crate::remarkable::ssh::DEFAULT_SSH_HOST;
+use crate::remarkable::{Transport, DEFAULT_SYNC_TIMEOUT};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::future::Future;
 use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::warn;
+
+pub mod reference;
+
+/// Settings read from the optional TOML config file
+///
+/// Located at `REMARKABLE2NOTION_CONFIG` or `<config dir>/remarkable2notion/config.toml`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct FileConfig {
+    pub meeting: MeetingPatterns,
+    pub relations: Vec<RelationConfig>,
+    pub ocr_rules: Vec<OcrRule>,
+    pub templates: Vec<TemplateConfig>,
+    /// Names and jargon OCR should recognise, see `Vocabulary`
+    pub vocabulary: Vec<String>,
+    /// Extra vocabulary for notebooks carrying the tag the set is named after
+    pub vocabularies: HashMap<String, Vec<String>>,
+    /// Heading levels and toggles of the generated pages
+    pub layout: LayoutConfig,
+}
+
+/// How the OCR text is laid out on the Notion page
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LayoutConfig {
+    /// Heading level (1-3) of the "OCR Extracted Text" section
+    pub content_heading: u8,
+    /// Heading level (1-3) of the "Action Items" section
+    pub action_items_heading: u8,
+    /// Give every notebook page's text a "Page N" heading of this level (1-3)
+    pub page_headings: Option<u8>,
+    /// Put every notebook page's text in a toggle
+    pub page_toggles: bool,
+}
+
+impl Default for LayoutConfig {
+    fn default() -> Self {
+        Self {
+            content_heading: 2,
+            action_items_heading: 2,
+            page_headings: None,
+            page_toggles: false,
+        }
+    }
+}
+
+/// Selects notebooks by reMarkable tag and/or folder; all given criteria must match
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct NotebookMatch {
+    /// Tag the notebook must carry (case-insensitive)
+    pub tag: Option<String>,
+    /// Folder path the notebook must be in, including subfolders
+    pub folder: Option<String>,
+}
+
+impl NotebookMatch {
+    pub fn matches(&self, tags: &[String], folder_path: &str) -> bool {
+        let tag_matches = self
+            .tag
+            .as_ref()
+            .map(|tag| tags.iter().any(|t| t.eq_ignore_ascii_case(tag)))
+            .unwrap_or(true);
+
+        let folder_matches = self
+            .folder
+            .as_ref()
+            .map(|folder| {
+                let folder = folder.trim_matches('/');
+                folder_path == folder || folder_path.starts_with(&format!("{}/", folder))
+            })
+            .unwrap_or(true);
+
+        (self.tag.is_some() || self.folder.is_some()) && tag_matches && folder_matches
+    }
+}
+
+/// Route matching notebooks to a specific OCR backend; the first matching rule wins
+#[derive(Debug, Clone, Deserialize)]
+pub struct OcrRule {
+    #[serde(flatten)]
+    pub matcher: NotebookMatch,
+    pub backend: OcrBackendKind,
+}
+
+/// Settings for pages using a reMarkable template, matched by template name
+#[derive(Debug, Clone, Deserialize)]
+pub struct TemplateConfig {
+    /// Template name as the tablet records it (e.g. "P Week 2"), case-insensitive
+    pub name: String,
+    /// Area cut off the rendered page before OCR
+    pub crop: Option<Margins>,
+    /// Areas read separately into page properties and blocks
+    #[serde(default)]
+    pub regions: Vec<RegionConfig>,
+}
+
+/// An area of a template whose text goes to a specific field of the page
+#[derive(Debug, Clone, Deserialize)]
+pub struct RegionConfig {
+    pub field: TemplateField,
+    /// The region is the part of the page inside these margins
+    pub area: Margins,
+    /// Text property to fill, for `field = "property"`
+    pub property: Option<String>,
+}
+
+/// Where the text of a template region ends up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TemplateField {
+    /// Page title (first line)
+    Title,
+    /// "Date" property
+    Date,
+    /// One to-do per line under "Action Items" (e.g. a checkbox column)
+    ActionItems,
+    /// The text property named by `property`
+    Property,
+}
+
+/// Margins of an area of the page, as fractions of its width and height
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default)]
+pub struct Margins {
+    pub left: f32,
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+}
+
+impl Margins {
+    fn is_valid(&self) -> bool {
+        let margins = [self.left, self.top, self.right, self.bottom];
+        margins.iter().all(|m| (0.0..1.0).contains(m))
+            && self.left + self.right < 1.0
+            && self.top + self.bottom < 1.0
+    }
+}
+
+/// Link notebooks to pages in another database (e.g. "Projects") via a relation property
+#[derive(Debug, Clone, Deserialize)]
+pub struct RelationConfig {
+    /// Relation property on the notebooks database
+    pub property: String,
+    /// Database whose page titles are matched against tags/folders
+    pub database_id: String,
+    #[serde(default = "RelationConfig::default_match_on")]
+    pub match_on: Vec<RelationSource>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RelationSource {
+    Tag,
+    Folder,
+}
+
+impl RelationConfig {
+    fn default_match_on() -> Vec<RelationSource> {
+        vec![RelationSource::Tag, RelationSource::Folder]
+    }
+}
+
+/// What to do with notebooks without any recognised text (`EMPTY_NOTEBOOKS`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmptyNotebookAction {
+    /// Sync them like any other notebook
+    Keep,
+    /// Don't create a page
+    Skip,
+    /// Create the page, then archive it
+    Archive,
+}
+
+impl FromStr for EmptyNotebookAction {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "keep" => Ok(EmptyNotebookAction::Keep),
+            "skip" => Ok(EmptyNotebookAction::Skip),
+            "archive" => Ok(EmptyNotebookAction::Archive),
+            other => Err(Error::Config(format!(
+                "Unknown empty notebook action '{}' (expected keep, skip or archive)",
+                other
+            ))),
+        }
+    }
+}
+
+/// What happens to the page of a notebook moved to the tablet's trash
+/// (`sync --delete-mode`, `DELETE_MODE`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeleteMode {
+    /// Leave the page as it is
+    Ignore,
+    /// Keep the page, with its sync status set to Archived
+    Archive,
+    /// Move the page to Notion's trash
+    Delete,
+}
+
+impl DeleteMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeleteMode::Ignore => "ignore",
+            DeleteMode::Archive => "archive",
+            DeleteMode::Delete => "delete",
+        }
+    }
+}
+
+impl FromStr for DeleteMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "ignore" => Ok(DeleteMode::Ignore),
+            "archive" => Ok(DeleteMode::Archive),
+            "delete" => Ok(DeleteMode::Delete),
+            other => Err(Error::Config(format!(
+                "Unknown delete mode '{}' (expected ignore, archive or delete)",
+                other
+            ))),
+        }
+    }
+}
+
+/// Which notebook data may leave the machine (`PRIVACY_MODE`,
+/// `sync --privacy-mode`).
+///
+/// Both restricted modes OCR with the local Apple backend instead of a cloud
+/// service, and keep PDFs and SVG pages off Google Drive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PrivacyMode {
+    /// Text, page images and PDFs go wherever they're configured to
+    #[default]
+    Off,
+    /// Pages get their images but no OCR text
+    Images,
+    /// Pages get their OCR text but no images
+    Text,
+}
+
+impl FromStr for PrivacyMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "off" => Ok(PrivacyMode::Off),
+            "images" => Ok(PrivacyMode::Images),
+            "text" => Ok(PrivacyMode::Text),
+            other => Err(Error::Config(format!(
+                "Unknown privacy mode '{}' (expected off, images or text)",
+                other
+            ))),
+        }
+    }
+}
+
+/// Language and day/month order of dates in handwriting, used to read
+/// journal, meeting and template dates (`DATE_LOCALE`). English month and
+/// weekday names are understood with every locale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DateLocale {
+    /// English, numeric dates day first (15/10/2024)
+    #[default]
+    En,
+    /// English, numeric dates month first (10/15/2024)
+    EnUs,
+    /// Dutch month and weekday names, day first
+    Nl,
+    /// German month and weekday names, day first
+    De,
+}
+
+impl FromStr for DateLocale {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().replace('_', "-").as_str() {
+            "en" | "en-gb" => Ok(DateLocale::En),
+            "en-us" => Ok(DateLocale::EnUs),
+            "nl" | "nl-nl" | "nl-be" => Ok(DateLocale::Nl),
+            "de" | "de-de" | "de-at" | "de-ch" => Ok(DateLocale::De),
+            other => Err(Error::Config(format!(
+                "Unknown date locale '{}' (expected en, en-us, nl or de)",
+                other
+            ))),
+        }
+    }
+}
+
+/// Where pages exported as SVG go (`SVG_EXPORT`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SvgExport {
+    #[default]
+    Off,
+    /// Next to the notebook's PDF in Google Drive
+    Drive,
+    /// On the Notion page, in place of the PNG page images
+    Notion,
+    /// Both of the above
+    Both,
+}
+
+impl SvgExport {
+    pub fn to_drive(self) -> bool {
+        matches!(self, SvgExport::Drive | SvgExport::Both)
+    }
+
+    pub fn to_notion(self) -> bool {
+        matches!(self, SvgExport::Notion | SvgExport::Both)
+    }
+}
+
+impl FromStr for SvgExport {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "off" | "false" | "0" | "no" => Ok(SvgExport::Off),
+            // `SVG_EXPORT=true` predates the Notion option
+            "drive" | "true" | "1" | "yes" | "on" => Ok(SvgExport::Drive),
+            "notion" => Ok(SvgExport::Notion),
+            "both" => Ok(SvgExport::Both),
+            other => Err(Error::Config(format!(
+                "Unknown SVG export '{}' (expected off, drive, notion or both)",
+                other
+            ))),
+        }
+    }
+}
+
+/// What the PDF Link property points at when Drive isn't configured
+/// (`PDF_LINK_MODE`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PdfLinkMode {
+    /// The PDF in the work directory, which is removed after the sync
+    #[default]
+    Local,
+    /// A copy of the PDF in `PDF_ARCHIVE_DIR`, by notebook path
+    Archive,
+    /// Leave the property empty
+    None,
+}
+
+impl FromStr for PdfLinkMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "local" => Ok(PdfLinkMode::Local),
+            "archive" => Ok(PdfLinkMode::Archive),
+            "none" => Ok(PdfLinkMode::None),
+            other => Err(Error::Config(format!(
+                "Unknown PDF link mode '{}' (expected local, archive or none)",
+                other
+            ))),
+        }
+    }
+}
+
+/// Cutoff for `sync --since`: a date (`2024-01-01`, from midnight local
+/// time) or an age (`12h`, `7d`, `2w`) counted back from now
+#[derive(Debug, Clone, Copy)]
+pub struct Since(pub chrono::DateTime<chrono::Utc>);
+
+impl FromStr for Since {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let invalid = || {
+            Error::Config(format!(
+                "Invalid --since '{}' (expected a date like 2024-01-01 or an age like 7d)",
+                s
+            ))
+        };
+
+        if let Ok(date) = chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+            let midnight = date
+                .and_hms_opt(0, 0, 0)
+                .and_then(|time| time.and_local_timezone(chrono::Local).earliest())
+                .ok_or_else(invalid)?;
+            return Ok(Since(midnight.with_timezone(&chrono::Utc)));
+        }
+
+        let split = s.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+        let (number, unit) = s.split_at(split);
+        let number: i64 = number.parse().map_err(|_| invalid())?;
+        let age = match unit {
+            "h" => chrono::Duration::try_hours(number),
+            "d" => chrono::Duration::try_days(number),
+            "w" => chrono::Duration::try_weeks(number),
+            _ => None,
+        }
+        .ok_or_else(invalid)?;
+        Ok(Since(chrono::Utc::now() - age))
+    }
+}
+
+/// What happens to a page edited in Notion since it was last synced
+/// (`MANUAL_EDITS`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManualEdits {
+    /// Replace the page's content without checking
+    Overwrite,
+    /// Log a warning, then replace the page's content
+    Warn,
+    /// Leave the page as it is until the edits are dealt with
+    Skip,
+    /// Only replace the sections the sync writes, keeping all other blocks
+    Managed,
+}
+
+impl FromStr for ManualEdits {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "overwrite" => Ok(ManualEdits::Overwrite),
+            "warn" => Ok(ManualEdits::Warn),
+            "skip" => Ok(ManualEdits::Skip),
+            "managed" => Ok(ManualEdits::Managed),
+            other => Err(Error::Config(format!(
+                "Unknown MANUAL_EDITS '{}' (expected overwrite, warn, skip or managed)",
+                other
+            ))),
+        }
+    }
+}
+
+/// What happens when a notebook's page turns out to be archived in Notion
+/// (`ARCHIVED_PAGES`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchivedPages {
+    /// Create a new page, leaving the archived one in the trash
+    Create,
+    /// Restore the archived page and update it
+    Unarchive,
+    /// Don't write the notebook
+    Skip,
+}
+
+impl FromStr for ArchivedPages {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "create" => Ok(ArchivedPages::Create),
+            "unarchive" => Ok(ArchivedPages::Unarchive),
+            "skip" => Ok(ArchivedPages::Skip),
+            other => Err(Error::Config(format!(
+                "Unknown ARCHIVED_PAGES '{}' (expected create, unarchive or skip)",
+                other
+            ))),
+        }
+    }
+}
+
+/// Order notebooks are started in with `sync --concurrency` (`NOTEBOOK_ORDER`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotebookOrder {
+    /// Largest PDF first, so the slowest notebooks don't start last
+    LargestFirst,
+    /// The order of the backup
+    Listed,
+}
+
+impl FromStr for NotebookOrder {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "largest-first" => Ok(NotebookOrder::LargestFirst),
+            "listed" => Ok(NotebookOrder::Listed),
+            other => Err(Error::Config(format!(
+                "Unknown NOTEBOOK_ORDER '{}' (expected largest-first or listed)",
+                other
+            ))),
+        }
+    }
+}
+
+/// How a failing stage affects its notebook (`ON_OCR_ERROR`, `ON_UPLOAD_ERROR`,
+/// `ON_NOTION_ERROR`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Warn and carry on without the stage's result
+    Skip,
+    /// Fail the notebook
+    Fail,
+    /// Try up to this many more times, then fail the notebook
+    Retry(u32),
+}
+
+impl ErrorPolicy {
+    /// Run `attempt` under this policy.
+    ///
+    /// Returns `None` when it failed and the policy is to skip it.
+    pub async fn run<T, F, Fut>(self, what: &str, mut attempt: F) -> Result<Option<T>>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let retries = match self {
+            ErrorPolicy::Retry(retries) => retries,
+            _ => 0,
+        };
+        let mut tries = 0;
+        loop {
+            match attempt().await {
+                Ok(value) => return Ok(Some(value)),
+                Err(e) if tries < retries => {
+                    tries += 1;
+                    warn!("{} failed, retrying ({}/{}): {}", what, tries, retries, e);
+                    tokio::time::sleep(Duration::from_secs(tries.into())).await;
+                }
+                Err(e) if self == ErrorPolicy::Skip => {
+                    warn!("{} failed, skipping: {}", what, e);
+                    return Ok(None);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl FromStr for ErrorPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim().to_lowercase();
+        let retries = s
+            .strip_prefix("retry(")
+            .and_then(|rest| rest.strip_suffix(')'))
+            .map(|n| n.trim().parse::<u32>());
+        match (s.as_str(), retries) {
+            ("skip", _) => Ok(ErrorPolicy::Skip),
+            ("fail", _) => Ok(ErrorPolicy::Fail),
+            ("retry", _) => Ok(ErrorPolicy::Retry(3)),
+            (_, Some(Ok(retries))) => Ok(ErrorPolicy::Retry(retries)),
+            _ => Err(Error::Config(format!(
+                "Unknown error policy '{}' (expected skip, fail or retry(n))",
+                s
+            ))),
+        }
+    }
+}
+
+/// Regex patterns used to pull fields out of `meeting`-tagged notebooks.
+///
+/// Each pattern is matched per line; capture group 1 holds the value.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct MeetingPatterns {
+    pub date: String,
+    pub attendees: String,
+    pub action_item: String,
+}
+
+impl Default for MeetingPatterns {
+    fn default() -> Self {
+        Self {
+            date: r"(?i)^\s*(?:date\s*:?\s*)?(.+?)\s*$".to_string(),
+            attendees: r"(?i)^\s*(?:w/|with:|attendees:)\s*(.+)$".to_string(),
+            action_item: r"(?i)^\s*(?:\[\s?\]|☐|□|todo:?|action:|ai:|->)\s*(.+)$".to_string(),
+        }
+    }
+}
+
+impl FileConfig {
+    /// Relation properties to expect on the notebooks database, with their
+    /// target database
+    pub fn relation_properties(&self) -> Vec<(String, String)> {
+        self.relations
+            .iter()
+            .map(|relation| (relation.property.clone(), relation.database_id.clone()))
+            .collect()
+    }
+
+    /// Load the config file, falling back to defaults when it doesn't exist
+    pub fn load() -> Result<Self> {
+        let path = match std::env::var("REMARKABLE2NOTION_CONFIG") {
+            Ok(path) => PathBuf::from(path),
+            Err(_) => dirs::config_dir()
+                .unwrap_or_else(|| PathBuf::from("."))
+                .join("remarkable2notion")
+                .join("config.toml"),
+        };
+
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = std::fs::read_to_string(&path)?;
+        let config: Self = toml::from_str(&content)
+            .map_err(|e| Error::Config(format!("Invalid config file {:?}: {}", path, e)))?;
+
+        for template in &config.templates {
+            let areas = template
+                .crop
+                .iter()
+                .chain(template.regions.iter().map(|region| &region.area));
+            for area in areas {
+                if !area.is_valid() {
+                    return Err(Error::Config(format!(
+                        "Invalid area for template '{}': margins must be fractions of the page and leave part of it",
+                        template.name
+                    )));
+                }
+            }
+            for region in &template.regions {
+                if region.field == TemplateField::Property && region.property.is_none() {
+                    return Err(Error::Config(format!(
+                        "Region of template '{}' needs the name of the property to fill",
+                        template.name
+                    )));
+                }
+            }
+        }
+
+        let layout = &config.layout;
+        let levels = [layout.content_heading, layout.action_items_heading]
+            .into_iter()
+            .chain(layout.page_headings);
+        for level in levels {
+            if !(1..=3).contains(&level) {
+                return Err(Error::Config(format!(
+                    "Invalid heading level {} in [layout]: Notion has levels 1 to 3",
+                    level
+                )));
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Settings for the template with the given name
+    pub fn template(&self, name: &str) -> Option<&TemplateConfig> {
+        self.templates
+            .iter()
+            .find(|template| template.name.eq_ignore_ascii_case(name))
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub notion_token: String,
     pub notion_database_id: String,
+    /// Plain page to create the notebooks' pages under instead of a database
+    pub notion_parent_page_id: Option<String>,
     pub remarkable_backup_dir: Option<PathBuf>,
     pub remarkable_password: Option<String>,
+    /// SSH destination of the tablet (e.g. `root@10.11.99.1`), used to write
+    /// back tags and by the SSH transport
+    pub remarkable_ssh_host: Option<String>,
+    /// How the tablet is backed up: RemarkableSync or directly over SSH
+    pub remarkable_transport: Transport,
+    /// How long RemarkableSync may run before it's considered hung and killed
+    pub remarkable_sync_timeout: Duration,
+    /// Tool converting `.rm` pages to PDF, instead of RemarkableSync's own PDFs
+    pub exporter: ExporterKind,
+    /// Command template for `EXPORTER=custom`
+    pub exporter_command: Option<String>,
+    /// Page format the exporter writes, if not its default
+    pub exporter_format: Option<PageFormat>,
+    /// Also export every page as SVG, for Drive and/or Notion (`SVG_EXPORT`)
+    pub svg_export: SvgExport,
+    /// Write per-page stroke data as JSON into the backup dir (`STROKE_SIDECARS`)
+    pub stroke_sidecars: bool,
+    pub empty_notebooks: EmptyNotebookAction,
+    /// Only notebooks with fewer pages than this count as empty (`EMPTY_NOTEBOOK_PAGES`)
+    pub empty_notebook_pages: usize,
+    /// Imported PDFs and EPUBs with more pages than this only sync their
+    /// marked pages (`SUMMARY_ONLY_PAGES`)
+    pub summary_only_pages: Option<usize>,
+    /// Comment on each page written with what the sync did (`SYNC_COMMENTS`)
+    pub sync_comments: bool,
+    /// Property holding each page's notebook UUID (`SOURCE_ID_PROPERTY`,
+    /// default "Source UUID")
+    pub source_id_property: String,
+    /// Write pages without text and queue their OCR for `sync --ocr-backlog`
+    /// (`DEFER_OCR`, `sync --defer-ocr`)
+    pub defer_ocr: bool,
+    pub delete_mode: DeleteMode,
+    /// Sync notebooks in the tablet's trash too, tagged "Trash", instead of
+    /// applying `delete_mode` (`INCLUDE_TRASH`, `sync --include-trash`)
+    pub include_trash: bool,
+    /// Which notebook data may leave the machine (`PRIVACY_MODE`, default off)
+    pub privacy_mode: PrivacyMode,
+    /// Pages edited in Notion since the last sync (`MANUAL_EDITS`, default warn)
+    pub manual_edits: ManualEdits,
+    /// Stop syncing notebooks whose page was archived in Notion
+    /// (`RESPECT_NOTION_ARCHIVE`)
+    pub respect_notion_archive: bool,
+    /// Notebooks whose page is archived in Notion (`ARCHIVED_PAGES`, default create)
+    pub archived_pages: ArchivedPages,
+    /// Order notebooks start in when several are processed at the same time
+    /// (`NOTEBOOK_ORDER`, default largest-first)
+    pub notebook_order: NotebookOrder,
+    /// Failed OCR of a page (`ON_OCR_ERROR`, default skip)
+    pub on_ocr_error: ErrorPolicy,
+    /// Failed upload of the PDF to Drive or of a page image to Notion
+    /// (`ON_UPLOAD_ERROR`, default fail)
+    pub on_upload_error: ErrorPolicy,
+    /// Failed write of the notebook's Notion page (`ON_NOTION_ERROR`, default fail)
+    pub on_notion_error: ErrorPolicy,
     pub google_oauth_client_id: Option<String>,
     pub google_oauth_client_secret: Option<String>,
+    /// Port receiving the OAuth authorization (`OAUTH_CALLBACK_PORT`, default
+    /// 8085, 0 for any free port)
+    pub oauth_callback_port: u16,
     pub google_drive_folder_id: Option<String>,
+    /// PDF Link without Drive: the work directory, an archive copy or nothing
+    pub pdf_link_mode: PdfLinkMode,
+    /// Directory PDFs are copied into with `PDF_LINK_MODE=archive`
+    pub pdf_archive_dir: Option<PathBuf>,
+    /// URL the archive is served at (`PDF_BASE_URL`), for http links instead of `file://`
+    pub pdf_base_url: Option<url::Url>,
+    /// Address of the built-in PDF server (`PDF_SERVER_BIND`)
+    pub pdf_server_bind: String,
     pub google_vision_api_key: Option<String>,
+    /// Project Vision requests are billed to (`GOOGLE_VISION_QUOTA_PROJECT`)
+    pub google_vision_quota_project: Option<String>,
+    pub ocr_backend: OcrBackendKind,
+    pub ocr_model: Option<String>,
+    pub gemini_api_key: Option<String>,
+    pub openai_api_key: Option<String>,
+    /// Failed notebooks are posted here as JSON (`ERROR_WEBHOOK_URL`)
+    pub error_webhook_url: Option<String>,
+    /// Failed notebooks are reported to this Sentry project (`SENTRY_DSN`)
+    pub sentry_dsn: Option<String>,
+    pub ollama_url: Option<String>,
+    pub ollama_model: Option<String>,
+    pub ollama_prompt: Option<String>,
+    pub smart_title: bool,
+    pub date_locale: DateLocale,
+    pub two_way_tags: bool,
+    pub notion_batch_size: usize,
+    /// Notion requests per second, across concurrent notebooks (`NOTION_RATE_LIMIT`)
+    pub notion_rate_limit: f64,
+    pub ocr_dpi: u32,
+    pub adaptive_dpi: Option<AdaptiveDpi>,
     pub dry_run: bool,
+    /// Read pages back after writing and check they match (`sync --verify`)
+    pub verify: bool,
+    /// Run RemarkableSync before scanning the backup; off when another tool
+    /// keeps the backup up to date (`daemon --watch-backup`)
+    pub device_sync: bool,
+    /// Skip notebooks RemarkableSync reported as unchanged (`sync --changed-only`)
+    pub changed_only: bool,
+    /// Process notebooks whose PDF didn't change since the last sync (`sync --force`)
+    pub force: bool,
+    /// Notebooks processed at the same time (`sync --concurrency`)
+    pub concurrency: usize,
+    /// Stop the run once this many notebooks failed (`sync --max-errors`,
+    /// `--fail-fast` being 1)
+    pub max_errors: Option<usize>,
+    /// Process at most this many notebooks, never synced ones first
+    /// (`sync --limit`)
+    pub limit: Option<usize>,
+    /// Only run the OCR queued by `DEFER_OCR`, without a device sync
+    /// (`sync --ocr-backlog`)
+    pub ocr_backlog: bool,
+    /// Only process notebooks last modified after this (`sync --since`)
+    pub since: Option<chrono::DateTime<chrono::Utc>>,
+    /// Only process notebooks starred on the tablet (`sync --favorites-only`)
+    pub favorites_only: bool,
+    /// Only process notebooks matching one of these patterns (`sync --only`)
+    pub only: Vec<Glob>,
+    /// Only process notebooks with these names, paths or UUIDs, on top of
+    /// `only`; an empty list selects nothing (`sync --stdin`)
+    pub listed: Option<Vec<String>>,
+    /// Only process notebooks with one of these tags (`sync --include-tag`)
+    pub include_tags: Vec<String>,
+    /// Skip notebooks with one of these tags (`sync --exclude-tag`)
+    pub exclude_tags: Vec<String>,
+    /// Write what would be sent to Notion as markdown here instead
+    /// (`sync --review-dir`)
+    pub review_dir: Option<PathBuf>,
     pub temp_dir: PathBuf,
+    pub file: FileConfig,
 }
 
 impl Config {
@@ -27,30 +813,300 @@ impl Config {
         if notion_token.is_empty() {
             return Err(Error::Config("Notion token is required".to_string()));
         }
-        if notion_database_id.is_empty() {
-            return Err(Error::Config("Notion database ID is required".to_string()));
+        let notion_parent_page_id = std::env::var("NOTION_PARENT_PAGE_ID")
+            .ok()
+            .filter(|id| !id.is_empty());
+        match (
+            notion_database_id.is_empty(),
+            notion_parent_page_id.is_some(),
+        ) {
+            (true, false) => {
+                return Err(Error::Config(
+                    "Notion database ID (or NOTION_PARENT_PAGE_ID) is required".to_string(),
+                ))
+            }
+            (false, true) => {
+                return Err(Error::Config(
+                    "Set either a Notion database ID or NOTION_PARENT_PAGE_ID, not both"
+                        .to_string(),
+                ))
+            }
+            _ => {}
         }
 
-        let temp_dir = std::env::temp_dir().join("remarkable2notion");
+        let temp_dir = work_dir();
         std::fs::create_dir_all(&temp_dir)?;
 
         // Optional Google integrations
         let google_oauth_client_id = std::env::var("GOOGLE_OAUTH_CLIENT_ID").ok();
         let google_oauth_client_secret = std::env::var("GOOGLE_OAUTH_CLIENT_SECRET").ok();
+        let oauth_callback_port =
+            env_parse("OAUTH_CALLBACK_PORT")?.unwrap_or(oauth::DEFAULT_CALLBACK_PORT);
         let google_drive_folder_id = std::env::var("GOOGLE_DRIVE_FOLDER_ID").ok();
+        let pdf_link_mode = env_parse("PDF_LINK_MODE")?.unwrap_or_default();
+        let pdf_archive_dir = std::env::var("PDF_ARCHIVE_DIR").ok().map(PathBuf::from);
+        if pdf_link_mode == PdfLinkMode::Archive && pdf_archive_dir.is_none() {
+            return Err(Error::Config(
+                "PDF_LINK_MODE=archive requires PDF_ARCHIVE_DIR".to_string(),
+            ));
+        }
+        // The archive served by `serve` or a web server/reverse proxy of your own
+        let pdf_base_url = match std::env::var("PDF_BASE_URL") {
+            Ok(base) => match url::Url::parse(&base) {
+                Ok(url) if !url.cannot_be_a_base() => Some(url),
+                _ => {
+                    return Err(Error::Config(format!(
+                        "Invalid PDF_BASE_URL '{}' (expected a URL like http://nas.local:8086)",
+                        base
+                    )))
+                }
+            },
+            Err(_) => None,
+        };
+        if pdf_base_url.is_some() && pdf_link_mode != PdfLinkMode::Archive {
+            return Err(Error::Config(
+                "PDF_BASE_URL links to the archive and requires PDF_LINK_MODE=archive".to_string(),
+            ));
+        }
+        let pdf_server_bind = std::env::var("PDF_SERVER_BIND")
+            .unwrap_or_else(|_| file_server::DEFAULT_BIND.to_string());
         let google_vision_api_key = std::env::var("GOOGLE_VISION_API_KEY").ok();
+        let google_vision_quota_project = std::env::var("GOOGLE_VISION_QUOTA_PROJECT").ok();
+
+        // OCR backend selection (Google Cloud Vision by default)
+        let ocr_backend = env_parse("OCR_BACKEND")?.unwrap_or(OcrBackendKind::Vision);
+        let ocr_model = std::env::var("OCR_MODEL").ok();
+        let gemini_api_key = std::env::var("GEMINI_API_KEY").ok();
+        let openai_api_key = std::env::var("OPENAI_API_KEY").ok();
+
+        // Optional failure reporting
+        let error_webhook_url = std::env::var("ERROR_WEBHOOK_URL").ok();
+        let sentry_dsn = std::env::var("SENTRY_DSN").ok();
+
+        // Optional local LLM correction (enabled by setting a model)
+        let ollama_url = std::env::var("OLLAMA_URL").ok();
+        let ollama_model = std::env::var("OLLAMA_MODEL").ok();
+        let ollama_prompt = std::env::var("OLLAMA_PROMPT").ok();
+        if let Some(ref prompt) = ollama_prompt {
+            if !prompt.contains("{text}") {
+                return Err(Error::Config(
+                    "OLLAMA_PROMPT must contain a {text} placeholder".to_string(),
+                ));
+            }
+        }
+
+        // Optional features
+        let smart_title = env_flag("SMART_TITLE");
+        let date_locale = env_parse("DATE_LOCALE")?.unwrap_or_default();
+
+        // Two-way tag sync writes tags added in Notion back to the tablet over SSH
+        // The SSH transport copies the tablet's files itself, over USB by default
+        let remarkable_transport: Transport =
+            env_parse("REMARKABLE_TRANSPORT")?.unwrap_or_default();
+        let remarkable_ssh_host = std::env::var("REMARKABLE_SSH_HOST").ok().or_else(|| {
+            (remarkable_transport == Transport::Ssh).then(|| DEFAULT_SSH_HOST.to_string())
+        });
+        let two_way_tags = env_flag("TWO_WAY_TAGS");
+        if two_way_tags && remarkable_ssh_host.is_none() {
+            return Err(Error::Config(
+                "TWO_WAY_TAGS requires REMARKABLE_SSH_HOST (e.g. root@10.11.99.1)".to_string(),
+            ));
+        }
+        let remarkable_sync_timeout = env_parse("REMARKABLE_SYNC_TIMEOUT")?
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_SYNC_TIMEOUT);
+        // Without RemarkableSync there are no PDFs, so the pages are rendered here
+        let exporter = match env_parse("EXPORTER")? {
+            Some(ExporterKind::RemarkableSync) if remarkable_transport == Transport::Ssh => {
+                return Err(Error::Config(
+                    "REMARKABLE_TRANSPORT=ssh can't use RemarkableSync's PDFs; set EXPORTER to native, rmc, lines-are-rusty or custom".to_string(),
+                ))
+            }
+            Some(exporter) => exporter,
+            None if remarkable_transport == Transport::Ssh => ExporterKind::Native,
+            None => ExporterKind::RemarkableSync,
+        };
+        let exporter_command = std::env::var("EXPORTER_COMMAND").ok();
+        let exporter_format = env_parse("EXPORTER_FORMAT")?;
+        let svg_export: SvgExport = env_parse("SVG_EXPORT")?.unwrap_or_default();
+        let stroke_sidecars = env_flag("STROKE_SIDECARS");
+        if svg_export.to_drive() && google_oauth_client_id.is_none() {
+            return Err(Error::Config(
+                "SVG_EXPORT=drive uploads to Google Drive and requires the Google OAuth settings; use SVG_EXPORT=notion without Drive"
+                    .to_string(),
+            ));
+        }
+
+        // Notebooks without text (test scribbles, blank notebooks) can be kept
+        // out of the database
+        let empty_notebooks = env_parse("EMPTY_NOTEBOOKS")?.unwrap_or(EmptyNotebookAction::Keep);
+        let empty_notebook_pages = env_parse("EMPTY_NOTEBOOK_PAGES")?.unwrap_or(3);
+        let summary_only_pages = env_parse("SUMMARY_ONLY_PAGES")?;
+        let sync_comments = env_flag("SYNC_COMMENTS");
+        let source_id_property = std::env::var("SOURCE_ID_PROPERTY")
+            .ok()
+            .filter(|name| !name.trim().is_empty())
+            .unwrap_or_else(|| notion::SOURCE_ID_PROPERTY.to_string());
+        let defer_ocr = env_flag("DEFER_OCR");
+        let delete_mode = env_parse("DELETE_MODE")?.unwrap_or(DeleteMode::Ignore);
+        let include_trash = env_flag("INCLUDE_TRASH");
+        let privacy_mode = env_parse("PRIVACY_MODE")?.unwrap_or_default();
+        let manual_edits = env_parse("MANUAL_EDITS")?.unwrap_or(ManualEdits::Warn);
+        let respect_notion_archive = env_flag("RESPECT_NOTION_ARCHIVE");
+        let archived_pages = env_parse("ARCHIVED_PAGES")?.unwrap_or(ArchivedPages::Create);
+        let notebook_order = env_parse("NOTEBOOK_ORDER")?.unwrap_or(NotebookOrder::LargestFirst);
+        let on_ocr_error = env_parse("ON_OCR_ERROR")?.unwrap_or(ErrorPolicy::Skip);
+        let on_upload_error = env_parse("ON_UPLOAD_ERROR")?.unwrap_or(ErrorPolicy::Fail);
+        let on_notion_error = env_parse("ON_NOTION_ERROR")?.unwrap_or(ErrorPolicy::Fail);
+
+        let notion_batch_size =
+            env_parse("NOTION_BATCH_SIZE")?.unwrap_or(notion::MAX_CHILDREN_PER_REQUEST);
+        let notion_rate_limit =
+            env_parse("NOTION_RATE_LIMIT")?.unwrap_or(rate_limit::NOTION_REQUESTS_PER_SECOND);
+        if !(notion_rate_limit > 0.0 && notion_rate_limit <= 100.0) {
+            return Err(Error::Config(format!(
+                "NOTION_RATE_LIMIT must be between 0 and 100 requests per second, got {}",
+                notion_rate_limit
+            )));
+        }
+
+        // Adaptive DPI renders at a lower resolution first and only re-renders
+        // pages Vision isn't confident about
+        let adaptive_dpi = if env_flag("ADAPTIVE_DPI") {
+            let defaults = AdaptiveDpi::default();
+            Some(AdaptiveDpi {
+                high_dpi: env_parse("OCR_HIGH_DPI")?.unwrap_or(defaults.high_dpi),
+                min_confidence: env_parse("OCR_MIN_CONFIDENCE")?.unwrap_or(defaults.min_confidence),
+            })
+        } else {
+            None
+        };
+        let default_dpi = if adaptive_dpi.is_some() {
+            100
+        } else {
+            DEFAULT_DPI
+        };
+        let ocr_dpi = env_parse("OCR_DPI")?.unwrap_or(default_dpi);
+
+        let file = FileConfig::load()?;
 
         Ok(Self {
             notion_token,
             notion_database_id,
+            notion_parent_page_id,
             remarkable_backup_dir,
             remarkable_password,
+            remarkable_ssh_host,
+            remarkable_transport,
+            remarkable_sync_timeout,
+            exporter,
+            exporter_command,
+            exporter_format,
+            svg_export,
+            stroke_sidecars,
+            empty_notebooks,
+            empty_notebook_pages,
+            summary_only_pages,
+            sync_comments,
+            source_id_property,
+            defer_ocr,
+            delete_mode,
+            include_trash,
+            privacy_mode,
+            manual_edits,
+            respect_notion_archive,
+            archived_pages,
+            notebook_order,
+            on_ocr_error,
+            on_upload_error,
+            on_notion_error,
             google_oauth_client_id,
             google_oauth_client_secret,
+            oauth_callback_port,
             google_drive_folder_id,
+            pdf_link_mode,
+            pdf_archive_dir,
+            pdf_base_url,
+            pdf_server_bind,
             google_vision_api_key,
+            google_vision_quota_project,
+            ocr_backend,
+            ocr_model,
+            gemini_api_key,
+            openai_api_key,
+            error_webhook_url,
+            sentry_dsn,
+            ollama_url,
+            ollama_model,
+            ollama_prompt,
+            smart_title,
+            date_locale,
+            two_way_tags,
+            notion_batch_size,
+            notion_rate_limit,
+            ocr_dpi,
+            adaptive_dpi,
             dry_run,
+            verify: false,
+            device_sync: true,
+            changed_only: false,
+            force: false,
+            concurrency: 1,
+            max_errors: None,
+            limit: None,
+            ocr_backlog: false,
+            since: None,
+            favorites_only: false,
+            only: Vec::new(),
+            listed: None,
+            include_tags: Vec::new(),
+            exclude_tags: Vec::new(),
+            review_dir: None,
             temp_dir,
+            file,
         })
     }
 }
+
+/// Directory for downloaded PDFs and page images while notebooks are
+/// processed, in a subdirectory per notebook
+pub fn work_dir() -> PathBuf {
+    std::env::temp_dir().join("remarkable2notion")
+}
+
+/// Directory for OCR output kept between runs until a notebook is synced,
+/// in a subdirectory per notebook
+pub fn cache_dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("remarkable2notion")
+}
+
+/// Read a boolean feature flag from the environment (`1`, `true`, `yes` or `on`)
+fn env_flag(name: &str) -> bool {
+    debug_assert!(
+        reference::is_documented(name),
+        "{} isn't in config::reference",
+        name
+    );
+    std::env::var(name)
+        .map(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes" | "on"))
+        .unwrap_or(false)
+}
+
+/// Parse an optional environment variable, failing on malformed values
+pub fn env_parse<T: std::str::FromStr>(name: &str) -> Result<Option<T>> {
+    debug_assert!(
+        reference::is_documented(name),
+        "{} isn't in config::reference",
+        name
+    );
+    match std::env::var(name) {
+        Ok(value) => value
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| Error::Config(format!("Invalid value for {}: {}", name, value))),
+        Err(_) => Ok(None),
+    }
+}
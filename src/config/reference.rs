@@ -0,0 +1,584 @@
+//! Every setting the tool reads, for `config docs` and `config schema`.
+//!
+//! Defaults come from the same constants and `Default` impls `Config::new`
+//! and `FileConfig` use. `env_parse` and `env_flag` check (in debug builds)
+//! that the variables they read are listed here.
+
+use super::{LayoutConfig, MeetingPatterns};
+use crate::correction::DEFAULT_OLLAMA_URL;
+use crate::file_server;
+use crate::notion;
+use crate::oauth;
+use crate::ocr::{AdaptiveDpi, DEFAULT_DPI};
+use crate::rate_limit;
+use crate::remarkable::ssh::DEFAULT_SSH_HOST;
+use crate::remarkable::DEFAULT_SYNC_TIMEOUT;
+use serde::Serialize;
+
+/// Where a setting is read from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    /// Environment variable (or `.env`)
+    Env,
+    /// Key in the TOML config file
+    File,
+}
+
+/// A setting and what it does
+#[derive(Debug, Serialize)]
+pub struct ConfigOption {
+    /// Variable name, or dotted key in the config file
+    pub key: &'static str,
+    pub source: Source,
+    /// "string", "flag", "integer", "number", "seconds", "path", "url", or
+    /// the accepted values separated by `|`
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+    /// Feature the setting belongs to
+    pub feature: &'static str,
+    pub description: &'static str,
+}
+
+fn env(
+    key: &'static str,
+    kind: &'static str,
+    default: Option<String>,
+    feature: &'static str,
+    description: &'static str,
+) -> ConfigOption {
+    ConfigOption {
+        key,
+        source: Source::Env,
+        kind,
+        default,
+        feature,
+        description,
+    }
+}
+
+fn file(
+    key: &'static str,
+    kind: &'static str,
+    default: Option<String>,
+    feature: &'static str,
+    description: &'static str,
+) -> ConfigOption {
+    ConfigOption {
+        key,
+        source: Source::File,
+        kind,
+        default,
+        feature,
+        description,
+    }
+}
+
+fn value(default: impl ToString) -> Option<String> {
+    Some(default.to_string())
+}
+
+/// All settings, grouped by feature
+pub fn options() -> Vec<ConfigOption> {
+    let adaptive = AdaptiveDpi::default();
+    let layout = LayoutConfig::default();
+    let meeting = MeetingPatterns::default();
+
+    vec![
+        // Notion
+        env(
+            "NOTION_TOKEN",
+            "string",
+            None,
+            "Notion",
+            "Integration token (required, or --notion-token)",
+        ),
+        env(
+            "NOTION_DATABASE_ID",
+            "string",
+            None,
+            "Notion",
+            "Database the notebooks' pages go in (or --notion-database-id)",
+        ),
+        env(
+            "NOTION_PARENT_PAGE_ID",
+            "string",
+            None,
+            "Notion",
+            "Page to create the notebooks' pages under instead of a database",
+        ),
+        env(
+            "SOURCE_ID_PROPERTY",
+            "string",
+            value(notion::SOURCE_ID_PROPERTY),
+            "Notion",
+            "Property holding each page's notebook UUID",
+        ),
+        env(
+            "NOTION_BATCH_SIZE",
+            "integer",
+            value(notion::MAX_CHILDREN_PER_REQUEST),
+            "Notion",
+            "Blocks per append request (1-100)",
+        ),
+        env(
+            "NOTION_RATE_LIMIT",
+            "number",
+            value(rate_limit::NOTION_REQUESTS_PER_SECOND),
+            "Notion",
+            "Requests per second, shared by all notebooks synced at the same time",
+        ),
+        env(
+            "SYNC_COMMENTS",
+            "flag",
+            None,
+            "Notion",
+            "Comment on each page written with what the sync did",
+        ),
+        env(
+            "SMART_TITLE",
+            "flag",
+            None,
+            "Notion",
+            "Use the first heading-like line of the text as page title",
+        ),
+        env(
+            "DATE_LOCALE",
+            "en|en-us|nl|de",
+            value("en"),
+            "Notion",
+            "How dates are written in journals, meetings and templates",
+        ),
+        env(
+            "MANUAL_EDITS",
+            "warn|skip|managed|overwrite",
+            value("warn"),
+            "Notion",
+            "Pages edited in Notion since the last sync",
+        ),
+        env(
+            "RESPECT_NOTION_ARCHIVE",
+            "flag",
+            None,
+            "Notion",
+            "Stop syncing notebooks whose page was archived in Notion",
+        ),
+        env(
+            "ARCHIVED_PAGES",
+            "create|unarchive|skip",
+            value("create"),
+            "Notion",
+            "Notebooks whose page is found archived while writing it",
+        ),
+        // reMarkable
+        env(
+            "REMARKABLE_BACKUP_DIR",
+            "path",
+            None,
+            "reMarkable",
+            "Directory RemarkableSync backs the tablet up to",
+        ),
+        env(
+            "REMARKABLE_PASSWORD",
+            "string",
+            None,
+            "reMarkable",
+            "Password of the tablet, for RemarkableSync",
+        ),
+        env(
+            "REMARKABLE_TRANSPORT",
+            "remarkablesync|ssh",
+            value("remarkablesync"),
+            "reMarkable",
+            "How the tablet is backed up",
+        ),
+        env(
+            "REMARKABLE_SSH_HOST",
+            "string",
+            value(DEFAULT_SSH_HOST),
+            "reMarkable",
+            "SSH destination of the tablet, for the ssh transport and two-way tags",
+        ),
+        env(
+            "REMARKABLE_SYNC_TIMEOUT",
+            "seconds",
+            value(DEFAULT_SYNC_TIMEOUT.as_secs()),
+            "reMarkable",
+            "Time before a hung backup is stopped",
+        ),
+        env(
+            "TWO_WAY_TAGS",
+            "flag",
+            None,
+            "reMarkable",
+            "Write tags added in Notion back to the tablet (needs REMARKABLE_SSH_HOST)",
+        ),
+        env(
+            "INCLUDE_TRASH",
+            "flag",
+            None,
+            "reMarkable",
+            "Sync notebooks in the tablet's trash too, tagged Trash",
+        ),
+        env(
+            "DELETE_MODE",
+            "ignore|archive|delete",
+            value("ignore"),
+            "reMarkable",
+            "Pages of notebooks deleted on the tablet",
+        ),
+        // Exporter
+        env(
+            "EXPORTER",
+            "remarkablesync|native|rmc|lines-are-rusty|custom",
+            value("remarkablesync (native with the ssh transport)"),
+            "Exporter",
+            "Tool converting notebooks to PDF",
+        ),
+        env(
+            "EXPORTER_COMMAND",
+            "string",
+            None,
+            "Exporter",
+            "Command for EXPORTER=custom, with {input} and {output}",
+        ),
+        env(
+            "EXPORTER_FORMAT",
+            "pdf|svg",
+            None,
+            "Exporter",
+            "Page format the exporter writes, if not its default",
+        ),
+        env(
+            "SVG_EXPORT",
+            "off|drive|notion|both",
+            value("off"),
+            "Exporter",
+            "Also export every page as SVG, to Google Drive and/or as the Notion page images",
+        ),
+        env(
+            "STROKE_SIDECARS",
+            "flag",
+            None,
+            "Exporter",
+            "Write per-page stroke data as JSON into the backup dir",
+        ),
+        // OCR
+        env(
+            "OCR_BACKEND",
+            "vision|gemini|openai|apple",
+            value("vision"),
+            "OCR",
+            "Service reading the handwriting",
+        ),
+        env(
+            "OCR_MODEL",
+            "string",
+            value("gemini-1.5-flash / gpt-4o"),
+            "OCR",
+            "Model of the gemini and openai backends",
+        ),
+        env("GOOGLE_VISION_API_KEY", "string", None, "OCR", "API key of the vision backend"),
+        env(
+            "GOOGLE_VISION_QUOTA_PROJECT",
+            "string",
+            None,
+            "OCR",
+            "Project Vision requests are billed to",
+        ),
+        env("GEMINI_API_KEY", "string", None, "OCR", "API key of the gemini backend"),
+        env("OPENAI_API_KEY", "string", None, "OCR", "API key of the openai backend"),
+        env(
+            "OCR_DPI",
+            "integer",
+            value(format!("{} (100 with ADAPTIVE_DPI)", DEFAULT_DPI)),
+            "OCR",
+            "Resolution pages are rendered at",
+        ),
+        env(
+            "ADAPTIVE_DPI",
+            "flag",
+            None,
+            "OCR",
+            "Render at low resolution first, re-render unclear pages",
+        ),
+        env(
+            "OCR_HIGH_DPI",
+            "integer",
+            value(adaptive.high_dpi),
+            "OCR",
+            "Resolution of re-rendered pages with ADAPTIVE_DPI",
+        ),
+        env(
+            "OCR_MIN_CONFIDENCE",
+            "number",
+            value(adaptive.min_confidence),
+            "OCR",
+            "Confidence below which ADAPTIVE_DPI re-renders a page",
+        ),
+        env(
+            "OLLAMA_MODEL",
+            "string",
+            None,
+            "OCR",
+            "Local model correcting the text (enables the correction)",
+        ),
+        env("OLLAMA_URL", "url", value(DEFAULT_OLLAMA_URL), "OCR", "Ollama server"),
+        env("OLLAMA_PROMPT", "string", None, "OCR", "Correction prompt, with a {text} placeholder"),
+        env(
+            "EMPTY_NOTEBOOKS",
+            "keep|skip|archive",
+            value("keep"),
+            "OCR",
+            "Notebooks OCR finds no text in",
+        ),
+        env(
+            "EMPTY_NOTEBOOK_PAGES",
+            "integer",
+            value(3),
+            "OCR",
+            "Only notebooks with fewer pages count as empty",
+        ),
+        env(
+            "SUMMARY_ONLY_PAGES",
+            "integer",
+            None,
+            "OCR",
+            "Imported PDFs and EPUBs with more pages only sync their marked pages",
+        ),
+        env(
+            "DEFER_OCR",
+            "flag",
+            None,
+            "OCR",
+            "Write pages right away and leave the OCR to sync --ocr-backlog",
+        ),
+        env(
+            "PRIVACY_MODE",
+            "off|images|text",
+            value("off"),
+            "OCR",
+            "Which notebook data may leave the machine",
+        ),
+        // Google Drive
+        env(
+            "GOOGLE_OAUTH_CLIENT_ID",
+            "string",
+            None,
+            "Google Drive",
+            "OAuth client ID (enables Drive)",
+        ),
+        env(
+            "GOOGLE_OAUTH_CLIENT_SECRET",
+            "string",
+            None,
+            "Google Drive",
+            "OAuth client secret, optional with PKCE",
+        ),
+        env(
+            "OAUTH_CALLBACK_PORT",
+            "integer",
+            value(oauth::DEFAULT_CALLBACK_PORT),
+            "Google Drive",
+            "Port receiving the authorization, 0 for any free one",
+        ),
+        env(
+            "GOOGLE_DRIVE_FOLDER_ID",
+            "string",
+            None,
+            "Google Drive",
+            "Folder PDFs are uploaded to",
+        ),
+        // PDF links
+        env(
+            "PDF_LINK_MODE",
+            "local|archive|none",
+            value("local"),
+            "PDF links",
+            "PDF Link without Google Drive",
+        ),
+        env(
+            "PDF_ARCHIVE_DIR",
+            "path",
+            None,
+            "PDF links",
+            "Directory PDFs are copied into with PDF_LINK_MODE=archive",
+        ),
+        env("PDF_BASE_URL", "url", None, "PDF links", "URL the archive is served at"),
+        env(
+            "PDF_SERVER_BIND",
+            "string",
+            value(file_server::DEFAULT_BIND),
+            "PDF links",
+            "Address of the built-in PDF server",
+        ),
+        // Error handling
+        env(
+            "ON_OCR_ERROR",
+            "skip|fail|retry(n)",
+            value("skip"),
+            "Error handling",
+            "Failed OCR of a page",
+        ),
+        env(
+            "ON_UPLOAD_ERROR",
+            "skip|fail|retry(n)",
+            value("fail"),
+            "Error handling",
+            "Failed upload of a PDF or page image",
+        ),
+        env(
+            "ON_NOTION_ERROR",
+            "skip|fail|retry(n)",
+            value("fail"),
+            "Error handling",
+            "Failed write of a notebook's page",
+        ),
+        env(
+            "ERROR_WEBHOOK_URL",
+            "url",
+            None,
+            "Error handling",
+            "Failed notebooks are posted here as JSON",
+        ),
+        env(
+            "SENTRY_DSN",
+            "url",
+            None,
+            "Error handling",
+            "Failed notebooks are reported to this Sentry project",
+        ),
+        // Sync
+        env(
+            "NOTEBOOK_ORDER",
+            "largest-first|listed",
+            value("largest-first"),
+            "Sync",
+            "Order notebooks start in with --concurrency",
+        ),
+        env(
+            "REMARKABLE2NOTION_CONFIG",
+            "path",
+            value("<config dir>/remarkable2notion/config.toml"),
+            "Sync",
+            "Config file",
+        ),
+        env(
+            "REMARKABLE2NOTION_STATE",
+            "path",
+            value("state.json next to the config file"),
+            "Sync",
+            "Sync state file",
+        ),
+        env("LOG_LEVEL", "error|warn|info|debug|trace", value("info"), "Sync", "Log level"),
+        // Daemon
+        env(
+            "GOOGLE_DRIVE_WATCH_FOLDER_ID",
+            "string",
+            None,
+            "Daemon",
+            "Drive folder the tablet exports to, watched by daemon",
+        ),
+        env(
+            "SCHEDULE",
+            "string",
+            None,
+            "Daemon",
+            "Cron expression or interval (e.g. 30m) to run daemon on",
+        ),
+        // Config file
+        file(
+            "meeting.date",
+            "regex",
+            value(meeting.date),
+            "Meeting notes",
+            "Date line of meeting notebooks (group 1 is the value)",
+        ),
+        file(
+            "meeting.attendees",
+            "regex",
+            value(meeting.attendees),
+            "Meeting notes",
+            "Attendees line of meeting notebooks",
+        ),
+        file(
+            "meeting.action_item",
+            "regex",
+            value(meeting.action_item),
+            "Meeting notes",
+            "Action item lines of meeting notebooks",
+        ),
+        file(
+            "relations",
+            "array of tables",
+            None,
+            "Relations",
+            "Relation properties linking notebooks to other databases (property, database_id, match_on)",
+        ),
+        file(
+            "ocr_rules",
+            "array of tables",
+            None,
+            "OCR",
+            "OCR backend per tag or folder (tag, folder, backend)",
+        ),
+        file(
+            "templates",
+            "array of tables",
+            None,
+            "Templates",
+            "Crop areas and regions per page template (name, crop, regions)",
+        ),
+        file(
+            "vocabulary",
+            "array of strings",
+            None,
+            "OCR",
+            "Names and jargon OCR should recognise",
+        ),
+        file(
+            "vocabularies",
+            "table of string arrays",
+            None,
+            "OCR",
+            "Extra vocabulary per notebook tag",
+        ),
+        file(
+            "layout.content_heading",
+            "integer",
+            value(layout.content_heading),
+            "Layout",
+            "Heading level (1-3) of the text section",
+        ),
+        file(
+            "layout.action_items_heading",
+            "integer",
+            value(layout.action_items_heading),
+            "Layout",
+            "Heading level (1-3) of the Action Items section",
+        ),
+        file(
+            "layout.page_headings",
+            "integer",
+            None,
+            "Layout",
+            "Give every page's text a heading of this level (1-3)",
+        ),
+        file(
+            "layout.page_toggles",
+            "boolean",
+            value(layout.page_toggles),
+            "Layout",
+            "Put every page's text in a toggle",
+        ),
+    ]
+}
+
+/// Whether `key` is an environment variable listed in `options`
+pub fn is_documented(key: &str) -> bool {
+    options()
+        .iter()
+        .any(|option| option.source == Source::Env && option.key == key)
+}
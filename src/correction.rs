@@ -0,0 +1,118 @@
+use crate::error::{ApiError, Error, Result};
+use crate::vocabulary::Vocabulary;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::json;
+use tracing::{debug, warn};
+
+pub const DEFAULT_OLLAMA_URL: &str = "http://localhost:11434";
+
+pub const DEFAULT_PROMPT: &str = "The following text was transcribed from handwriting by OCR \
+and contains recognition errors. Fix misrecognized words, spacing and punctuation while keeping \
+the wording, language and line breaks of the original. Reply with the corrected text only.\n\n{text}";
+
+/// Page separator inserted by the OCR step; kept out of the LLM prompt
+const PAGE_SEPARATOR_PREFIX: &str = "\n\n--- Page ";
+
+#[derive(Debug, Deserialize)]
+struct GenerateResponse {
+    response: String,
+}
+
+/// Post-corrects OCR transcripts with a local LLM served by Ollama
+pub struct OllamaCorrector {
+    client: Client,
+    url: String,
+    model: String,
+    prompt: String,
+}
+
+impl OllamaCorrector {
+    /// `prompt` must contain a `{text}` placeholder for the transcript
+    pub fn new(url: String, model: String, prompt: String) -> Self {
+        Self {
+            client: Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+            model,
+            prompt,
+        }
+    }
+
+    /// Correct the transcript page by page, falling back to the original text
+    /// of any page the model fails on. The model is told to keep the terms
+    /// of `vocabulary` as they're spelled.
+    pub async fn correct(&self, text: &str, vocabulary: &Vocabulary) -> String {
+        let mut corrected = String::with_capacity(text.len());
+
+        for (idx, segment) in text.split(PAGE_SEPARATOR_PREFIX).enumerate() {
+            // Every segment after the first starts with the rest of a separator
+            let (separator, page_text) = if idx == 0 {
+                ("", segment)
+            } else {
+                match segment.find(" ---\n\n") {
+                    Some(end) => segment.split_at(end + " ---\n\n".len()),
+                    None => ("", segment),
+                }
+            };
+
+            if idx > 0 {
+                corrected.push_str(PAGE_SEPARATOR_PREFIX);
+            }
+            corrected.push_str(separator);
+
+            if page_text.trim().is_empty() {
+                corrected.push_str(page_text);
+                continue;
+            }
+
+            match self.correct_page(page_text, vocabulary).await {
+                Ok(page) => corrected.push_str(&page),
+                Err(e) => {
+                    warn!("LLM correction failed, keeping OCR text: {}", e);
+                    corrected.push_str(page_text);
+                }
+            }
+        }
+
+        corrected
+    }
+
+    async fn correct_page(&self, text: &str, vocabulary: &Vocabulary) -> Result<String> {
+        debug!(
+            "Correcting {} characters with Ollama model {}",
+            text.len(),
+            self.model
+        );
+
+        let mut prompt = self.prompt.replace("{text}", text);
+        if !vocabulary.is_empty() {
+            prompt = format!("{}\n\n{}", vocabulary.prompt_hint(), prompt);
+        }
+
+        let request_body = json!({
+            "model": self.model,
+            "prompt": prompt,
+            "stream": false
+        });
+
+        let response = self
+            .client
+            .post(format!("{}/api/generate", self.url))
+            .json(&request_body)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(ApiError::from_response("Ollama", "Ollama request failed", response).await);
+        }
+
+        let result: GenerateResponse = response.json().await?;
+        let corrected = result.response.trim();
+
+        if corrected.is_empty() {
+            return Err(Error::Ocr("Ollama returned an empty response".to_string()));
+        }
+
+        Ok(corrected.to_string())
+    }
+}
@@ -0,0 +1,213 @@
+use crate::error::{Error, Result};
+use crate::google_drive::{DriveChange, GoogleDriveClient};
+use crate::schedule::Schedule;
+use crate::sync::SyncEngine;
+use chrono::{DateTime, Local};
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::Path;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
+
+/// Poll the Drive changes feed and sync the notebooks whose PDF exports changed.
+///
+/// Runs until `cancel` is triggered.
+pub async fn watch_drive(
+    engine: &SyncEngine,
+    drive: &GoogleDriveClient,
+    folder_id: &str,
+    interval: Duration,
+    cancel: &CancellationToken,
+) -> Result<()> {
+    let mut page_token = drive.start_page_token().await?;
+    info!(
+        "Watching Google Drive folder {} every {}s",
+        folder_id,
+        interval.as_secs()
+    );
+
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = cancel.cancelled() => {
+                info!("Stopping daemon");
+                return Ok(());
+            }
+        }
+
+        let changes = match drive.changes_since(&page_token).await {
+            Ok((changes, next_token)) => {
+                page_token = next_token;
+                changes
+            }
+            Err(e) => {
+                warn!("Failed to check Drive for changes: {}", e);
+                continue;
+            }
+        };
+
+        let notebooks = exported_notebooks(&changes, folder_id);
+        if notebooks.is_empty() {
+            debug!("No new exports ({} unrelated changes)", changes.len());
+            continue;
+        }
+
+        info!("New exports for {} notebooks, syncing", notebooks.len());
+        sync(engine, &notebooks, cancel).await;
+    }
+}
+
+/// Watch the backup directory and sync the notebooks whose PDFs change.
+///
+/// For setups where RemarkableSync (or another tool) updates the backup on
+/// its own; the engine should be built with device sync disabled. A sync
+/// starts once no PDF has changed for `settle`, so a backup in progress is
+/// picked up as a whole. Runs until `cancel` is triggered.
+pub async fn watch_backup(
+    engine: &SyncEngine,
+    settle: Duration,
+    cancel: &CancellationToken,
+) -> Result<()> {
+    let pdf_dir = engine.backup_dir().join("PDF");
+    std::fs::create_dir_all(&pdf_dir)?;
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event| {
+        let _ = tx.send(event);
+    })
+    .map_err(watch_error)?;
+    watcher
+        .watch(&pdf_dir, RecursiveMode::Recursive)
+        .map_err(watch_error)?;
+    info!("Watching {:?} for changed notebooks", pdf_dir);
+
+    let mut changed = HashSet::new();
+    loop {
+        tokio::select! {
+            event = rx.recv() => {
+                match event {
+                    Some(Ok(event)) => {
+                        if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                            changed.extend(event.paths.iter().filter_map(|path| notebook_name(path)));
+                        }
+                    }
+                    Some(Err(e)) => warn!("Filesystem watch error: {}", e),
+                    None => return Ok(()),
+                }
+                continue;
+            }
+            _ = tokio::time::sleep(settle), if !changed.is_empty() => {}
+            _ = cancel.cancelled() => {
+                info!("Stopping daemon");
+                return Ok(());
+            }
+        }
+
+        let notebooks = std::mem::take(&mut changed);
+        info!(
+            "{} notebooks changed in the backup, syncing",
+            notebooks.len()
+        );
+        sync(engine, &notebooks, cancel).await;
+    }
+}
+
+/// Run a full sync, device sync included, whenever the schedule says so.
+///
+/// Runs never overlap: scheduled times that pass while a run is still
+/// active are skipped, and the next run starts at the first scheduled time
+/// after it finished. Runs until `cancel` is triggered.
+pub async fn run_scheduled(
+    engine: &SyncEngine,
+    schedule: &Schedule,
+    cancel: &CancellationToken,
+) -> Result<()> {
+    let mut next = next_run(schedule, Local::now())?;
+    info!("Scheduled, first sync at {}", next.format("%Y-%m-%d %H:%M"));
+
+    loop {
+        let wait = (next - Local::now()).to_std().unwrap_or_default();
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {}
+            _ = cancel.cancelled() => {
+                info!("Stopping daemon");
+                return Ok(());
+            }
+        }
+
+        match engine.sync(cancel).await {
+            Ok(report) => info!(
+                "Sync {} complete: {} succeeded, {} failed",
+                report.run_id, report.succeeded, report.failed
+            ),
+            Err(e) => error!("Sync failed: {}", e),
+        }
+        if cancel.is_cancelled() {
+            return Ok(());
+        }
+
+        let now = Local::now();
+        let mut skipped = 0;
+        next = next_run(schedule, next)?;
+        while next <= now {
+            skipped += 1;
+            next = next_run(schedule, next)?;
+        }
+        if skipped > 0 {
+            warn!(
+                "Previous sync was still running, skipped {} scheduled runs",
+                skipped
+            );
+        }
+        info!("Next sync at {}", next.format("%Y-%m-%d %H:%M"));
+    }
+}
+
+fn next_run(schedule: &Schedule, after: DateTime<Local>) -> Result<DateTime<Local>> {
+    schedule
+        .next_after(after)
+        .ok_or_else(|| Error::Config("Schedule never runs".to_string()))
+}
+
+async fn sync(engine: &SyncEngine, notebooks: &HashSet<String>, cancel: &CancellationToken) {
+    match engine.sync_notebooks(Some(notebooks), cancel).await {
+        Ok(report) => info!(
+            "Sync {} complete: {} succeeded, {} failed",
+            report.run_id, report.succeeded, report.failed
+        ),
+        Err(e) => error!("Sync failed: {}", e),
+    }
+}
+
+/// Notebook name of a PDF in the backup (its file name without extension)
+fn notebook_name(path: &Path) -> Option<String> {
+    if path.extension().and_then(|ext| ext.to_str()) != Some("pdf") {
+        return None;
+    }
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+}
+
+fn watch_error(e: notify::Error) -> Error {
+    Error::Io(std::io::Error::other(format!(
+        "Failed to watch backup directory: {}",
+        e
+    )))
+}
+
+/// Notebook names of PDFs added or updated in the watched folder.
+///
+/// Our own uploads are skipped, or every sync would trigger the next one.
+fn exported_notebooks(changes: &[DriveChange], folder_id: &str) -> HashSet<String> {
+    changes
+        .iter()
+        .filter(|change| !change.removed)
+        .filter_map(|change| change.file.as_ref())
+        .filter(|file| {
+            !file.trashed && !file.is_own_upload() && file.parents.iter().any(|p| p == folder_id)
+        })
+        .filter_map(|file| file.name.strip_suffix(".pdf"))
+        .map(str::to_string)
+        .collect()
+}
@@ -1,3 +1,4 @@
+use serde::Serialize;
 use std::fmt;
 
 #[derive(Debug)]
@@ -9,6 +10,150 @@ pub enum Error {
     Reqwest(reqwest::Error),
     Config(String),
     OAuth(String),
+    /// Google has to be authorized again (revoked refresh token, new
+    /// scopes), and there's no terminal to sign in from
+    Reauthorize(String),
+    /// A request answered with an error status
+    Api(ApiError),
+    /// An error while syncing a notebook, with the stage it happened in
+    Stage {
+        stage: &'static str,
+        source: Box<Error>,
+    },
+}
+
+/// Details of a failed API request, as far as the response provides them
+#[derive(Debug)]
+pub struct ApiError {
+    /// "Notion", "Google Drive", ...
+    pub service: &'static str,
+    /// What was being done, e.g. "Failed to create page"
+    pub action: String,
+    pub status: reqwest::StatusCode,
+    /// Machine-readable error code, e.g. Notion's `validation_error`
+    pub code: Option<String>,
+    pub message: String,
+    pub request_id: Option<String>,
+}
+
+impl ApiError {
+    /// Build the error from an unsuccessful response, reading the error
+    /// code and request ID from Notion and Google style error bodies
+    pub async fn from_response(
+        service: &'static str,
+        action: impl Into<String>,
+        response: reqwest::Response,
+    ) -> Error {
+        let status = response.status();
+        let header_id = response
+            .headers()
+            .get("x-request-id")
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let body = response
+            .text()
+            .await
+            .unwrap_or_else(|_| "<failed to read response body>".to_string());
+
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap_or_default();
+        // Google nests the details under "error"; Notion has them at the top level
+        let details = match json.get("error") {
+            Some(error) if error.is_object() => error,
+            _ => &json,
+        };
+        let text = |key: &str| {
+            details
+                .get(key)
+                .and_then(|v| v.as_str())
+                .map(str::to_string)
+        };
+
+        let code = text("code").or_else(|| text("status"));
+        let message = text("message").unwrap_or_else(|| body.trim().to_string());
+        let request_id = text("request_id").or(header_id);
+
+        Error::Api(ApiError {
+            service,
+            action: action.into(),
+            status,
+            code,
+            message,
+            request_id,
+        })
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} API error: {}: {}",
+            self.service, self.action, self.status
+        )?;
+        if let Some(ref code) = self.code {
+            write!(f, " {}", code)?;
+        }
+        if !self.message.is_empty() {
+            write!(f, " - {}", self.message)?;
+        }
+        if let Some(ref request_id) = self.request_id {
+            write!(f, " (request ID {})", request_id)?;
+        }
+        Ok(())
+    }
+}
+
+/// Structured error details for the `--json` report
+#[derive(Debug, Default, Serialize)]
+pub struct ErrorContext {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stage: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http_status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+impl Error {
+    /// The stage and API details recorded in this error
+    pub fn context(&self) -> ErrorContext {
+        match self {
+            Error::Stage { stage, source } => ErrorContext {
+                stage: Some(stage),
+                ..source.context()
+            },
+            Error::Api(api) => ErrorContext {
+                service: Some(api.service),
+                http_status: Some(api.status.as_u16()),
+                code: api.code.clone(),
+                request_id: api.request_id.clone(),
+                ..ErrorContext::default()
+            },
+            _ => ErrorContext::default(),
+        }
+    }
+}
+
+/// Record the notebook sync stage an error happened in
+pub trait StageExt<T> {
+    fn stage(self, stage: &'static str) -> Result<T>;
+}
+
+impl<T> StageExt<T> for Result<T> {
+    fn stage(self, stage: &'static str) -> Result<T> {
+        self.map_err(|e| match e {
+            // The innermost stage is the most precise
+            Error::Stage { .. } => e,
+            e => Error::Stage {
+                stage,
+                source: Box::new(e),
+            },
+        })
+    }
 }
 
 impl fmt::Display for Error {
@@ -21,6 +166,9 @@ impl fmt::Display for Error {
             Error::Reqwest(err) => write!(f, "HTTP error: {}", err),
             Error::Config(msg) => write!(f, "Configuration error: {}", msg),
             Error::OAuth(msg) => write!(f, "OAuth error: {}", msg),
+            Error::Reauthorize(msg) => write!(f, "Google has to be authorized again: {}", msg),
+            Error::Api(api) => write!(f, "{}", api),
+            Error::Stage { stage, source } => write!(f, "{}: {}", stage, source),
         }
     }
 }
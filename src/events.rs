@@ -0,0 +1,37 @@
+use serde::Serialize;
+
+/// Progress of a sync run, for showing live progress without parsing logs.
+///
+/// Subscribe with `SyncEngine::subscribe`; `sync --events` prints them as JSON lines.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum SyncEvent {
+    NotebookStarted {
+        notebook: String,
+    },
+    OcrCompleted {
+        notebook: String,
+        pages: usize,
+        characters: usize,
+    },
+    /// A page image was added to the notebook's Notion page
+    PageUploaded {
+        notebook: String,
+        page: usize,
+    },
+    NotebookCompleted {
+        notebook: String,
+    },
+    NotebookFailed {
+        notebook: String,
+        reason: String,
+    },
+    /// Writing the page failed and was skipped (`ON_NOTION_ERROR=skip`)
+    NotebookSkipped {
+        notebook: String,
+    },
+    /// The run was cancelled before the notebook was uploaded
+    NotebookCancelled {
+        notebook: String,
+    },
+}
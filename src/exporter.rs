@@ -0,0 +1,406 @@
+use crate::error::{Error, Result};
+use crate::process;
+use crate::remarkable::{lines, page_ids, render};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::process::Command;
+use tracing::debug;
+
+/// How long an exporter may take for one page
+const PAGE_TIMEOUT: Duration = Duration::from_secs(60);
+
+const RMC_COMMAND: &str = "rmc -t svg -o {output} {input}";
+const LINES_ARE_RUSTY_COMMAND: &str = "lines-are-rusty {input} -o {output}";
+
+/// Tools that convert notebook pages instead of RemarkableSync (`EXPORTER`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExporterKind {
+    /// Use the PDFs RemarkableSync writes to the backup
+    RemarkableSync,
+    /// Render the strokes in-process, without external tools
+    Native,
+    /// [rmc](https://github.com/ricklupton/rmc), which also reads the v6 format
+    Rmc,
+    /// [lines-are-rusty](https://github.com/ax3l/lines-are-rusty)
+    LinesAreRusty,
+    /// Any command, from the `EXPORTER_COMMAND` template
+    Custom,
+}
+
+impl FromStr for ExporterKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "remarkablesync" | "default" => Ok(ExporterKind::RemarkableSync),
+            "native" => Ok(ExporterKind::Native),
+            "rmc" => Ok(ExporterKind::Rmc),
+            "lines-are-rusty" | "lines_are_rusty" => Ok(ExporterKind::LinesAreRusty),
+            "custom" => Ok(ExporterKind::Custom),
+            other => Err(Error::Config(format!(
+                "Unknown exporter '{}' (expected remarkablesync, native, rmc, lines-are-rusty or custom)",
+                other
+            ))),
+        }
+    }
+}
+
+/// File format an exporter writes pages in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageFormat {
+    Pdf,
+    Svg,
+}
+
+impl PageFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            PageFormat::Pdf => "pdf",
+            PageFormat::Svg => "svg",
+        }
+    }
+}
+
+impl FromStr for PageFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "pdf" => Ok(PageFormat::Pdf),
+            "svg" => Ok(PageFormat::Svg),
+            other => Err(Error::Config(format!(
+                "Unknown page format '{}' (expected pdf or svg)",
+                other
+            ))),
+        }
+    }
+}
+
+/// Converts a notebook's `.rm` page files into a PDF with an external tool,
+/// or with the built-in renderer.
+///
+/// Each tool writes one file per page in its own format; pages are brought
+/// to PDF (SVGs via `rsvg-convert`) and joined with `pdfunite`. The built-in
+/// renderer writes the whole PDF itself.
+#[derive(Clone)]
+pub struct Exporter {
+    tool: Tool,
+    format: PageFormat,
+}
+
+#[derive(Clone)]
+enum Tool {
+    /// Command line with `{input}` and `{output}` placeholders
+    Command(Vec<String>),
+    /// `remarkable::render`
+    Native,
+}
+
+impl Exporter {
+    /// The configured exporter, or `None` to keep using RemarkableSync's PDFs
+    pub fn new(
+        kind: ExporterKind,
+        command: Option<&str>,
+        format: Option<PageFormat>,
+    ) -> Result<Option<Self>> {
+        let (template, default_format) = match kind {
+            ExporterKind::RemarkableSync => return Ok(None),
+            ExporterKind::Native => {
+                return Ok(Some(Self {
+                    tool: Tool::Native,
+                    format: format.unwrap_or(PageFormat::Pdf),
+                }))
+            }
+            ExporterKind::Rmc => (RMC_COMMAND, PageFormat::Svg),
+            ExporterKind::LinesAreRusty => (LINES_ARE_RUSTY_COMMAND, PageFormat::Pdf),
+            ExporterKind::Custom => {
+                let command = command.ok_or_else(|| {
+                    Error::Config("EXPORTER=custom requires EXPORTER_COMMAND".to_string())
+                })?;
+                if !command.contains("{input}") || !command.contains("{output}") {
+                    return Err(Error::Config(
+                        "EXPORTER_COMMAND must contain {input} and {output} placeholders"
+                            .to_string(),
+                    ));
+                }
+                (command, PageFormat::Pdf)
+            }
+        };
+
+        Ok(Some(Self::from_template(
+            template,
+            format.unwrap_or(default_format),
+        )))
+    }
+
+    /// The built-in renderer, writing PDF
+    pub fn native() -> Self {
+        Self {
+            tool: Tool::Native,
+            format: PageFormat::Pdf,
+        }
+    }
+
+    fn from_template(template: &str, format: PageFormat) -> Self {
+        Self {
+            tool: Tool::Command(template.split_whitespace().map(str::to_string).collect()),
+            format,
+        }
+    }
+
+    /// Exporter for SVG pages: the configured one if it writes SVG or is the
+    /// built-in renderer, rmc otherwise
+    pub fn svg(configured: Option<&Exporter>) -> Exporter {
+        match configured {
+            Some(exporter) if exporter.format == PageFormat::Svg => exporter.clone(),
+            Some(Exporter {
+                tool: Tool::Native, ..
+            }) => Self {
+                tool: Tool::Native,
+                format: PageFormat::Svg,
+            },
+            _ => Self::from_template(RMC_COMMAND, PageFormat::Svg),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match &self.tool {
+            Tool::Command(template) => &template[0],
+            Tool::Native => "native renderer",
+        }
+    }
+
+    /// Export the pages of document `uuid` in the backup's `Notebooks` dir to one PDF
+    pub async fn export(&self, notebooks_dir: &Path, uuid: &str, output: &Path) -> Result<()> {
+        if let Tool::Native = self.tool {
+            return export_native(notebooks_dir, uuid, output);
+        }
+        let work_dir = output.with_extension("pages");
+        std::fs::create_dir_all(&work_dir)?;
+
+        let result = self.export_to(notebooks_dir, uuid, &work_dir, output).await;
+        std::fs::remove_dir_all(&work_dir).ok();
+        result
+    }
+
+    async fn export_to(
+        &self,
+        notebooks_dir: &Path,
+        uuid: &str,
+        work_dir: &Path,
+        output: &Path,
+    ) -> Result<()> {
+        let pages = self.export_pages(notebooks_dir, uuid, work_dir).await?;
+
+        let mut pdfs = Vec::new();
+        for (_, page) in pages {
+            pdfs.push(match self.format {
+                PageFormat::Pdf => page,
+                PageFormat::Svg => svg_to_pdf(&page).await?,
+            });
+        }
+
+        join_pdfs(&pdfs, output).await
+    }
+
+    /// Draw the notebook's strokes over the pages of the PDF it was imported
+    /// from, with `qpdf`. Each exported page goes on top of the original
+    /// page with the same number.
+    pub async fn export_annotated(
+        &self,
+        notebooks_dir: &Path,
+        uuid: &str,
+        original: &Path,
+        output: &Path,
+    ) -> Result<()> {
+        let work_dir = output.with_extension("overlay");
+        std::fs::create_dir_all(&work_dir)?;
+
+        let result = self
+            .overlay_to(notebooks_dir, uuid, original, &work_dir, output)
+            .await;
+        std::fs::remove_dir_all(&work_dir).ok();
+        result
+    }
+
+    async fn overlay_to(
+        &self,
+        notebooks_dir: &Path,
+        uuid: &str,
+        original: &Path,
+        work_dir: &Path,
+        output: &Path,
+    ) -> Result<()> {
+        let pages = self.export_pages(notebooks_dir, uuid, work_dir).await?;
+
+        let mut cmd = Command::new("qpdf");
+        cmd.arg(original);
+        for (page_num, page) in pages {
+            let pdf = match self.format {
+                PageFormat::Pdf => page,
+                PageFormat::Svg => svg_to_pdf(&page).await?,
+            };
+            cmd.arg("--overlay")
+                .arg(pdf)
+                .arg(format!("--to={}", page_num))
+                .arg("--");
+        }
+        cmd.arg(output);
+
+        let result = process::output(&mut cmd, PAGE_TIMEOUT).await.map_err(|e| {
+            Error::Remarkable(format!(
+                "Failed to run qpdf: {}. Install qpdf to merge annotations into imported PDFs",
+                e
+            ))
+        })?;
+        // Exit code 3 means success with warnings
+        if !result.status.success() && result.status.code() != Some(3) {
+            return Err(Error::Remarkable(format!(
+                "Merging the annotations into {:?} failed: {}",
+                original,
+                String::from_utf8_lossy(&result.stderr).trim()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Run the exporter on every page, returning the files by (1-based) page number.
+    ///
+    /// Pages without strokes have no `.rm` file and are left out.
+    pub async fn export_pages(
+        &self,
+        notebooks_dir: &Path,
+        uuid: &str,
+        work_dir: &Path,
+    ) -> Result<Vec<(usize, PathBuf)>> {
+        let page_ids = page_ids(notebooks_dir, uuid)?;
+        let mut pages = Vec::new();
+
+        for (i, page_id) in page_ids.iter().enumerate() {
+            let input = notebooks_dir.join(uuid).join(format!("{}.rm", page_id));
+            if !input.exists() {
+                debug!("Page {} of {} has no strokes, skipping", i + 1, uuid);
+                continue;
+            }
+
+            let output = work_dir.join(format!("page-{:04}.{}", i + 1, self.format.extension()));
+            self.run(&input, &output).await?;
+            pages.push((i + 1, output));
+        }
+
+        if pages.is_empty() {
+            return Err(Error::Remarkable(format!(
+                "{} exported no pages for {}",
+                self.name(),
+                uuid
+            )));
+        }
+
+        debug!("{} exported {} pages of {}", self.name(), pages.len(), uuid);
+        Ok(pages)
+    }
+
+    async fn run(&self, input: &Path, output: &Path) -> Result<()> {
+        let template = match &self.tool {
+            Tool::Command(template) => template,
+            Tool::Native => {
+                let page = lines::parse(&std::fs::read(input)?)?;
+                return Ok(std::fs::write(
+                    output,
+                    match self.format {
+                        PageFormat::Pdf => render::pdf(&[Some(page)]),
+                        PageFormat::Svg => render::svg(&page).into_bytes(),
+                    },
+                )?);
+            }
+        };
+        let args: Vec<String> = template
+            .iter()
+            .map(|arg| {
+                arg.replace("{input}", &input.to_string_lossy())
+                    .replace("{output}", &output.to_string_lossy())
+            })
+            .collect();
+
+        let mut cmd = Command::new(&args[0]);
+        cmd.args(&args[1..]);
+        let result = process::output(&mut cmd, PAGE_TIMEOUT)
+            .await
+            .map_err(|e| Error::Remarkable(format!("Failed to run {}: {}", self.name(), e)))?;
+
+        if !result.status.success() || !output.exists() {
+            return Err(Error::Remarkable(format!(
+                "{} failed on {:?}: {}",
+                self.name(),
+                input,
+                String::from_utf8_lossy(&result.stderr).trim()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Render every page, blank ones included, into a single PDF
+fn export_native(notebooks_dir: &Path, uuid: &str, output: &Path) -> Result<()> {
+    let pages = page_ids(notebooks_dir, uuid)?
+        .iter()
+        .map(|page_id| {
+            let input = notebooks_dir.join(uuid).join(format!("{}.rm", page_id));
+            if !input.exists() {
+                return Ok(None);
+            }
+            lines::parse(&std::fs::read(&input)?).map(Some)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if pages.is_empty() {
+        return Err(Error::Remarkable(format!("{} has no pages", uuid)));
+    }
+
+    std::fs::write(output, render::pdf(&pages))?;
+    debug!("Rendered {} pages of {}", pages.len(), uuid);
+    Ok(())
+}
+
+async fn svg_to_pdf(svg: &Path) -> Result<PathBuf> {
+    let pdf = svg.with_extension("pdf");
+    let mut cmd = Command::new("rsvg-convert");
+    cmd.arg("-f").arg("pdf").arg("-o").arg(&pdf).arg(svg);
+    let output = process::output(&mut cmd, PAGE_TIMEOUT).await.map_err(|e| {
+        Error::Remarkable(format!(
+            "Failed to run rsvg-convert: {}. Install librsvg to convert SVG pages",
+            e
+        ))
+    })?;
+
+    if !output.status.success() {
+        return Err(Error::Remarkable(format!(
+            "Converting {:?} to PDF failed: {}",
+            svg,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(pdf)
+}
+
+async fn join_pdfs(pages: &[PathBuf], output: &Path) -> Result<()> {
+    if let [page] = pages {
+        std::fs::copy(page, output)?;
+        return Ok(());
+    }
+
+    let mut cmd = Command::new("pdfunite");
+    cmd.args(pages).arg(output);
+    let result = process::output(&mut cmd, PAGE_TIMEOUT)
+        .await
+        .map_err(|e| Error::Remarkable(format!("Failed to run pdfunite: {}", e)))?;
+
+    if !result.status.success() {
+        return Err(Error::Remarkable(format!(
+            "Joining pages into {:?} failed: {}",
+            output,
+            String::from_utf8_lossy(&result.stderr).trim()
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,478 @@
+use crate::config::{DateLocale, MeetingPatterns, RegionConfig, TemplateField};
+use crate::error::{Error, Result};
+use chrono::{Datelike, NaiveDate};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+
+/// One day of a journal notebook, split off at a date heading
+#[derive(Debug, Clone)]
+pub struct JournalEntry {
+    pub date: NaiveDate,
+    pub content: String,
+    /// 1-based page numbers the entry's text was found on
+    pub pages: Vec<usize>,
+}
+
+/// Fields pulled out of a `meeting`-tagged notebook
+#[derive(Debug, Clone, Default)]
+pub struct MeetingNotes {
+    pub date: Option<NaiveDate>,
+    pub attendees: Vec<String>,
+    pub action_items: Vec<String>,
+}
+
+/// Fields read from the regions of templated pages
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TemplateFields {
+    pub title: Option<String>,
+    pub date: Option<NaiveDate>,
+    pub action_items: Vec<String>,
+    /// Text of text properties, by property name
+    pub properties: BTreeMap<String, String>,
+}
+
+impl TemplateFields {
+    /// Add the OCR text of a region. Of single-valued fields the first page
+    /// with a value wins; action items are collected from all pages.
+    pub fn add(
+        &mut self,
+        region: &RegionConfig,
+        text: &str,
+        default_year: i32,
+        locale: DateLocale,
+    ) {
+        let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+
+        match region.field {
+            TemplateField::Title => {
+                if self.title.is_none() {
+                    self.title = lines.next().map(str::to_string);
+                }
+            }
+            TemplateField::Date => {
+                if self.date.is_none() {
+                    self.date =
+                        lines.find_map(|line| parse_date_heading(line, default_year, locale));
+                }
+            }
+            TemplateField::ActionItems => {
+                for line in lines {
+                    let item = line
+                        .trim_start_matches(['☐', '□', '☑', '☒', '[', ']', '-', '*', ' '])
+                        .trim();
+                    if !item.is_empty() {
+                        self.action_items.push(item.to_string());
+                    }
+                }
+            }
+            TemplateField::Property => {
+                let Some(ref property) = region.property else {
+                    return;
+                };
+                let value = lines.collect::<Vec<_>>().join(" ");
+                if !value.is_empty() {
+                    self.properties.entry(property.clone()).or_insert(value);
+                }
+            }
+        }
+    }
+}
+
+const MONTHS: [&str; 12] = [
+    "january",
+    "february",
+    "march",
+    "april",
+    "may",
+    "june",
+    "july",
+    "august",
+    "september",
+    "october",
+    "november",
+    "december",
+];
+
+const WEEKDAYS: [&str; 7] = [
+    "monday",
+    "tuesday",
+    "wednesday",
+    "thursday",
+    "friday",
+    "saturday",
+    "sunday",
+];
+
+const MONTHS_NL: [&str; 12] = [
+    "januari",
+    "februari",
+    "maart",
+    "april",
+    "mei",
+    "juni",
+    "juli",
+    "augustus",
+    "september",
+    "oktober",
+    "november",
+    "december",
+];
+
+const WEEKDAYS_NL: [&str; 7] = [
+    "maandag",
+    "dinsdag",
+    "woensdag",
+    "donderdag",
+    "vrijdag",
+    "zaterdag",
+    "zondag",
+];
+
+const MONTHS_DE: [&str; 12] = [
+    "januar",
+    "februar",
+    "märz",
+    "april",
+    "mai",
+    "juni",
+    "juli",
+    "august",
+    "september",
+    "oktober",
+    "november",
+    "dezember",
+];
+
+const WEEKDAYS_DE: [&str; 7] = [
+    "montag",
+    "dienstag",
+    "mittwoch",
+    "donnerstag",
+    "freitag",
+    "samstag",
+    "sonntag",
+];
+
+/// Month and weekday names of one language
+type Names = (&'static [&'static str; 12], &'static [&'static str; 7]);
+
+/// Names to recognise: the locale's own, then English
+fn names(locale: DateLocale) -> &'static [Names] {
+    match locale {
+        DateLocale::En | DateLocale::EnUs => &[(&MONTHS, &WEEKDAYS)],
+        DateLocale::Nl => &[(&MONTHS_NL, &WEEKDAYS_NL), (&MONTHS, &WEEKDAYS)],
+        DateLocale::De => &[(&MONTHS_DE, &WEEKDAYS_DE), (&MONTHS, &WEEKDAYS)],
+    }
+}
+
+fn numeric_date_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"^(\d{1,4})[-/.](\d{1,2})[-/.](\d{1,4})$").expect("valid numeric date regex")
+    })
+}
+
+fn day_month_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"^(\d{1,2})(?:st|nd|rd|th|ste|de|e)?\.?\s+(\p{L}+)\.?,?(?:\s+(\d{4}))?$")
+            .expect("valid day-month regex")
+    })
+}
+
+fn month_day_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"^(\p{L}+)\.?\s+(\d{1,2})(?:st|nd|rd|th|ste|de|e)?,?(?:\s+(\d{4}))?$")
+            .expect("valid month-day regex")
+    })
+}
+
+/// Look up a month by full name or (3+ letter) abbreviation
+fn month_number(name: &str, locale: DateLocale) -> Option<u32> {
+    if name.chars().count() < 3 {
+        return None;
+    }
+    names(locale).iter().find_map(|(months, _)| {
+        months
+            .iter()
+            .position(|m| m.starts_with(name))
+            .map(|idx| idx as u32 + 1)
+    })
+}
+
+/// Strip a leading weekday ("Monday,", "Tue", "Dinsdag") from a heading
+fn strip_weekday(line: &str, locale: DateLocale) -> &str {
+    let first_word_end = line
+        .find(|c: char| c.is_whitespace() || c == ',')
+        .unwrap_or(line.len());
+    let first_word = line[..first_word_end].trim_end_matches('.');
+
+    let is_weekday = first_word.chars().count() >= 3
+        && names(locale)
+            .iter()
+            .any(|(_, weekdays)| weekdays.iter().any(|d| d.starts_with(first_word)));
+    if is_weekday {
+        line[first_word_end..]
+            .trim_start_matches(|c: char| c.is_whitespace() || c == ',')
+            .trim()
+    } else {
+        line
+    }
+}
+
+/// Parse a line that consists of nothing but a date, e.g. a journal heading.
+///
+/// Supports ISO dates (`2024-10-15`), numeric dates (`15-10-2024`,
+/// `15/10/24`, month first with the `en-us` locale) and written dates
+/// (`15 October 2024`, `Oct 15th`, `3 maart`, `1. Mai`), optionally preceded
+/// by a weekday. Dates without a year use `default_year`.
+pub fn parse_date_heading(line: &str, default_year: i32, locale: DateLocale) -> Option<NaiveDate> {
+    let line = line.trim().trim_start_matches('#').trim().to_lowercase();
+    if line.is_empty() || line.len() > 40 {
+        return None;
+    }
+    let line = strip_weekday(&line, locale);
+
+    if let Some(caps) = numeric_date_re().captures(line) {
+        let a: u32 = caps[1].parse().ok()?;
+        let b: u32 = caps[2].parse().ok()?;
+        let c: u32 = caps[3].parse().ok()?;
+
+        return if caps[1].len() == 4 {
+            NaiveDate::from_ymd_opt(a as i32, b, c)
+        } else {
+            let year = if caps[3].len() == 2 { 2000 + c } else { c };
+            let (day, month) = if locale == DateLocale::EnUs {
+                (b, a)
+            } else {
+                (a, b)
+            };
+            NaiveDate::from_ymd_opt(year as i32, month, day)
+        };
+    }
+
+    let (day, month, year) = if let Some(caps) = day_month_re().captures(line) {
+        (caps.get(1), caps.get(2), caps.get(3))
+    } else if let Some(caps) = month_day_re().captures(line) {
+        (caps.get(2), caps.get(1), caps.get(3))
+    } else {
+        return None;
+    };
+
+    let day: u32 = day?.as_str().parse().ok()?;
+    let month = month_number(month?.as_str(), locale)?;
+    let year = year
+        .and_then(|y| y.as_str().parse().ok())
+        .unwrap_or(default_year);
+
+    NaiveDate::from_ymd_opt(year, month, day)
+}
+
+/// Parse the page number out of a page separator inserted by the OCR step
+/// (`--- Page 3 ---`, or `--- Page 3 · 2024-05-02 ---` when labelled with a date)
+fn page_separator(line: &str) -> Option<usize> {
+    let label = line
+        .trim()
+        .strip_prefix("--- Page ")?
+        .strip_suffix(" ---")?;
+    label.split(" · ").next()?.parse().ok()
+}
+
+/// Split OCR text into one entry per date heading.
+///
+/// Text before the first date heading is kept with the first entry. Returns an
+/// empty list when the text contains no date headings at all.
+pub fn split_journal(text: &str, default_year: i32, locale: DateLocale) -> Vec<JournalEntry> {
+    let mut entries: Vec<JournalEntry> = Vec::new();
+    let mut preamble = String::new();
+    let mut current_page = 1;
+
+    for line in text.lines() {
+        if let Some(page) = page_separator(line) {
+            current_page = page;
+            continue;
+        }
+
+        if let Some(date) = parse_date_heading(line, default_year, locale) {
+            // Text before the first heading belongs to the first entry
+            let (content, pages) = if entries.is_empty() && !preamble.trim().is_empty() {
+                (std::mem::take(&mut preamble), (1..=current_page).collect())
+            } else {
+                (String::new(), vec![current_page])
+            };
+            entries.push(JournalEntry {
+                date,
+                content,
+                pages,
+            });
+            continue;
+        }
+
+        let (target, pages) = match entries.last_mut() {
+            Some(entry) => (&mut entry.content, Some(&mut entry.pages)),
+            None => (&mut preamble, None),
+        };
+
+        if !target.is_empty() {
+            target.push('\n');
+        }
+        target.push_str(line);
+
+        if let Some(pages) = pages {
+            if pages.last() != Some(&current_page) && !line.trim().is_empty() {
+                pages.push(current_page);
+            }
+        }
+    }
+
+    for entry in &mut entries {
+        entry.content = entry.content.trim().to_string();
+    }
+
+    entries
+}
+
+/// Year to assume for date headings that don't mention one
+pub fn default_year(timestamp: Option<&str>) -> i32 {
+    timestamp
+        .and_then(|ts| chrono::DateTime::parse_from_rfc3339(ts).ok())
+        .map(|dt| dt.year())
+        .unwrap_or_else(|| chrono::Utc::now().year())
+}
+
+fn compile_pattern(name: &str, pattern: &str) -> Result<Regex> {
+    Regex::new(pattern)
+        .map_err(|e| Error::Config(format!("Invalid meeting pattern '{}': {}", name, e)))
+}
+
+/// Extract meeting fields (date, attendees, action items) from OCR text.
+///
+/// The first line whose `date` capture parses as a date wins; attendees are
+/// split on commas, `&` and "and"; every `action_item` match becomes an item.
+pub fn extract_meeting(
+    text: &str,
+    patterns: &MeetingPatterns,
+    default_year: i32,
+    locale: DateLocale,
+) -> Result<MeetingNotes> {
+    let date_re = compile_pattern("date", &patterns.date)?;
+    let attendees_re = compile_pattern("attendees", &patterns.attendees)?;
+    let action_re = compile_pattern("action_item", &patterns.action_item)?;
+
+    let mut notes = MeetingNotes::default();
+
+    for line in text.lines() {
+        if let Some(caps) = action_re.captures(line) {
+            if let Some(item) = caps.get(1) {
+                notes.action_items.push(item.as_str().trim().to_string());
+            }
+            continue;
+        }
+
+        if let Some(caps) = attendees_re.captures(line) {
+            if let Some(list) = caps.get(1) {
+                let names = list
+                    .as_str()
+                    .split([',', '&', ';'])
+                    .flat_map(|part| part.split(" and "))
+                    .map(|name| name.trim().trim_end_matches('.').to_string());
+
+                for name in names {
+                    if !name.is_empty() && !notes.attendees.contains(&name) {
+                        notes.attendees.push(name);
+                    }
+                }
+            }
+            continue;
+        }
+
+        if notes.date.is_none() {
+            notes.date = date_re
+                .captures(line)
+                .and_then(|caps| caps.get(1))
+                .and_then(|value| parse_date_heading(value.as_str(), default_year, locale));
+        }
+    }
+
+    Ok(notes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn parses_numeric_dates() {
+        let parse = |line| parse_date_heading(line, 2024, DateLocale::En);
+        assert_eq!(parse("2024-10-15"), Some(date(2024, 10, 15)));
+        assert_eq!(parse("## 15-10-2024"), Some(date(2024, 10, 15)));
+        assert_eq!(parse("15/10/24"), Some(date(2024, 10, 15)));
+        assert_eq!(parse("31/02/2024"), None);
+        assert_eq!(
+            parse_date_heading("10/15/2024", 2024, DateLocale::EnUs),
+            Some(date(2024, 10, 15))
+        );
+    }
+
+    #[test]
+    fn parses_written_dates() {
+        let parse = |line, locale| parse_date_heading(line, 2023, locale);
+        assert_eq!(
+            parse("Monday, 15 October 2024", DateLocale::En),
+            Some(date(2024, 10, 15))
+        );
+        assert_eq!(parse("Oct 15th", DateLocale::En), Some(date(2023, 10, 15)));
+        assert_eq!(
+            parse("Dinsdag 3 maart 2020", DateLocale::Nl),
+            Some(date(2020, 3, 3))
+        );
+        assert_eq!(parse("1. Mai", DateLocale::De), Some(date(2023, 5, 1)));
+        // English names are understood in every locale
+        assert_eq!(parse("3 March", DateLocale::Nl), Some(date(2023, 3, 3)));
+    }
+
+    #[test]
+    fn rejects_lines_that_are_not_dates() {
+        let parse = |line| parse_date_heading(line, 2024, DateLocale::En);
+        assert_eq!(parse("Meeting notes"), None);
+        assert_eq!(parse("15 things to do"), None);
+        assert_eq!(parse("Ma 3"), None);
+        assert_eq!(parse(""), None);
+    }
+
+    #[test]
+    fn splits_journal_at_date_headings() {
+        let text = "--- Page 1 ---\n\
+                    Notes from the train\n\
+                    Monday 14 October 2024\n\
+                    first day\n\
+                    --- Page 2 · 2024-10-15 ---\n\
+                    still first day\n\
+                    15 October 2024\n\
+                    second day";
+        let entries = split_journal(text, 2024, DateLocale::En);
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].date, date(2024, 10, 14));
+        assert_eq!(
+            entries[0].content,
+            "Notes from the train\nfirst day\nstill first day"
+        );
+        assert_eq!(entries[0].pages, vec![1, 2]);
+        assert_eq!(entries[1].date, date(2024, 10, 15));
+        assert_eq!(entries[1].content, "second day");
+        assert_eq!(entries[1].pages, vec![2]);
+    }
+
+    #[test]
+    fn journal_without_headings_has_no_entries() {
+        assert!(split_journal("just some text\nno dates", 2024, DateLocale::En).is_empty());
+    }
+}
@@ -0,0 +1,160 @@
+use crate::error::{Error, Result};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tiny_http::{Header, Method, Request, Response, Server};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+/// Address the PDF server listens on unless `PDF_SERVER_BIND` says otherwise
+pub const DEFAULT_BIND: &str = "127.0.0.1:8086";
+
+/// How often the server checks whether it should stop
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Serve the files under `dir` (the PDF archive) over plain HTTP until
+/// `cancel` is triggered.
+///
+/// Only GET and HEAD of files inside `dir` are answered; there are no
+/// directory listings. Put a reverse proxy in front for HTTPS or access
+/// control.
+pub async fn serve(dir: &Path, bind: &str, cancel: &CancellationToken) -> Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let root = dir.canonicalize()?;
+    let server = Server::http(bind).map_err(|e| {
+        Error::Io(std::io::Error::other(format!(
+            "Failed to start PDF server on {}: {}",
+            bind, e
+        )))
+    })?;
+    info!("Serving {:?} on http://{}", root, bind);
+
+    let cancel = cancel.clone();
+    tokio::task::spawn_blocking(move || {
+        while !cancel.is_cancelled() {
+            match server.recv_timeout(POLL_INTERVAL) {
+                Ok(Some(request)) => respond(&root, request),
+                Ok(None) => {}
+                Err(e) => warn!("PDF server failed to receive a request: {}", e),
+            }
+        }
+    })
+    .await
+    .map_err(|e| Error::Io(std::io::Error::other(e)))?;
+
+    debug!("PDF server stopped");
+    Ok(())
+}
+
+fn respond(root: &Path, request: Request) {
+    debug!("PDF server: {} {}", request.method(), request.url());
+    let result = match request.method() {
+        Method::Get | Method::Head => match resolve(root, request.url()) {
+            Some(path) => match std::fs::File::open(&path) {
+                Ok(file) => {
+                    let content_type = match path.extension().and_then(|e| e.to_str()) {
+                        Some("pdf") => "application/pdf",
+                        Some("svg") => "image/svg+xml",
+                        _ => "application/octet-stream",
+                    };
+                    let header =
+                        Header::from_bytes("Content-Type", content_type).expect("valid header");
+                    request.respond(Response::from_file(file).with_header(header))
+                }
+                Err(e) => {
+                    warn!("PDF server can't read {:?}: {}", path, e);
+                    request.respond(Response::empty(500))
+                }
+            },
+            None => request.respond(Response::empty(404)),
+        },
+        _ => request.respond(Response::empty(405)),
+    };
+    if let Err(e) = result {
+        debug!("PDF server failed to respond: {}", e);
+    }
+}
+
+/// The file a request path refers to, if it's inside `root`
+fn resolve(root: &Path, url: &str) -> Option<PathBuf> {
+    let path = url.split(['?', '#']).next()?;
+    let mut file = root.to_path_buf();
+    for segment in path.split('/').filter(|s| !s.is_empty()) {
+        let segment = percent_decode(segment)?;
+        if segment == "." || segment == ".." || segment.contains(['/', '\\']) {
+            return None;
+        }
+        file.push(segment);
+    }
+
+    // Symlinks out of the archive aren't followed
+    let file = file.canonicalize().ok()?;
+    (file.starts_with(root) && file.is_file()).then_some(file)
+}
+
+fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_percent_escapes() {
+        assert_eq!(
+            percent_decode("My%20Notes.pdf").as_deref(),
+            Some("My Notes.pdf")
+        );
+        assert_eq!(percent_decode("caf%C3%A9").as_deref(), Some("café"));
+        assert_eq!(percent_decode("plain").as_deref(), Some("plain"));
+        assert_eq!(percent_decode("bad%2"), None);
+        assert_eq!(percent_decode("bad%zz"), None);
+        assert_eq!(percent_decode("%ff"), None);
+    }
+
+    #[test]
+    fn resolves_files_inside_the_archive_only() {
+        let dir = std::env::temp_dir().join(format!("r2n-file-server-{}", std::process::id()));
+        let root = dir.join("archive");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(root.join("Work")).unwrap();
+        std::fs::write(root.join("Work/My Notes.pdf"), b"%PDF").unwrap();
+        std::fs::write(dir.join("secret.txt"), b"secret").unwrap();
+        let root = root.canonicalize().unwrap();
+
+        assert_eq!(
+            resolve(&root, "/Work/My%20Notes.pdf?download=1"),
+            Some(root.join("Work/My Notes.pdf"))
+        );
+        assert_eq!(
+            resolve(&root, "//Work//My%20Notes.pdf"),
+            Some(root.join("Work/My Notes.pdf"))
+        );
+        // Directories, missing files and escapes from the archive
+        assert_eq!(resolve(&root, "/Work"), None);
+        assert_eq!(resolve(&root, "/Work/Other.pdf"), None);
+        assert_eq!(resolve(&root, "/../secret.txt"), None);
+        assert_eq!(resolve(&root, "/Work/%2e%2e/%2e%2e/secret.txt"), None);
+        assert_eq!(resolve(&root, "/..%2fsecret.txt"), None);
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(dir.join("secret.txt"), root.join("link.txt")).unwrap();
+            assert_eq!(resolve(&root, "/link.txt"), None);
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,78 @@
+use crate::error::{Error, Result};
+use regex::Regex;
+use std::str::FromStr;
+
+/// A notebook name pattern: `*` matches any run of characters, `?` a single
+/// one, everything else itself. Matching is case-insensitive, so a pattern
+/// without wildcards selects a notebook by its exact name.
+#[derive(Debug, Clone)]
+pub struct Glob {
+    pattern: String,
+    regex: Regex,
+}
+
+impl Glob {
+    pub fn matches(&self, name: &str) -> bool {
+        self.regex.is_match(name)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+}
+
+impl FromStr for Glob {
+    type Err = Error;
+
+    fn from_str(pattern: &str) -> Result<Self> {
+        let mut re = String::from("(?i)^");
+        for c in pattern.chars() {
+            match c {
+                '*' => re.push_str(".*"),
+                '?' => re.push('.'),
+                c => re.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        re.push('$');
+
+        let regex = Regex::new(&re)
+            .map_err(|e| Error::Config(format!("Invalid pattern '{}': {}", pattern, e)))?;
+        Ok(Self {
+            pattern: pattern.to_string(),
+            regex,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glob(pattern: &str) -> Glob {
+        pattern.parse().unwrap()
+    }
+
+    #[test]
+    fn plain_pattern_matches_exact_name_ignoring_case() {
+        let glob = glob("Team Standup");
+        assert!(glob.matches("team standup"));
+        assert!(!glob.matches("Team Standup 2"));
+        assert!(!glob.matches("My Team Standup"));
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(glob("Journal*").matches("Journal 2024"));
+        assert!(glob("Journal*").matches("Journal"));
+        assert!(!glob("Journal*").matches("My Journal"));
+        assert!(glob("Week ?").matches("Week 3"));
+        assert!(!glob("Week ?").matches("Week 12"));
+        assert!(glob("Work/*").matches("Work/Roadmap"));
+    }
+
+    #[test]
+    fn other_characters_are_literal() {
+        assert!(glob("v1.0 (draft)").matches("V1.0 (Draft)"));
+        assert!(!glob("v1.0").matches("v1x0"));
+    }
+}
@@ -1,12 +1,102 @@
-use crate::error::{Error, Result};
+use crate::error::{ApiError, Error, Result};
 use crate::oauth::GoogleOAuthClient;
+use crate::retry::{self, RetryExt};
 use reqwest::Client;
+use serde::Deserialize;
 use serde_json::json;
+use std::collections::HashMap;
 use std::path::Path;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use tracing::{debug, warn};
 
+const DRIVE_API_BASE: &str = "https://www.googleapis.com/drive/v3";
+const DRIVE_UPLOAD_BASE: &str = "https://www.googleapis.com/upload/drive/v3";
+
+/// appProperties entry marking files uploaded by this tool
+const APP_MARKER_KEY: &str = "createdBy";
+const APP_MARKER_VALUE: &str = "remarkable2notion";
+
+/// appProperties keys identifying the notebook and sync run of an upload
+const NOTEBOOK_ID_KEY: &str = "notebookId";
+const RUN_ID_KEY: &str = "syncRunId";
+/// appProperties key holding the page number of single-page exports (SVGs)
+const PAGE_KEY: &str = "page";
+
+/// A file uploaded to Drive and its shareable link
+#[derive(Debug, Clone)]
+pub struct UploadedFile {
+    pub id: String,
+    pub url: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DriveFile {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub app_properties: HashMap<String, String>,
+}
+
+impl DriveFile {
+    /// Sync run that last uploaded the file
+    pub fn run_id(&self) -> Option<&str> {
+        self.app_properties.get(RUN_ID_KEY).map(String::as_str)
+    }
+}
+
+/// An entry of the Drive changes feed
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DriveChange {
+    #[serde(default)]
+    pub removed: bool,
+    pub file: Option<ChangedFile>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangedFile {
+    pub name: String,
+    #[serde(default)]
+    pub parents: Vec<String>,
+    #[serde(default)]
+    pub trashed: bool,
+    #[serde(default)]
+    pub app_properties: HashMap<String, String>,
+}
+
+impl ChangedFile {
+    /// Whether this tool uploaded the file (so changing it shouldn't trigger a sync)
+    pub fn is_own_upload(&self) -> bool {
+        self.app_properties.get(APP_MARKER_KEY).map(String::as_str) == Some(APP_MARKER_VALUE)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ChangeList {
+    #[serde(default)]
+    changes: Vec<DriveChange>,
+    next_page_token: Option<String>,
+    new_start_page_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StartPageToken {
+    start_page_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FileList {
+    #[serde(default)]
+    files: Vec<DriveFile>,
+    next_page_token: Option<String>,
+}
+
 pub struct GoogleDriveClient {
     client: Client,
     oauth_client: Arc<GoogleOAuthClient>,
@@ -46,10 +136,7 @@ impl GoogleDriveClient {
             .ok_or_else(|| Error::Io(std::io::Error::other("No stored token found")))?;
 
         // Refresh using OAuth client
-        let new_token = self
-            .oauth_client
-            .refresh_token(&stored_token.refresh_token)
-            .await?;
+        let new_token = self.oauth_client.refresh_token(&stored_token).await?;
 
         // Update in-memory token
         *self.access_token.write().await = new_token.access_token;
@@ -58,12 +145,65 @@ impl GoogleDriveClient {
         Ok(())
     }
 
-    pub async fn upload_pdf(&self, pdf_path: &Path, notebook_name: &str) -> Result<String> {
+    /// Upload a notebook's PDF, replacing the file uploaded for it before (if any).
+    ///
+    /// Files are tagged with the notebook ID and sync run in their appProperties,
+    /// so an existing upload keeps its ID and link when the notebook is renamed.
+    pub async fn upload_pdf(
+        &self,
+        pdf_path: &Path,
+        notebook_name: &str,
+        notebook_id: &str,
+        run_id: &str,
+    ) -> Result<UploadedFile> {
         debug!("Uploading PDF to Google Drive: {}", notebook_name);
+
+        let existing = self.find_notebook_file(notebook_id, None).await?;
+        let app_properties = json!({
+            APP_MARKER_KEY: APP_MARKER_VALUE,
+            NOTEBOOK_ID_KEY: notebook_id,
+            RUN_ID_KEY: run_id
+        });
+
         self.upload_file(
             pdf_path,
             &format!("{}.pdf", notebook_name),
             "application/pdf",
+            &app_properties,
+            existing.as_ref().map(|file| file.id.as_str()),
+        )
+        .await
+    }
+
+    /// Upload a notebook page as SVG next to the notebook's PDF, replacing a
+    /// previous upload of the same page
+    pub async fn upload_svg_page(
+        &self,
+        svg_path: &Path,
+        notebook_name: &str,
+        notebook_id: &str,
+        page_num: usize,
+        run_id: &str,
+    ) -> Result<UploadedFile> {
+        debug!(
+            "Uploading SVG of page {} to Google Drive: {}",
+            page_num, notebook_name
+        );
+
+        let existing = self.find_notebook_file(notebook_id, Some(page_num)).await?;
+        let app_properties = json!({
+            APP_MARKER_KEY: APP_MARKER_VALUE,
+            NOTEBOOK_ID_KEY: notebook_id,
+            RUN_ID_KEY: run_id,
+            PAGE_KEY: page_num.to_string()
+        });
+
+        self.upload_file(
+            svg_path,
+            &format!("{} - page {}.svg", notebook_name, page_num),
+            "image/svg+xml",
+            &app_properties,
+            existing.as_ref().map(|file| file.id.as_str()),
         )
         .await
     }
@@ -73,13 +213,15 @@ impl GoogleDriveClient {
         file_path: &Path,
         filename: &str,
         mime_type: &str,
-    ) -> Result<String> {
+        app_properties: &serde_json::Value,
+        existing_id: Option<&str>,
+    ) -> Result<UploadedFile> {
         // Try upload, retry once if token is expired
         match self
-            .upload_file_internal(file_path, filename, mime_type)
+            .upload_file_internal(file_path, filename, mime_type, app_properties, existing_id)
             .await
         {
-            Ok(url) => Ok(url),
+            Ok(file) => Ok(file),
             Err(e) => {
                 // Check if it's a 401 Unauthorized error
                 if e.to_string().contains("401") {
@@ -88,8 +230,14 @@ impl GoogleDriveClient {
 
                     // Retry the upload with new token
                     debug!("Retrying upload with refreshed token...");
-                    self.upload_file_internal(file_path, filename, mime_type)
-                        .await
+                    self.upload_file_internal(
+                        file_path,
+                        filename,
+                        mime_type,
+                        app_properties,
+                        existing_id,
+                    )
+                    .await
                 } else {
                     Err(e)
                 }
@@ -102,47 +250,60 @@ impl GoogleDriveClient {
         file_path: &Path,
         filename: &str,
         mime_type: &str,
-    ) -> Result<String> {
+        app_properties: &serde_json::Value,
+        existing_id: Option<&str>,
+    ) -> Result<UploadedFile> {
         let file_bytes = tokio::fs::read(file_path).await?;
 
         // Prepare metadata
         let mut metadata = json!({
             "name": filename,
-            "mimeType": mime_type
+            "mimeType": mime_type,
+            "appProperties": app_properties
         });
 
-        if let Some(folder_id) = &self.folder_id {
-            metadata["parents"] = json!([folder_id]);
+        // Parents can only be set on creation
+        if existing_id.is_none() {
+            if let Some(folder_id) = &self.folder_id {
+                metadata["parents"] = json!([folder_id]);
+            }
         }
 
-        // Create multipart upload
-        let metadata_part =
-            reqwest::multipart::Part::text(metadata.to_string()).mime_str("application/json")?;
+        let token = self.get_token().await;
+        let response = retry::send_with(|| {
+            // Create multipart upload
+            let metadata_part = reqwest::multipart::Part::text(metadata.to_string())
+                .mime_str("application/json")?;
 
-        let file_part = reqwest::multipart::Part::bytes(file_bytes)
-            .file_name(filename.to_string())
-            .mime_str(mime_type)?;
+            let file_part = reqwest::multipart::Part::bytes(file_bytes.clone())
+                .file_name(filename.to_string())
+                .mime_str(mime_type)?;
 
-        let form = reqwest::multipart::Form::new()
-            .part("metadata", metadata_part)
-            .part("file", file_part);
+            let form = reqwest::multipart::Form::new()
+                .part("metadata", metadata_part)
+                .part("file", file_part);
 
-        // Upload file
-        let response = self
-            .client
-            .post("https://www.googleapis.com/upload/drive/v3/files?uploadType=multipart")
-            .bearer_auth(&self.get_token().await)
-            .multipart(form)
-            .send()
-            .await?;
+            // Upload a new file, or replace the content of the existing one
+            let request = match existing_id {
+                Some(file_id) => self.client.patch(format!(
+                    "{}/files/{}?uploadType=multipart",
+                    DRIVE_UPLOAD_BASE, file_id
+                )),
+                None => self
+                    .client
+                    .post(format!("{}/files?uploadType=multipart", DRIVE_UPLOAD_BASE)),
+            };
+            Ok(request.bearer_auth(&token).multipart(form))
+        })
+        .await?;
 
         if !response.status().is_success() {
-            let status = response.status();
-            let body = response.text().await?;
-            return Err(Error::Io(std::io::Error::other(format!(
-                "Google Drive upload failed: {} - {}",
-                status, body
-            ))));
+            return Err(ApiError::from_response(
+                "Google Drive",
+                "Google Drive upload failed",
+                response,
+            )
+            .await);
         }
 
         let result: serde_json::Value = response.json().await?;
@@ -152,11 +313,180 @@ impl GoogleDriveClient {
 
         debug!("File uploaded to Google Drive with ID: {}", file_id);
 
-        // Make file publicly readable and get shareable link
-        let share_url = self.make_file_public(file_id).await?;
+        // Make new files publicly readable; updated files keep their permission
+        let share_url = if existing_id.is_some() {
+            share_link(file_id)
+        } else {
+            self.make_file_public(file_id).await?
+        };
 
         debug!("File uploaded to Google Drive: {}", share_url);
-        Ok(share_url)
+        Ok(UploadedFile {
+            id: file_id.to_string(),
+            url: share_url,
+        })
+    }
+
+    /// The file previously uploaded for a notebook (`page` `None`) or one of
+    /// its pages, by its appProperties tags
+    pub async fn find_notebook_file(
+        &self,
+        notebook_id: &str,
+        page: Option<usize>,
+    ) -> Result<Option<DriveFile>> {
+        let query = format!(
+            "appProperties has {{ key='{}' and value='{}' }} and trashed=false",
+            NOTEBOOK_ID_KEY,
+            notebook_id.replace('\\', "\\\\").replace('\'', "\\'")
+        );
+        let page = page.map(|page| page.to_string());
+        Ok(self
+            .list_files(&query)
+            .await?
+            .into_iter()
+            .find(|file| file.app_properties.get(PAGE_KEY) == page.as_ref()))
+    }
+
+    /// Token marking the current position in the changes feed
+    pub async fn start_page_token(&self) -> Result<String> {
+        let response = self
+            .client
+            .get(format!("{}/changes/startPageToken", DRIVE_API_BASE))
+            .bearer_auth(&self.get_token().await)
+            .send_retrying()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(ApiError::from_response(
+                "Google Drive",
+                "Failed to get Drive changes token",
+                response,
+            )
+            .await);
+        }
+
+        let token: StartPageToken = response.json().await?;
+        Ok(token.start_page_token)
+    }
+
+    /// Changes since `page_token`, and the token to continue from next time
+    pub async fn changes_since(&self, page_token: &str) -> Result<(Vec<DriveChange>, String)> {
+        // Polling runs for hours, so the access token will expire at some point
+        match self.changes_since_internal(page_token).await {
+            Err(e) if e.to_string().contains("401") => {
+                self.refresh_token_if_needed().await?;
+                self.changes_since_internal(page_token).await
+            }
+            result => result,
+        }
+    }
+
+    async fn changes_since_internal(&self, page_token: &str) -> Result<(Vec<DriveChange>, String)> {
+        let mut changes = Vec::new();
+        let mut token = page_token.to_string();
+
+        loop {
+            let response = self
+                .client
+                .get(format!("{}/changes", DRIVE_API_BASE))
+                .bearer_auth(&self.get_token().await)
+                .query(&[
+                    ("pageToken", token.as_str()),
+                    (
+                        "fields",
+                        "nextPageToken,newStartPageToken,changes(removed,file(name,parents,trashed,appProperties))",
+                    ),
+                    ("pageSize", "1000"),
+                ])
+                .send_retrying()
+                .await?;
+
+            if !response.status().is_success() {
+                return Err(ApiError::from_response(
+                    "Google Drive",
+                    "Failed to list Drive changes",
+                    response,
+                )
+                .await);
+            }
+
+            let list: ChangeList = response.json().await?;
+            changes.extend(list.changes);
+
+            match (list.next_page_token, list.new_start_page_token) {
+                (Some(next), _) => token = next,
+                (None, Some(new_start)) => return Ok((changes, new_start)),
+                (None, None) => return Ok((changes, token)),
+            }
+        }
+    }
+
+    /// All non-trashed files this tool uploaded, found by their appProperties marker
+    pub async fn list_own_files(&self) -> Result<Vec<DriveFile>> {
+        let query = format!(
+            "appProperties has {{ key='{}' and value='{}' }} and trashed=false",
+            APP_MARKER_KEY, APP_MARKER_VALUE
+        );
+        self.list_files(&query).await
+    }
+
+    async fn list_files(&self, query: &str) -> Result<Vec<DriveFile>> {
+        let mut files = Vec::new();
+        let mut page_token: Option<String> = None;
+        loop {
+            let mut request = self
+                .client
+                .get(format!("{}/files", DRIVE_API_BASE))
+                .bearer_auth(&self.get_token().await)
+                .query(&[
+                    ("q", query),
+                    ("fields", "nextPageToken,files(id,name,appProperties)"),
+                    ("pageSize", "1000"),
+                ]);
+            if let Some(ref token) = page_token {
+                request = request.query(&[("pageToken", token)]);
+            }
+
+            let response = request.send_retrying().await?;
+            if !response.status().is_success() {
+                return Err(ApiError::from_response(
+                    "Google Drive",
+                    "Failed to list Google Drive files",
+                    response,
+                )
+                .await);
+            }
+
+            let list: FileList = response.json().await?;
+            files.extend(list.files);
+
+            match list.next_page_token {
+                Some(token) => page_token = Some(token),
+                None => break,
+            }
+        }
+
+        Ok(files)
+    }
+
+    pub async fn delete_file(&self, file_id: &str) -> Result<()> {
+        let response = self
+            .client
+            .delete(format!("{}/files/{}", DRIVE_API_BASE, file_id))
+            .bearer_auth(&self.get_token().await)
+            .send_retrying()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(ApiError::from_response(
+                "Google Drive",
+                "Failed to delete Google Drive file",
+                response,
+            )
+            .await);
+        }
+
+        Ok(())
     }
 
     async fn make_file_public(&self, file_id: &str) -> Result<String> {
@@ -168,28 +498,26 @@ impl GoogleDriveClient {
 
         let response = self
             .client
-            .post(format!(
-                "https://www.googleapis.com/drive/v3/files/{}/permissions",
-                file_id
-            ))
+            .post(format!("{}/files/{}/permissions", DRIVE_API_BASE, file_id))
             .bearer_auth(&self.get_token().await)
             .json(&permission_body)
-            .send()
+            .send_retrying()
             .await?;
 
         if !response.status().is_success() {
-            let status = response.status();
-            let body = response.text().await?;
-            return Err(Error::Io(std::io::Error::other(format!(
-                "Failed to make file public: {} - {}",
-                status, body
-            ))));
+            return Err(ApiError::from_response(
+                "Google Drive",
+                "Failed to make file public",
+                response,
+            )
+            .await);
         }
 
-        // Return direct link to image (for embedding)
-        Ok(format!(
-            "https://drive.google.com/uc?export=view&id={}",
-            file_id
-        ))
+        Ok(share_link(file_id))
     }
 }
+
+/// Direct link to a file (for embedding)
+fn share_link(file_id: &str) -> String {
+    format!("https://drive.google.com/uc?export=view&id={}", file_id)
+}
@@ -1,12 +1,31 @@
-use crate::error::{Error, Result};
+use crate::error::{ApiError, Error, Result};
+use crate::ocr::{OcrBackend, PageText};
+use crate::retry::RetryExt;
+use async_trait::async_trait;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat};
 use reqwest::Client;
 use serde_json::json;
-use std::path::{Path, PathBuf};
-use tracing::{debug, warn};
+use std::io::Cursor;
+use std::path::Path;
+use tracing::debug;
+
+const VISION_ANNOTATE_URL: &str = "https://vision.googleapis.com/v1/images:annotate";
+
+/// Vision rejects requests over 10 MB, and base64 makes images a third bigger
+const MAX_IMAGE_BYTES: usize = 7 * 1024 * 1024;
+
+/// Oversized images aren't scaled below this width; smaller handwriting
+/// stops being legible
+const MIN_SCALED_WIDTH: u32 = 1600;
+
+/// Most strips an image is cut into when scaling isn't enough
+const MAX_TILES: u32 = 16;
 
 pub struct GoogleVisionClient {
     client: Client,
     api_key: String,
+    quota_project: Option<String>,
 }
 
 impl GoogleVisionClient {
@@ -14,68 +33,21 @@ impl GoogleVisionClient {
         Self {
             client: Client::new(),
             api_key,
+            quota_project: None,
         }
     }
 
-    /// Extract text AND keep images from PDF (for uploading to Notion)
-    pub async fn extract_text_and_images_from_pdf(
-        &self,
-        pdf_path: &Path,
-    ) -> Result<(String, Vec<PathBuf>)> {
-        debug!("Extracting text using Google Cloud Vision: {:?}", pdf_path);
-
-        // First, extract images from PDF using pdftoppm
-        let page_images = self.extract_images_from_pdf(pdf_path)?;
-
-        if page_images.is_empty() {
-            return Ok(("(No pages found in PDF)".to_string(), Vec::new()));
-        }
-
-        debug!(
-            "Processing {} pages with Google Cloud Vision",
-            page_images.len()
-        );
-
-        let mut full_text = String::new();
-
-        // Process each page image
-        for (i, image_path) in page_images.iter().enumerate() {
-            debug!("Processing page {} of {}", i + 1, page_images.len());
-
-            match self.extract_text_from_image(image_path).await {
-                Ok(text) => {
-                    if !text.trim().is_empty() {
-                        if !full_text.is_empty() {
-                            full_text.push_str(&format!("\n\n--- Page {} ---\n\n", i + 1));
-                        }
-                        full_text.push_str(&text);
-                    }
-                }
-                Err(e) => {
-                    warn!("Failed to process page {}: {}", i + 1, e);
-                }
-            }
-        }
-
-        if full_text.trim().is_empty() {
-            warn!("No text extracted from PDF");
-            full_text = "(No text detected)".to_string();
-        } else {
-            debug!(
-                "Extracted {} characters using Google Cloud Vision",
-                full_text.len()
-            );
-        }
-
-        Ok((full_text, page_images))
+    /// Bill requests and count quota against this project rather than the
+    /// one the API key belongs to (`x-goog-user-project`)
+    pub fn with_quota_project(mut self, quota_project: Option<String>) -> Self {
+        self.quota_project = quota_project;
+        self
     }
 
-    /// Extract text from a single image using Vision API
-    async fn extract_text_from_image(&self, image_path: &Path) -> Result<String> {
-        // Read image and encode to base64
-        let image_bytes = tokio::fs::read(image_path).await?;
+    /// Run text detection on an image that fits in a request
+    async fn annotate(&self, image_bytes: &[u8]) -> Result<PageText> {
         let image_base64 =
-            base64::Engine::encode(&base64::engine::general_purpose::STANDARD, &image_bytes);
+            base64::Engine::encode(&base64::engine::general_purpose::STANDARD, image_bytes);
 
         // Call Vision API for image annotation
         let request_body = json!({
@@ -89,84 +61,138 @@ impl GoogleVisionClient {
             }]
         });
 
-        let url = format!(
-            "https://vision.googleapis.com/v1/images:annotate?key={}",
-            self.api_key
-        );
+        // The key goes in a header so it stays out of URLs in logs and errors
+        let mut request = self
+            .client
+            .post(VISION_ANNOTATE_URL)
+            .header("x-goog-api-key", &self.api_key);
+        if let Some(ref project) = self.quota_project {
+            request = request.header("x-goog-user-project", project);
+        }
 
-        let response = self.client.post(&url).json(&request_body).send().await?;
+        let response = request.json(&request_body).send_retrying().await?;
 
         if !response.status().is_success() {
-            let status = response.status();
-            let body = response.text().await?;
-            return Err(Error::Ocr(format!(
-                "Google Vision API failed: {} - {}",
-                status, body
-            )));
+            return Err(ApiError::from_response(
+                "Google Vision",
+                "Google Vision API failed",
+                response,
+            )
+            .await);
         }
 
         let result: serde_json::Value = response.json().await?;
 
-        // Extract text from response
-        if let Some(responses) = result["responses"].as_array() {
-            if let Some(first_response) = responses.first() {
-                if let Some(text) = first_response["fullTextAnnotation"]["text"].as_str() {
-                    return Ok(text.to_string());
-                }
-            }
-        }
+        // Extract text and page confidence from response
+        let annotation = &result["responses"][0]["fullTextAnnotation"];
+        let text = annotation["text"].as_str().unwrap_or_default().to_string();
+
+        let confidences: Vec<f64> = annotation["pages"]
+            .as_array()
+            .map(|pages| {
+                pages
+                    .iter()
+                    .filter_map(|page| page["confidence"].as_f64())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let confidence = if confidences.is_empty() {
+            None
+        } else {
+            Some((confidences.iter().sum::<f64>() / confidences.len() as f64) as f32)
+        };
 
-        Ok(String::new())
+        Ok(PageText { text, confidence })
     }
+}
 
-    /// Extract images from PDF pages using pdftoppm
-    fn extract_images_from_pdf(&self, pdf_path: &Path) -> Result<Vec<PathBuf>> {
-        use std::process::Command;
-
-        let temp_dir = std::env::temp_dir();
-        let base_name = pdf_path
-            .file_stem()
-            .and_then(|s| s.to_str())
-            .ok_or_else(|| Error::Ocr("Invalid PDF filename".to_string()))?;
-
-        let image_prefix = temp_dir.join(format!("{}_page", base_name));
-
-        debug!("Converting PDF to images using pdftoppm");
-
-        // Convert PDF to PNG images (one per page)
-        let status = Command::new("pdftoppm")
-            .arg("-png")
-            .arg(pdf_path)
-            .arg(&image_prefix)
-            .status()
-            .map_err(|e| Error::Ocr(format!("Failed to run pdftoppm: {}", e)))?;
+#[async_trait]
+impl OcrBackend for GoogleVisionClient {
+    fn name(&self) -> &'static str {
+        "Google Cloud Vision"
+    }
 
-        if !status.success() {
-            return Err(Error::Ocr("PDF to image conversion failed".to_string()));
+    /// Extract text from a single image using Vision API.
+    ///
+    /// Images too large for a request are scaled down, or cut into strips
+    /// from top to bottom whose text is joined.
+    async fn recognize(&self, image_path: &Path) -> Result<PageText> {
+        let image_bytes = tokio::fs::read(image_path).await?;
+        if image_bytes.len() <= MAX_IMAGE_BYTES {
+            return self.annotate(&image_bytes).await;
         }
 
-        // Find all generated PNG files
-        let parent_dir = image_prefix.parent().unwrap();
-        let prefix_name = image_prefix.file_name().unwrap().to_str().unwrap();
-
-        let mut page_images: Vec<_> = std::fs::read_dir(parent_dir)?
-            .filter_map(|e| e.ok())
-            .filter(|e| {
-                e.file_name()
-                    .to_str()
-                    .map(|s| s.starts_with(prefix_name) && s.ends_with(".png"))
-                    .unwrap_or(false)
-            })
-            .map(|e| e.path())
-            .collect();
+        let tiles = fit_image(&image_bytes)?;
+        debug!(
+            "{:?} is {} bytes, sending it as {} smaller image(s)",
+            image_path,
+            image_bytes.len(),
+            tiles.len()
+        );
+        let mut texts = Vec::new();
+        let mut confidences = Vec::new();
+        for tile in &tiles {
+            let page = self.annotate(tile).await?;
+            if !page.text.trim().is_empty() {
+                texts.push(page.text.trim_end().to_string());
+            }
+            confidences.extend(page.confidence);
+        }
+        let confidence = (!confidences.is_empty())
+            .then(|| confidences.iter().sum::<f32>() / confidences.len() as f32);
+        Ok(PageText {
+            text: texts.join("\n"),
+            confidence,
+        })
+    }
+}
 
-        page_images.sort();
+/// Fit an oversized image into requests: scaled down as far as legibility
+/// allows, then cut into horizontal strips if it's still too large
+fn fit_image(image_bytes: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let image = image::load_from_memory(image_bytes)
+        .map_err(|e| Error::Ocr(format!("Failed to read image: {}", e)))?;
+
+    // Encoded size grows roughly with the pixel count
+    let scale = (MAX_IMAGE_BYTES as f64 / image_bytes.len() as f64).sqrt() * 0.9;
+    let width = ((image.width() as f64 * scale) as u32).max(MIN_SCALED_WIDTH.min(image.width()));
+    let image = if width < image.width() {
+        image.resize(width, image.height(), FilterType::Triangle)
+    } else {
+        image
+    };
+
+    let png = encode_png(&image)?;
+    if png.len() <= MAX_IMAGE_BYTES {
+        return Ok(vec![png]);
+    }
 
-        if page_images.is_empty() {
-            return Err(Error::Ocr("No images generated from PDF".to_string()));
+    let mut strips = (png.len() / MAX_IMAGE_BYTES) as u32 + 1;
+    while strips <= MAX_TILES {
+        let height = image.height().div_ceil(strips);
+        let tiles = (0..strips)
+            .map(|i| {
+                let top = i * height;
+                let strip = image.crop_imm(0, top, image.width(), height.min(image.height() - top));
+                encode_png(&strip)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if tiles.iter().all(|tile| tile.len() <= MAX_IMAGE_BYTES) {
+            return Ok(tiles);
         }
-
-        debug!("Extracted {} page images", page_images.len());
-        Ok(page_images)
+        strips += 1;
     }
+    Err(Error::Ocr(format!(
+        "Image of {} bytes doesn't fit in {} Vision requests",
+        image_bytes.len(),
+        MAX_TILES
+    )))
+}
+
+fn encode_png(image: &DynamicImage) -> Result<Vec<u8>> {
+    let mut png = Cursor::new(Vec::new());
+    image
+        .write_to(&mut png, ImageFormat::Png)
+        .map_err(|e| Error::Ocr(format!("Failed to encode image: {}", e)))?;
+    Ok(png.into_inner())
 }
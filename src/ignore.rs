@@ -0,0 +1,171 @@
+use crate::error::{Error, Result};
+use regex::Regex;
+use std::path::Path;
+use tracing::debug;
+
+/// Name of the ignore file in the backup directory
+pub const IGNORE_FILE: &str = ".r2nignore";
+
+/// Notebooks to leave out of the sync, from gitignore-style patterns matched
+/// against notebook paths like `Work/Meetings/Standup`:
+///
+/// - `Scratch` matches a notebook or folder with that name anywhere
+/// - `Work/Drafts` (containing a `/`) matches from the top of the tablet
+/// - `Archive/` (trailing `/`) matches folders only
+/// - `*` and `?` match within a name, `**` across folders
+/// - `!pattern` brings back notebooks an earlier pattern ignored
+///
+/// Blank lines and lines starting with `#` are skipped. Matching is
+/// case-insensitive, like `--only`.
+#[derive(Debug, Default)]
+pub struct IgnoreFile {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug)]
+struct Rule {
+    regex: Regex,
+    negated: bool,
+    folders_only: bool,
+}
+
+impl IgnoreFile {
+    /// Read an ignore file; a missing file ignores nothing
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = std::fs::read_to_string(path)?;
+        let ignore = Self::parse(&text)?;
+        debug!("Loaded {} patterns from {:?}", ignore.rules.len(), path);
+        Ok(ignore)
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let rules = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(Rule::parse)
+            .collect::<Result<_>>()?;
+        Ok(Self { rules })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Whether the notebook at `path` (folders and name separated by `/`)
+    /// is ignored; the last matching pattern decides
+    pub fn is_ignored(&self, path: &str) -> bool {
+        let folders: Vec<&str> = path
+            .match_indices('/')
+            .map(|(index, _)| &path[..index])
+            .collect();
+
+        let mut ignored = false;
+        for rule in &self.rules {
+            let matches = (!rule.folders_only && rule.regex.is_match(path))
+                || folders.iter().any(|folder| rule.regex.is_match(folder));
+            if matches {
+                ignored = !rule.negated;
+            }
+        }
+        ignored
+    }
+}
+
+impl Rule {
+    fn parse(line: &str) -> Result<Self> {
+        let (negated, pattern) = match line.strip_prefix('!') {
+            Some(pattern) => (true, pattern),
+            None => (false, line),
+        };
+        let (folders_only, pattern) = match pattern.strip_suffix('/') {
+            Some(pattern) => (true, pattern),
+            None => (false, pattern),
+        };
+        // A slash anywhere but the end anchors the pattern at the top
+        let anchored = pattern.contains('/');
+        let pattern = pattern.trim_start_matches('/');
+
+        let mut re = String::from(if anchored { "(?i)^" } else { "(?i)(?:^|.*/)" });
+        let mut chars = pattern.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    if chars.peek() == Some(&'/') {
+                        chars.next();
+                        re.push_str("(?:.*/)?");
+                    } else {
+                        re.push_str(".*");
+                    }
+                }
+                '*' => re.push_str("[^/]*"),
+                '?' => re.push_str("[^/]"),
+                c => re.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        re.push('$');
+
+        let regex = Regex::new(&re)
+            .map_err(|e| Error::Config(format!("Invalid ignore pattern '{}': {}", line, e)))?;
+        Ok(Self {
+            regex,
+            negated,
+            folders_only,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ignore(text: &str) -> IgnoreFile {
+        IgnoreFile::parse(text).unwrap()
+    }
+
+    #[test]
+    fn name_matches_anywhere() {
+        let ignore = ignore("Scratch");
+        assert!(ignore.is_ignored("Scratch"));
+        assert!(ignore.is_ignored("Work/scratch"));
+        assert!(ignore.is_ignored("Scratch/Notes"));
+        assert!(!ignore.is_ignored("Scratchpad"));
+    }
+
+    #[test]
+    fn slash_anchors_at_the_top() {
+        let ignore = ignore("Work/Drafts");
+        assert!(ignore.is_ignored("Work/Drafts"));
+        assert!(ignore.is_ignored("Work/Drafts/Idea"));
+        assert!(!ignore.is_ignored("Personal/Work/Drafts"));
+    }
+
+    #[test]
+    fn trailing_slash_matches_folders_only() {
+        let ignore = ignore("Archive/");
+        assert!(ignore.is_ignored("Archive/Old"));
+        assert!(ignore.is_ignored("Work/Archive/Old"));
+        assert!(!ignore.is_ignored("Archive"));
+    }
+
+    #[test]
+    fn wildcards() {
+        let ignore = ignore("Draft*\n**/Tmp");
+        assert!(ignore.is_ignored("Draft 3"));
+        assert!(ignore.is_ignored("Work/Drafts"));
+        assert!(ignore.is_ignored("Tmp"));
+        assert!(ignore.is_ignored("a/b/Tmp"));
+        assert!(!ignore.is_ignored("Work/Notes"));
+    }
+
+    #[test]
+    fn last_matching_pattern_decides() {
+        let ignore = ignore("# Work stays private\n\nWork\n!Work/Keep");
+        assert!(ignore.is_ignored("Work/Other"));
+        assert!(!ignore.is_ignored("Work/Keep"));
+    }
+}
@@ -0,0 +1,161 @@
+use crate::error::{ApiError, Result};
+use crate::ocr::{OcrBackend, PageText};
+use crate::retry::RetryExt;
+use async_trait::async_trait;
+use reqwest::Client;
+use serde_json::json;
+use std::path::Path;
+use tracing::debug;
+
+const GEMINI_API_BASE: &str = "https://generativelanguage.googleapis.com/v1beta";
+const OPENAI_API_BASE: &str = "https://api.openai.com/v1";
+
+const TRANSCRIBE_PROMPT: &str = "Transcribe the handwritten text on this notebook page exactly \
+as written. Keep the original language and line breaks, render lists as lists, and do not add \
+commentary, headings or formatting that isn't on the page. If the page contains no text, reply \
+with an empty response.";
+
+/// Multimodal LLM providers usable for handwriting transcription
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LlmProvider {
+    Gemini,
+    OpenAi,
+}
+
+impl LlmProvider {
+    fn default_model(&self) -> &'static str {
+        match self {
+            LlmProvider::Gemini => "gemini-1.5-flash",
+            LlmProvider::OpenAi => "gpt-4o",
+        }
+    }
+}
+
+/// OCR backend that asks a vision-capable LLM to transcribe page images
+pub struct LlmVisionClient {
+    client: Client,
+    provider: LlmProvider,
+    api_key: String,
+    model: String,
+}
+
+impl LlmVisionClient {
+    pub fn new(provider: LlmProvider, api_key: String, model: Option<String>) -> Self {
+        Self {
+            client: Client::new(),
+            provider,
+            api_key,
+            model: model.unwrap_or_else(|| provider.default_model().to_string()),
+        }
+    }
+
+    async fn transcribe_gemini(&self, image_base64: &str) -> Result<String> {
+        let request_body = json!({
+            "contents": [{
+                "parts": [
+                    { "text": TRANSCRIBE_PROMPT },
+                    {
+                        "inline_data": {
+                            "mime_type": "image/png",
+                            "data": image_base64
+                        }
+                    }
+                ]
+            }]
+        });
+
+        let response = self
+            .client
+            .post(format!(
+                "{}/models/{}:generateContent",
+                GEMINI_API_BASE, self.model
+            ))
+            .header("x-goog-api-key", &self.api_key)
+            .json(&request_body)
+            .send_retrying()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(ApiError::from_response("Gemini", "Request failed", response).await);
+        }
+
+        let result: serde_json::Value = response.json().await?;
+        let text = result["candidates"][0]["content"]["parts"]
+            .as_array()
+            .map(|parts| {
+                parts
+                    .iter()
+                    .filter_map(|part| part["text"].as_str())
+                    .collect::<Vec<_>>()
+                    .join("")
+            })
+            .unwrap_or_default();
+
+        Ok(text)
+    }
+
+    async fn transcribe_openai(&self, image_base64: &str) -> Result<String> {
+        let request_body = json!({
+            "model": self.model,
+            "messages": [{
+                "role": "user",
+                "content": [
+                    { "type": "text", "text": TRANSCRIBE_PROMPT },
+                    {
+                        "type": "image_url",
+                        "image_url": {
+                            "url": format!("data:image/png;base64,{}", image_base64)
+                        }
+                    }
+                ]
+            }]
+        });
+
+        let response = self
+            .client
+            .post(format!("{}/chat/completions", OPENAI_API_BASE))
+            .bearer_auth(&self.api_key)
+            .json(&request_body)
+            .send_retrying()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(ApiError::from_response("OpenAI", "Request failed", response).await);
+        }
+
+        let result: serde_json::Value = response.json().await?;
+        Ok(result["choices"][0]["message"]["content"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string())
+    }
+}
+
+#[async_trait]
+impl OcrBackend for LlmVisionClient {
+    fn name(&self) -> &'static str {
+        match self.provider {
+            LlmProvider::Gemini => "Gemini",
+            LlmProvider::OpenAi => "OpenAI",
+        }
+    }
+
+    async fn recognize(&self, image_path: &Path) -> Result<PageText> {
+        let image_bytes = tokio::fs::read(image_path).await?;
+        let image_base64 =
+            base64::Engine::encode(&base64::engine::general_purpose::STANDARD, &image_bytes);
+
+        debug!("Transcribing {:?} with {}", image_path, self.model);
+
+        let text = match self.provider {
+            LlmProvider::Gemini => self.transcribe_gemini(&image_base64).await?,
+            LlmProvider::OpenAi => self.transcribe_openai(&image_base64).await?,
+        };
+
+        // LLMs don't report a confidence, so adaptive DPI never kicks in
+        Ok(PageText {
+            text: text.trim().to_string(),
+            confidence: None,
+        })
+    }
+}
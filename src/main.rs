@@ -1,22 +1,55 @@
+mod alerts;
+mod apple_vision;
+mod cache;
 mod cli;
 mod config;
+mod correction;
+mod daemon;
 mod error;
+mod events;
+mod exporter;
+mod extract;
+mod file_server;
+mod glob;
 mod google_drive;
 mod google_vision;
+mod ignore;
+mod llm_vision;
 mod notion;
 mod oauth;
+mod ocr;
+mod process;
+mod rate_limit;
+mod redact;
 mod remarkable;
+mod repair;
+mod report;
+mod retry;
+mod review;
+mod schedule;
+mod state;
 mod sync;
 mod test;
+mod vocabulary;
 
 use clap::Parser;
-use cli::{Cli, Commands};
+use cli::{AuthCommands, Cli, Commands, ConfigCommands, NotionCommands, SchemaCommands};
 use config::Config;
+use google_drive::GoogleDriveClient;
+use oauth::GoogleOAuthClient;
+use redact::redact;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 use sync::SyncEngine;
+use tokio_util::sync::CancellationToken;
 use tracing::Level;
 use tracing_subscriber::FmtSubscriber;
 
+/// Exit status when Google has to be authorized again, so scripts can tell
+/// it from other failures
+const EXIT_REAUTHORIZE: i32 = 3;
+
 #[tokio::main]
 async fn main() {
     // Load .env file if it exists
@@ -30,87 +63,637 @@ async fn main() {
             notion_database_id,
             dry_run,
             verbose,
+            json,
+            verify,
+            changed_only,
+            force,
+            concurrency,
+            fail_fast,
+            max_errors,
+            limit,
+            defer_ocr,
+            ocr_backlog,
+            since,
+            favorites_only,
+            only,
+            stdin,
+            include_tags,
+            exclude_tags,
+            delete_mode,
+            include_trash,
+            privacy_mode,
+            review_dir,
+            events,
         } => {
-            // Log level from env var LOG_LEVEL or --verbose flag
-            let level = std::env::var("LOG_LEVEL")
-                .ok()
-                .and_then(|l| match l.to_lowercase().as_str() {
-                    "trace" => Some(Level::TRACE),
-                    "debug" => Some(Level::DEBUG),
-                    "info" => Some(Level::INFO),
-                    "warn" => Some(Level::WARN),
-                    "error" => Some(Level::ERROR),
-                    _ => None,
-                })
-                .unwrap_or(if verbose { Level::DEBUG } else { Level::INFO });
-
-            let subscriber = FmtSubscriber::builder().with_max_level(level).finish();
-            tracing::subscriber::set_global_default(subscriber)
-                .expect("Failed to set tracing subscriber");
+            let listed = stdin.then(|| match read_notebook_list() {
+                Ok(listed) => listed,
+                Err(e) => {
+                    eprintln!("Failed to read notebooks from stdin: {}", e);
+                    std::process::exit(1);
+                }
+            });
 
-            // Print ASCII art header
-            const VERSION: &str = env!("CARGO_PKG_VERSION");
-            eprintln!("     _____          ___    _____");
-            eprintln!(" ___|     |        |_  |  |   | |");
-            eprintln!("|  _| | | |        |  _|  | | | |");
-            eprintln!("|_|e|_|_|_|arkable |___|  |_|___|otion v{}", VERSION);
-            eprintln!("---------------------------------------------");
+            // Reviewing implies a dry run
+            let engine = start_engine(
+                notion_token,
+                notion_database_id,
+                dry_run || review_dir.is_some(),
+                verbose,
+                |config| {
+                    config.verify = verify;
+                    config.changed_only = changed_only;
+                    config.force = force;
+                    config.concurrency = concurrency;
+                    config.max_errors = if fail_fast {
+                        Some(1)
+                    } else {
+                        max_errors.map(|n| n as usize)
+                    };
+                    config.limit = limit;
+                    config.defer_ocr = (config.defer_ocr || defer_ocr) && !ocr_backlog;
+                    if ocr_backlog {
+                        config.ocr_backlog = true;
+                        config.device_sync = false;
+                    }
+                    config.since = since.map(|since| since.0);
+                    config.favorites_only = favorites_only;
+                    config.only = only;
+                    config.listed = listed;
+                    config.include_tags = include_tags;
+                    config.exclude_tags = exclude_tags;
+                    if let Some(mode) = delete_mode {
+                        config.delete_mode = mode;
+                    }
+                    config.include_trash |= include_trash;
+                    if let Some(mode) = privacy_mode {
+                        config.privacy_mode = mode;
+                    }
+                    config.review_dir = review_dir;
+                },
+            )
+            .await;
 
-            let notion_token = notion_token
-                .or_else(|| std::env::var("NOTION_TOKEN").ok())
+            let printer = events.then(|| tokio::spawn(print_events(engine.subscribe())));
+
+            let cancel = cancel_on_ctrl_c();
+            let report = match engine.sync(&cancel).await {
+                Ok(report) => report,
+                Err(e) => {
+                    eprintln!("Sync failed: {}", redact(&e.to_string()));
+                    std::process::exit(exit_code(&e));
+                }
+            };
+
+            // Closing the channel lets the printer finish the remaining events
+            drop(engine);
+            if let Some(printer) = printer {
+                printer.await.ok();
+            }
+
+            if json {
+                match serde_json::to_string_pretty(&report) {
+                    Ok(output) => println!("{}", output),
+                    Err(e) => eprintln!(
+                        "Failed to serialize sync report: {}",
+                        redact(&e.to_string())
+                    ),
+                }
+            }
+
+            if report.failed > 0 {
+                std::process::exit(1);
+            }
+        }
+
+        Commands::Daemon {
+            notion_token,
+            notion_database_id,
+            watch_drive_folder,
+            watch_backup,
+            schedule,
+            interval,
+            settle,
+            serve_pdfs,
+            verbose,
+        } => {
+            let schedule = match schedule {
+                Some(schedule) => Some(schedule),
+                None => match std::env::var("SCHEDULE")
+                    .ok()
+                    .map(|s| s.parse())
+                    .transpose()
+                {
+                    Ok(schedule) => schedule,
+                    Err(e) => {
+                        eprintln!("Configuration error: {}", e);
+                        std::process::exit(1);
+                    }
+                },
+            };
+            if let Some(schedule) =
+                schedule.filter(|_| !watch_backup && watch_drive_folder.is_none())
+            {
+                let engine =
+                    start_engine(notion_token, notion_database_id, false, verbose, |_| {}).await;
+
+                let cancel = cancel_on_ctrl_c();
+                if serve_pdfs {
+                    spawn_pdf_server(engine.config(), &cancel);
+                }
+                if let Err(e) = daemon::run_scheduled(&engine, &schedule, &cancel).await {
+                    eprintln!("Daemon failed: {}", redact(&e.to_string()));
+                    std::process::exit(1);
+                }
+                return;
+            }
+
+            if watch_backup {
+                // RemarkableSync runs on its own, just pick up what it writes
+                let engine =
+                    start_engine(notion_token, notion_database_id, false, verbose, |config| {
+                        config.device_sync = false
+                    })
+                    .await;
+
+                let cancel = cancel_on_ctrl_c();
+                if serve_pdfs {
+                    spawn_pdf_server(engine.config(), &cancel);
+                }
+                if let Err(e) =
+                    daemon::watch_backup(&engine, Duration::from_secs(settle), &cancel).await
+                {
+                    eprintln!("Daemon failed: {}", redact(&e.to_string()));
+                    std::process::exit(1);
+                }
+                return;
+            }
+
+            let folder_id = watch_drive_folder
+                .or_else(|| std::env::var("GOOGLE_DRIVE_WATCH_FOLDER_ID").ok())
                 .unwrap_or_else(|| {
-                    eprintln!("Error: NOTION_TOKEN not provided via --notion-token or NOTION_TOKEN env var");
+                    eprintln!("Error: Drive folder to watch not provided via --watch-drive-folder or GOOGLE_DRIVE_WATCH_FOLDER_ID env var (or use --watch-backup)");
                     std::process::exit(1);
                 });
 
-            let notion_database_id = notion_database_id
-                .or_else(|| std::env::var("NOTION_DATABASE_ID").ok())
-                .unwrap_or_else(|| {
-                    eprintln!("Error: NOTION_DATABASE_ID not provided via --notion-database-id or NOTION_DATABASE_ID env var");
+            let engine =
+                start_engine(notion_token, notion_database_id, false, verbose, |_| {}).await;
+
+            let drive = match google_drive_from_env().await {
+                Ok(Some(drive)) => drive,
+                Ok(None) => {
+                    eprintln!("Error: watching Drive requires GOOGLE_OAUTH_CLIENT_ID");
+                    std::process::exit(1);
+                }
+                Err(e) => {
+                    eprintln!(
+                        "Failed to connect to Google Drive: {}",
+                        redact(&e.to_string())
+                    );
+                    std::process::exit(exit_code(&e));
+                }
+            };
+
+            let cancel = cancel_on_ctrl_c();
+            if serve_pdfs {
+                spawn_pdf_server(engine.config(), &cancel);
+            }
+            if let Err(e) = daemon::watch_drive(
+                &engine,
+                &drive,
+                &folder_id,
+                Duration::from_secs(interval),
+                &cancel,
+            )
+            .await
+            {
+                eprintln!("Daemon failed: {}", redact(&e.to_string()));
+                std::process::exit(1);
+            }
+        }
+
+        Commands::Schema { command } => match command {
+            SchemaCommands::Check {
+                notion_token,
+                notion_database_id,
+                fix,
+                yes,
+                verbose,
+            } => {
+                let level = if verbose { Level::DEBUG } else { Level::WARN };
+                let subscriber = FmtSubscriber::builder()
+                    .with_max_level(level)
+                    .with_writer(redact::stderr())
+                    .finish();
+                tracing::subscriber::set_global_default(subscriber)
+                    .expect("Failed to set tracing subscriber");
+
+                let token = notion_token
+                    .or_else(|| std::env::var("NOTION_TOKEN").ok())
+                    .unwrap_or_else(|| {
+                        eprintln!("Error: NOTION_TOKEN not provided via --notion-token or NOTION_TOKEN env var");
+                        std::process::exit(1);
+                    });
+                let database_id = notion_database_id
+                    .or_else(|| std::env::var("NOTION_DATABASE_ID").ok())
+                    .unwrap_or_else(|| {
+                        eprintln!("Error: NOTION_DATABASE_ID not provided via --notion-database-id or NOTION_DATABASE_ID env var");
+                        std::process::exit(1);
+                    });
+                let source_id_property = std::env::var("SOURCE_ID_PROPERTY")
+                    .ok()
+                    .filter(|name| !name.trim().is_empty())
+                    .unwrap_or_else(|| notion::SOURCE_ID_PROPERTY.to_string());
+
+                let file = config::FileConfig::load().unwrap_or_else(|e| {
+                    eprintln!("Failed to load the config file: {}", redact(&e.to_string()));
                     std::process::exit(1);
                 });
 
-            let remarkable_backup_dir = std::env::var("REMARKABLE_BACKUP_DIR")
-                .ok()
-                .map(PathBuf::from);
+                let notion = notion::NotionClient::new(token, database_id)
+                    .with_source_id_property(source_id_property)
+                    .with_relations(file.relation_properties());
+                let issues = match notion.check_schema().await {
+                    Ok(issues) => issues,
+                    Err(e) => {
+                        eprintln!("Failed to check the schema: {}", redact(&e.to_string()));
+                        std::process::exit(1);
+                    }
+                };
+                if issues.is_empty() {
+                    println!("The database has every property the sync writes.");
+                    return;
+                }
+
+                for issue in &issues {
+                    println!("  - {}", issue);
+                }
+                let issue_count = issues.len();
+                let fixable: Vec<_> = issues.into_iter().filter(|i| i.is_fixable()).collect();
+                let unfixable = issue_count - fixable.len();
+                if !fix || fixable.is_empty() {
+                    if !fixable.is_empty() {
+                        println!("\nRun with --fix to apply {} of these.", fixable.len());
+                    }
+                    std::process::exit(1);
+                }
+
+                if !yes && !confirm(&format!("Apply {} fixes to the database?", fixable.len())) {
+                    eprintln!("Nothing changed.");
+                    std::process::exit(1);
+                }
+                match notion.fix_schema(&fixable).await {
+                    Ok(fixed) => println!("Fixed {} of {} issues.", fixed, fixed + unfixable),
+                    Err(e) => {
+                        eprintln!("Failed to fix the schema: {}", redact(&e.to_string()));
+                        std::process::exit(1);
+                    }
+                }
+                if unfixable > 0 {
+                    std::process::exit(1);
+                }
+            }
+        },
+
+        Commands::Config { command } => match command {
+            ConfigCommands::Docs => {
+                let options = config::reference::options();
+                let mut features: Vec<&str> = Vec::new();
+                for option in &options {
+                    if !features.contains(&option.feature) {
+                        features.push(option.feature);
+                    }
+                }
+                for feature in features {
+                    println!("{}", feature);
+                    for option in options.iter().filter(|option| option.feature == feature) {
+                        let key = match option.source {
+                            config::reference::Source::Env => option.key.to_string(),
+                            config::reference::Source::File => {
+                                format!("{} (config file)", option.key)
+                            }
+                        };
+                        let default = option
+                            .default
+                            .as_ref()
+                            .map(|default| format!(", default {}", default))
+                            .unwrap_or_default();
+                        println!("  {}  {}{}", key, option.kind, default);
+                        println!("      {}", option.description);
+                    }
+                    println!();
+                }
+            }
+            ConfigCommands::Schema => {
+                match serde_json::to_string_pretty(&config::reference::options()) {
+                    Ok(output) => println!("{}", output),
+                    Err(e) => {
+                        eprintln!("Failed to serialize the settings: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+            }
+        },
+
+        Commands::Auth { command } => {
+            match command {
+                AuthCommands::Scopes { verbose } => {
+                    let level = if verbose { Level::DEBUG } else { Level::INFO };
+                    let subscriber = FmtSubscriber::builder()
+                        .with_max_level(level)
+                        .with_writer(redact::stderr())
+                        .finish();
+                    tracing::subscriber::set_global_default(subscriber)
+                        .expect("Failed to set tracing subscriber");
+
+                    let oauth_client = match oauth_client_from_env() {
+                        Ok(Some(client)) => client,
+                        Ok(None) => {
+                            eprintln!("Error: GOOGLE_OAUTH_CLIENT_ID not set, no Google features are enabled");
+                            std::process::exit(1);
+                        }
+                        Err(e) => {
+                            eprintln!("Configuration error: {}", redact(&e.to_string()));
+                            std::process::exit(1);
+                        }
+                    };
+
+                    // Asks for the missing scopes if enabled features need more
+                    let scopes = match oauth_client.get_valid_token().await {
+                        Ok(token) => oauth_client.token_scopes(&token).await,
+                        Err(e) => Err(e),
+                    };
+                    let scopes = match scopes {
+                        Ok(scopes) => scopes,
+                        Err(e) => {
+                            eprintln!(
+                                "Failed to look up the granted scopes: {}",
+                                redact(&e.to_string())
+                            );
+                            std::process::exit(exit_code(&e));
+                        }
+                    };
+
+                    for scope in scopes {
+                        println!(
+                            "{}  {}",
+                            scope,
+                            oauth::scope_purpose(&scope).unwrap_or("(not used)")
+                        );
+                    }
+                }
+            }
+        }
+
+        Commands::Notion { command } => match command {
+            NotionCommands::ListDatabases {
+                notion_token,
+                verbose,
+            } => {
+                let level = if verbose { Level::DEBUG } else { Level::WARN };
+                let subscriber = FmtSubscriber::builder()
+                    .with_max_level(level)
+                    .with_writer(redact::stderr())
+                    .finish();
+                tracing::subscriber::set_global_default(subscriber)
+                    .expect("Failed to set tracing subscriber");
+
+                let token = notion_token
+                    .or_else(|| std::env::var("NOTION_TOKEN").ok())
+                    .unwrap_or_else(|| {
+                        eprintln!("Error: NOTION_TOKEN not provided via --notion-token or NOTION_TOKEN env var");
+                        std::process::exit(1);
+                    });
+
+                let databases = match notion::list_databases(&token).await {
+                    Ok(databases) => databases,
+                    Err(e) => {
+                        eprintln!("Failed to list databases: {}", redact(&e.to_string()));
+                        std::process::exit(1);
+                    }
+                };
 
-            let remarkable_password = std::env::var("REMARKABLE_PASSWORD").ok();
+                if databases.is_empty() {
+                    eprintln!("No databases found. Share a database with your integration via \"...\" → \"Add connections\".");
+                    std::process::exit(1);
+                }
 
-            let config = match Config::new(
+                for database in databases {
+                    println!("{}  {}", database.id.replace('-', ""), database.name());
+                }
+            }
+            NotionCommands::BootstrapViews {
                 notion_token,
                 notion_database_id,
-                remarkable_backup_dir,
-                remarkable_password,
-                dry_run,
                 verbose,
-            ) {
-                Ok(cfg) => cfg,
-                Err(e) => {
-                    eprintln!("Configuration error: {}", e);
+            } => {
+                let level = if verbose { Level::DEBUG } else { Level::WARN };
+                let subscriber = FmtSubscriber::builder()
+                    .with_max_level(level)
+                    .with_writer(redact::stderr())
+                    .finish();
+                tracing::subscriber::set_global_default(subscriber)
+                    .expect("Failed to set tracing subscriber");
+
+                let token = notion_token
+                    .or_else(|| std::env::var("NOTION_TOKEN").ok())
+                    .unwrap_or_else(|| {
+                        eprintln!("Error: NOTION_TOKEN not provided via --notion-token or NOTION_TOKEN env var");
+                        std::process::exit(1);
+                    });
+                let database_id = notion_database_id
+                    .or_else(|| std::env::var("NOTION_DATABASE_ID").ok())
+                    .unwrap_or_else(|| {
+                        eprintln!("Error: NOTION_DATABASE_ID not provided via --notion-database-id or NOTION_DATABASE_ID env var");
+                        std::process::exit(1);
+                    });
+
+                // The API can't create views, only the properties they use
+                let notion = notion::NotionClient::new(token, database_id);
+                let result = match notion.verify_connection().await {
+                    Ok(()) => notion.check_schema().await,
+                    Err(e) => Err(e),
+                };
+                let issues = result.unwrap_or_else(|e| {
+                    eprintln!("Failed to check the database: {}", redact(&e.to_string()));
+                    std::process::exit(1);
+                });
+                if !issues.is_empty() {
+                    for issue in &issues {
+                        eprintln!("  {}", issue);
+                    }
+                    eprintln!("Fix the database with `schema check --fix` first.");
                     std::process::exit(1);
                 }
-            };
 
-            let engine = match SyncEngine::new(config).await {
-                Ok(eng) => eng,
-                Err(e) => {
-                    eprintln!("Failed to initialize sync engine: {}", e);
+                println!("The database has every property the default views use.");
+                println!("Notion's API can't create views, so add them in Notion with \"+\" next to the current view:\n");
+                for view in notion::DEFAULT_VIEWS {
+                    println!("  {:<17}{}", view.name, view.setup);
+                }
+            }
+        },
+
+        Commands::Repair {
+            notion_token,
+            notion_database_id,
+            dedupe,
+            merge_duplicates,
+            storage,
+            dry_run,
+            verbose,
+        } => {
+            let level = if verbose { Level::DEBUG } else { Level::INFO };
+            let subscriber = FmtSubscriber::builder()
+                .with_max_level(level)
+                .with_writer(redact::stdout())
+                .finish();
+            tracing::subscriber::set_global_default(subscriber)
+                .expect("Failed to set tracing subscriber");
+
+            if !dedupe && !merge_duplicates && !storage {
+                eprintln!(
+                    "Please specify at least one repair: --dedupe, --merge-duplicates or --storage"
+                );
+                eprintln!("Run with --help for more information");
+                std::process::exit(1);
+            }
+
+            if dedupe || merge_duplicates {
+                let token = notion_token
+                    .or_else(|| std::env::var("NOTION_TOKEN").ok())
+                    .unwrap_or_else(|| {
+                        eprintln!("Error: NOTION_TOKEN not provided via --notion-token or NOTION_TOKEN env var");
+                        std::process::exit(1);
+                    });
+
+                let db_id = notion_database_id
+                    .or_else(|| std::env::var("NOTION_DATABASE_ID").ok())
+                    .unwrap_or_else(|| {
+                        eprintln!("Error: NOTION_DATABASE_ID not provided via --notion-database-id or NOTION_DATABASE_ID env var");
+                        std::process::exit(1);
+                    });
+
+                let notion = notion::NotionClient::new(token, db_id);
+                // Merge first so dedupe doesn't clean up pages about to be archived
+                if merge_duplicates {
+                    if let Err(e) = repair::merge_duplicates(&notion, dry_run).await {
+                        eprintln!("Merging duplicates failed: {}", redact(&e.to_string()));
+                        std::process::exit(1);
+                    }
+                }
+                if dedupe {
+                    if let Err(e) = repair::dedupe(&notion, dry_run).await {
+                        eprintln!("Dedupe failed: {}", redact(&e.to_string()));
+                        std::process::exit(1);
+                    }
+                }
+            }
+
+            if storage {
+                let drive = match google_drive_from_env().await {
+                    Ok(drive) => drive,
+                    Err(e) => {
+                        eprintln!(
+                            "Failed to connect to Google Drive: {}",
+                            redact(&e.to_string())
+                        );
+                        std::process::exit(exit_code(&e));
+                    }
+                };
+
+                if let Err(e) = repair::storage(drive.as_ref(), &config::work_dir(), dry_run).await
+                {
+                    eprintln!("Storage repair failed: {}", redact(&e.to_string()));
                     std::process::exit(1);
                 }
-            };
+            }
+        }
+
+        Commands::Backfill {
+            notion_token,
+            notion_database_id,
+            dry_run,
+            verbose,
+        } => {
+            // Works from the backup as it is, without syncing the tablet first
+            let engine = start_engine(
+                notion_token,
+                notion_database_id,
+                dry_run,
+                verbose,
+                |config| config.device_sync = false,
+            )
+            .await;
+
+            if let Err(e) = engine.backfill().await {
+                eprintln!("Backfill failed: {}", redact(&e.to_string()));
+                std::process::exit(1);
+            }
+        }
 
-            if let Err(e) = engine.verify_prerequisites().await {
-                eprintln!("Prerequisites check failed: {}", e);
-                eprintln!("\nPlease ensure:");
-                eprintln!("  1. RemarkableSync is installed (brew install remarkablesync)");
-                eprintln!("  2. Tesseract is installed (brew install tesseract)");
-                eprintln!("  3. Notion token and database ID are correct");
-                eprintln!("  4. ReMarkable tablet is connected via USB");
+        Commands::Verify {
+            notion_token,
+            notion_database_id,
+            unique,
+            fix,
+            verbose,
+        } => {
+            if !unique {
+                eprintln!("Please specify a check: --unique");
+                eprintln!("Run with --help for more information");
                 std::process::exit(1);
             }
 
-            if let Err(e) = engine.sync().await {
-                eprintln!("Sync failed: {}", e);
+            let engine = start_engine(notion_token, notion_database_id, false, verbose, |config| {
+                config.device_sync = false
+            })
+            .await;
+
+            match engine.verify_unique(fix).await {
+                Ok(0) => {}
+                Ok(duplicated) => {
+                    eprintln!(
+                        "{} notebooks have more than one page{}",
+                        duplicated,
+                        if fix {
+                            ""
+                        } else {
+                            "; run with --fix to merge them"
+                        }
+                    );
+                    std::process::exit(1);
+                }
+                Err(e) => {
+                    eprintln!("Verify failed: {}", redact(&e.to_string()));
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        Commands::Serve { dir, bind, verbose } => {
+            let level = if verbose { Level::DEBUG } else { Level::INFO };
+            let subscriber = FmtSubscriber::builder()
+                .with_max_level(level)
+                .with_writer(redact::stdout())
+                .finish();
+            tracing::subscriber::set_global_default(subscriber)
+                .expect("Failed to set tracing subscriber");
+
+            let Some(dir) =
+                dir.or_else(|| std::env::var("PDF_ARCHIVE_DIR").ok().map(PathBuf::from))
+            else {
+                eprintln!(
+                    "Error: directory to serve not provided via --dir or PDF_ARCHIVE_DIR env var"
+                );
+                std::process::exit(1);
+            };
+            let bind = bind
+                .or_else(|| std::env::var("PDF_SERVER_BIND").ok())
+                .unwrap_or_else(|| file_server::DEFAULT_BIND.to_string());
+
+            let cancel = cancel_on_ctrl_c();
+            if let Err(e) = file_server::serve(&dir, &bind, &cancel).await {
+                eprintln!("PDF server failed: {}", redact(&e.to_string()));
                 std::process::exit(1);
             }
         }
@@ -124,7 +707,10 @@ async fn main() {
             verbose,
         } => {
             let level = if verbose { Level::DEBUG } else { Level::INFO };
-            let subscriber = FmtSubscriber::builder().with_max_level(level).finish();
+            let subscriber = FmtSubscriber::builder()
+                .with_max_level(level)
+                .with_writer(redact::stdout())
+                .finish();
             tracing::subscriber::set_global_default(subscriber)
                 .expect("Failed to set tracing subscriber");
 
@@ -135,14 +721,14 @@ async fn main() {
                 let password = std::env::var("REMARKABLE_PASSWORD").ok();
 
                 if let Err(e) = test::test_remarkable(backup_dir, password).await {
-                    eprintln!("RemarkableSync test failed: {}", e);
+                    eprintln!("reMarkable test failed: {}", redact(&e.to_string()));
                     std::process::exit(1);
                 }
             }
 
             if let Some(ref pdf_path) = ocr {
                 if let Err(e) = test::test_ocr(Path::new(pdf_path)).await {
-                    eprintln!("OCR test failed: {}", e);
+                    eprintln!("OCR test failed: {}", redact(&e.to_string()));
                     std::process::exit(1);
                 }
             }
@@ -163,7 +749,7 @@ async fn main() {
                     });
 
                 if let Err(e) = test::test_notion(&token, &db_id).await {
-                    eprintln!("Notion test failed: {}", e);
+                    eprintln!("Notion test failed: {}", redact(&e.to_string()));
                     std::process::exit(1);
                 }
             }
@@ -176,3 +762,266 @@ async fn main() {
         }
     }
 }
+
+/// Google OAuth client from the settings in the environment, if configured
+fn oauth_client_from_env() -> error::Result<Option<GoogleOAuthClient>> {
+    let Ok(client_id) = std::env::var("GOOGLE_OAUTH_CLIENT_ID") else {
+        return Ok(None);
+    };
+    let client_secret = std::env::var("GOOGLE_OAUTH_CLIENT_SECRET").ok();
+
+    let callback_port =
+        config::env_parse("OAUTH_CALLBACK_PORT")?.unwrap_or(oauth::DEFAULT_CALLBACK_PORT);
+    Ok(Some(
+        GoogleOAuthClient::new(client_id, client_secret)?.with_callback_port(callback_port),
+    ))
+}
+
+/// Google Drive client from the OAuth settings in the environment, if configured
+async fn google_drive_from_env() -> error::Result<Option<GoogleDriveClient>> {
+    let Some(oauth_client) = oauth_client_from_env()? else {
+        return Ok(None);
+    };
+    let oauth_client = Arc::new(oauth_client);
+    let folder_id = std::env::var("GOOGLE_DRIVE_FOLDER_ID").ok();
+    Ok(Some(GoogleDriveClient::new(oauth_client, folder_id).await?))
+}
+
+/// Notebook names, paths or UUIDs from stdin, one per line. Blank lines are
+/// skipped and JSON strings (`jq` without `-r`) are unquoted.
+fn read_notebook_list() -> std::io::Result<Vec<String>> {
+    use std::io::{BufRead, IsTerminal};
+
+    if std::io::stdin().is_terminal() {
+        return Err(std::io::Error::other(
+            "nothing is piped in (e.g. `echo \"Meeting notes\" | remarkable2notion sync --stdin`)",
+        ));
+    }
+    let mut listed = Vec::new();
+    for line in std::io::stdin().lock().lines() {
+        listed.extend(notebook_list_entry(&line?));
+    }
+    Ok(listed)
+}
+
+/// One line of the `--stdin` list, unquoted; `None` for a blank line
+fn notebook_list_entry(line: &str) -> Option<String> {
+    let line = line.trim();
+    if line.is_empty() {
+        return None;
+    }
+    Some(serde_json::from_str::<String>(line).unwrap_or_else(|_| line.to_string()))
+}
+
+/// Ask a yes/no question on the terminal; without one the answer is no
+fn confirm(question: &str) -> bool {
+    use std::io::{BufRead, IsTerminal, Write};
+
+    if !std::io::stdin().is_terminal() {
+        eprintln!(
+            "{} Not without a terminal; pass --yes to confirm.",
+            question
+        );
+        return false;
+    }
+    eprint!("{} [y/N] ", question);
+    std::io::stderr().flush().ok();
+    let mut answer = String::new();
+    if std::io::stdin().lock().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+/// Exit status for a command that failed with `e`
+fn exit_code(e: &error::Error) -> i32 {
+    match e {
+        error::Error::Reauthorize(_) => EXIT_REAUTHORIZE,
+        error::Error::Stage { source, .. } => exit_code(source),
+        _ => 1,
+    }
+}
+
+/// Serve the PDF archive in the background for `daemon --serve-pdfs`
+fn spawn_pdf_server(config: &Config, cancel: &CancellationToken) {
+    let Some(dir) = config.pdf_archive_dir.clone() else {
+        eprintln!("Error: --serve-pdfs requires PDF_ARCHIVE_DIR");
+        std::process::exit(1);
+    };
+    let bind = config.pdf_server_bind.clone();
+    let cancel = cancel.clone();
+    tokio::spawn(async move {
+        if let Err(e) = file_server::serve(&dir, &bind, &cancel).await {
+            eprintln!("PDF server failed: {}", redact(&e.to_string()));
+        }
+    });
+}
+
+/// A token cancelled by Ctrl-C; a second Ctrl-C exits immediately
+fn cancel_on_ctrl_c() -> CancellationToken {
+    let cancel = CancellationToken::new();
+    let token = cancel.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            eprintln!("Stopping after the notebooks in progress, press Ctrl-C again to quit now");
+            token.cancel();
+            if tokio::signal::ctrl_c().await.is_ok() {
+                std::process::exit(130);
+            }
+        }
+    });
+    cancel
+}
+
+/// Print sync events as JSON lines until the engine is dropped
+async fn print_events(mut events: tokio::sync::broadcast::Receiver<events::SyncEvent>) {
+    use tokio::sync::broadcast::error::RecvError;
+
+    loop {
+        match events.recv().await {
+            Ok(event) => match serde_json::to_string(&event) {
+                Ok(line) => println!("{}", line),
+                Err(e) => eprintln!("Failed to serialize sync event: {}", redact(&e.to_string())),
+            },
+            Err(RecvError::Lagged(missed)) => eprintln!("Missed {} sync events", missed),
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+/// Set up logging, print the header and build a sync engine whose prerequisites
+/// check out, exiting on any failure
+async fn start_engine(
+    notion_token: Option<String>,
+    notion_database_id: Option<String>,
+    dry_run: bool,
+    verbose: bool,
+    configure: impl FnOnce(&mut Config),
+) -> SyncEngine {
+    // Log level from env var LOG_LEVEL or --verbose flag
+    let level = std::env::var("LOG_LEVEL")
+        .ok()
+        .and_then(|l| match l.to_lowercase().as_str() {
+            "trace" => Some(Level::TRACE),
+            "debug" => Some(Level::DEBUG),
+            "info" => Some(Level::INFO),
+            "warn" => Some(Level::WARN),
+            "error" => Some(Level::ERROR),
+            _ => None,
+        })
+        .unwrap_or(if verbose { Level::DEBUG } else { Level::INFO });
+
+    // Keep stdout clean for the JSON report
+    let subscriber = FmtSubscriber::builder()
+        .with_max_level(level)
+        .with_writer(redact::stderr())
+        .finish();
+    tracing::subscriber::set_global_default(subscriber).expect("Failed to set tracing subscriber");
+
+    // Print ASCII art header
+    const VERSION: &str = env!("CARGO_PKG_VERSION");
+    eprintln!("     _____          ___    _____");
+    eprintln!(" ___|     |        |_  |  |   | |");
+    eprintln!("|  _| | | |        |  _|  | | | |");
+    eprintln!("|_|e|_|_|_|arkable |___|  |_|___|otion v{}", VERSION);
+    eprintln!("---------------------------------------------");
+
+    let notion_token = notion_token
+        .or_else(|| std::env::var("NOTION_TOKEN").ok())
+        .unwrap_or_else(|| {
+            eprintln!(
+                "Error: NOTION_TOKEN not provided via --notion-token or NOTION_TOKEN env var"
+            );
+            std::process::exit(1);
+        });
+
+    // Syncing to a parent page needs no database
+    let notion_database_id = notion_database_id
+        .or_else(|| std::env::var("NOTION_DATABASE_ID").ok())
+        .or_else(|| {
+            std::env::var("NOTION_PARENT_PAGE_ID")
+                .is_ok()
+                .then(String::new)
+        })
+        .unwrap_or_else(|| {
+            eprintln!("Error: NOTION_DATABASE_ID not provided via --notion-database-id or NOTION_DATABASE_ID env var (or NOTION_PARENT_PAGE_ID)");
+            std::process::exit(1);
+        });
+
+    let remarkable_backup_dir = std::env::var("REMARKABLE_BACKUP_DIR")
+        .ok()
+        .map(PathBuf::from);
+
+    let remarkable_password = std::env::var("REMARKABLE_PASSWORD").ok();
+
+    let mut config = match Config::new(
+        notion_token,
+        notion_database_id,
+        remarkable_backup_dir,
+        remarkable_password,
+        dry_run,
+        verbose,
+    ) {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            eprintln!("Configuration error: {}", redact(&e.to_string()));
+            std::process::exit(1);
+        }
+    };
+    configure(&mut config);
+
+    let engine = match SyncEngine::new(config).await {
+        Ok(eng) => eng,
+        Err(e) => {
+            eprintln!(
+                "Failed to initialize sync engine: {}",
+                redact(&e.to_string())
+            );
+            std::process::exit(exit_code(&e));
+        }
+    };
+
+    if let Err(e) = engine.verify_prerequisites().await {
+        eprintln!("Prerequisites check failed: {}", redact(&e.to_string()));
+        eprintln!("\nPlease ensure:");
+        eprintln!("  1. RemarkableSync is installed (brew install remarkablesync)");
+        eprintln!("  2. Tesseract is installed (brew install tesseract)");
+        eprintln!("  3. Notion token and database ID are correct");
+        eprintln!("  4. ReMarkable tablet is connected via USB");
+        std::process::exit(1);
+    }
+
+    engine
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notebook_list_entries_are_unquoted() {
+        assert_eq!(
+            notebook_list_entry("\"Meeting notes\""),
+            Some("Meeting notes".to_string())
+        );
+        assert_eq!(
+            notebook_list_entry("  \"Work/Say \\\"hi\\\"\"  "),
+            Some("Work/Say \"hi\"".to_string())
+        );
+        assert_eq!(
+            notebook_list_entry("Meeting notes"),
+            Some("Meeting notes".to_string())
+        );
+        // Only whole JSON strings are unquoted
+        assert_eq!(
+            notebook_list_entry("\"Draft\" v2"),
+            Some("\"Draft\" v2".to_string())
+        );
+    }
+
+    #[test]
+    fn blank_notebook_list_lines_are_skipped() {
+        assert_eq!(notebook_list_entry(""), None);
+        assert_eq!(notebook_list_entry("  \t "), None);
+    }
+}
@@ -1,19 +1,93 @@
-use crate::error::{Error, Result};
+use crate::config::{ErrorPolicy, LayoutConfig};
+use crate::error::{ApiError, Error, Result};
+use crate::rate_limit::{RateLimiter, NOTION_REQUESTS_PER_SECOND};
+use crate::retry::{self, RetryExt, Sent};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::collections::HashSet;
 use std::path::Path;
+use std::sync::Arc;
 use tracing::{debug, warn};
 
+pub mod blocks;
+pub mod models;
+
+pub use blocks::BlockBuilder;
+pub use models::{Block, Page};
+use models::{Database, FileUpload, List, Property, SelectOption};
+
 const NOTION_API_VERSION: &str = "2022-06-28";
 const NOTION_API_BASE: &str = "https://api.notion.com/v1";
 
+/// Heading above the OCR text on every synced page
+pub const CONTENT_HEADING: &str = "OCR Extracted Text";
+
+/// Heading above the to-dos of meeting notes
+pub const ACTION_ITEMS_HEADING: &str = "Action Items";
+
+/// Start of the paragraph naming the local PDF when Drive isn't configured
+pub const PDF_REFERENCE_PREFIX: &str = "📎 PDF: ";
+
+/// Start of the callout flagging an incomplete sync
+pub const SYNC_WARNING_PREFIX: &str = "Incomplete sync: ";
+
+/// Property holding a page's notebook UUID unless `SOURCE_ID_PROPERTY` names another
+pub const SOURCE_ID_PROPERTY: &str = "Source UUID";
+
+/// Property holding the key of the write that created a page, so a retried
+/// create can find the page its first attempt made
+pub const WRITE_KEY_PROPERTY: &str = "Sync Write Key";
+
+/// Notion accepts at most 100 children per create/append request
+pub const MAX_CHILDREN_PER_REQUEST: usize = 100;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NotionPage {
     pub id: String,
     pub title: String,
+    pub last_edited_time: Option<String>,
+}
+
+impl NotionPage {
+    /// The ID and title of a queried page, unless it has no title
+    pub fn from_page(page: &Page) -> Option<Self> {
+        Some(NotionPage {
+            id: page.id.clone(),
+            title: page.title()?,
+            last_edited_time: page.last_edited_time.clone(),
+        })
+    }
+}
+
+/// A database view worth having on top of the synced pages
+pub struct ViewRecipe {
+    pub name: &'static str,
+    /// How to set it up in Notion
+    pub setup: &'static str,
 }
 
+/// Views `notion bootstrap-views` recommends. Notion's API has no endpoints
+/// for views, so they're added by hand.
+pub const DEFAULT_VIEWS: &[ViewRecipe] = &[
+    ViewRecipe {
+        name: "By Folder",
+        setup: "Table view, Group by: Folder",
+    },
+    ViewRecipe {
+        name: "Recent",
+        setup: "Table view, Sort: Last Modified, descending",
+    },
+    ViewRecipe {
+        name: "Gallery",
+        setup: "Gallery view, Card preview: Page content (shows the first page image)",
+    },
+    ViewRecipe {
+        name: "Needs Attention",
+        setup: "Table view, Filter: Sync Status is Failed or Stale",
+    },
+];
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NotebookMetadata {
     pub created_time: Option<String>,
@@ -21,28 +95,95 @@ pub struct NotebookMetadata {
     pub folder_path: String,
 }
 
-#[derive(Debug, Deserialize)]
-struct QueryResponse {
-    results: Vec<PageResult>,
+/// Properties tying a page to the notebook it was made from
+#[derive(Debug, Clone)]
+pub struct SourceProperties {
+    /// Document UUID on the tablet
+    pub uuid: Option<String>,
+    pub folder: String,
+    /// SHA-256 of the notebook's PDF in the backup
+    pub sync_hash: Option<String>,
+    pub page_count: Option<usize>,
+    /// Size of the notebook's PDF in the backup, in megabytes
+    pub pdf_size_mb: Option<f64>,
+    /// "Notebook", "PDF", "EPUB" or "Annotated PDF"
+    pub document_type: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
-struct PageResult {
-    id: String,
-    properties: serde_json::Value,
+impl SourceProperties {
+    /// Property values by name, leaving out the ones that aren't known. The
+    /// UUID goes in `id_property` (`SOURCE_ID_PROPERTY`).
+    pub fn values(&self, id_property: &str) -> serde_json::Map<String, serde_json::Value> {
+        let mut values = serde_json::Map::new();
+        if let Some(ref uuid) = self.uuid {
+            values.insert(id_property.to_string(), rich_text_value(uuid));
+        }
+        values.insert("Folder".to_string(), rich_text_value(&self.folder));
+        if let Some(ref hash) = self.sync_hash {
+            values.insert("Sync Hash".to_string(), rich_text_value(hash));
+        }
+        if let Some(count) = self.page_count {
+            values.insert("Page Count".to_string(), json!({ "number": count }));
+        }
+        if let Some(size) = self.pdf_size_mb {
+            values.insert("PDF Size (MB)".to_string(), json!({ "number": size }));
+        }
+        if let Some(ref document_type) = self.document_type {
+            values.insert(
+                "Document Type".to_string(),
+                json!({ "select": { "name": document_type } }),
+            );
+        }
+        values
+    }
+}
+
+/// Outcome of `update_page`
+#[derive(Debug)]
+pub struct PageUpdate {
+    /// Blocks asked to keep that still existed on the page
+    pub kept: HashSet<String>,
+    /// Last block of the OCR text; kept blocks follow it
+    pub content_end: String,
+}
+
+/// Values of the "Sync Status" select property, for rolling up sync health in Notion
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncStatus {
+    Synced,
+    Stale,
+    Failed,
+    Archived,
+    /// Written without text, waiting for the OCR backlog (`DEFER_OCR`)
+    Queued,
 }
 
-#[derive(Debug, Deserialize)]
-struct BlockResponse {
-    results: Vec<serde_json::Value>,
-    has_more: bool,
-    next_cursor: Option<String>,
+impl SyncStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SyncStatus::Synced => "Synced",
+            SyncStatus::Stale => "Stale",
+            SyncStatus::Failed => "Failed",
+            SyncStatus::Archived => "Archived",
+            SyncStatus::Queued => "OCR Pending",
+        }
+    }
 }
 
 pub struct NotionClient {
     client: Client,
     token: String,
+    /// Shared with the other clients using the same integration
+    limiter: Arc<RateLimiter>,
     database_id: String,
+    /// Page to create pages under instead of the database
+    parent_page_id: Option<String>,
+    batch_size: usize,
+    layout: LayoutConfig,
+    /// Property holding each page's notebook UUID
+    source_id_property: String,
+    /// Relation properties the sync writes, with their target database
+    relations: Vec<(String, String)>,
 }
 
 impl NotionClient {
@@ -51,10 +192,62 @@ impl NotionClient {
         Self {
             client,
             token,
+            limiter: Arc::new(RateLimiter::per_second(NOTION_REQUESTS_PER_SECOND)),
             database_id,
+            parent_page_id: None,
+            batch_size: MAX_CHILDREN_PER_REQUEST,
+            layout: LayoutConfig::default(),
+            source_id_property: SOURCE_ID_PROPERTY.to_string(),
+            relations: Vec::new(),
         }
     }
 
+    /// Share a rate limiter with other clients, so together they stay within
+    /// Notion's request limit
+    pub fn with_rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.limiter = limiter;
+        self
+    }
+
+    /// Create pages as children of a plain page instead of in the database.
+    ///
+    /// Plain pages only have a title, so the other properties are written
+    /// to a table at the top of each page.
+    pub fn with_parent_page(mut self, page_id: String) -> Self {
+        self.parent_page_id = Some(page_id);
+        self
+    }
+
+    /// Whether pages go under a parent page rather than into a database
+    pub fn uses_parent_page(&self) -> bool {
+        self.parent_page_id.is_some()
+    }
+
+    /// Heading levels and toggles used for page content
+    pub fn with_layout(mut self, layout: LayoutConfig) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Property holding each page's notebook UUID (`SOURCE_ID_PROPERTY`)
+    pub fn with_source_id_property(mut self, name: String) -> Self {
+        self.source_id_property = name;
+        self
+    }
+
+    /// Relation properties (`[[relations]]`) the schema check expects, by
+    /// property name and target database ID
+    pub fn with_relations(mut self, relations: Vec<(String, String)>) -> Self {
+        self.relations = relations;
+        self
+    }
+
+    /// Number of blocks sent per append request (capped at Notion's limit of 100)
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.clamp(1, MAX_CHILDREN_PER_REQUEST);
+        self
+    }
+
     fn headers(&self) -> reqwest::header::HeaderMap {
         let mut headers = reqwest::header::HeaderMap::new();
         headers.insert(
@@ -69,50 +262,363 @@ impl NotionClient {
     pub async fn verify_connection(&self) -> Result<()> {
         debug!("Verifying Notion API connection");
 
+        let url = match self.parent_page_id {
+            Some(ref page_id) => format!("{}/pages/{}", NOTION_API_BASE, page_id),
+            None => format!("{}/databases/{}", NOTION_API_BASE, self.database_id),
+        };
         let response = self
             .client
-            .get(format!(
+            .get(url)
+            .headers(self.headers())
+            .send_limited(&self.limiter)
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(ApiError::from_response(
+                "Notion",
+                "Failed to verify Notion connection",
+                response,
+            )
+            .await);
+        }
+
+        debug!("Notion connection verified");
+        Ok(())
+    }
+
+    /// Log how the database differs from the properties the sync writes.
+    /// Nothing is changed; that's left to `schema check --fix`, which asks
+    /// first.
+    pub async fn report_schema_issues(&self) -> Result<Vec<SchemaIssue>> {
+        if self.parent_page_id.is_some() {
+            debug!("Syncing to a parent page, no database properties to check");
+            return Ok(Vec::new());
+        }
+        debug!("Checking the database properties");
+
+        let issues = self.check_schema().await?;
+        for issue in &issues {
+            warn!("{} (fix with `schema check --fix`)", issue);
+        }
+        Ok(issues)
+    }
+
+    /// Differences between the database and the properties the sync writes
+    pub async fn check_schema(&self) -> Result<Vec<SchemaIssue>> {
+        if self.parent_page_id.is_some() {
+            return Ok(Vec::new());
+        }
+
+        let database = self.get_database().await?;
+        let expected = self.expected_properties();
+        let mut issues = Vec::new();
+
+        for (name, definition) in expected["properties"].as_object().into_iter().flatten() {
+            let Some((kind, config)) = definition.as_object().and_then(|d| d.iter().next()) else {
+                continue;
+            };
+
+            let Some(actual) = database.properties.get(name) else {
+                // Notion treats names as distinct, but a "tags" next to "Tags" is surely a mistake
+                let similar = database
+                    .properties
+                    .iter()
+                    .find(|(other, _)| other.trim().eq_ignore_ascii_case(name.trim()));
+                issues.push(match similar {
+                    Some((other, schema)) => SchemaIssue::Conflict {
+                        name: name.clone(),
+                        existing: other.clone(),
+                        same_type: schema.kind == *kind,
+                    },
+                    None => SchemaIssue::Missing {
+                        name: name.clone(),
+                        kind: kind.clone(),
+                    },
+                });
+                continue;
+            };
+
+            if actual.kind != *kind {
+                issues.push(SchemaIssue::WrongType {
+                    name: name.clone(),
+                    expected: kind.clone(),
+                    actual: actual.kind.clone(),
+                });
+                continue;
+            }
+
+            // Relations are only ever created, an existing one (possibly
+            // two-way) isn't rewritten
+            if let (Some(relation), Some(target)) =
+                (&actual.relation, config["database_id"].as_str())
+            {
+                if !same_id(&relation.database_id, target) {
+                    issues.push(SchemaIssue::WrongRelation {
+                        name: name.clone(),
+                        expected: target.to_string(),
+                        actual: relation.database_id.clone(),
+                    });
+                }
+                continue;
+            }
+
+            let existing: Vec<&str> = match (&actual.select, &actual.multi_select) {
+                (Some(schema), _) | (None, Some(schema)) => {
+                    schema.options.iter().map(|o| o.name.as_str()).collect()
+                }
+                _ => Vec::new(),
+            };
+            let missing: Vec<serde_json::Value> = config["options"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter(|option| {
+                    option["name"]
+                        .as_str()
+                        .is_some_and(|option| !existing.contains(&option))
+                })
+                .cloned()
+                .collect();
+            if !missing.is_empty() {
+                issues.push(SchemaIssue::MissingOptions {
+                    name: name.clone(),
+                    kind: kind.clone(),
+                    existing: existing
+                        .iter()
+                        .map(|name| json!({ "name": name }))
+                        .collect(),
+                    missing,
+                });
+            }
+        }
+
+        issues.sort_by(|a, b| a.name().cmp(b.name()));
+        Ok(issues)
+    }
+
+    /// Apply the fixable issues in one schema update, returning how many
+    pub async fn fix_schema(&self, issues: &[SchemaIssue]) -> Result<usize> {
+        let expected = self.expected_properties();
+        let mut properties = serde_json::Map::new();
+        let mut fixed = 0;
+        for issue in issues {
+            match issue {
+                SchemaIssue::Missing { name, .. } => {
+                    properties.insert(name.clone(), expected["properties"][name].clone());
+                }
+                SchemaIssue::Conflict {
+                    name,
+                    existing,
+                    same_type: true,
+                } => {
+                    properties.insert(existing.clone(), json!({ "name": name }));
+                }
+                // Options that aren't listed are removed, so the existing
+                // ones are sent along
+                SchemaIssue::MissingOptions {
+                    name,
+                    kind,
+                    existing,
+                    missing,
+                } => {
+                    let options: Vec<_> = existing.iter().chain(missing).cloned().collect();
+                    properties.insert(name.clone(), json!({ kind: { "options": options } }));
+                }
+                _ => continue,
+            }
+            fixed += 1;
+        }
+        if fixed == 0 {
+            return Ok(0);
+        }
+
+        let response = self
+            .client
+            .patch(format!(
                 "{}/databases/{}",
                 NOTION_API_BASE, self.database_id
             ))
             .headers(self.headers())
-            .send()
+            .json(&json!({ "properties": properties }))
+            .send_limited(&self.limiter)
             .await?;
 
         if !response.status().is_success() {
-            let status = response.status();
-            let body = response.text().await?;
-            return Err(Error::Notion(format!(
-                "Failed to verify Notion connection: {} - {}",
-                status, body
-            )));
+            return Err(ApiError::from_response(
+                "Notion",
+                "Failed to update database schema",
+                response,
+            )
+            .await);
         }
+        Ok(fixed)
+    }
 
-        debug!("Notion connection verified");
-        Ok(())
+    /// Properties the sync writes, including the relations, as a schema
+    /// update that adds them
+    fn expected_properties(&self) -> serde_json::Value {
+        let mut expected = schema_properties(&self.source_id_property);
+        for (property, database_id) in &self.relations {
+            expected["properties"][property] = json!({
+                "relation": {
+                    "database_id": database_id,
+                    "single_property": {}
+                }
+            });
+        }
+        expected
+    }
+
+    /// Check up front that the integration may do what a sync does, instead
+    /// of failing notebook after notebook with 403s. Returns a description of
+    /// every missing capability and of properties that can't be written.
+    ///
+    /// Notion has no endpoint listing an integration's capabilities, so each
+    /// one is probed with a request that is invalid on purpose: a 403 means
+    /// the capability is missing, a validation error that it's there.
+    pub async fn audit_permissions(&self, comments: bool) -> Result<Vec<String>> {
+        let mut problems = Vec::new();
+        let parent = match self.parent_page_id {
+            Some(ref page_id) => json!({ "page_id": page_id }),
+            None => json!({ "database_id": self.database_id }),
+        };
+        let target = self.parent_page_id.as_ref().unwrap_or(&self.database_id);
+
+        // A property that doesn't exist fails validation
+        let insert = self
+            .client
+            .post(format!("{}/pages", NOTION_API_BASE))
+            .json(&json!({
+                "parent": parent,
+                "properties": { "remarkable2notion permission check": { "rich_text": [] } }
+            }));
+        if self.probe(insert).await? {
+            problems.push(
+                "The integration lacks the \"Insert content\" capability, needed to create pages"
+                    .to_string(),
+            );
+        }
+
+        // An update that changes nothing
+        let update = match self.parent_page_id {
+            Some(ref page_id) => self
+                .client
+                .patch(format!("{}/pages/{}", NOTION_API_BASE, page_id))
+                .json(&json!({})),
+            None => self
+                .client
+                .patch(format!(
+                    "{}/databases/{}",
+                    NOTION_API_BASE, self.database_id
+                ))
+                .json(&json!({ "properties": {} })),
+        };
+        if self.probe(update).await? {
+            problems.push(
+                "The integration lacks the \"Update content\" capability, needed to update pages"
+                    .to_string(),
+            );
+        }
+
+        if comments {
+            // A comment without text fails validation
+            let comment = self
+                .client
+                .post(format!("{}/comments", NOTION_API_BASE))
+                .json(&json!({ "parent": { "page_id": target } }));
+            if self.probe(comment).await? {
+                problems.push(
+                    "The integration lacks the \"Insert comments\" capability, needed for SYNC_COMMENTS"
+                        .to_string(),
+                );
+            }
+        }
+
+        // Pages can't be written without the properties; missing select
+        // options are created by Notion on first use
+        for issue in self.check_schema().await? {
+            if !matches!(issue, SchemaIssue::MissingOptions { .. }) {
+                problems.push(format!("Property {}", issue));
+            }
+        }
+
+        Ok(problems)
+    }
+
+    /// Send a probe of `audit_permissions`, returning whether Notion refused
+    /// it for lack of permission
+    async fn probe(&self, request: reqwest::RequestBuilder) -> Result<bool> {
+        let response = request
+            .headers(self.headers())
+            .send_limited(&self.limiter)
+            .await?;
+        let status = response.status();
+        if status.is_success() {
+            // Not expected, but don't leave a stray page behind if it happens
+            let created: serde_json::Value = response.json().await.unwrap_or_default();
+            if created["object"] == "page" {
+                if let Some(id) = created["id"].as_str() {
+                    self.delete_page(id).await.ok();
+                }
+            }
+            return Ok(false);
+        }
+        debug!("Permission probe answered {}", status);
+        Ok(status == reqwest::StatusCode::FORBIDDEN)
     }
 
-    pub async fn ensure_database_properties(&self) -> Result<()> {
-        debug!("Ensuring database has required properties");
+    /// Add all `tags` as options of the Tags property in a single schema update.
+    ///
+    /// Registering up front gives every tag a stable color instead of relying on
+    /// Notion creating options implicitly page by page. Returns the tags Notion
+    /// would reject, which are left off the pages.
+    pub async fn register_tags(&self, tags: &[String]) -> Result<Vec<String>> {
+        let rejected: Vec<String> = tags
+            .iter()
+            .filter(|tag| !is_valid_tag(tag))
+            .cloned()
+            .collect();
+
+        // Tags are plain text in the property table of a parent page
+        if self.parent_page_id.is_some() {
+            return Ok(rejected);
+        }
+
+        let mut database = self.get_database().await?;
+        let existing: Vec<SelectOption> = database
+            .properties
+            .remove("Tags")
+            .and_then(|schema| schema.multi_select)
+            .map(|select| select.options)
+            .unwrap_or_default();
+
+        let new_tags: Vec<&String> = tags
+            .iter()
+            .filter(|tag| is_valid_tag(tag) && !existing.iter().any(|o| &o.name == *tag))
+            .collect();
+
+        if new_tags.is_empty() {
+            debug!("All {} tags already registered", tags.len());
+            return Ok(rejected);
+        }
+
+        // Existing options must be repeated, or Notion drops them
+        let mut options: Vec<serde_json::Value> = existing
+            .iter()
+            .map(|option| json!({ "name": option.name, "color": option.color }))
+            .collect();
+        options.extend(
+            new_tags
+                .iter()
+                .map(|tag| json!({ "name": tag, "color": tag_color(tag) })),
+        );
 
         let update_body = json!({
             "properties": {
-                "PDF Link": {
-                    "url": {}
-                },
                 "Tags": {
                     "multi_select": {
-                        "options": []
+                        "options": options
                     }
-                },
-                "Folder": {
-                    "rich_text": {}
-                },
-                "Created": {
-                    "date": {}
-                },
-                "Last Modified": {
-                    "date": {}
                 }
             }
         });
@@ -125,25 +631,20 @@ impl NotionClient {
             ))
             .headers(self.headers())
             .json(&update_body)
-            .send()
+            .send_limited(&self.limiter)
             .await?;
 
         if !response.status().is_success() {
-            let status = response.status();
-            let body = response.text().await?;
-            warn!(
-                "Failed to update database schema (may already exist): {} - {}",
-                status, body
+            return Err(
+                ApiError::from_response("Notion", "Failed to register tags", response).await,
             );
-        } else {
-            debug!("Database properties ensured");
         }
 
-        Ok(())
+        debug!("Registered {} new tags", new_tags.len());
+        Ok(rejected)
     }
 
-    async fn get_title_property_name(&self) -> Result<String> {
-        // Get database schema to find the title property
+    async fn get_database(&self) -> Result<Database> {
         let response = self
             .client
             .get(format!(
@@ -151,24 +652,31 @@ impl NotionClient {
                 NOTION_API_BASE, self.database_id
             ))
             .headers(self.headers())
-            .send()
+            .send_limited(&self.limiter)
             .await?;
 
         if !response.status().is_success() {
-            return Err(Error::Notion("Failed to get database schema".to_string()));
+            return Err(ApiError::from_response(
+                "Notion",
+                "Failed to get database schema",
+                response,
+            )
+            .await);
         }
 
-        let db_info: serde_json::Value = response.json().await?;
+        models::parse(response, "database").await
+    }
 
-        // Find the title property
-        if let Some(properties) = db_info.get("properties").and_then(|p| p.as_object()) {
-            for (key, value) in properties.iter() {
-                if let Some(prop_type) = value.get("type").and_then(|t| t.as_str()) {
-                    if prop_type == "title" {
-                        return Ok(key.clone());
-                    }
-                }
-            }
+    async fn get_title_property_name(&self) -> Result<String> {
+        if self.parent_page_id.is_some() {
+            return Ok("title".to_string());
+        }
+
+        // Get database schema to find the title property
+        let database = self.get_database().await?;
+
+        if let Some(name) = database.title_property() {
+            return Ok(name.to_string());
         }
 
         Err(Error::Notion(
@@ -179,70 +687,80 @@ impl NotionClient {
     pub async fn find_page_by_title(&self, title: &str) -> Result<Option<NotionPage>> {
         debug!("Searching for page with title: {}", title);
 
-        // Query all pages and filter client-side since we don't know the exact property name
-        let query_body = json!({
-            "page_size": 100
-        });
+        if self.parent_page_id.is_some() {
+            let pages = self.query_pages().await?;
+            return Ok(pages
+                .iter()
+                .filter_map(NotionPage::from_page)
+                .find(|page| page.title == title));
+        }
 
-        let response = self
-            .client
-            .post(format!(
-                "{}/databases/{}/query",
-                NOTION_API_BASE, self.database_id
-            ))
-            .headers(self.headers())
-            .json(&query_body)
-            .send()
-            .await?;
+        // Notion filters on the title, the exact match is checked here
+        let title_prop_name = self.get_title_property_name().await?;
+        let mut start_cursor: Option<String> = None;
+        loop {
+            let mut query_body = json!({
+                "filter": {
+                    "property": title_prop_name,
+                    "title": { "equals": title }
+                },
+                "page_size": 100
+            });
+            if let Some(ref cursor) = start_cursor {
+                query_body["start_cursor"] = json!(cursor);
+            }
 
-        if !response.status().is_success() {
-            let status = response.status();
-            let body = response.text().await?;
-            warn!("Query failed: {} - {}", status, body);
-            return Ok(None);
-        }
+            let response = self
+                .client
+                .post(format!(
+                    "{}/databases/{}/query",
+                    NOTION_API_BASE, self.database_id
+                ))
+                .headers(self.headers())
+                .json(&query_body)
+                .send_limited(&self.limiter)
+                .await?;
 
-        let query_result: QueryResponse = response.json().await?;
-
-        // Search through results for matching title
-        for page in query_result.results {
-            if let Some(props) = page.properties.as_object() {
-                // Look through all properties to find title type
-                for (_key, value) in props.iter() {
-                    if let Some(prop_type) = value.get("type") {
-                        if prop_type == "title" {
-                            if let Some(title_array) = value.get("title").and_then(|t| t.as_array())
-                            {
-                                if let Some(first_title) = title_array.first() {
-                                    if let Some(text_content) =
-                                        first_title.get("plain_text").and_then(|t| t.as_str())
-                                    {
-                                        if text_content == title {
-                                            debug!("Found existing page with ID: {}", page.id);
-                                            return Ok(Some(NotionPage {
-                                                id: page.id.clone(),
-                                                title: title.to_string(),
-                                            }));
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
+            if !response.status().is_success() {
+                return Err(ApiError::from_response("Notion", "Query failed", response).await);
+            }
+
+            let query_result: List<Page> = models::parse(response, "database query").await?;
+
+            // Search through results for matching title
+            for page in query_result.results {
+                if page.title().as_deref() == Some(title) {
+                    debug!("Found existing page with ID: {}", page.id);
+                    return Ok(Some(NotionPage {
+                        id: page.id,
+                        title: title.to_string(),
+                        last_edited_time: page.last_edited_time,
+                    }));
                 }
             }
+
+            match query_result.next_cursor {
+                Some(cursor) if query_result.has_more => start_cursor = Some(cursor),
+                _ => break,
+            }
         }
 
         debug!("No existing page found");
         Ok(None)
     }
 
+    /// Create a page with `content` under the database or parent page.
+    ///
+    /// `write_key` is unique to this write; when the request has to be sent
+    /// again after a timeout, the page it may have created already is looked
+    /// up by it (by title under a parent page) instead of creating another.
     pub async fn create_page(
         &self,
         title: &str,
         content: &str,
         metadata: &NotebookMetadata,
         tags: &[String],
+        write_key: &str,
     ) -> Result<NotionPage> {
         debug!("Creating Notion page: {}", title);
 
@@ -264,9 +782,7 @@ impl NotionClient {
         // Add tags if we have any
         if !tags.is_empty() {
             debug!("Adding {} tags: {:?}", tags.len(), tags);
-            properties["Tags"] = json!({
-                "multi_select": tags.iter().map(|tag| json!({"name": tag})).collect::<Vec<_>>()
-            });
+            properties["Tags"] = tag_options(tags);
         }
 
         // Add creation date if available
@@ -287,6 +803,8 @@ impl NotionClient {
             });
         }
 
+        properties[WRITE_KEY_PROPERTY] = rich_text_value(write_key);
+
         // Add folder if available (empty string for root level)
         properties["Folder"] = json!({
             "rich_text": if metadata.folder_path.is_empty() {
@@ -302,255 +820,351 @@ impl NotionClient {
             }
         });
 
+        let mut children = self.content_blocks(content);
+        let parent = match self.parent_page_id {
+            Some(ref page_id) => {
+                // Everything but the title goes in the property table
+                if let Some(properties) = properties.as_object_mut() {
+                    properties.remove(WRITE_KEY_PROPERTY);
+                }
+                children.insert(0, property_table(&properties));
+                properties = json!({ "title": properties["title"].take() });
+                json!({ "page_id": page_id })
+            }
+            None => json!({ "database_id": self.database_id }),
+        };
+
+        // Create with the first batch of content, append the rest afterwards
+        let rest = children.split_off(children.len().min(self.batch_size));
+        let first_batch = children;
+
         let create_body = json!({
-            "parent": {
-                "database_id": self.database_id
-            },
+            "parent": parent,
             "properties": properties,
-            "children": [
-                {
-                    "object": "block",
-                    "type": "heading_2",
-                    "heading_2": {
-                        "rich_text": [
-                            {
-                                "type": "text",
-                                "text": {
-                                    "content": "OCR Extracted Text"
-                                }
-                            }
-                        ]
-                    }
-                },
-                {
-                    "object": "block",
-                    "type": "paragraph",
-                    "paragraph": {
-                        "rich_text": [
-                            {
-                                "type": "text",
-                                "text": {
-                                    "content": if content.len() > 2000 {
-                                        &content[..2000]
-                                    } else {
-                                        content
-                                    }
-                                }
-                            }
-                        ]
-                    }
-                }
-            ]
+            "children": &first_batch
         });
 
-        let response = self
+        let request = self
             .client
             .post(format!("{}/pages", NOTION_API_BASE))
             .headers(self.headers())
-            .json(&create_body)
-            .send()
-            .await?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let body = response.text().await?;
-            return Err(Error::Notion(format!(
-                "Failed to create page: {} - {}",
-                status, body
-            )));
-        }
+            .json(&create_body);
+        let sent = retry::send_checked(request, &self.limiter, || {
+            self.created_page(title, write_key)
+        })
+        .await?;
+        let page_id = match sent {
+            Sent::Response(response) => {
+                if !response.status().is_success() {
+                    return Err(ApiError::from_response(
+                        "Notion",
+                        "Failed to create page",
+                        response,
+                    )
+                    .await);
+                }
+                let page: Page = models::parse(response, "create page").await?;
+                page.id
+            }
+            // Created with the first batch, the rest still goes below
+            Sent::Applied(page_id) => page_id,
+        };
 
-        let response_json: serde_json::Value = response.json().await?;
-        let page_id = response_json["id"]
-            .as_str()
-            .ok_or_else(|| Error::Notion("No page ID in response".to_string()))?
-            .to_string();
+        self.append_blocks(&page_id, rest, None).await?;
 
         debug!("Created page with ID: {}", page_id);
 
         Ok(NotionPage {
             id: page_id,
             title: title.to_string(),
+            last_edited_time: None,
         })
     }
 
+    /// The page an earlier attempt of the create with `write_key` made
+    async fn created_page(&self, title: &str, write_key: &str) -> Result<Option<String>> {
+        if self.parent_page_id.is_some() {
+            return Ok(self.find_page_by_title(title).await?.map(|page| page.id));
+        }
+
+        let query_body = json!({
+            "filter": {
+                "property": WRITE_KEY_PROPERTY,
+                "rich_text": { "equals": write_key }
+            },
+            "page_size": 1
+        });
+        let response = self
+            .client
+            .post(format!(
+                "{}/databases/{}/query",
+                NOTION_API_BASE, self.database_id
+            ))
+            .headers(self.headers())
+            .json(&query_body)
+            .send_limited(&self.limiter)
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(ApiError::from_response("Notion", "Query failed", response).await);
+        }
+
+        let result: List<Page> = models::parse(response, "database query").await?;
+        Ok(result.results.into_iter().next().map(|page| page.id))
+    }
+
+    /// Update the page properties and replace its content, except for the blocks in `keep`.
+    ///
+    /// With `managed_only` only the blocks the sync writes are replaced (see
+    /// `managed_blocks`); anything added to the page in Notion stays.
     pub async fn update_page(
         &self,
         page_id: &str,
         content: &str,
         metadata: &NotebookMetadata,
         tags: &[String],
-    ) -> Result<()> {
+        keep: &HashSet<String>,
+        managed_only: bool,
+    ) -> Result<PageUpdate> {
         debug!("Updating Notion page: {}", page_id);
 
         // Update properties (tags and folder)
-        let mut properties = json!({});
+        let properties = notebook_properties(metadata, tags);
 
-        // Always update tags (even if empty, to clear old tags)
-        if !tags.is_empty() {
-            debug!("Updating {} tags: {:?}", tags.len(), tags);
-            properties["Tags"] = json!({
-                "multi_select": tags.iter().map(|tag| json!({"name": tag})).collect::<Vec<_>>()
-            });
+        // Send the property updates
+        if self.parent_page_id.is_some() {
+            self.record_properties(page_id, &properties).await?;
         } else {
-            debug!("Clearing tags");
-            properties["Tags"] = json!({
-                "multi_select": []
+            let update_props = json!({
+                "properties": properties
             });
+
+            let response = self
+                .client
+                .patch(format!("{}/pages/{}", NOTION_API_BASE, page_id))
+                .headers(self.headers())
+                .json(&update_props)
+                .send_limited(&self.limiter)
+                .await?;
+
+            if !response.status().is_success() {
+                return Err(ApiError::from_response(
+                    "Notion",
+                    "Failed to update Notion page properties",
+                    response,
+                )
+                .await);
+            }
         }
 
-        // Always update folder (even if empty, to clear old folder when moved to root)
-        let folder_rich_text = if metadata.folder_path.is_empty() {
-            vec![]
-        } else {
-            vec![json!({
-                "text": {
-                    "content": metadata.folder_path
-                }
-            })]
+        // Replace the content, keeping the requested blocks (unchanged images).
+        // The heading stays, so the new text can be inserted above the kept
+        // blocks, and so do the text blocks at the start and end of the text
+        // that didn't change: a notebook page that changed only replaces the
+        // blocks of its own text.
+        let blocks = self.list_blocks(page_id).await?;
+        let heading_id = blocks
+            .iter()
+            .find(|block| block.is_heading(CONTENT_HEADING))
+            .map(|block| block.id.clone());
+        let managed = managed_only.then(|| managed_blocks(&blocks));
+        let paragraphs = self.text_blocks(content).build();
+        let (unchanged_start, unchanged_end) = match heading_id {
+            Some(ref heading_id) => unchanged_text(&blocks, heading_id, &paragraphs),
+            None => (Vec::new(), Vec::new()),
         };
-        properties["Folder"] = json!({
-            "rich_text": folder_rich_text
-        });
 
-        // Update creation date if available
-        if let Some(ref created) = metadata.created_time {
-            properties["Created"] = json!({
-                "date": {
-                    "start": created
-                }
-            });
-        }
+        let mut kept = HashSet::new();
+        for block in &blocks {
+            if Some(&block.id) == heading_id.as_ref()
+                || unchanged_start.contains(&block.id)
+                || unchanged_end.contains(&block.id)
+            {
+                continue;
+            }
+            // The property table of a page under a parent page stays on top
+            if self.parent_page_id.is_some() && block.kind == "table" {
+                continue;
+            }
+            if keep.contains(&block.id) {
+                kept.insert(block.id.clone());
+                continue;
+            }
+            if managed
+                .as_ref()
+                .is_some_and(|managed| !managed.contains(block.id.as_str()))
+            {
+                continue;
+            }
 
-        // Update last modified date if available
-        if let Some(ref modified) = metadata.modified_time {
-            properties["Last Modified"] = json!({
-                "date": {
-                    "start": modified
-                }
-            });
+            self.delete_block(&block.id).await?;
         }
 
-        // Send the property updates
-        let update_props = json!({
-            "properties": properties
-        });
-
-        let response = self
-            .client
-            .patch(format!("{}/pages/{}", NOTION_API_BASE, page_id))
-            .headers(self.headers())
-            .json(&update_props)
-            .send()
-            .await?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let body = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "<failed to read response body>".to_string());
-            return Err(Error::Notion(format!(
-                "Failed to update Notion page properties (HTTP {}): {}",
-                status, body
-            )));
-        }
+        let content_end = match heading_id {
+            Some(heading_id) => {
+                let changed: Vec<serde_json::Value> = paragraphs
+                    [unchanged_start.len()..paragraphs.len() - unchanged_end.len()]
+                    .to_vec();
+                debug!(
+                    "Replacing {} of {} text blocks",
+                    changed.len(),
+                    paragraphs.len()
+                );
+                let anchor = unchanged_start.last().unwrap_or(&heading_id);
+                let ids = self.append_blocks(page_id, changed, Some(anchor)).await?;
+                unchanged_end
+                    .last()
+                    .or(ids.last())
+                    .cloned()
+                    .unwrap_or_else(|| anchor.clone())
+            }
+            None => {
+                kept.clear();
+                let ids = self
+                    .append_blocks(page_id, self.content_blocks(content), None)
+                    .await?;
+                ids.last().cloned().unwrap_or_default()
+            }
+        };
 
-        // Delete all existing blocks (with pagination)
-        let mut has_more = true;
-        let mut cursor: Option<String> = None;
+        debug!("Page updated successfully, kept {} blocks", kept.len());
+        Ok(PageUpdate { kept, content_end })
+    }
 
-        while has_more {
-            let mut url = format!(
-                "{}/blocks/{}/children?page_size=100",
-                NOTION_API_BASE, page_id
-            );
-            if let Some(ref c) = cursor {
-                url = format!("{}&start_cursor={}", url, c);
+    /// Append blocks to a page or block in batches of at most `batch_size`.
+    ///
+    /// Without `after` the blocks go to the end of the parent. With `after`
+    /// they're inserted after that block, and each batch is anchored to the
+    /// last block of the previous one so the original order is preserved.
+    /// Returns the IDs of the created top-level blocks.
+    pub async fn append_blocks(
+        &self,
+        parent_id: &str,
+        blocks: Vec<serde_json::Value>,
+        after: Option<&str>,
+    ) -> Result<Vec<String>> {
+        let mut created_ids = Vec::with_capacity(blocks.len());
+        let mut anchor = after.map(|id| id.to_string());
+
+        for batch in blocks.chunks(self.batch_size) {
+            let mut append_body = json!({
+                "children": batch
+            });
+            if let Some(ref after_id) = anchor {
+                append_body["after"] = json!(after_id);
             }
 
-            let children_response = self.client.get(&url).headers(self.headers()).send().await?;
-
-            if children_response.status().is_success() {
-                let blocks: BlockResponse = children_response.json().await?;
+            // Blocks appended by a timed out attempt are created from here on
+            let since = (chrono::Utc::now() - chrono::Duration::minutes(1))
+                .format("%Y-%m-%dT%H:%M:00.000Z")
+                .to_string();
+            let request = self
+                .client
+                .patch(format!("{}/blocks/{}/children", NOTION_API_BASE, parent_id))
+                .headers(self.headers())
+                .json(&append_body);
+            let sent = retry::send_checked(request, &self.limiter, || {
+                self.appended_blocks(parent_id, batch, anchor.as_deref(), &since)
+            })
+            .await?;
 
-                for block in blocks.results {
-                    if let Some(block_id) = block["id"].as_str() {
-                        // Delete the block (this will also delete its children)
-                        self.client
-                            .delete(format!("{}/blocks/{}", NOTION_API_BASE, block_id))
-                            .headers(self.headers())
-                            .send()
-                            .await?;
+            let batch_ids: Vec<String> = match sent {
+                Sent::Response(response) => {
+                    if !response.status().is_success() {
+                        return Err(ApiError::from_response(
+                            "Notion",
+                            "Failed to append blocks",
+                            response,
+                        )
+                        .await);
                     }
+                    let result: List<Block> = models::parse(response, "append blocks").await?;
+                    result.results.into_iter().map(|block| block.id).collect()
                 }
+                Sent::Applied(ids) => ids,
+            };
 
-                has_more = blocks.has_more;
-                cursor = blocks.next_cursor;
-            } else {
-                has_more = false;
+            if anchor.is_some() {
+                anchor = batch_ids.last().cloned().or(anchor);
             }
+            created_ids.extend(batch_ids);
         }
 
-        let append_body = json!({
-            "children": [
-                {
-                    "object": "block",
-                    "type": "heading_2",
-                    "heading_2": {
-                        "rich_text": [
-                            {
-                                "type": "text",
-                                "text": {
-                                    "content": "OCR Extracted Text"
-                                }
-                            }
-                        ]
-                    }
-                },
-                {
-                    "object": "block",
-                    "type": "paragraph",
-                    "paragraph": {
-                        "rich_text": [
-                            {
-                                "type": "text",
-                                "text": {
-                                    "content": if content.len() > 2000 {
-                                        &content[..2000]
-                                    } else {
-                                        content
-                                    }
-                                }
-                            }
-                        ]
-                    }
+        debug!("Appended {} blocks to {}", created_ids.len(), parent_id);
+        Ok(created_ids)
+    }
+
+    /// IDs of the blocks an earlier attempt of appending `batch` (after
+    /// `after`, or at the end) created: blocks of the same types and text
+    /// (captions for images) in that place, created no earlier than `since`
+    async fn appended_blocks(
+        &self,
+        parent_id: &str,
+        batch: &[serde_json::Value],
+        after: Option<&str>,
+        since: &str,
+    ) -> Result<Option<Vec<String>>> {
+        let children = self.list_blocks(parent_id).await?;
+        let start = match after {
+            Some(after) => match children.iter().position(|block| block.id == after) {
+                Some(index) => index + 1,
+                None => return Ok(None),
+            },
+            None => children.len().saturating_sub(batch.len()),
+        };
+        let Some(candidates) = children.get(start..start + batch.len()) else {
+            return Ok(None);
+        };
+
+        let matches = candidates.iter().zip(batch).all(|(block, sent)| {
+            let created = block
+                .created_time
+                .as_deref()
+                .is_some_and(|time| time >= since);
+            let same = match (blocks::block_text(sent), blocks::image_caption(sent)) {
+                (Some((kind, text)), _) => {
+                    block.kind == kind && block.text().is_none_or(|found| found == text)
                 }
-            ]
+                // An image without a caption could be one appended just
+                // before, so it's sent again rather than guessed
+                (None, Some(caption)) => {
+                    !caption.is_empty()
+                        && block.kind == "image"
+                        && block.image_caption() == Some(caption)
+                }
+                (None, None) => sent["type"].as_str() == Some(block.kind.as_str()),
+            };
+            created && same
         });
+        Ok(matches.then(|| candidates.iter().map(|block| block.id.clone()).collect()))
+    }
 
+    /// Delete a block (this will also delete its children)
+    pub async fn delete_block(&self, block_id: &str) -> Result<()> {
         let response = self
             .client
-            .patch(format!("{}/blocks/{}/children", NOTION_API_BASE, page_id))
+            .delete(format!("{}/blocks/{}", NOTION_API_BASE, block_id))
             .headers(self.headers())
-            .json(&append_body)
-            .send()
+            .send_limited(&self.limiter)
             .await?;
 
         if !response.status().is_success() {
-            let status = response.status();
-            let body = response.text().await?;
-            return Err(Error::Notion(format!(
-                "Failed to update page: {} - {}",
-                status, body
-            )));
+            return Err(
+                ApiError::from_response("Notion", "Failed to delete block", response).await,
+            );
         }
 
-        debug!("Page updated successfully");
         Ok(())
     }
 
-    pub async fn upload_pdf(&self, page_id: &str, pdf_path: &Path) -> Result<()> {
+    /// Mention the PDF on the page when it isn't on Drive, and point the PDF
+    /// Link property at `link` (a local copy) if there is one
+    pub async fn add_pdf_reference(
+        &self,
+        page_id: &str,
+        pdf_path: &Path,
+        link: Option<&str>,
+    ) -> Result<()> {
         debug!("Adding PDF reference to page: {}", page_id);
 
         let pdf_name = pdf_path
@@ -561,60 +1175,52 @@ impl NotionClient {
         // Add a paragraph with PDF reference
         self.add_pdf_text_reference(page_id, pdf_name).await?;
 
-        // Also set the PDF Link property to the local path
-        self.set_pdf_link(page_id, pdf_path).await?;
+        if let Some(link) = link {
+            self.set_pdf_link(page_id, link).await?;
+        }
 
         Ok(())
     }
 
     async fn add_pdf_text_reference(&self, page_id: &str, pdf_name: &str) -> Result<()> {
         let append_body = json!({
-            "children": [
-                {
-                    "object": "block",
-                    "type": "paragraph",
-                    "paragraph": {
-                        "rich_text": [
-                            {
-                                "type": "text",
-                                "text": {
-                                    "content": format!("📎 PDF: {}", pdf_name)
-                                }
-                            }
-                        ]
-                    }
-                }
-            ]
+            "children": BlockBuilder::new()
+                .paragraph(&format!("{}{}", PDF_REFERENCE_PREFIX, pdf_name))
+                .build()
         });
 
         self.client
             .patch(format!("{}/blocks/{}/children", NOTION_API_BASE, page_id))
             .headers(self.headers())
             .json(&append_body)
-            .send()
+            .send_limited(&self.limiter)
             .await?;
 
         Ok(())
     }
 
-    pub async fn set_pdf_link(&self, page_id: &str, pdf_path: &Path) -> Result<()> {
-        // For local file path fallback (used when upload fails)
-        let pdf_path_str = pdf_path.to_string_lossy().to_string();
-
+    async fn set_pdf_link(&self, page_id: &str, link: &str) -> Result<()> {
+        // For local file links (used without Drive or when the upload fails)
         let update_body = json!({
             "properties": {
                 "PDF Link": {
-                    "url": format!("file://{}", pdf_path_str)
+                    "url": link
                 }
             }
         });
 
+        if self.parent_page_id.is_some() {
+            return self
+                .record_properties(page_id, &update_body["properties"])
+                .await;
+        }
+
         let response = self
             .client
             .patch(format!("{}/pages/{}", NOTION_API_BASE, page_id))
             .headers(self.headers())
             .json(&update_body)
-            .send()
+            .send_limited(&self.limiter)
             .await?;
 
         if !response.status().is_success() {
@@ -638,115 +1244,498 @@ impl NotionClient {
             }
         });
 
+        if self.parent_page_id.is_some() {
+            return self
+                .record_properties(page_id, &update_body["properties"])
+                .await;
+        }
+
         let response = self
             .client
             .patch(format!("{}/pages/{}", NOTION_API_BASE, page_id))
             .headers(self.headers())
             .json(&update_body)
-            .send()
+            .send_limited(&self.limiter)
             .await?;
 
         if !response.status().is_success() {
-            let status = response.status();
-            let body = response.text().await?;
-            return Err(crate::error::Error::Notion(format!(
-                "Failed to set PDF link: {} - {}",
-                status, body
-            )));
+            return Err(
+                ApiError::from_response("Notion", "Failed to set PDF link", response).await,
+            );
         }
 
         debug!("PDF Link property updated with URL: {}", pdf_url);
         Ok(())
     }
 
-    /// Upload images directly to Notion storage (not external URLs)
-    pub async fn add_uploaded_images(
+    /// The page whose Source UUID (`SOURCE_ID_PROPERTY`) is `uuid`. Pages
+    /// under a parent page keep it in their property table, so they aren't
+    /// found.
+    pub async fn find_page_by_source_id(&self, uuid: &str) -> Result<Option<NotionPage>> {
+        if self.parent_page_id.is_some() {
+            return Ok(None);
+        }
+
+        let query_body = json!({
+            "filter": {
+                "property": self.source_id_property,
+                "rich_text": { "equals": uuid }
+            },
+            "page_size": 1
+        });
+        let response = self
+            .client
+            .post(format!(
+                "{}/databases/{}/query",
+                NOTION_API_BASE, self.database_id
+            ))
+            .headers(self.headers())
+            .json(&query_body)
+            .send_limited(&self.limiter)
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(ApiError::from_response("Notion", "Query failed", response).await);
+        }
+
+        let result: List<Page> = models::parse(response, "database query").await?;
+        Ok(result.results.iter().find_map(NotionPage::from_page))
+    }
+
+    /// The notebook UUID a queried page was written for, from the Source ID property
+    pub fn page_source_id(&self, page: &Page) -> Option<String> {
+        match page.properties.get(&self.source_id_property) {
+            Some(Property::RichText { rich_text }) if !rich_text.is_empty() => Some(
+                rich_text
+                    .iter()
+                    .map(|text| text.plain_text.as_str())
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Change a page's title, e.g. after its notebook was renamed
+    pub async fn rename_page(&self, page_id: &str, title: &str) -> Result<()> {
+        let title_prop_name = self.get_title_property_name().await?;
+        let update_body = json!({
+            "properties": {
+                title_prop_name: {
+                    "title": [{ "text": { "content": title } }]
+                }
+            }
+        });
+
+        let response = self
+            .client
+            .patch(format!("{}/pages/{}", NOTION_API_BASE, page_id))
+            .headers(self.headers())
+            .json(&update_body)
+            .send_limited(&self.limiter)
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(ApiError::from_response("Notion", "Failed to rename page", response).await);
+        }
+
+        debug!("Renamed {} to '{}'", page_id, title);
+        Ok(())
+    }
+
+    pub async fn get_page(&self, page_id: &str) -> Result<Page> {
+        let response = self
+            .client
+            .get(format!("{}/pages/{}", NOTION_API_BASE, page_id))
+            .headers(self.headers())
+            .send_limited(&self.limiter)
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(ApiError::from_response("Notion", "Failed to get page", response).await);
+        }
+
+        models::parse(response, "page").await
+    }
+
+    /// Names of the tags currently set on a page
+    pub async fn get_page_tags(&self, page_id: &str) -> Result<Vec<String>> {
+        let page = self.get_page(page_id).await?;
+        Ok(page_tags(&page))
+    }
+
+    /// All top-level blocks of a page, following pagination
+    pub async fn list_blocks(&self, page_id: &str) -> Result<Vec<Block>> {
+        let mut blocks = Vec::new();
+        let mut cursor: Option<String> = None;
+
+        loop {
+            let mut url = format!(
+                "{}/blocks/{}/children?page_size=100",
+                NOTION_API_BASE, page_id
+            );
+            if let Some(ref c) = cursor {
+                url = format!("{}&start_cursor={}", url, c);
+            }
+
+            let response = self
+                .client
+                .get(&url)
+                .headers(self.headers())
+                .send_limited(&self.limiter)
+                .await?;
+
+            if !response.status().is_success() {
+                return Err(
+                    ApiError::from_response("Notion", "Failed to list blocks", response).await,
+                );
+            }
+
+            let list: List<Block> = models::parse(response, "block children").await?;
+            blocks.extend(list.results);
+
+            match list.next_cursor {
+                Some(next) if list.has_more => cursor = Some(next),
+                _ => break,
+            }
+        }
+
+        Ok(blocks)
+    }
+
+    pub async fn set_sync_status(&self, page_id: &str, status: SyncStatus) -> Result<()> {
+        let update_body = json!({
+            "properties": {
+                "Sync Status": {
+                    "select": {
+                        "name": status.as_str()
+                    }
+                }
+            }
+        });
+
+        if self.parent_page_id.is_some() {
+            return self
+                .record_properties(page_id, &update_body["properties"])
+                .await;
+        }
+
+        let response = self
+            .client
+            .patch(format!("{}/pages/{}", NOTION_API_BASE, page_id))
+            .headers(self.headers())
+            .json(&update_body)
+            .send_limited(&self.limiter)
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(
+                ApiError::from_response("Notion", "Failed to set sync status", response).await,
+            );
+        }
+
+        debug!("Sync Status set to {}", status.as_str());
+        Ok(())
+    }
+
+    /// Set the "Date" property, e.g. the day of a journal entry
+    pub async fn set_date(&self, page_id: &str, date: &str) -> Result<()> {
+        let update_body = json!({
+            "properties": {
+                "Date": {
+                    "date": {
+                        "start": date
+                    }
+                }
+            }
+        });
+
+        if self.parent_page_id.is_some() {
+            return self
+                .record_properties(page_id, &update_body["properties"])
+                .await;
+        }
+
+        let response = self
+            .client
+            .patch(format!("{}/pages/{}", NOTION_API_BASE, page_id))
+            .headers(self.headers())
+            .json(&update_body)
+            .send_limited(&self.limiter)
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(ApiError::from_response("Notion", "Failed to set date", response).await);
+        }
+
+        debug!("Date property updated: {}", date);
+        Ok(())
+    }
+
+    /// Overwrite the given properties of a page, leaving the others alone
+    pub async fn update_properties(
         &self,
         page_id: &str,
-        image_paths: &[(usize, &Path)],
+        properties: serde_json::Value,
     ) -> Result<()> {
-        if image_paths.is_empty() {
-            return Ok(());
+        let update_body = json!({ "properties": properties });
+
+        if self.parent_page_id.is_some() {
+            return self
+                .record_properties(page_id, &update_body["properties"])
+                .await;
         }
 
-        debug!(
-            "Uploading {} images to Notion: {}",
-            image_paths.len(),
-            page_id
-        );
+        let response = self
+            .client
+            .patch(format!("{}/pages/{}", NOTION_API_BASE, page_id))
+            .headers(self.headers())
+            .json(&update_body)
+            .send_limited(&self.limiter)
+            .await?;
 
-        let mut children = Vec::new();
-
-        for (page_num, image_path) in image_paths {
-            match self.upload_file_to_notion(image_path).await {
-                Ok(file_id) => {
-                    children.push(json!({
-                        "object": "block",
-                        "type": "image",
-                        "image": {
-                            "type": "file_upload",
-                            "file_upload": {
-                                "id": file_id
-                            },
-                            "caption": [
-                                {
-                                    "type": "text",
-                                    "text": {
-                                        "content": format!("Page {}", page_num)
-                                    }
-                                }
-                            ]
-                        }
-                    }));
-                }
-                Err(e) => {
-                    warn!("Failed to upload image {}: {}", page_num, e);
+        if !response.status().is_success() {
+            return Err(
+                ApiError::from_response("Notion", "Failed to update properties", response).await,
+            );
+        }
+
+        debug!("Properties updated on {}", page_id);
+        Ok(())
+    }
+
+    /// Update the tags, folder and dates of a page without touching its content
+    pub async fn update_notebook_properties(
+        &self,
+        page_id: &str,
+        metadata: &NotebookMetadata,
+        tags: &[String],
+    ) -> Result<()> {
+        self.update_properties(page_id, notebook_properties(metadata, tags))
+            .await
+    }
+
+    /// Set the Source UUID (or `SOURCE_ID_PROPERTY`), Folder, Sync Hash and
+    /// Page Count properties
+    pub async fn set_source_properties(
+        &self,
+        page_id: &str,
+        source: &SourceProperties,
+    ) -> Result<()> {
+        self.update_properties(
+            page_id,
+            serde_json::Value::Object(source.values(&self.source_id_property)),
+        )
+        .await
+    }
+
+    /// Point a relation property at the given pages
+    pub async fn set_relation(
+        &self,
+        page_id: &str,
+        property: &str,
+        related_page_ids: &[String],
+    ) -> Result<()> {
+        let update_body = json!({
+            "properties": {
+                property: {
+                    "relation": related_page_ids.iter().map(|id| json!({"id": id})).collect::<Vec<_>>()
                 }
             }
+        });
+
+        if self.parent_page_id.is_some() {
+            return self
+                .record_properties(page_id, &update_body["properties"])
+                .await;
         }
 
-        if children.is_empty() {
-            return Ok(());
+        let response = self
+            .client
+            .patch(format!("{}/pages/{}", NOTION_API_BASE, page_id))
+            .headers(self.headers())
+            .json(&update_body)
+            .send_limited(&self.limiter)
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(ApiError::from_response(
+                "Notion",
+                format!("Failed to set relation '{}'", property),
+                response,
+            )
+            .await);
         }
 
-        let append_body = json!({
-            "children": children
+        debug!("Relation '{}' set to {:?}", property, related_page_ids);
+        Ok(())
+    }
+
+    /// Set the "Attendees" property of a meeting page
+    pub async fn set_attendees(&self, page_id: &str, attendees: &[String]) -> Result<()> {
+        let update_body = json!({
+            "properties": {
+                "Attendees": {
+                    "multi_select": attendees.iter().map(|name| json!({"name": name})).collect::<Vec<_>>()
+                }
+            }
         });
 
+        if self.parent_page_id.is_some() {
+            return self
+                .record_properties(page_id, &update_body["properties"])
+                .await;
+        }
+
         let response = self
             .client
-            .patch(format!("{}/blocks/{}/children", NOTION_API_BASE, page_id))
-            .header("Notion-Version", NOTION_API_VERSION)
-            .bearer_auth(&self.token)
-            .json(&append_body)
-            .send()
+            .patch(format!("{}/pages/{}", NOTION_API_BASE, page_id))
+            .headers(self.headers())
+            .json(&update_body)
+            .send_limited(&self.limiter)
             .await?;
 
         if !response.status().is_success() {
-            let status = response.status();
-            let body = response.text().await?;
-            return Err(Error::Io(std::io::Error::other(format!(
-                "Failed to add uploaded images: {} - {}",
-                status, body
-            ))));
+            return Err(
+                ApiError::from_response("Notion", "Failed to set attendees", response).await,
+            );
+        }
+
+        debug!("Attendees property updated: {:?}", attendees);
+        Ok(())
+    }
+
+    /// Append an "Action Items" heading followed by a to-do block per item
+    pub async fn add_action_items(&self, page_id: &str, items: &[String]) -> Result<()> {
+        if items.is_empty() {
+            return Ok(());
         }
 
-        debug!("Added {} uploaded images to page", children.len());
+        self.append_blocks(page_id, self.action_item_blocks(items), None)
+            .await?;
+
+        debug!("Added {} action items", items.len());
         Ok(())
     }
 
+    /// The "Action Items" heading followed by a to-do per item
+    pub fn action_item_blocks(&self, items: &[String]) -> Vec<serde_json::Value> {
+        let mut children =
+            BlockBuilder::new().heading(self.layout.action_items_heading, ACTION_ITEMS_HEADING);
+        for item in items {
+            children = children.todo(item, false);
+        }
+        children.build()
+    }
+
+    /// The "OCR Extracted Text" heading followed by the text
+    pub fn content_blocks(&self, content: &str) -> Vec<serde_json::Value> {
+        BlockBuilder::new()
+            .heading(self.layout.content_heading, CONTENT_HEADING)
+            .extend(self.text_blocks(content))
+            .build()
+    }
+
+    /// The text as paragraphs, per notebook page under a heading or in a
+    /// toggle when the layout asks for it
+    fn text_blocks(&self, content: &str) -> BlockBuilder {
+        let layout = &self.layout;
+        if layout.page_headings.is_none() && !layout.page_toggles {
+            return BlockBuilder::new().paragraphs(content);
+        }
+
+        let mut builder = BlockBuilder::new();
+        for (label, text) in page_sections(content) {
+            let paragraphs = BlockBuilder::new().paragraphs(&text);
+            builder = match (layout.page_headings, layout.page_toggles) {
+                (Some(level), true) => builder.toggle_heading(level, &label, paragraphs),
+                (Some(level), false) => builder.heading(level, &label).extend(paragraphs),
+                (None, _) => builder.toggle(&label, paragraphs),
+            };
+        }
+        builder
+    }
+
+    /// Text of the top-level blocks `text_blocks` produces for the given
+    /// text, for checking what Notion stored
+    pub fn content_chunks(&self, content: &str) -> Vec<String> {
+        let layout = &self.layout;
+        if layout.page_headings.is_none() && !layout.page_toggles {
+            return blocks::text_chunks(content, blocks::MAX_TEXT_LENGTH);
+        }
+
+        let mut chunks = Vec::new();
+        for (label, text) in page_sections(content) {
+            chunks.push(label);
+            if !layout.page_toggles {
+                chunks.extend(blocks::text_chunks(&text, blocks::MAX_TEXT_LENGTH));
+            }
+        }
+        chunks
+    }
+
+    /// Upload images directly to Notion storage (not external URLs).
+    ///
+    /// Images are given as (page number, path, caption). The image blocks are
+    /// inserted after `after`, or at the end of the page. Images that fail to
+    /// upload are handled per `error_policy`. Returns the page number and
+    /// block ID of every image that was added.
+    pub async fn add_uploaded_images(
+        &self,
+        page_id: &str,
+        image_paths: &[(usize, &Path, &str)],
+        after: Option<&str>,
+        error_policy: ErrorPolicy,
+    ) -> Result<Vec<(usize, String)>> {
+        if image_paths.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        debug!(
+            "Uploading {} images to Notion: {}",
+            image_paths.len(),
+            page_id
+        );
+
+        let mut children = BlockBuilder::new();
+        let mut page_nums = Vec::new();
+
+        for (page_num, image_path, caption) in image_paths {
+            let what = format!("Upload of image {}", page_num);
+            if let Some(file_id) = error_policy
+                .run(&what, || self.upload_file_to_notion(image_path))
+                .await?
+            {
+                children = children.uploaded_image(&file_id, caption);
+                page_nums.push(*page_num);
+            }
+        }
+
+        if children.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let block_ids = self.append_blocks(page_id, children.build(), after).await?;
+
+        debug!("Added {} uploaded images to page", block_ids.len());
+        Ok(page_nums.into_iter().zip(block_ids).collect())
+    }
+
     /// Upload a file directly to Notion and return its file ID
     async fn upload_file_to_notion(&self, file_path: &Path) -> Result<String> {
         let filename = file_path
             .file_name()
             .and_then(|n| n.to_str())
             .unwrap_or("image.png");
+        let content_type = match file_path.extension().and_then(|e| e.to_str()) {
+            Some("svg") => "image/svg+xml",
+            _ => "image/png",
+        };
 
         // Step 1: Create file upload
         let create_body = json!({
             "mode": "single_part",
             "filename": filename,
-            "content_type": "image/png"
+            "content_type": content_type
         });
 
         debug!("Creating file upload for: {}", filename);
@@ -757,26 +1746,23 @@ impl NotionClient {
             .header("Notion-Version", "2025-09-03") // File upload API requires newer version
             .bearer_auth(&self.token)
             .json(&create_body)
-            .send()
+            .send_limited(&self.limiter)
             .await?;
 
         if !create_response.status().is_success() {
-            let status = create_response.status();
-            let body = create_response.text().await?;
-            return Err(Error::Io(std::io::Error::other(format!(
-                "Failed to create file upload: {} - {}",
-                status, body
-            ))));
+            return Err(ApiError::from_response(
+                "Notion",
+                "Failed to create file upload",
+                create_response,
+            )
+            .await);
         }
 
-        let create_result: serde_json::Value = create_response.json().await?;
-        let file_id = create_result["id"]
-            .as_str()
-            .ok_or_else(|| Error::Io(std::io::Error::other("No file ID in create response")))?
-            .to_string();
+        let upload: FileUpload = models::parse(create_response, "file upload").await?;
+        let file_id = upload.id;
 
-        let upload_url = create_result["upload_url"]
-            .as_str()
+        let upload_url = upload
+            .upload_url
             .ok_or_else(|| Error::Io(std::io::Error::other("No upload_url in create response")))?;
 
         // Step 2: Upload file data
@@ -784,28 +1770,32 @@ impl NotionClient {
 
         let file_bytes = tokio::fs::read(file_path).await?;
 
-        let file_part = reqwest::multipart::Part::bytes(file_bytes)
-            .file_name(filename.to_string())
-            .mime_str("image/png")?;
+        let upload_response = retry::send_with_limit(
+            || {
+                let file_part = reqwest::multipart::Part::bytes(file_bytes.clone())
+                    .file_name(filename.to_string())
+                    .mime_str(content_type)?;
 
-        let form = reqwest::multipart::Form::new().part("file", file_part);
+                let form = reqwest::multipart::Form::new().part("file", file_part);
 
-        let upload_response = self
-            .client
-            .post(upload_url)
-            .header("Notion-Version", "2025-09-03") // File upload API requires newer version
-            .bearer_auth(&self.token)
-            .multipart(form)
-            .send()
-            .await?;
+                Ok(self
+                    .client
+                    .post(&upload_url)
+                    .header("Notion-Version", "2025-09-03") // File upload API requires newer version
+                    .bearer_auth(&self.token)
+                    .multipart(form))
+            },
+            Some(&self.limiter),
+        )
+        .await?;
 
         if !upload_response.status().is_success() {
-            let status = upload_response.status();
-            let body = upload_response.text().await?;
-            return Err(Error::Io(std::io::Error::other(format!(
-                "Failed to upload file data: {} - {}",
-                status, body
-            ))));
+            return Err(ApiError::from_response(
+                "Notion",
+                "Failed to upload file data",
+                upload_response,
+            )
+            .await);
         }
 
         debug!("File uploaded successfully: {}", file_id);
@@ -814,6 +1804,21 @@ impl NotionClient {
     }
 
     pub async fn get_all_pages(&self) -> Result<Vec<NotionPage>> {
+        let pages = self.query_pages().await?;
+        Ok(pages.iter().filter_map(NotionPage::from_page).collect())
+    }
+
+    /// Every page in the database with all its properties, or the child
+    /// pages of the parent page with just their titles
+    pub async fn query_pages(&self) -> Result<Vec<Page>> {
+        if let Some(ref page_id) = self.parent_page_id {
+            debug!("Listing child pages of parent page");
+            let blocks = self.list_blocks(page_id).await?;
+            let pages: Vec<Page> = blocks.iter().filter_map(Block::as_page).collect();
+            debug!("Found {} total pages", pages.len());
+            return Ok(pages);
+        }
+
         debug!("Querying all pages from database");
 
         let mut all_pages = Vec::new();
@@ -837,66 +1842,177 @@ impl NotionClient {
                 ))
                 .headers(self.headers())
                 .json(&query_body)
-                .send()
+                .send_limited(&self.limiter)
                 .await?;
 
             if !response.status().is_success() {
-                let status = response.status();
-                let body = response.text().await?;
-                return Err(Error::Notion(format!(
-                    "Query failed: {} - {}",
-                    status, body
-                )));
-            }
-
-            let query_result: serde_json::Value = response.json().await?;
-
-            has_more = query_result
-                .get("has_more")
-                .and_then(|v| v.as_bool())
-                .unwrap_or(false);
-            start_cursor = query_result
-                .get("next_cursor")
-                .and_then(|v| v.as_str())
-                .map(|s| s.to_string());
-
-            if let Some(results) = query_result.get("results").and_then(|r| r.as_array()) {
-                for page in results {
-                    if let Some(page_id) = page.get("id").and_then(|id| id.as_str()) {
-                        if let Some(props) = page.get("properties").and_then(|p| p.as_object()) {
-                            // Look through all properties to find title type
-                            for (_key, value) in props.iter() {
-                                if let Some(prop_type) = value.get("type") {
-                                    if prop_type == "title" {
-                                        if let Some(title_array) =
-                                            value.get("title").and_then(|t| t.as_array())
-                                        {
-                                            if let Some(first_title) = title_array.first() {
-                                                if let Some(text_content) = first_title
-                                                    .get("plain_text")
-                                                    .and_then(|t| t.as_str())
-                                                {
-                                                    all_pages.push(NotionPage {
-                                                        id: page_id.to_string(),
-                                                        title: text_content.to_string(),
-                                                    });
-                                                    break;
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
+                return Err(ApiError::from_response("Notion", "Query failed", response).await);
             }
+
+            let query_result: List<Page> = models::parse(response, "database query").await?;
+
+            has_more = query_result.has_more;
+            start_cursor = query_result.next_cursor;
+
+            all_pages.extend(query_result.results);
         }
 
         debug!("Found {} total pages", all_pages.len());
         Ok(all_pages)
     }
 
+    async fn search_databases(&self) -> Result<Vec<Database>> {
+        debug!("Searching for databases shared with the integration");
+
+        let mut databases = Vec::new();
+        let mut has_more = true;
+        let mut start_cursor: Option<String> = None;
+
+        while has_more {
+            let mut search_body = json!({
+                "filter": {
+                    "property": "object",
+                    "value": "database"
+                },
+                "page_size": 100
+            });
+
+            if let Some(ref cursor) = start_cursor {
+                search_body["start_cursor"] = json!(cursor);
+            }
+
+            let response = self
+                .client
+                .post(format!("{}/search", NOTION_API_BASE))
+                .headers(self.headers())
+                .json(&search_body)
+                .send_limited(&self.limiter)
+                .await?;
+
+            if !response.status().is_success() {
+                return Err(ApiError::from_response("Notion", "Search failed", response).await);
+            }
+
+            let result: List<Database> = models::parse(response, "search").await?;
+            has_more = result.has_more;
+            start_cursor = result.next_cursor;
+            databases.extend(result.results);
+        }
+
+        debug!("Found {} databases", databases.len());
+        Ok(databases)
+    }
+
+    /// Write property values to the property table of a page under a parent
+    /// page, updating the rows that exist and adding the others
+    async fn record_properties(&self, page_id: &str, properties: &serde_json::Value) -> Result<()> {
+        let rows = property_rows(properties);
+        if rows.is_empty() {
+            return Ok(());
+        }
+
+        let blocks = self.list_blocks(page_id).await?;
+        let Some(table) = blocks.iter().find(|block| block.kind == "table") else {
+            // The table was removed in Notion; put a new one at the end
+            self.append_blocks(page_id, vec![property_table(properties)], None)
+                .await?;
+            return Ok(());
+        };
+
+        let existing = self.list_blocks(&table.id).await?;
+        let mut new_rows = Vec::new();
+        for row in rows {
+            let current = existing
+                .iter()
+                .find(|block| block.cells().first() == row.first());
+            match current {
+                Some(current) if current.cells() == row => {}
+                Some(current) => {
+                    let response = self
+                        .client
+                        .patch(format!("{}/blocks/{}", NOTION_API_BASE, current.id))
+                        .headers(self.headers())
+                        .json(&blocks::table_row(&row))
+                        .send_limited(&self.limiter)
+                        .await?;
+
+                    if !response.status().is_success() {
+                        return Err(ApiError::from_response(
+                            "Notion",
+                            "Failed to update property table",
+                            response,
+                        )
+                        .await);
+                    }
+                }
+                None => new_rows.push(blocks::table_row(&row)),
+            }
+        }
+        if !new_rows.is_empty() {
+            self.append_blocks(&table.id, new_rows, None).await?;
+        }
+
+        debug!("Property table updated on {}", page_id);
+        Ok(())
+    }
+
+    /// Take a page out of Notion's trash
+    pub async fn restore_page(&self, page_id: &str) -> Result<()> {
+        let response = self
+            .client
+            .patch(format!("{}/pages/{}", NOTION_API_BASE, page_id))
+            .headers(self.headers())
+            .json(&json!({ "archived": false }))
+            .send_limited(&self.limiter)
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(
+                ApiError::from_response("Notion", "Failed to restore page", response).await,
+            );
+        }
+
+        debug!("Page restored");
+        Ok(())
+    }
+
+    /// Put a warning callout about an incomplete sync below the content
+    /// heading, where the next update of the page removes it again
+    pub async fn add_sync_warning(&self, page_id: &str, text: &str) -> Result<()> {
+        let blocks = self.list_blocks(page_id).await?;
+        let heading = blocks
+            .iter()
+            .find(|block| block.is_heading(CONTENT_HEADING))
+            .map(|block| block.id.as_str());
+        let callout = BlockBuilder::new()
+            .callout("⚠️", &format!("{}{}", SYNC_WARNING_PREFIX, text))
+            .build();
+        self.append_blocks(page_id, callout, heading).await?;
+        Ok(())
+    }
+
+    /// Add a comment to the page; the integration needs the "Insert
+    /// comments" capability
+    pub async fn add_comment(&self, page_id: &str, text: &str) -> Result<()> {
+        let response = self
+            .client
+            .post(format!("{}/comments", NOTION_API_BASE))
+            .headers(self.headers())
+            .json(&json!({
+                "parent": { "page_id": page_id },
+                "rich_text": [{ "text": { "content": text } }]
+            }))
+            .send_limited(&self.limiter)
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(ApiError::from_response("Notion", "Failed to add comment", response).await);
+        }
+
+        debug!("Comment added to page {}", page_id);
+        Ok(())
+    }
+
     pub async fn delete_page(&self, page_id: &str) -> Result<()> {
         debug!("Deleting page: {}", page_id);
 
@@ -909,19 +2025,452 @@ impl NotionClient {
             .patch(format!("{}/pages/{}", NOTION_API_BASE, page_id))
             .headers(self.headers())
             .json(&update_props)
-            .send()
+            .send_limited(&self.limiter)
             .await?;
 
         if !response.status().is_success() {
-            let status = response.status();
-            let body = response.text().await?;
-            return Err(Error::Notion(format!(
-                "Failed to delete page: {} - {}",
-                status, body
-            )));
+            return Err(ApiError::from_response("Notion", "Failed to delete page", response).await);
         }
 
         debug!("Page deleted");
         Ok(())
     }
 }
+
+/// Databases the integration has been given access to, via the search endpoint
+pub async fn list_databases(token: &str) -> Result<Vec<Database>> {
+    // Search isn't scoped to a database, so any ID will do
+    let notion = NotionClient::new(token.to_string(), String::new());
+    notion.search_databases().await
+}
+
+/// Names of the options selected in a page's Tags property
+pub fn page_tags(page: &Page) -> Vec<String> {
+    match page.properties.get("Tags") {
+        Some(Property::MultiSelect { multi_select }) => {
+            multi_select.iter().map(|o| o.name.clone()).collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Name of the option selected in a page's Sync Status property
+pub fn page_sync_status(page: &Page) -> Option<&str> {
+    match page.properties.get("Sync Status") {
+        Some(Property::Select { select }) => select.as_ref().map(|o| o.name.as_str()),
+        _ => None,
+    }
+}
+
+/// IDs of the blocks the sync writes: the "OCR Extracted Text" and "Action
+/// Items" sections, the page images and the PDF reference
+pub fn managed_blocks(blocks: &[Block]) -> HashSet<&str> {
+    let mut managed = HashSet::new();
+    // Whether the previous block was part of a section, and which
+    let mut in_content = false;
+    let mut in_action_items = false;
+    for block in blocks {
+        let text = block.text().unwrap_or_default();
+        let is_pdf_reference = block.kind == "paragraph" && text.starts_with(PDF_REFERENCE_PREFIX);
+
+        if block.is_heading(CONTENT_HEADING) || block.is_heading(ACTION_ITEMS_HEADING) {
+            in_content = block.is_heading(CONTENT_HEADING);
+            in_action_items = !in_content;
+            managed.insert(block.id.as_str());
+            continue;
+        }
+        let in_section =
+            (in_content && (block.is_text() || is_sync_warning(block)) && !is_pdf_reference)
+                || (in_action_items && block.kind == "to_do");
+        if !in_section {
+            in_content = false;
+            in_action_items = false;
+        }
+
+        let is_page_image = block.kind == "image"
+            && block
+                .caption()
+                .is_some_and(|caption| caption.starts_with("Page "));
+        if in_section || is_pdf_reference || is_page_image {
+            managed.insert(block.id.as_str());
+        }
+    }
+    managed
+}
+
+/// IDs of the text blocks below the content heading that are the same as
+/// the first and the last of `paragraphs`, in page order
+fn unchanged_text(
+    blocks: &[Block],
+    heading_id: &str,
+    paragraphs: &[serde_json::Value],
+) -> (Vec<String>, Vec<String>) {
+    let section: Vec<&Block> = blocks
+        .iter()
+        .skip_while(|block| block.id != heading_id)
+        .skip(1)
+        .filter(|block| !is_sync_warning(block))
+        .take_while(|block| {
+            block.is_text()
+                && !block
+                    .text()
+                    .unwrap_or_default()
+                    .starts_with(PDF_REFERENCE_PREFIX)
+        })
+        .collect();
+    let same = |block: &Block, paragraph: &serde_json::Value| {
+        !block.has_children
+            && blocks::block_text(paragraph)
+                .is_some_and(|(kind, text)| block.kind == kind && block.text() == Some(text))
+    };
+
+    let start = section
+        .iter()
+        .zip(paragraphs)
+        .take_while(|(block, paragraph)| same(block, paragraph))
+        .count();
+    let end = section[start..]
+        .iter()
+        .rev()
+        .zip(paragraphs[start..].iter().rev())
+        .take_while(|(block, paragraph)| same(block, paragraph))
+        .count();
+    let ids = |blocks: &[&Block]| blocks.iter().map(|block| block.id.clone()).collect();
+    (ids(&section[..start]), ids(&section[section.len() - end..]))
+}
+
+/// A way the database differs from the properties the sync writes
+#[derive(Debug)]
+pub enum SchemaIssue {
+    /// The property doesn't exist
+    Missing { name: String, kind: String },
+    /// A property of another type has the name; converting it could lose
+    /// data, so it's left to the user
+    WrongType {
+        name: String,
+        expected: String,
+        actual: String,
+    },
+    /// A relation property points at another database than configured;
+    /// changing it could drop the other side of a two-way relation
+    WrongRelation {
+        name: String,
+        expected: String,
+        actual: String,
+    },
+    /// The property is missing, but one differing only in case or spacing
+    /// exists; fixed by renaming that one if it has the right type
+    Conflict {
+        name: String,
+        existing: String,
+        same_type: bool,
+    },
+    /// Options the sync sets are missing from a select property
+    MissingOptions {
+        name: String,
+        kind: String,
+        existing: Vec<serde_json::Value>,
+        missing: Vec<serde_json::Value>,
+    },
+}
+
+impl SchemaIssue {
+    pub fn name(&self) -> &str {
+        match self {
+            SchemaIssue::Missing { name, .. }
+            | SchemaIssue::WrongType { name, .. }
+            | SchemaIssue::WrongRelation { name, .. }
+            | SchemaIssue::Conflict { name, .. }
+            | SchemaIssue::MissingOptions { name, .. } => name,
+        }
+    }
+
+    /// Whether `fix_schema` can fix it
+    pub fn is_fixable(&self) -> bool {
+        match self {
+            SchemaIssue::WrongType { .. } | SchemaIssue::WrongRelation { .. } => false,
+            SchemaIssue::Conflict { same_type, .. } => *same_type,
+            _ => true,
+        }
+    }
+}
+
+impl std::fmt::Display for SchemaIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SchemaIssue::Missing { name, kind } => {
+                write!(f, "'{}' is missing (add it as {})", name, kind)
+            }
+            SchemaIssue::WrongType {
+                name,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "'{}' is {}, the sync writes {} (rename or change it in Notion)",
+                name, actual, expected
+            ),
+            SchemaIssue::WrongRelation {
+                name,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "'{}' relates to database {}, the config says {} (change it in Notion or the config)",
+                name, actual, expected
+            ),
+            SchemaIssue::Conflict {
+                name,
+                existing,
+                same_type: true,
+            } => write!(f, "'{}' is missing, but '{}' exists (rename it)", name, existing),
+            SchemaIssue::Conflict { name, existing, .. } => write!(
+                f,
+                "'{}' is missing, and '{}' of another type is in the way (rename or remove it in Notion)",
+                name, existing
+            ),
+            SchemaIssue::MissingOptions { name, missing, .. } => {
+                let names: Vec<&str> = missing
+                    .iter()
+                    .filter_map(|option| option["name"].as_str())
+                    .collect();
+                write!(f, "'{}' lacks the options {} (add them)", name, names.join(", "))
+            }
+        }
+    }
+}
+
+/// Whether two Notion IDs are the same, with or without dashes
+fn same_id(a: &str, b: &str) -> bool {
+    a.replace('-', "").eq_ignore_ascii_case(&b.replace('-', ""))
+}
+
+/// Properties the sync writes, as a schema update that adds them
+fn schema_properties(id_property: &str) -> serde_json::Value {
+    let mut schema = json!({
+        "properties": {
+            "PDF Link": {
+                "url": {}
+            },
+            "Tags": {
+                "multi_select": {
+                    "options": []
+                }
+            },
+            "Folder": {
+                "rich_text": {}
+            },
+            "Created": {
+                "date": {}
+            },
+            "Last Modified": {
+                "date": {}
+            },
+            "Date": {
+                "date": {}
+            },
+            "Attendees": {
+                "multi_select": {
+                    "options": []
+                }
+            },
+            "Sync Hash": {
+                "rich_text": {}
+            },
+            "Page Count": {
+                "number": {}
+            },
+            "PDF Size (MB)": {
+                "number": {}
+            },
+            "Document Type": {
+                "select": {
+                    "options": [
+                        { "name": "Notebook", "color": "default" },
+                        { "name": "PDF", "color": "blue" },
+                        { "name": "EPUB", "color": "purple" },
+                        { "name": "Annotated PDF", "color": "orange" }
+                    ]
+                }
+            },
+            WRITE_KEY_PROPERTY: {
+                "rich_text": {}
+            },
+            "Sync Status": {
+                "select": {
+                    "options": [
+                        { "name": SyncStatus::Synced.as_str(), "color": "green" },
+                        { "name": SyncStatus::Stale.as_str(), "color": "yellow" },
+                        { "name": SyncStatus::Failed.as_str(), "color": "red" },
+                        { "name": SyncStatus::Archived.as_str(), "color": "gray" },
+                        { "name": SyncStatus::Queued.as_str(), "color": "blue" }
+                    ]
+                }
+            }
+        }
+    });
+    schema["properties"][id_property] = json!({ "rich_text": {} });
+    schema
+}
+
+/// Whether the block is the callout `add_sync_warning` puts below the
+/// content heading
+pub fn is_sync_warning(block: &Block) -> bool {
+    block.kind == "callout"
+        && block
+            .text()
+            .is_some_and(|text| text.starts_with(SYNC_WARNING_PREFIX))
+}
+
+/// Table block listing property values, for pages under a parent page
+fn property_table(properties: &serde_json::Value) -> serde_json::Value {
+    let mut rows = vec![vec!["Property".to_string(), "Value".to_string()]];
+    rows.extend(property_rows(properties));
+    BlockBuilder::new().table(&rows).build().remove(0)
+}
+
+/// (name, value) rows for property values, leaving out the title
+fn property_rows(properties: &serde_json::Value) -> Vec<Vec<String>> {
+    properties
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter(|(_, value)| value.get("title").is_none())
+        .map(|(name, value)| vec![name.clone(), property_text(value)])
+        .collect()
+}
+
+/// A property value as text: options and relations comma-separated, dates
+/// as their start
+fn property_text(value: &serde_json::Value) -> String {
+    let Some((kind, value)) = value.as_object().and_then(|object| object.iter().next()) else {
+        return String::new();
+    };
+    let joined = |pointer: &str| {
+        value
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|item| item.pointer(pointer).and_then(|v| v.as_str()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    match kind.as_str() {
+        "rich_text" => joined("/text/content"),
+        "multi_select" => joined("/name"),
+        "relation" => joined("/id"),
+        "select" => value["name"].as_str().unwrap_or_default().to_string(),
+        "date" => value["start"].as_str().unwrap_or_default().to_string(),
+        "url" => value.as_str().unwrap_or_default().to_string(),
+        "number" if !value.is_null() => value.to_string(),
+        _ => String::new(),
+    }
+}
+
+/// Split OCR text at its "--- Page N ---" separators into (label, text)
+/// pairs, the label being e.g. "Page 2" or "Page 2 · 2024-05-02"
+fn page_sections(content: &str) -> Vec<(String, String)> {
+    let mut sections = vec![("Page 1".to_string(), Vec::new())];
+    for line in content.split('\n') {
+        let label = line
+            .strip_prefix("--- ")
+            .and_then(|rest| rest.strip_suffix(" ---"))
+            .filter(|label| label.starts_with("Page "));
+        match label {
+            Some(label) => sections.push((label.to_string(), Vec::new())),
+            None => sections.last_mut().unwrap().1.push(line),
+        }
+    }
+    sections
+        .into_iter()
+        .map(|(label, lines)| (label, lines.join("\n").trim().to_string()))
+        .filter(|(_, text)| !text.is_empty())
+        .collect()
+}
+
+/// Notion rejects option names containing commas, and caps them at 100 characters
+pub fn is_valid_tag(tag: &str) -> bool {
+    !tag.trim().is_empty() && !tag.contains(',') && tag.chars().count() <= 100
+}
+
+/// Rich text property value holding `text`, or clearing it when empty
+pub fn rich_text_value(text: &str) -> serde_json::Value {
+    if text.is_empty() {
+        json!({ "rich_text": [] })
+    } else {
+        json!({ "rich_text": [{ "text": { "content": text } }] })
+    }
+}
+
+/// Tags, Folder, Created and Last Modified values for a notebook's page;
+/// empty tags and folder clear the old ones
+fn notebook_properties(metadata: &NotebookMetadata, tags: &[String]) -> serde_json::Value {
+    let mut properties = json!({});
+
+    // Always update tags (even if empty, to clear old tags)
+    if !tags.is_empty() {
+        debug!("Updating {} tags: {:?}", tags.len(), tags);
+        properties["Tags"] = tag_options(tags);
+    } else {
+        debug!("Clearing tags");
+        properties["Tags"] = json!({
+            "multi_select": []
+        });
+    }
+
+    // Always update folder (even if empty, to clear old folder when moved to root)
+    let folder_rich_text = if metadata.folder_path.is_empty() {
+        vec![]
+    } else {
+        vec![json!({
+            "text": {
+                "content": metadata.folder_path
+            }
+        })]
+    };
+    properties["Folder"] = json!({
+        "rich_text": folder_rich_text
+    });
+
+    // Update creation date if available
+    if let Some(ref created) = metadata.created_time {
+        properties["Created"] = json!({
+            "date": {
+                "start": created
+            }
+        });
+    }
+
+    // Update last modified date if available
+    if let Some(ref modified) = metadata.modified_time {
+        properties["Last Modified"] = json!({
+            "date": {
+                "start": modified
+            }
+        });
+    }
+
+    properties
+}
+
+/// Multi-select value for the Tags property, leaving out tags Notion would reject
+fn tag_options(tags: &[String]) -> serde_json::Value {
+    json!({
+        "multi_select": tags
+            .iter()
+            .filter(|tag| is_valid_tag(tag))
+            .map(|tag| json!({"name": tag}))
+            .collect::<Vec<_>>()
+    })
+}
+
+/// A color derived from the tag name, so a tag keeps its color across databases
+fn tag_color(tag: &str) -> &'static str {
+    const COLORS: [&str; 9] = [
+        "gray", "brown", "orange", "yellow", "green", "blue", "purple", "pink", "red",
+    ];
+    let hash = tag.bytes().fold(0usize, |acc, b| {
+        acc.wrapping_mul(31).wrapping_add(b as usize)
+    });
+    COLORS[hash % COLORS.len()]
+}
@@ -0,0 +1,287 @@
+// Not every block type is used by the sync itself
+#![allow(dead_code)]
+
+use serde_json::{json, Value};
+
+/// Maximum length of a single rich text object
+pub const MAX_TEXT_LENGTH: usize = 2000;
+
+/// Composes Notion block payloads for create/append requests.
+///
+/// Text longer than Notion's 2000-character limit is split into several
+/// blocks, so callers never have to think about it.
+///
+/// ```ignore
+/// let blocks = BlockBuilder::new()
+///     .heading(2, "OCR Extracted Text")
+///     .paragraphs(&text)
+///     .todo("Call Anna", false)
+///     .build();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct BlockBuilder {
+    blocks: Vec<Value>,
+}
+
+impl BlockBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Heading of level 1, 2 or 3 (other levels are clamped)
+    pub fn heading(mut self, level: u8, text: &str) -> Self {
+        let block_type = format!("heading_{}", level.clamp(1, 3));
+        self.blocks.push(json!({
+            "object": "block",
+            "type": block_type,
+            block_type.as_str(): {
+                "rich_text": rich_text(text)
+            }
+        }));
+        self
+    }
+
+    /// A single paragraph; text must fit in one rich text object
+    pub fn paragraph(mut self, text: &str) -> Self {
+        self.blocks.push(text_block("paragraph", text));
+        self
+    }
+
+    /// One or more paragraphs, splitting text that exceeds the length limit
+    pub fn paragraphs(mut self, text: &str) -> Self {
+        for chunk in text_chunks(text, MAX_TEXT_LENGTH) {
+            self.blocks.push(text_block("paragraph", &chunk));
+        }
+        self
+    }
+
+    pub fn todo(mut self, text: &str, checked: bool) -> Self {
+        self.blocks.push(json!({
+            "object": "block",
+            "type": "to_do",
+            "to_do": {
+                "rich_text": rich_text(text),
+                "checked": checked
+            }
+        }));
+        self
+    }
+
+    pub fn callout(mut self, emoji: &str, text: &str) -> Self {
+        self.blocks.push(json!({
+            "object": "block",
+            "type": "callout",
+            "callout": {
+                "rich_text": rich_text(text),
+                "icon": {
+                    "type": "emoji",
+                    "emoji": emoji
+                }
+            }
+        }));
+        self
+    }
+
+    /// Block-level KaTeX equation
+    pub fn equation(mut self, expression: &str) -> Self {
+        self.blocks.push(json!({
+            "object": "block",
+            "type": "equation",
+            "equation": {
+                "expression": expression
+            }
+        }));
+        self
+    }
+
+    /// Toggle whose children are built by `children`
+    pub fn toggle(mut self, text: &str, children: BlockBuilder) -> Self {
+        let mut block = text_block("toggle", text);
+        if !children.is_empty() {
+            block["toggle"]["children"] = Value::Array(children.build());
+        }
+        self.blocks.push(block);
+        self
+    }
+
+    /// Heading that folds away the blocks built by `children`
+    pub fn toggle_heading(mut self, level: u8, text: &str, children: BlockBuilder) -> Self {
+        let block_type = format!("heading_{}", level.clamp(1, 3));
+        let mut block = json!({
+            "object": "block",
+            "type": block_type,
+            block_type.as_str(): {
+                "rich_text": rich_text(text),
+                "is_toggleable": true
+            }
+        });
+        if !children.is_empty() {
+            block[block_type.as_str()]["children"] = Value::Array(children.build());
+        }
+        self.blocks.push(block);
+        self
+    }
+
+    /// Table of text cells, the first row being the column header
+    pub fn table(mut self, rows: &[Vec<String>]) -> Self {
+        let width = rows.first().map(Vec::len).unwrap_or(1);
+        self.blocks.push(json!({
+            "object": "block",
+            "type": "table",
+            "table": {
+                "table_width": width,
+                "has_column_header": true,
+                "has_row_header": false,
+                "children": rows.iter().map(|row| table_row(row)).collect::<Vec<_>>()
+            }
+        }));
+        self
+    }
+
+    /// Image stored in Notion via the file upload API
+    pub fn uploaded_image(mut self, file_upload_id: &str, caption: &str) -> Self {
+        self.blocks.push(json!({
+            "object": "block",
+            "type": "image",
+            "image": {
+                "type": "file_upload",
+                "file_upload": {
+                    "id": file_upload_id
+                },
+                "caption": rich_text(caption)
+            }
+        }));
+        self
+    }
+
+    /// Image hosted elsewhere (e.g. Google Drive)
+    pub fn external_image(mut self, url: &str, caption: &str) -> Self {
+        self.blocks.push(json!({
+            "object": "block",
+            "type": "image",
+            "image": {
+                "type": "external",
+                "external": {
+                    "url": url
+                },
+                "caption": rich_text(caption)
+            }
+        }));
+        self
+    }
+
+    /// Append all blocks of another builder
+    pub fn extend(mut self, other: BlockBuilder) -> Self {
+        self.blocks.extend(other.blocks);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn build(self) -> Vec<Value> {
+        self.blocks
+    }
+}
+
+/// Plain rich text array for a string (empty strings yield an empty array)
+pub fn rich_text(text: &str) -> Value {
+    if text.is_empty() {
+        return json!([]);
+    }
+    json!([
+        {
+            "type": "text",
+            "text": {
+                "content": text
+            }
+        }
+    ])
+}
+
+/// Type and text of a block built here, for comparing it with a block on a
+/// page; None for blocks with children, whose text doesn't cover them
+pub fn block_text(block: &Value) -> Option<(&str, String)> {
+    let kind = block["type"].as_str()?;
+    let body = &block[kind];
+    if body.get("children").is_some() {
+        return None;
+    }
+    let text = body["rich_text"]
+        .as_array()?
+        .iter()
+        .filter_map(|part| part["text"]["content"].as_str())
+        .collect();
+    Some((kind, text))
+}
+
+/// Caption of an image block built here
+pub fn image_caption(block: &Value) -> Option<String> {
+    let parts = block["image"]["caption"].as_array()?;
+    Some(
+        parts
+            .iter()
+            .filter_map(|part| part["text"]["content"].as_str())
+            .collect(),
+    )
+}
+
+/// A row of a table block, for creating tables or appending rows to them
+pub fn table_row(cells: &[String]) -> Value {
+    json!({
+        "object": "block",
+        "type": "table_row",
+        "table_row": {
+            "cells": cells.iter().map(|cell| rich_text(cell)).collect::<Vec<_>>()
+        }
+    })
+}
+
+fn text_block(block_type: &str, text: &str) -> Value {
+    json!({
+        "object": "block",
+        "type": block_type,
+        block_type: {
+            "rich_text": rich_text(text)
+        }
+    })
+}
+
+/// Split text into chunks of at most `max_chars` characters, preferring line breaks
+pub fn text_chunks(text: &str, max_chars: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut current_len = 0;
+
+    for line in text.split_inclusive('\n') {
+        let line_len = line.chars().count();
+
+        if current_len + line_len > max_chars && !current.is_empty() {
+            chunks.push(std::mem::take(&mut current));
+            current_len = 0;
+        }
+
+        if line_len > max_chars {
+            // A single line that doesn't fit: hard-split on character boundaries
+            let chars: Vec<char> = line.chars().collect();
+            for piece in chars.chunks(max_chars) {
+                chunks.push(piece.iter().collect());
+            }
+            continue;
+        }
+
+        current.push_str(line);
+        current_len += line_len;
+    }
+
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+
+    chunks
+}
@@ -0,0 +1,293 @@
+// Response types mirror the API; not every field is read yet
+#![allow(dead_code)]
+
+use crate::error::{Error, Result};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// A paginated list response (`/databases/{id}/query`, `/blocks/{id}/children`, `/search`)
+#[derive(Debug, Deserialize)]
+pub struct List<T> {
+    pub results: Vec<T>,
+    #[serde(default)]
+    pub has_more: bool,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Page {
+    pub id: String,
+    #[serde(default)]
+    pub archived: bool,
+    /// ISO 8601 timestamp of the last edit, by anyone
+    pub last_edited_time: Option<String>,
+    #[serde(default)]
+    pub properties: HashMap<String, Property>,
+}
+
+impl Page {
+    /// Plain text of the page's title property
+    pub fn title(&self) -> Option<String> {
+        self.properties
+            .values()
+            .find_map(|property| match property {
+                Property::Title { title } => Some(plain_text(title)),
+                _ => None,
+            })
+    }
+}
+
+/// A page property value, tagged by its `type`
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Property {
+    Title {
+        title: Vec<RichText>,
+    },
+    RichText {
+        rich_text: Vec<RichText>,
+    },
+    Select {
+        select: Option<SelectOption>,
+    },
+    MultiSelect {
+        multi_select: Vec<SelectOption>,
+    },
+    Date {
+        date: Option<DateValue>,
+    },
+    Url {
+        url: Option<String>,
+    },
+    Relation {
+        relation: Vec<Reference>,
+    },
+    Number {
+        number: Option<f64>,
+    },
+    /// Property types we don't read
+    #[serde(other)]
+    Other,
+}
+
+impl Property {
+    /// Whether the property has no value set
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Property::Title { title } => title.is_empty(),
+            Property::RichText { rich_text } => rich_text.is_empty(),
+            Property::Select { select } => select.is_none(),
+            Property::MultiSelect { multi_select } => multi_select.is_empty(),
+            Property::Date { date } => date.is_none(),
+            Property::Url { url } => url.is_none(),
+            Property::Relation { relation } => relation.is_empty(),
+            Property::Number { number } => number.is_none(),
+            Property::Other => false,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RichText {
+    pub plain_text: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SelectOption {
+    pub name: String,
+    pub color: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DateValue {
+    pub start: String,
+    pub end: Option<String>,
+}
+
+/// A bare `{ "id": ... }` object, as used by relations and created objects
+#[derive(Debug, Deserialize)]
+pub struct Reference {
+    pub id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Block {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub has_children: bool,
+    pub created_time: Option<String>,
+    pub last_edited_time: Option<String>,
+    pub paragraph: Option<TextBlock>,
+    pub heading_1: Option<TextBlock>,
+    pub heading_2: Option<TextBlock>,
+    pub heading_3: Option<TextBlock>,
+    pub toggle: Option<TextBlock>,
+    pub callout: Option<TextBlock>,
+    pub image: Option<ImageBlock>,
+    pub child_page: Option<ChildPage>,
+    pub table_row: Option<TableRow>,
+}
+
+impl Block {
+    /// Plain text of paragraph, heading, toggle and callout blocks
+    pub fn text(&self) -> Option<String> {
+        [
+            &self.paragraph,
+            &self.heading_1,
+            &self.heading_2,
+            &self.heading_3,
+            &self.toggle,
+            &self.callout,
+        ]
+        .into_iter()
+        .flatten()
+        .next()
+        .map(|block| plain_text(&block.rich_text))
+    }
+
+    /// Caption of an image block
+    pub fn image_caption(&self) -> Option<String> {
+        self.image.as_ref().map(|image| plain_text(&image.caption))
+    }
+
+    /// Whether this is a heading, of any level, with the given text
+    pub fn is_heading(&self, text: &str) -> bool {
+        self.kind.starts_with("heading_") && self.text().as_deref() == Some(text)
+    }
+
+    /// Whether the block can hold OCR text: a paragraph, or the heading or
+    /// toggle of a notebook page
+    pub fn is_text(&self) -> bool {
+        matches!(
+            self.kind.as_str(),
+            "paragraph" | "heading_1" | "heading_2" | "heading_3" | "toggle"
+        )
+    }
+
+    /// A child page block as a page with just its title
+    pub fn as_page(&self) -> Option<Page> {
+        let child_page = self.child_page.as_ref()?;
+        let title = Property::Title {
+            title: vec![RichText {
+                plain_text: child_page.title.clone(),
+            }],
+        };
+        Some(Page {
+            id: self.id.clone(),
+            archived: false,
+            last_edited_time: self.last_edited_time.clone(),
+            properties: HashMap::from([("title".to_string(), title)]),
+        })
+    }
+
+    /// Plain text of the cells of a table row
+    pub fn cells(&self) -> Vec<String> {
+        self.table_row
+            .as_ref()
+            .map(|row| row.cells.iter().map(|cell| plain_text(cell)).collect())
+            .unwrap_or_default()
+    }
+
+    /// Caption of an image block
+    pub fn caption(&self) -> Option<String> {
+        self.image.as_ref().map(|image| plain_text(&image.caption))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImageBlock {
+    #[serde(default)]
+    pub caption: Vec<RichText>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChildPage {
+    pub title: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TableRow {
+    #[serde(default)]
+    pub cells: Vec<Vec<RichText>>,
+}
+
+/// Content of a block whose payload is just rich text
+#[derive(Debug, Deserialize)]
+pub struct TextBlock {
+    #[serde(default)]
+    pub rich_text: Vec<RichText>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Database {
+    pub id: String,
+    #[serde(default)]
+    pub title: Vec<RichText>,
+    #[serde(default)]
+    pub properties: HashMap<String, PropertySchema>,
+}
+
+impl Database {
+    /// Plain text title, or a placeholder for untitled databases
+    pub fn name(&self) -> String {
+        let name = plain_text(&self.title);
+        if name.is_empty() {
+            "(untitled)".to_string()
+        } else {
+            name
+        }
+    }
+
+    /// Name of the database's title property ("Name" by default, but renameable)
+    pub fn title_property(&self) -> Option<&str> {
+        self.properties
+            .iter()
+            .find(|(_, schema)| schema.kind == "title")
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+/// A property definition in a database schema
+#[derive(Debug, Deserialize)]
+pub struct PropertySchema {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub select: Option<SelectSchema>,
+    pub multi_select: Option<SelectSchema>,
+    pub relation: Option<RelationSchema>,
+}
+
+/// Target of a relation property
+#[derive(Debug, Deserialize)]
+pub struct RelationSchema {
+    pub database_id: String,
+}
+
+/// Options defined on a select or multi_select property
+#[derive(Debug, Deserialize)]
+pub struct SelectSchema {
+    #[serde(default)]
+    pub options: Vec<SelectOption>,
+}
+
+/// Response of `POST /file_uploads`
+#[derive(Debug, Deserialize)]
+pub struct FileUpload {
+    pub id: String,
+    pub upload_url: Option<String>,
+}
+
+/// Concatenated plain text of a rich text array
+pub fn plain_text(rich_text: &[RichText]) -> String {
+    rich_text.iter().map(|t| t.plain_text.as_str()).collect()
+}
+
+/// Deserialize a Notion response body, naming what was expected on failure
+pub async fn parse<T: DeserializeOwned>(response: reqwest::Response, what: &str) -> Result<T> {
+    let body = response.text().await?;
+    serde_json::from_str(&body)
+        .map_err(|e| Error::Notion(format!("Unexpected {} response: {}", what, e)))
+}
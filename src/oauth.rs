@@ -1,17 +1,48 @@
-use crate::error::Result;
+use crate::error::{ApiError, Error, Result};
+use http_body_util::Full;
+use hyper::body::{Bytes, Incoming};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Request, Response, StatusCode, Uri};
+use hyper_util::rt::TokioIo;
+use oauth2::basic::{BasicClient, BasicErrorResponseType, BasicTokenResponse};
 use oauth2::reqwest::async_http_client;
 use oauth2::{
-    basic::BasicClient, AuthUrl, AuthorizationCode, ClientId, ClientSecret, CsrfToken, RedirectUrl,
-    RefreshToken, Scope, TokenResponse, TokenUrl,
+    AuthUrl, AuthorizationCode, ClientId, ClientSecret, CsrfToken, PkceCodeChallenge, RedirectUrl,
+    RefreshToken, RequestTokenError, Scope, TokenResponse, TokenUrl,
 };
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::Infallible;
 use std::fs;
+use std::io::{self, IsTerminal};
 use std::path::PathBuf;
-use tracing::{debug, info};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+/// Upload and manage the files the app created in Google Drive
+pub const DRIVE_FILE_SCOPE: &str = "https://www.googleapis.com/auth/drive.file";
+
+/// What a scope is used for, for `auth scopes`
+pub fn scope_purpose(scope: &str) -> Option<&'static str> {
+    match scope {
+        DRIVE_FILE_SCOPE => Some("Google Drive uploads"),
+        _ => None,
+    }
+}
 
 const AUTH_URL: &str = "https://accounts.google.com/o/oauth2/v2/auth";
 const TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
-const REDIRECT_URL: &str = "http://localhost:8085";
+const TOKEN_INFO_URL: &str = "https://oauth2.googleapis.com/tokeninfo";
+
+/// Port the OAuth callback server listens on unless `OAUTH_CALLBACK_PORT`
+/// says otherwise; 0 picks a free one
+pub const DEFAULT_CALLBACK_PORT: u16 = 8085;
+
+/// How long to wait for the user to authorize in the browser
+const CALLBACK_TIMEOUT: Duration = Duration::from_secs(5 * 60);
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct StoredToken {
@@ -19,22 +50,39 @@ pub struct StoredToken {
     pub refresh_token: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub expires_at: Option<i64>,
+    /// Scopes granted with the token, `None` for tokens saved before they
+    /// were recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scopes: Option<Vec<String>>,
+}
+
+impl StoredToken {
+    /// The granted scopes; older tokens only ever asked for Drive
+    pub fn scopes(&self) -> Vec<String> {
+        self.scopes
+            .clone()
+            .unwrap_or_else(|| vec![DRIVE_FILE_SCOPE.to_string()])
+    }
 }
 
 pub struct GoogleOAuthClient {
     client: BasicClient,
     token_file: PathBuf,
+    callback_port: u16,
+    /// Scopes the enabled features need; only Google Drive uses OAuth so far
+    scopes: Vec<&'static str>,
 }
 
 impl GoogleOAuthClient {
-    pub fn new(client_id: String, client_secret: String) -> Result<Self> {
+    /// The authorization is protected with PKCE, so Desktop app clients can
+    /// leave out the client secret
+    pub fn new(client_id: String, client_secret: Option<String>) -> Result<Self> {
         let client = BasicClient::new(
             ClientId::new(client_id),
-            Some(ClientSecret::new(client_secret)),
+            client_secret.map(ClientSecret::new),
             AuthUrl::new(AUTH_URL.to_string())?,
             Some(TokenUrl::new(TOKEN_URL.to_string())?),
-        )
-        .set_redirect_uri(RedirectUrl::new(REDIRECT_URL.to_string())?);
+        );
 
         // Store token in same directory as credentials
         let mut token_file = dirs::config_dir().unwrap_or_else(|| PathBuf::from("."));
@@ -42,7 +90,22 @@ impl GoogleOAuthClient {
         fs::create_dir_all(&token_file)?;
         token_file.push("google_token.json");
 
-        Ok(Self { client, token_file })
+        Ok(Self {
+            client,
+            token_file,
+            callback_port: DEFAULT_CALLBACK_PORT,
+            scopes: vec![DRIVE_FILE_SCOPE],
+        })
+    }
+
+    /// Port to receive the authorization on, 0 for any free one.
+    ///
+    /// The redirect URI is `http://localhost:<port>`; Desktop app OAuth
+    /// clients accept it with any port, other client types need the exact
+    /// URI registered.
+    pub fn with_callback_port(mut self, port: u16) -> Self {
+        self.callback_port = port;
+        self
     }
 
     /// Load token from file if it exists
@@ -56,18 +119,20 @@ impl GoogleOAuthClient {
         Ok(Some(token))
     }
 
-    /// Save token to file
+    /// Save token to file, replacing the old one in one step
     fn save_token(&self, token: &StoredToken) -> Result<()> {
         let content = serde_json::to_string_pretty(token)?;
-        fs::write(&self.token_file, content)?;
+        let partial = self.token_file.with_extension("partial");
+        fs::write(&partial, content)?;
 
         // Set restrictive permissions (Unix only - 0o600 = rw-------)
         #[cfg(unix)]
         {
             use std::os::unix::fs::PermissionsExt;
             let permissions = std::fs::Permissions::from_mode(0o600);
-            fs::set_permissions(&self.token_file, permissions)?;
+            fs::set_permissions(&partial, permissions)?;
         }
+        fs::rename(&partial, &self.token_file)?;
 
         debug!("Token saved to {:?}", self.token_file);
         Ok(())
@@ -75,12 +140,30 @@ impl GoogleOAuthClient {
 
     /// Perform initial OAuth flow (opens browser)
     pub async fn authorize(&self) -> Result<StoredToken> {
-        let (auth_url, csrf_token) = self
+        // Listen before sending the user off, so the redirect URI has the
+        // actual port
+        let listener = Self::callback_listener(self.callback_port).await?;
+        let port = listener.local_addr()?.port();
+        let redirect_url = format!("http://localhost:{}", port);
+        let client = self
             .client
+            .clone()
+            .set_redirect_uri(RedirectUrl::new(redirect_url.clone())?);
+
+        // The code is only exchanged together with the verifier, so an
+        // intercepted redirect is of no use
+        let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+        // Scopes granted before are kept, so asking for a new one doesn't
+        // take away the others
+        let (auth_url, csrf_token) = client
             .authorize_url(CsrfToken::new_random)
-            .add_scope(Scope::new(
-                "https://www.googleapis.com/auth/drive.file".to_string(),
-            ))
+            .set_pkce_challenge(pkce_challenge)
+            .add_scopes(
+                self.scopes
+                    .iter()
+                    .map(|scope| Scope::new(scope.to_string())),
+            )
+            .add_extra_param("include_granted_scopes", "true")
             .url();
 
         info!("\n{}", "=".repeat(70));
@@ -97,8 +180,7 @@ impl GoogleOAuthClient {
             info!("Please open the URL manually in your browser.");
         }
 
-        // Start local server to receive callback
-        let (code, state) = Self::receive_callback()?;
+        let (code, state) = Self::receive_callback(listener).await?;
 
         // Verify CSRF token
         if state != *csrf_token.secret() {
@@ -108,9 +190,9 @@ impl GoogleOAuthClient {
         }
 
         // Exchange authorization code for access token
-        let token_result = self
-            .client
+        let token_result = client
             .exchange_code(AuthorizationCode::new(code))
+            .set_pkce_verifier(pkce_verifier)
             .request_async(async_http_client)
             .await
             .map_err(|e| crate::error::Error::OAuth(format!("Token exchange failed: {}", e)))?;
@@ -132,6 +214,7 @@ impl GoogleOAuthClient {
             access_token,
             refresh_token,
             expires_at,
+            scopes: Some(granted_scopes(&token_result, &self.scopes)),
         };
 
         self.save_token(&stored_token)?;
@@ -142,15 +225,33 @@ impl GoogleOAuthClient {
     }
 
     /// Refresh access token using refresh token
-    pub async fn refresh_token(&self, refresh_token: &str) -> Result<StoredToken> {
+    pub async fn refresh_token(&self, token: &StoredToken) -> Result<StoredToken> {
+        let refresh_token = token.refresh_token.as_str();
         debug!("Refreshing access token...");
 
-        let token_result = self
+        let token_result = match self
             .client
             .exchange_refresh_token(&RefreshToken::new(refresh_token.to_string()))
             .request_async(async_http_client)
             .await
-            .map_err(|e| crate::error::Error::OAuth(format!("Token refresh failed: {}", e)))?;
+        {
+            Ok(token_result) => token_result,
+            // Google answers invalid_grant once the user revoked access, the
+            // password changed or the token expired unused
+            Err(RequestTokenError::ServerResponse(response))
+                if *response.error() == BasicErrorResponseType::InvalidGrant =>
+            {
+                return self
+                    .reauthorize("the stored refresh token is no longer valid")
+                    .await;
+            }
+            Err(e) => {
+                return Err(crate::error::Error::OAuth(format!(
+                    "Token refresh failed: {}",
+                    e
+                )))
+            }
+        };
 
         let access_token = token_result.access_token().secret().to_string();
 
@@ -168,6 +269,7 @@ impl GoogleOAuthClient {
             access_token,
             refresh_token,
             expires_at,
+            scopes: Some(granted_scopes(&token_result, &token.scopes())),
         };
 
         self.save_token(&stored_token)?;
@@ -176,6 +278,56 @@ impl GoogleOAuthClient {
         Ok(stored_token)
     }
 
+    /// Sign in again, if there's someone at a terminal to do so. The token
+    /// file is only replaced once the new authorization succeeded, so an
+    /// abandoned consent leaves the old token in place.
+    async fn reauthorize(&self, reason: &str) -> Result<StoredToken> {
+        if !io::stdin().is_terminal() {
+            return Err(Error::Reauthorize(format!(
+                "{}. Run the command in a terminal to sign in again, or delete {:?}",
+                reason, self.token_file
+            )));
+        }
+
+        warn!("Google has to be authorized again: {}", reason);
+        self.authorize().await
+    }
+
+    /// Scopes the enabled features need that `token` wasn't granted
+    pub fn missing_scopes(&self, token: &StoredToken) -> Vec<&'static str> {
+        let granted = token.scopes();
+        self.scopes
+            .iter()
+            .filter(|scope| !granted.iter().any(|granted| granted == *scope))
+            .copied()
+            .collect()
+    }
+
+    /// Scopes Google reports for the token's access token
+    pub async fn token_scopes(&self, token: &StoredToken) -> Result<Vec<String>> {
+        #[derive(Deserialize)]
+        struct TokenInfo {
+            #[serde(default)]
+            scope: String,
+        }
+
+        let response = reqwest::Client::new()
+            .get(TOKEN_INFO_URL)
+            .query(&[("access_token", &token.access_token)])
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(ApiError::from_response(
+                "Google OAuth",
+                "Failed to look up the granted scopes",
+                response,
+            )
+            .await);
+        }
+        let info: TokenInfo = response.json().await?;
+        Ok(info.scope.split_whitespace().map(str::to_string).collect())
+    }
+
     /// Get valid access token (refreshes if expired)
     pub async fn get_valid_token(&self) -> Result<StoredToken> {
         if let Some(token) = self.load_token()? {
@@ -187,9 +339,17 @@ impl GoogleOAuthClient {
                 false
             };
 
-            if needs_refresh {
+            let missing = self.missing_scopes(&token);
+            if !missing.is_empty() {
+                info!(
+                    "Enabled features need more permissions: {}",
+                    missing.join(", ")
+                );
+                self.reauthorize("enabled features need more permissions")
+                    .await
+            } else if needs_refresh {
                 info!("Access token expired, refreshing...");
-                self.refresh_token(&token.refresh_token).await
+                self.refresh_token(&token).await
             } else {
                 Ok(token)
             }
@@ -199,56 +359,120 @@ impl GoogleOAuthClient {
         }
     }
 
-    /// Start local HTTP server to receive OAuth callback
-    fn receive_callback() -> Result<(String, String)> {
-        use tiny_http::{Response, Server};
-
-        let server = Server::http("127.0.0.1:8085").map_err(|e| {
-            crate::error::Error::Io(std::io::Error::other(format!(
-                "Failed to start callback server: {}",
-                e
-            )))
-        })?;
-
-        // Wait for exactly one request
-        let request = server.recv().map_err(|e| {
-            crate::error::Error::Io(std::io::Error::other(format!(
-                "Failed to receive callback: {}",
-                e
-            )))
-        })?;
-
-        let url = format!("http://localhost:8085{}", request.url());
-        let parsed_url = url::Url::parse(&url)?;
-
-        let code = parsed_url
-            .query_pairs()
-            .find(|(key, _)| key == "code")
-            .map(|(_, value)| value.to_string())
-            .ok_or_else(|| {
-                crate::error::Error::Io(std::io::Error::other("No authorization code in callback"))
-            })?;
+    /// Start the local HTTP server that receives the OAuth callback
+    async fn callback_listener(port: u16) -> Result<TcpListener> {
+        TcpListener::bind(("127.0.0.1", port)).await.map_err(|e| {
+            let hint = if port == 0 {
+                String::new()
+            } else {
+                " Set OAUTH_CALLBACK_PORT to a free port, or to 0 to pick one.".to_string()
+            };
+            Error::OAuth(format!(
+                "Failed to start the callback server on port {}: {}.{}",
+                port, e, hint
+            ))
+        })
+    }
 
-        let state = parsed_url
-            .query_pairs()
-            .find(|(key, _)| key == "state")
-            .map(|(_, value)| value.to_string())
-            .ok_or_else(|| {
-                crate::error::Error::Io(std::io::Error::other("No state in callback"))
-            })?;
+    /// Wait for the OAuth callback, returning its code and state.
+    ///
+    /// Gives up after `CALLBACK_TIMEOUT` or on Ctrl-C, so an abandoned
+    /// browser page doesn't keep the process waiting forever.
+    async fn receive_callback(listener: TcpListener) -> Result<(String, String)> {
+        let (sender, mut receiver) = mpsc::channel(1);
+        tokio::select! {
+            result = serve_callbacks(listener, sender) => Err(Error::OAuth(format!(
+                "Callback server failed: {}",
+                result.err().map(|e| e.to_string()).unwrap_or_default()
+            ))),
+            Some(callback) = receiver.recv() => callback,
+            _ = tokio::time::sleep(CALLBACK_TIMEOUT) => Err(Error::OAuth(format!(
+                "No authorization received within {} minutes. Run again to retry.",
+                CALLBACK_TIMEOUT.as_secs() / 60
+            ))),
+            _ = tokio::signal::ctrl_c() => {
+                Err(Error::OAuth("Authorization cancelled".to_string()))
+            }
+        }
+    }
+}
 
-        // Send success response to browser
-        let response = Response::from_string(
-            "<html><body><h1>✅ Authorization successful!</h1>\
-             <p>You can close this window and return to the terminal.</p></body></html>",
-        );
-        request.respond(response).map_err(|e| {
-            crate::error::Error::Io(std::io::Error::other(format!(
-                "Failed to send response: {}",
-                e
-            )))
-        })?;
+/// Scopes listed in a token response, or `requested` if Google left them out
+fn granted_scopes(response: &BasicTokenResponse, requested: &[impl ToString]) -> Vec<String> {
+    match response.scopes() {
+        Some(scopes) => scopes.iter().map(|scope| scope.to_string()).collect(),
+        None => requested.iter().map(|scope| scope.to_string()).collect(),
+    }
+}
 
-        Ok((code, state))
+/// The outcome of an authorization, as sent by the browser to the callback
+type Callback = Result<(String, String)>;
+
+/// Answer requests on `listener` until the callback arrives, which is sent
+/// to `sender`. Other requests (e.g. for a favicon) get a 404.
+async fn serve_callbacks(listener: TcpListener, sender: mpsc::Sender<Callback>) -> io::Result<()> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let sender = sender.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |request: Request<Incoming>| {
+                let sender = sender.clone();
+                async move {
+                    let response = match parse_callback(request.uri()) {
+                        Some(callback) => {
+                            let page = match callback {
+                                Ok(_) => {
+                                    "<html><body><h1>✅ Authorization successful!</h1>\
+                                     <p>You can close this window and return to the terminal.</p></body></html>"
+                                }
+                                Err(_) => {
+                                    "<html><body><h1>❌ Authorization failed</h1>\
+                                     <p>See the terminal for details.</p></body></html>"
+                                }
+                            };
+                            sender.try_send(callback).ok();
+                            Response::new(Full::new(Bytes::from(page)))
+                        }
+                        None => {
+                            let mut response = Response::new(Full::new(Bytes::new()));
+                            *response.status_mut() = StatusCode::NOT_FOUND;
+                            response
+                        }
+                    };
+                    Ok::<_, Infallible>(response)
+                }
+            });
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                debug!("OAuth callback connection failed: {}", e);
+            }
+        });
+    }
+}
+
+/// Code and state of a callback request, None for other requests
+fn parse_callback(uri: &Uri) -> Option<Callback> {
+    if uri.path() != "/" {
+        return None;
+    }
+    let query: HashMap<String, String> =
+        url::form_urlencoded::parse(uri.query().unwrap_or_default().as_bytes())
+            .into_owned()
+            .collect();
+
+    if let Some(error) = query.get("error") {
+        return Some(Err(Error::OAuth(format!(
+            "Authorization was denied: {}",
+            error
+        ))));
     }
+    let code = query.get("code")?.clone();
+    Some(
+        query
+            .get("state")
+            .map(|state| (code, state.clone()))
+            .ok_or_else(|| Error::OAuth("No state in callback".to_string())),
+    )
 }
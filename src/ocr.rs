@@ -0,0 +1,555 @@
+use crate::apple_vision::AppleVisionClient;
+use crate::config::{Config, ErrorPolicy, Margins};
+use crate::error::{Error, Result};
+use crate::google_vision::GoogleVisionClient;
+use crate::llm_vision::{LlmProvider, LlmVisionClient};
+use crate::process;
+use crate::state;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::process::Command;
+use tracing::{debug, warn};
+
+/// pdftoppm's default resolution
+pub const DEFAULT_DPI: u32 = 150;
+
+/// Page text used when OCR found nothing on any page
+pub const NO_TEXT: &str = "(No text detected)";
+
+/// How long pdftoppm may take to render a notebook
+const RENDER_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// OCR result for a single page image
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PageText {
+    pub text: String,
+    /// Average page confidence (0.0 - 1.0), if the backend reports one
+    pub confidence: Option<f32>,
+}
+
+/// OCR result for all pages of a notebook
+pub struct DocumentText {
+    /// Page texts joined with "--- Page N ---" separators
+    pub text: String,
+    /// Average confidence of the pages, if the backend reports one
+    pub confidence: Option<f32>,
+    /// Pages the error policy skipped after OCR failed
+    pub failed_pages: Vec<usize>,
+    /// Text of every page recognized, by `page_key`, for reusing it when the
+    /// page hasn't changed
+    pub pages: HashMap<String, PageText>,
+}
+
+/// A handwriting recognition service that turns a page image into text
+#[async_trait]
+pub trait OcrBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    async fn recognize(&self, image_path: &Path) -> Result<PageText>;
+}
+
+/// Selectable OCR backends (`OCR_BACKEND`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(try_from = "String")]
+pub enum OcrBackendKind {
+    Vision,
+    Gemini,
+    OpenAi,
+    Apple,
+}
+
+impl FromStr for OcrBackendKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "vision" | "google" => Ok(OcrBackendKind::Vision),
+            "gemini" => Ok(OcrBackendKind::Gemini),
+            "openai" | "gpt" => Ok(OcrBackendKind::OpenAi),
+            "apple" => Ok(OcrBackendKind::Apple),
+            other => Err(Error::Config(format!(
+                "Unknown OCR backend '{}' (expected vision, gemini, openai or apple)",
+                other
+            ))),
+        }
+    }
+}
+
+impl OcrBackendKind {
+    /// Whether the backend OCRs on this machine, without sending page images anywhere
+    pub fn is_local(self) -> bool {
+        matches!(self, OcrBackendKind::Apple)
+    }
+}
+
+impl TryFrom<String> for OcrBackendKind {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+/// Build the backend of the given kind from the configured credentials
+pub fn create_backend(kind: OcrBackendKind, config: &Config) -> Result<Box<dyn OcrBackend>> {
+    let backend: Box<dyn OcrBackend> = match kind {
+        OcrBackendKind::Vision => {
+            let api_key = config.google_vision_api_key.clone().ok_or_else(|| {
+                Error::Config(
+                    "Google Cloud Vision API key is required. Set GOOGLE_VISION_API_KEY in .env file."
+                        .to_string(),
+                )
+            })?;
+            Box::new(
+                GoogleVisionClient::new(api_key)
+                    .with_quota_project(config.google_vision_quota_project.clone()),
+            )
+        }
+        OcrBackendKind::Gemini => {
+            let api_key = config.gemini_api_key.clone().ok_or_else(|| {
+                Error::Config("GEMINI_API_KEY is required for the gemini OCR backend".to_string())
+            })?;
+            Box::new(LlmVisionClient::new(
+                LlmProvider::Gemini,
+                api_key,
+                config.ocr_model.clone(),
+            ))
+        }
+        OcrBackendKind::OpenAi => {
+            let api_key = config.openai_api_key.clone().ok_or_else(|| {
+                Error::Config("OPENAI_API_KEY is required for the openai OCR backend".to_string())
+            })?;
+            Box::new(LlmVisionClient::new(
+                LlmProvider::OpenAi,
+                api_key,
+                config.ocr_model.clone(),
+            ))
+        }
+        OcrBackendKind::Apple => Box::new(AppleVisionClient::new()?),
+    };
+
+    debug!("Using {} for OCR", backend.name());
+    Ok(backend)
+}
+
+/// Re-render pages at a higher DPI when the OCR confidence is below a threshold
+#[derive(Debug, Clone, Copy)]
+pub struct AdaptiveDpi {
+    pub high_dpi: u32,
+    pub min_confidence: f32,
+}
+
+impl Default for AdaptiveDpi {
+    fn default() -> Self {
+        Self {
+            high_dpi: 300,
+            min_confidence: 0.8,
+        }
+    }
+}
+
+/// Renders PDF pages with pdftoppm and runs them through an OCR backend
+pub struct OcrEngine {
+    backend: Box<dyn OcrBackend>,
+    dpi: u32,
+    adaptive: Option<AdaptiveDpi>,
+    error_policy: ErrorPolicy,
+}
+
+impl OcrEngine {
+    pub fn new(backend: Box<dyn OcrBackend>) -> Self {
+        Self {
+            backend,
+            dpi: DEFAULT_DPI,
+            adaptive: None,
+            error_policy: ErrorPolicy::Skip,
+        }
+    }
+
+    /// Render at `dpi` first, re-rendering low-confidence pages per `adaptive`
+    pub fn with_dpi(mut self, dpi: u32, adaptive: Option<AdaptiveDpi>) -> Self {
+        self.dpi = dpi;
+        self.adaptive = adaptive;
+        self
+    }
+
+    /// What to do when a page can't be recognised (skipped by default)
+    pub fn with_error_policy(mut self, error_policy: ErrorPolicy) -> Self {
+        self.error_policy = error_policy;
+        self
+    }
+
+    /// Extract text AND keep images from PDF (for uploading to Notion)
+    pub async fn extract_text_and_images_from_pdf(
+        &self,
+        pdf_path: &Path,
+        output_dir: &Path,
+    ) -> Result<(String, Vec<PathBuf>)> {
+        debug!(
+            "Extracting text using {}: {:?}",
+            self.backend.name(),
+            pdf_path
+        );
+
+        // First, extract images from PDF using pdftoppm
+        let base_name = pdf_path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .ok_or_else(|| Error::Ocr("Invalid PDF filename".to_string()))?;
+        let mut page_images = self
+            .extract_images_from_pdf(pdf_path, output_dir, base_name)
+            .await?;
+
+        if page_images.is_empty() {
+            return Ok(("(No pages found in PDF)".to_string(), Vec::new()));
+        }
+
+        let document = self
+            .extract_text_from_pages(pdf_path, &mut page_images, &[], &[], &HashMap::new())
+            .await?;
+        Ok((document.text, page_images))
+    }
+
+    /// The settings that affect OCR output, to tell whether cached output
+    /// still applies
+    pub fn settings(&self) -> String {
+        format!(
+            "{} {}dpi {:?}",
+            self.backend.name(),
+            self.dpi,
+            self.adaptive
+        )
+    }
+
+    /// OCR rendered page images, joining the text with page separators.
+    ///
+    /// With adaptive DPI enabled, pages below the confidence threshold are
+    /// re-rendered at the high DPI and OCR'd again; their entry in
+    /// `page_images` is replaced by the sharper image. Pages with an entry in
+    /// `crops` are cropped for OCR only; the images themselves stay whole.
+    /// Pages that fail are handled according to the engine's error policy.
+    /// Pages whose image and crop have an entry in `known` (see `page_key`)
+    /// take their text from it instead of being OCR'd again.
+    ///
+    /// `page_numbers` holds the PDF page of each image when they aren't all
+    /// the pages in order (see [`Self::extract_page_images`]); leave it empty
+    /// otherwise.
+    pub async fn extract_text_from_pages(
+        &self,
+        pdf_path: &Path,
+        page_images: &mut [PathBuf],
+        crops: &[Option<Margins>],
+        page_numbers: &[usize],
+        known: &HashMap<String, PageText>,
+    ) -> Result<DocumentText> {
+        debug!(
+            "Processing {} pages with {}",
+            page_images.len(),
+            self.backend.name()
+        );
+
+        let mut full_text = String::new();
+        let mut confidences = Vec::new();
+        let mut failed_pages = Vec::new();
+        let mut pages = HashMap::new();
+        let mut reused = 0;
+
+        // Process each page image
+        let page_count = page_images.len();
+        for (i, image_path) in page_images.iter_mut().enumerate() {
+            let page_num = page_numbers.get(i).copied().unwrap_or(i + 1);
+            debug!("Processing page {} ({} of {})", page_num, i + 1, page_count);
+
+            let crop = crops.get(i).copied().flatten();
+            // Keyed by the image at the normal resolution, before adaptive
+            // DPI replaces it
+            let key = page_key(image_path, crop).ok();
+            if let Some((key, page)) = key.as_ref().and_then(|key| known.get_key_value(key)) {
+                debug!("Page {} unchanged, reusing its text", page_num);
+                reused += 1;
+                confidences.extend(page.confidence);
+                push_page_text(&mut full_text, &page.text, page_num, page_numbers);
+                pages.insert(key.clone(), page.clone());
+                continue;
+            }
+
+            let what = format!("OCR of page {}", page_num);
+            let path: &Path = image_path;
+            let mut result = self
+                .error_policy
+                .run(&what, || self.recognize(path, crop))
+                .await?;
+
+            if let (Some(adaptive), Some(page)) = (self.adaptive, &result) {
+                match page.confidence {
+                    Some(confidence) if confidence < adaptive.min_confidence => {
+                        debug!(
+                            "Page {} confidence {:.2} below {:.2}, re-rendering at {} DPI",
+                            page_num, confidence, adaptive.min_confidence, adaptive.high_dpi
+                        );
+                        let hires = self
+                            .render_page(pdf_path, page_num, adaptive.high_dpi, image_path)
+                            .await;
+                        match hires {
+                            Ok(hires_path) => {
+                                result = self
+                                    .error_policy
+                                    .run(&what, || self.recognize(&hires_path, crop))
+                                    .await?;
+                                std::fs::remove_file(&*image_path).ok();
+                                *image_path = hires_path;
+                            }
+                            Err(e) => warn!("Failed to re-render page {}: {}", page_num, e),
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            match result {
+                Some(page) => {
+                    confidences.extend(page.confidence);
+                    push_page_text(&mut full_text, &page.text, page_num, page_numbers);
+                    if let Some(key) = key {
+                        pages.insert(key, page);
+                    }
+                }
+                None => failed_pages.push(page_num),
+            }
+        }
+        if reused > 0 {
+            debug!(
+                "OCR'd {} of {} pages, the others were unchanged",
+                page_count - reused,
+                page_count
+            );
+        }
+
+        if full_text.trim().is_empty() {
+            warn!("No text extracted from PDF");
+            full_text = NO_TEXT.to_string();
+        } else {
+            debug!(
+                "Extracted {} characters using {}",
+                full_text.len(),
+                self.backend.name()
+            );
+        }
+
+        let confidence = (!confidences.is_empty())
+            .then(|| confidences.iter().sum::<f32>() / confidences.len() as f32);
+        Ok(DocumentText {
+            text: full_text,
+            confidence,
+            failed_pages,
+            pages,
+        })
+    }
+
+    /// OCR the part of a page image inside the given margins
+    pub async fn recognize_area(&self, image_path: &Path, area: Margins) -> Result<String> {
+        Ok(self.recognize(image_path, Some(area)).await?.text)
+    }
+
+    /// OCR a page image, cropped first if a crop is given
+    async fn recognize(&self, image_path: &Path, crop: Option<Margins>) -> Result<PageText> {
+        let Some(crop) = crop else {
+            return self.backend.recognize(image_path).await;
+        };
+
+        let cropped = crop_image(image_path, crop)?;
+        let result = self.backend.recognize(&cropped).await;
+        std::fs::remove_file(&cropped).ok();
+        result
+    }
+
+    /// Render a single (1-based) page of the PDF at the given DPI, next to
+    /// the page's image at the normal resolution
+    async fn render_page(
+        &self,
+        pdf_path: &Path,
+        page_num: usize,
+        dpi: u32,
+        page_image: &Path,
+    ) -> Result<PathBuf> {
+        let stem = page_image
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .ok_or_else(|| Error::Ocr("Invalid image filename".to_string()))?;
+
+        // Distinct suffix so the hi-res image never matches the page scan
+        let output_prefix = page_image.with_file_name(format!("{}_hires_{}dpi", stem, dpi));
+        self.render_single(pdf_path, page_num, dpi, output_prefix)
+            .await
+    }
+
+    /// Render one (1-based) page of the PDF to `<output_prefix>.png`
+    async fn render_single(
+        &self,
+        pdf_path: &Path,
+        page_num: usize,
+        dpi: u32,
+        output_prefix: PathBuf,
+    ) -> Result<PathBuf> {
+        let mut cmd = Command::new("pdftoppm");
+        cmd.arg("-png")
+            .arg("-singlefile")
+            .arg("-r")
+            .arg(dpi.to_string())
+            .arg("-f")
+            .arg(page_num.to_string())
+            .arg("-l")
+            .arg(page_num.to_string())
+            .arg(pdf_path)
+            .arg(&output_prefix);
+        let output = process::output(&mut cmd, RENDER_TIMEOUT)
+            .await
+            .map_err(|e| Error::Ocr(format!("Failed to run pdftoppm: {}", e)))?;
+
+        if !output.status.success() {
+            return Err(Error::Ocr(format!(
+                "Rendering page {} at {} DPI failed",
+                page_num, dpi
+            )));
+        }
+
+        let mut output_path = output_prefix.into_os_string();
+        output_path.push(".png");
+        Ok(PathBuf::from(output_path))
+    }
+
+    /// Extract images from PDF pages into `output_dir` using pdftoppm.
+    ///
+    /// The images are named `<prefix>_page-<n>.png`; only files with the
+    /// prefix are picked up, so it should be unique to the notebook and run.
+    pub async fn extract_images_from_pdf(
+        &self,
+        pdf_path: &Path,
+        output_dir: &Path,
+        prefix: &str,
+    ) -> Result<Vec<PathBuf>> {
+        let image_prefix = output_dir.join(format!("{}_page", prefix));
+
+        debug!("Converting PDF to images using pdftoppm");
+
+        // Convert PDF to PNG images (one per page)
+        let mut cmd = Command::new("pdftoppm");
+        cmd.arg("-png")
+            .arg("-r")
+            .arg(self.dpi.to_string())
+            .arg(pdf_path)
+            .arg(&image_prefix);
+        let output = process::output(&mut cmd, RENDER_TIMEOUT)
+            .await
+            .map_err(|e| Error::Ocr(format!("Failed to run pdftoppm: {}", e)))?;
+
+        if !output.status.success() {
+            return Err(Error::Ocr("PDF to image conversion failed".to_string()));
+        }
+
+        // Find all generated PNG files
+        let parent_dir = image_prefix.parent().unwrap();
+        let prefix_name = image_prefix.file_name().unwrap().to_str().unwrap();
+
+        let mut page_images: Vec<_> = std::fs::read_dir(parent_dir)?
+            .filter_map(|e| e.ok())
+            .filter(|e| {
+                e.file_name()
+                    .to_str()
+                    .map(|s| s.starts_with(prefix_name) && s.ends_with(".png"))
+                    .unwrap_or(false)
+            })
+            .map(|e| e.path())
+            .collect();
+
+        page_images.sort();
+
+        if page_images.is_empty() {
+            return Err(Error::Ocr("No images generated from PDF".to_string()));
+        }
+
+        debug!("Extracted {} page images", page_images.len());
+        Ok(page_images)
+    }
+
+    /// Extract images of only the given (1-based) pages, named like
+    /// [`Self::extract_images_from_pdf`] does, in the order given
+    pub async fn extract_page_images(
+        &self,
+        pdf_path: &Path,
+        output_dir: &Path,
+        prefix: &str,
+        pages: &[usize],
+    ) -> Result<Vec<PathBuf>> {
+        debug!(
+            "Converting {} PDF pages to images using pdftoppm",
+            pages.len()
+        );
+
+        let mut page_images = Vec::with_capacity(pages.len());
+        for &page_num in pages {
+            let output_prefix = output_dir.join(format!("{}_page-{}", prefix, page_num));
+            page_images.push(
+                self.render_single(pdf_path, page_num, self.dpi, output_prefix)
+                    .await?,
+            );
+        }
+        Ok(page_images)
+    }
+}
+
+/// Write the part of the image inside the crop margins next to it
+fn crop_image(image_path: &Path, crop: Margins) -> Result<PathBuf> {
+    let image = image::open(image_path)
+        .map_err(|e| Error::Ocr(format!("Failed to read {:?}: {}", image_path, e)))?;
+
+    let (width, height) = (image.width() as f32, image.height() as f32);
+    let x = (crop.left * width) as u32;
+    let y = (crop.top * height) as u32;
+    let cropped = image.crop_imm(
+        x,
+        y,
+        ((1.0 - crop.left - crop.right) * width) as u32,
+        ((1.0 - crop.top - crop.bottom) * height) as u32,
+    );
+
+    let stem = image_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .ok_or_else(|| Error::Ocr("Invalid image filename".to_string()))?;
+    let output = image_path.with_file_name(format!("{}_crop.png", stem));
+    cropped
+        .save(&output)
+        .map_err(|e| Error::Ocr(format!("Failed to write {:?}: {}", output, e)))?;
+
+    debug!(
+        "Cropped {:?} to {}x{}",
+        image_path,
+        cropped.width(),
+        cropped.height()
+    );
+    Ok(output)
+}
+
+/// Identifies a page's OCR input: the hash of its image and the crop
+pub fn page_key(image_path: &Path, crop: Option<Margins>) -> Result<String> {
+    Ok(format!("{} {:?}", state::file_hash(image_path)?, crop))
+}
+
+/// Append a page's text, after a separator unless it's the first
+fn push_page_text(full_text: &mut String, text: &str, page_num: usize, page_numbers: &[usize]) {
+    if text.trim().is_empty() {
+        return;
+    }
+    if !full_text.is_empty() {
+        full_text.push_str(&format!("\n\n--- Page {} ---\n\n", page_num));
+    } else if !page_numbers.is_empty() {
+        // A subset of pages labels its first page too
+        full_text.push_str(&format!("--- Page {} ---\n\n", page_num));
+    }
+    full_text.push_str(text);
+}
@@ -0,0 +1,19 @@
+use std::io::{Error, ErrorKind};
+use std::process::Output;
+use std::time::Duration;
+use tokio::process::Command;
+
+/// Run a command to completion without blocking the runtime.
+///
+/// The child is killed once `timeout` passes, and also when the returned
+/// future is dropped (e.g. a cancelled sync), so no process outlives its caller.
+pub async fn output(cmd: &mut Command, timeout: Duration) -> std::io::Result<Output> {
+    cmd.kill_on_drop(true);
+    match tokio::time::timeout(timeout, cmd.output()).await {
+        Ok(output) => output,
+        Err(_) => Err(Error::new(
+            ErrorKind::TimedOut,
+            format!("timed out after {}s", timeout.as_secs()),
+        )),
+    }
+}
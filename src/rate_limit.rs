@@ -0,0 +1,44 @@
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Requests per second Notion allows on average per integration
+pub const NOTION_REQUESTS_PER_SECOND: f64 = 3.0;
+
+/// Spaces requests out evenly to at most a number per second, across every
+/// task and client sharing the limiter
+#[derive(Debug)]
+pub struct RateLimiter {
+    interval: Duration,
+    /// When the next request may go out
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn per_second(requests: f64) -> Self {
+        Self {
+            interval: Duration::from_secs_f64(1.0 / requests),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Wait for the next free slot
+    pub async fn acquire(&self) {
+        let slot = {
+            let mut next = self.next.lock().unwrap();
+            let slot = (*next).max(Instant::now());
+            *next = slot + self.interval;
+            slot
+        };
+        tokio::time::sleep_until(slot).await;
+    }
+
+    /// Hold every request back for `delay`, after the API said to slow down
+    pub fn pause(&self, delay: Duration) {
+        let until = Instant::now() + delay;
+        let mut next = self.next.lock().unwrap();
+        if *next < until {
+            *next = until;
+        }
+    }
+}
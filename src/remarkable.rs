@@ -1,10 +1,56 @@
 use crate::error::{Error, Result};
+use crate::exporter::Exporter;
+use crate::ignore::{IgnoreFile, IGNORE_FILE};
 use crate::notion::NotebookMetadata;
-use serde::Deserialize;
-use std::collections::HashMap;
+use crate::process;
+use crate::remarkable::ssh::SshClient;
+use crate::remarkable::sync_output::SyncOutput;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
-use std::process::Command;
-use tracing::{debug, info};
+use std::process::Stdio;
+use std::str::FromStr;
+use std::time::{Duration, UNIX_EPOCH};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::Command;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+pub mod lines;
+pub mod render;
+pub mod ssh;
+pub mod sync_output;
+
+/// How long RemarkableSync may run by default; a full first backup can take minutes
+pub const DEFAULT_SYNC_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// How long the RemarkableSync version check may take
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How documents get from the tablet into the backup (`REMARKABLE_TRANSPORT`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Transport {
+    /// Run RemarkableSync, which backs up the tablet and converts notebooks to PDF
+    #[default]
+    RemarkableSync,
+    /// Copy the tablet's files over SSH and convert them with the `EXPORTER`
+    Ssh,
+}
+
+impl FromStr for Transport {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "remarkablesync" | "default" => Ok(Transport::RemarkableSync),
+            "ssh" => Ok(Transport::Ssh),
+            other => Err(Error::Config(format!(
+                "Unknown transport '{}' (expected remarkablesync or ssh)",
+                other
+            ))),
+        }
+    }
+}
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Notebook {
@@ -12,9 +58,21 @@ pub struct Notebook {
     pub path: String,
     #[allow(dead_code)]
     pub id: String,
+    /// Document UUID on the tablet, when the notebook's metadata was found
+    pub uuid: Option<String>,
     pub metadata: NotebookMetadata,
     pub tags: Vec<String>,
     pub is_deleted: bool,
+    /// Starred as a favorite on the tablet
+    #[serde(default)]
+    pub pinned: bool,
+    /// Whether RemarkableSync exported the notebook this run; `None` when it
+    /// didn't run or its output couldn't be parsed
+    #[serde(default)]
+    pub exported: Option<bool>,
+    /// Matched by `.r2nignore`: not synced, but still on the tablet
+    #[serde(default)]
+    pub ignored: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -28,13 +86,33 @@ struct Tag {
 struct ContentFile {
     #[serde(default)]
     tags: Vec<Tag>,
+    /// `pdf` or `epub` for imported documents, `notebook` otherwise
+    #[serde(rename = "fileType")]
+    file_type: Option<String>,
+    #[serde(default, rename = "pageTags")]
+    page_tags: Vec<PageTag>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PageTag {
+    #[serde(rename = "pageId")]
+    page_id: String,
 }
 
 #[derive(Debug, Deserialize)]
 struct MetadataFile {
     #[serde(rename = "visibleName")]
     visible_name: String,
+    /// `DocumentType` or `CollectionType` (a folder)
+    #[serde(rename = "type")]
+    kind: Option<String>,
     parent: Option<String>,
+    /// Set on documents the tablet is about to remove
+    #[serde(default)]
+    deleted: bool,
+    /// Starred as a favorite
+    #[serde(default)]
+    pinned: bool,
     #[serde(rename = "createdTime")]
     created_time: Option<String>,
     #[serde(rename = "lastModified")]
@@ -44,15 +122,23 @@ struct MetadataFile {
 /// Indexed metadata for O(1) lookups by notebook name
 #[derive(Debug, Clone)]
 struct IndexedMetadata {
+    uuid: Option<String>,
     created_time: Option<String>,
     modified_time: Option<String>,
     tags: Vec<String>,
     is_deleted: bool,
+    pinned: bool,
 }
 
 pub struct RemarkableClient {
     backup_dir: PathBuf,
     password: Option<String>,
+    transport: Transport,
+    ssh: Option<SshClient>,
+    device_sync: bool,
+    favorites_only: bool,
+    sync_timeout: Duration,
+    exporter: Option<Exporter>,
 }
 
 impl RemarkableClient {
@@ -69,21 +155,86 @@ impl RemarkableClient {
         Ok(Self {
             backup_dir,
             password,
+            transport: Transport::default(),
+            ssh: None,
+            device_sync: true,
+            favorites_only: false,
+            sync_timeout: DEFAULT_SYNC_TIMEOUT,
+            exporter: None,
         })
     }
 
+    /// SSH destination of the tablet, required for writing changes back and
+    /// for the SSH transport
+    pub fn with_ssh_host(mut self, ssh_host: Option<String>) -> Self {
+        self.ssh = ssh_host.map(SshClient::new);
+        self
+    }
+
+    /// How to back up the tablet when device sync is enabled
+    pub fn with_transport(mut self, transport: Transport) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// Whether to back up the tablet before reading the backup directory.
+    ///
+    /// Disable when RemarkableSync or another tool updates the backup
+    /// independently.
+    pub fn with_device_sync(mut self, device_sync: bool) -> Self {
+        self.device_sync = device_sync;
+        self
+    }
+
+    /// Only list notebooks starred (pinned) on the tablet
+    pub fn with_favorites_only(mut self, favorites_only: bool) -> Self {
+        self.favorites_only = favorites_only;
+        self
+    }
+
+    /// Kill RemarkableSync (or the SSH copy) if it runs longer than this
+    pub fn with_sync_timeout(mut self, sync_timeout: Duration) -> Self {
+        self.sync_timeout = sync_timeout;
+        self
+    }
+
+    /// Convert notebooks from their `.rm` pages with another tool
+    pub fn with_exporter(mut self, exporter: Option<Exporter>) -> Self {
+        self.exporter = exporter;
+        self
+    }
+
+    pub fn backup_dir(&self) -> &Path {
+        &self.backup_dir
+    }
+
     pub async fn check_installation(&self) -> Result<()> {
+        if !self.device_sync {
+            debug!("Device sync disabled, RemarkableSync not required");
+            return Ok(());
+        }
+        if self.transport == Transport::Ssh {
+            if self.exporter.is_none() {
+                return Err(Error::Config(
+                    "REMARKABLE_TRANSPORT=ssh needs an EXPORTER to convert notebooks".to_string(),
+                ));
+            }
+            return self.ssh_client("the SSH transport")?.check().await;
+        }
+
         debug!("Checking RemarkableSync installation");
 
-        let output = Command::new("RemarkableSync")
-            .arg("--version")
-            .output()
-            .map_err(|e| {
-                Error::Remarkable(format!(
-                    "RemarkableSync not found: {}. Install with: brew install remarkablesync",
-                    e
-                ))
-            })?;
+        let output = process::output(
+            Command::new("RemarkableSync").arg("--version"),
+            COMMAND_TIMEOUT,
+        )
+        .await
+        .map_err(|e| {
+            Error::Remarkable(format!(
+                "RemarkableSync not found: {}. Install with: brew install remarkablesync",
+                e
+            ))
+        })?;
 
         if !output.status.success() {
             return Err(Error::Remarkable(
@@ -97,6 +248,69 @@ impl RemarkableClient {
     }
 
     pub async fn list_notebooks(&self) -> Result<Vec<Notebook>> {
+        let exported: Option<HashSet<String>> = match (self.device_sync, self.transport) {
+            (true, Transport::RemarkableSync) => self
+                .sync_backup()
+                .await?
+                .exported()
+                .map(|exported| exported.into_iter().map(str::to_string).collect()),
+            (true, Transport::Ssh) => Some(self.pull_backup().await?),
+            (false, _) => {
+                debug!("Reading notebooks from {:?}", self.backup_dir);
+                None
+            }
+        };
+
+        // Find all converted PDFs in the backup directory
+        // RemarkableSync uses capital 'PDF' directory
+        let pdfs_dir = self.backup_dir.join("PDF");
+        if !pdfs_dir.exists() {
+            debug!("No PDF directory found yet - no notebooks synced");
+            return Ok(Vec::new());
+        }
+
+        // Pre-index all metadata and content files for O(1) lookups
+        let metadata_index = self.build_metadata_index()?;
+        debug!("Built metadata index with {} entries", metadata_index.len());
+
+        let mut notebooks = Vec::new();
+        Self::scan_pdfs_recursive(&pdfs_dir, "", &mut notebooks, &metadata_index)?;
+
+        // Read on every scan, so edits apply to a running daemon
+        let ignore = IgnoreFile::load(&self.backup_dir.join(IGNORE_FILE))?;
+        if !ignore.is_empty() {
+            for notebook in &mut notebooks {
+                notebook.ignored = ignore.is_ignored(&notebook.path);
+                if notebook.ignored {
+                    debug!("Ignoring {} ({})", notebook.path, IGNORE_FILE);
+                }
+            }
+        }
+
+        if self.favorites_only {
+            notebooks.retain(|notebook| notebook.pinned);
+            debug!("Keeping {} starred notebooks", notebooks.len());
+        }
+
+        if let Some(exported) = exported {
+            for notebook in &mut notebooks {
+                let was_exported = exported.contains(&notebook.name)
+                    || notebook
+                        .uuid
+                        .as_ref()
+                        .is_some_and(|uuid| exported.contains(uuid));
+                notebook.exported = Some(was_exported);
+            }
+        }
+
+        debug!("Found {} notebooks", notebooks.len());
+        Ok(notebooks)
+    }
+
+    /// Run RemarkableSync to back up the tablet and convert notebooks to PDF.
+    ///
+    /// Its output is logged line by line as it runs (visible with `--verbose`).
+    async fn sync_backup(&self) -> Result<SyncOutput> {
         info!("Syncing from reMarkable (USB)...");
         debug!("⚠️  Make sure your ReMarkable tablet is connected via USB!");
 
@@ -105,22 +319,38 @@ impl RemarkableClient {
         cmd.arg("sync")
             .arg("--backup-dir")
             .arg(&self.backup_dir)
-            .arg("--skip-templates"); // Skip templates to avoid errors
+            .arg("--skip-templates") // Skip templates to avoid errors
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
 
         if let Some(ref password) = self.password {
             cmd.arg("--password").arg(password);
         }
 
-        let output = cmd
-            .output()
+        let mut child = cmd
+            .spawn()
             .map_err(|e| Error::Remarkable(format!("Failed to run RemarkableSync: {}", e)))?;
+        let stdout = stream_output(child.stdout.take());
+        let stderr = stream_output(child.stderr.take());
 
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            let stdout = String::from_utf8_lossy(&output.stdout);
+        let status = match tokio::time::timeout(self.sync_timeout, child.wait()).await {
+            Ok(status) => status?,
+            Err(_) => {
+                child.kill().await?;
+                return Err(Error::Remarkable(format!(
+                    "RemarkableSync did not finish within {}s and was killed. Check the USB connection or raise REMARKABLE_SYNC_TIMEOUT.",
+                    self.sync_timeout.as_secs()
+                )));
+            }
+        };
+        let stdout = stdout.await.unwrap_or_default();
+        let stderr = stderr.await.unwrap_or_default();
+        let output = SyncOutput::parse(&stdout);
 
+        if !status.success() {
             // Check if it's just a template error but files synced successfully
-            if stdout.contains("All files are up to date") || stdout.contains("Backup completed") {
+            if output.backup_succeeded() {
                 debug!("Files synced successfully (template error ignored)");
             } else {
                 return Err(Error::Remarkable(format!(
@@ -130,23 +360,81 @@ impl RemarkableClient {
             }
         }
 
-        // Find all converted PDFs in the backup directory
-        // RemarkableSync uses capital 'PDF' directory
-        let pdfs_dir = self.backup_dir.join("PDF");
-        if !pdfs_dir.exists() {
-            debug!("No PDF directory found yet - no notebooks synced");
-            return Ok(Vec::new());
+        for error in output.errors() {
+            warn!("RemarkableSync: {}", error);
+        }
+        match output.exported() {
+            Some(exported) => debug!(
+                "RemarkableSync exported {} documents, skipped {}",
+                exported.len(),
+                output.skipped()
+            ),
+            None => {
+                debug!("RemarkableSync output not recognised, treating all notebooks as changed")
+            }
         }
 
-        // Pre-index all metadata and content files for O(1) lookups
-        let metadata_index = self.build_metadata_index()?;
-        debug!("Built metadata index with {} entries", metadata_index.len());
+        Ok(output)
+    }
 
-        let mut notebooks = Vec::new();
-        Self::scan_pdfs_recursive(&pdfs_dir, "", &mut notebooks, &metadata_index)?;
+    /// Copy the tablet's documents over SSH and convert the notebooks that
+    /// changed since their PDF was written, returning the converted UUIDs.
+    ///
+    /// PDFs land where RemarkableSync would put them (`PDF/<folder>/<name>.pdf`),
+    /// so the rest of the sync doesn't care which transport ran. Imported
+    /// PDFs are copied as they are, without annotations.
+    async fn pull_backup(&self) -> Result<HashSet<String>> {
+        let ssh = self.ssh_client("the SSH transport")?;
+        let exporter = self.exporter.as_ref().ok_or_else(|| {
+            Error::Config(
+                "REMARKABLE_TRANSPORT=ssh needs an EXPORTER to convert notebooks".to_string(),
+            )
+        })?;
+        info!("Syncing from reMarkable (SSH, {})...", ssh.host());
 
-        debug!("Found {} notebooks", notebooks.len());
-        Ok(notebooks)
+        let notebooks_dir = self.backup_dir.join("Notebooks");
+        ssh.pull(&notebooks_dir, self.sync_timeout).await?;
+
+        let mut exported = HashSet::new();
+        for (uuid, path, modified) in document_paths(&notebooks_dir)? {
+            let pdf = self.backup_dir.join("PDF").join(format!("{}.pdf", path));
+            let written = std::fs::metadata(&pdf)
+                .and_then(|meta| meta.modified())
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|age| age.as_millis() as i64);
+            if written.is_some_and(|written| modified.is_some_and(|modified| written >= modified)) {
+                continue;
+            }
+            if let Some(parent) = pdf.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+
+            let original = notebooks_dir.join(format!("{}.pdf", uuid));
+            let result = if original.exists() {
+                std::fs::copy(&original, &pdf)
+                    .map(|_| ())
+                    .map_err(Error::from)
+            } else {
+                exporter.export(&notebooks_dir, &uuid, &pdf).await
+            };
+            match result {
+                Ok(()) => {
+                    debug!("Converted {}", path);
+                    exported.insert(uuid);
+                }
+                Err(e) => warn!("Failed to convert '{}': {}", path, e),
+            }
+        }
+
+        debug!("Converted {} documents", exported.len());
+        Ok(exported)
+    }
+
+    fn ssh_client(&self, purpose: &str) -> Result<&SshClient> {
+        self.ssh.as_ref().ok_or_else(|| {
+            Error::Config(format!("REMARKABLE_SSH_HOST is required for {}", purpose))
+        })
     }
 
     fn scan_pdfs_recursive(
@@ -177,23 +465,26 @@ impl RemarkableClient {
                 };
 
                 // O(1) lookup from pre-built index
-                let (created_time, modified_time, tags, is_deleted) =
+                let (uuid, created_time, modified_time, tags, is_deleted, pinned) =
                     if let Some(meta) = metadata_index.get(&name) {
                         (
+                            meta.uuid.clone(),
                             meta.created_time.clone(),
                             meta.modified_time.clone(),
                             meta.tags.clone(),
                             meta.is_deleted,
+                            meta.pinned,
                         )
                     } else {
                         debug!("No metadata found for {}", name);
-                        (None, None, Vec::new(), false)
+                        (None, None, None, Vec::new(), false, false)
                     };
 
                 notebooks.push(Notebook {
                     name,
                     path: full_name.clone(),
                     id: full_name.clone(),
+                    uuid,
                     metadata: NotebookMetadata {
                         created_time,
                         modified_time,
@@ -201,6 +492,9 @@ impl RemarkableClient {
                     },
                     tags,
                     is_deleted,
+                    pinned,
+                    exported: None,
+                    ignored: false,
                 });
             }
         }
@@ -279,10 +573,12 @@ impl RemarkableClient {
                         index.insert(
                             metadata.visible_name.clone(),
                             IndexedMetadata {
+                                uuid,
                                 created_time,
                                 modified_time,
                                 tags,
                                 is_deleted,
+                                pinned: metadata.pinned,
                             },
                         );
                     }
@@ -299,13 +595,62 @@ impl RemarkableClient {
         notebook: &Notebook,
         output_dir: &Path,
     ) -> Result<PathBuf> {
+        let output_path = output_dir.join(format!("{}.pdf", notebook.name));
+
+        // RemarkableSync's PDFs lose the colors of Paper Pro notebooks, so
+        // those are rendered natively instead
+        let native;
+        let exporter = match self.exporter {
+            Some(ref exporter) => Some(exporter),
+            None if self.has_color(notebook) => {
+                debug!("'{}' has colored strokes", notebook.name);
+                native = Exporter::native();
+                Some(&native)
+            }
+            None => None,
+        };
+        if let (true, Some(uuid)) = (self.is_annotated_pdf(notebook), &notebook.uuid) {
+            let notebooks_dir = self.backup_dir.join("Notebooks");
+            let original = notebooks_dir.join(format!("{}.pdf", uuid));
+            if original.exists() {
+                let native = Exporter::native();
+                let exporter = exporter.unwrap_or(&native);
+                debug!("Merging the annotations of {}", notebook.name);
+                match exporter
+                    .export_annotated(&notebooks_dir, uuid, &original, &output_path)
+                    .await
+                {
+                    Ok(()) => return Ok(output_path),
+                    Err(e) => warn!(
+                        "Couldn't merge the annotations of '{}' into its PDF: {}",
+                        notebook.name, e
+                    ),
+                }
+            }
+        }
+
+        // The SSH transport already converted the notebook with the exporter
+        let exporter = exporter.filter(|_| self.transport == Transport::RemarkableSync);
+        if let (Some(exporter), Some(uuid)) = (exporter, &notebook.uuid) {
+            debug!("Exporting {} with {}", notebook.name, exporter.name());
+            match exporter
+                .export(&self.backup_dir.join("Notebooks"), uuid, &output_path)
+                .await
+            {
+                Ok(()) => return Ok(output_path),
+                Err(e) => warn!(
+                    "{} couldn't export '{}', using RemarkableSync's PDF: {}",
+                    exporter.name(),
+                    notebook.name,
+                    e
+                ),
+            }
+        }
+
         debug!("Copying notebook PDF: {}", notebook.name);
 
         // The PDF is already in the backup directory (capital PDF), just copy it
-        let source_path = self
-            .backup_dir
-            .join("PDF")
-            .join(format!("{}.pdf", notebook.path));
+        let source_path = self.backup_pdf(notebook);
 
         if !source_path.exists() {
             return Err(Error::Remarkable(format!(
@@ -314,10 +659,447 @@ impl RemarkableClient {
             )));
         }
 
-        let output_path = output_dir.join(format!("{}.pdf", notebook.name));
         std::fs::copy(&source_path, &output_path)?;
 
         debug!("Copied to: {:?}", output_path);
         Ok(output_path)
     }
+
+    /// Whether any page of the notebook has strokes in color (Paper Pro)
+    pub fn has_color(&self, notebook: &Notebook) -> bool {
+        let Some(ref uuid) = notebook.uuid else {
+            return false;
+        };
+        let notebooks_dir = self.backup_dir.join("Notebooks");
+        let Ok(page_ids) = page_ids(&notebooks_dir, uuid) else {
+            return false;
+        };
+        page_ids.iter().any(|page_id| {
+            std::fs::read(notebooks_dir.join(uuid).join(format!("{}.rm", page_id)))
+                .ok()
+                .and_then(|data| lines::parse(&data).ok())
+                .is_some_and(|page| lines::has_color(&page))
+        })
+    }
+
+    /// PDF RemarkableSync wrote for the notebook in the backup
+    pub fn backup_pdf(&self, notebook: &Notebook) -> PathBuf {
+        self.backup_dir
+            .join("PDF")
+            .join(format!("{}.pdf", notebook.path))
+    }
+
+    /// Number of pages according to the notebook's content file
+    pub fn page_count(&self, notebook: &Notebook) -> Option<usize> {
+        let uuid = notebook.uuid.as_deref()?;
+        page_list(&self.backup_dir.join("Notebooks"), uuid)
+            .ok()
+            .map(|pages| pages.len())
+    }
+
+    /// Write each page's strokes as JSON to `Strokes/<uuid>/page-<n>.json` in
+    /// the backup dir, returning how many sidecars were (re)written.
+    ///
+    /// Sidecars newer than their `.rm` file are left alone.
+    pub fn write_stroke_sidecars(&self, notebook: &Notebook) -> Result<usize> {
+        #[derive(Serialize)]
+        struct Sidecar<'a> {
+            notebook: &'a str,
+            page: usize,
+            page_id: &'a str,
+            /// When the page file was last written
+            modified: Option<String>,
+            #[serde(flatten)]
+            strokes: lines::Page,
+        }
+
+        let uuid = notebook.uuid.as_ref().ok_or_else(|| {
+            Error::Remarkable(format!("No document UUID known for '{}'", notebook.name))
+        })?;
+        let notebooks_dir = self.backup_dir.join("Notebooks");
+        let sidecar_dir = self.backup_dir.join("Strokes").join(uuid);
+        std::fs::create_dir_all(&sidecar_dir)?;
+
+        let mut written = 0;
+        for (i, page_id) in page_ids(&notebooks_dir, uuid)?.iter().enumerate() {
+            let rm_path = notebooks_dir.join(uuid).join(format!("{}.rm", page_id));
+            let Ok(rm_modified) = std::fs::metadata(&rm_path).and_then(|m| m.modified()) else {
+                continue; // blank page
+            };
+
+            let sidecar_path = sidecar_dir.join(format!("page-{}.json", i + 1));
+            let up_to_date = std::fs::metadata(&sidecar_path)
+                .and_then(|m| m.modified())
+                .is_ok_and(|modified| modified >= rm_modified);
+            if up_to_date {
+                continue;
+            }
+
+            let sidecar = Sidecar {
+                notebook: &notebook.name,
+                page: i + 1,
+                page_id,
+                modified: Some(
+                    chrono::DateTime::<chrono::Utc>::from(rm_modified)
+                        .to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+                ),
+                strokes: lines::parse(&std::fs::read(&rm_path)?)?,
+            };
+            std::fs::write(&sidecar_path, serde_json::to_string(&sidecar)?)?;
+            written += 1;
+        }
+
+        debug!("Wrote {} stroke sidecars for '{}'", written, notebook.name);
+        Ok(written)
+    }
+
+    /// When each page was last written, in page order.
+    ///
+    /// Taken from the `.content` page list where the format records it,
+    /// otherwise from the page's `.rm` file. Empty if the notebook's pages
+    /// can't be read.
+    pub fn page_times(&self, notebook: &Notebook) -> Vec<Option<chrono::DateTime<chrono::Utc>>> {
+        let Some(uuid) = &notebook.uuid else {
+            return Vec::new();
+        };
+        let notebooks_dir = self.backup_dir.join("Notebooks");
+        let pages = match page_list(&notebooks_dir, uuid) {
+            Ok(pages) => pages,
+            Err(e) => {
+                debug!("No page list for '{}': {}", notebook.name, e);
+                return Vec::new();
+            }
+        };
+
+        pages
+            .into_iter()
+            .map(|page| {
+                page.modified.or_else(|| {
+                    let rm_path = notebooks_dir.join(uuid).join(format!("{}.rm", page.id));
+                    std::fs::metadata(rm_path)
+                        .and_then(|m| m.modified())
+                        .ok()
+                        .map(chrono::DateTime::from)
+                })
+            })
+            .collect()
+    }
+
+    /// Template of every page in document order; empty when the page list isn't available
+    pub fn page_templates(&self, notebook: &Notebook) -> Vec<Option<String>> {
+        let Some(uuid) = &notebook.uuid else {
+            return Vec::new();
+        };
+        match page_list(&self.backup_dir.join("Notebooks"), uuid) {
+            Ok(pages) => pages.into_iter().map(|page| page.template).collect(),
+            Err(e) => {
+                debug!("No page list for '{}': {}", notebook.name, e);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Whether the notebook is an imported PDF or EPUB rather than handwritten
+    pub fn is_imported(&self, notebook: &Notebook) -> bool {
+        self.content_file(notebook)
+            .and_then(|content| content.file_type)
+            .is_some_and(|file_type| file_type == "pdf" || file_type == "epub")
+    }
+
+    /// Whether the notebook is an imported PDF with handwriting on it
+    pub fn is_annotated_pdf(&self, notebook: &Notebook) -> bool {
+        let Some(uuid) = &notebook.uuid else {
+            return false;
+        };
+        let is_pdf = self
+            .content_file(notebook)
+            .and_then(|content| content.file_type)
+            .is_some_and(|file_type| file_type == "pdf");
+        let notebooks_dir = self.backup_dir.join("Notebooks");
+        is_pdf
+            && page_ids(&notebooks_dir, uuid).is_ok_and(|page_ids| {
+                page_ids.iter().any(|page_id| {
+                    notebooks_dir
+                        .join(uuid)
+                        .join(format!("{}.rm", page_id))
+                        .exists()
+                })
+            })
+    }
+
+    /// The kind of document, for the page's "Document Type" property
+    pub fn document_type(&self, notebook: &Notebook) -> Option<&'static str> {
+        if self.is_annotated_pdf(notebook) {
+            return Some("Annotated PDF");
+        }
+        let content = self.content_file(notebook)?;
+        Some(match content.file_type.as_deref() {
+            Some("pdf") => "PDF",
+            Some("epub") => "EPUB",
+            _ => "Notebook",
+        })
+    }
+
+    /// The (1-based) pages someone wrote on, highlighted or tagged
+    pub fn marked_pages(&self, notebook: &Notebook) -> Vec<usize> {
+        let Some(uuid) = &notebook.uuid else {
+            return Vec::new();
+        };
+        let notebooks_dir = self.backup_dir.join("Notebooks");
+        let pages = match page_list(&notebooks_dir, uuid) {
+            Ok(pages) => pages,
+            Err(e) => {
+                debug!("No page list for '{}': {}", notebook.name, e);
+                return Vec::new();
+            }
+        };
+        let tagged: HashSet<String> = self
+            .content_file(notebook)
+            .map(|content| {
+                content
+                    .page_tags
+                    .into_iter()
+                    .map(|tag| tag.page_id)
+                    .collect()
+            })
+            .unwrap_or_default();
+        let highlights_dir = notebooks_dir.join(format!("{}.highlights", uuid));
+
+        pages
+            .iter()
+            .enumerate()
+            .filter(|(_, page)| {
+                tagged.contains(&page.id)
+                    || notebooks_dir
+                        .join(uuid)
+                        .join(format!("{}.rm", page.id))
+                        .exists()
+                    || highlights_dir.join(format!("{}.json", page.id)).exists()
+            })
+            .map(|(i, _)| i + 1)
+            .collect()
+    }
+
+    fn content_file(&self, notebook: &Notebook) -> Option<ContentFile> {
+        let path = self
+            .backup_dir
+            .join("Notebooks")
+            .join(format!("{}.content", notebook.uuid.as_ref()?));
+        serde_json::from_str(&std::fs::read_to_string(path).ok()?).ok()
+    }
+
+    /// Add tags to a notebook on the tablet by rewriting its `.content` file over SSH.
+    ///
+    /// The backup copy is updated as well so the next run sees the new tags.
+    /// Takes effect on the tablet after `restart_ui`.
+    pub async fn add_tags(&self, notebook: &Notebook, tags: &[String]) -> Result<()> {
+        let ssh = self.ssh_client("writing tags back")?;
+        let uuid = notebook.uuid.as_ref().ok_or_else(|| {
+            Error::Remarkable(format!("No document UUID known for '{}'", notebook.name))
+        })?;
+
+        let content_path = self
+            .backup_dir
+            .join("Notebooks")
+            .join(format!("{}.content", uuid));
+        let mut content: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&content_path)?).map_err(|e| {
+                Error::Remarkable(format!("Invalid content file {:?}: {}", content_path, e))
+            })?;
+
+        let timestamp = chrono::Utc::now().timestamp_millis();
+        let existing = content["tags"].as_array().cloned().unwrap_or_default();
+        let mut updated = existing.clone();
+        for tag in tags {
+            if !existing.iter().any(|t| t["name"].as_str() == Some(tag)) {
+                updated.push(serde_json::json!({ "name": tag, "timestamp": timestamp }));
+            }
+        }
+        if updated.len() == existing.len() {
+            return Ok(());
+        }
+        content["tags"] = serde_json::Value::Array(updated);
+
+        std::fs::write(&content_path, serde_json::to_string_pretty(&content)?)?;
+
+        ssh.upload(&content_path, &format!("{}.content", uuid))
+            .await?;
+
+        debug!("Wrote {} tags back to '{}'", tags.len(), notebook.name);
+        Ok(())
+    }
+
+    /// Restart the tablet UI so it picks up rewritten `.content` files
+    pub async fn restart_ui(&self) -> Result<()> {
+        self.ssh_client("restarting the tablet UI")?
+            .run("systemctl restart xochitl")
+            .await
+    }
+}
+
+/// Documents in a copy of the tablet's document directory, as (UUID, path
+/// like `Work/Meetings/Standup`, last modified in ms); trashed ones are left out
+fn document_paths(notebooks_dir: &Path) -> Result<Vec<(String, String, Option<i64>)>> {
+    let mut entries = HashMap::new();
+    for entry in std::fs::read_dir(notebooks_dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|s| s.to_str()) != Some("metadata") {
+            continue;
+        }
+        let (Some(uuid), Ok(text)) = (
+            path.file_stem().and_then(|s| s.to_str()),
+            std::fs::read_to_string(&path),
+        ) else {
+            continue;
+        };
+        match serde_json::from_str::<MetadataFile>(&text) {
+            Ok(metadata) => {
+                entries.insert(uuid.to_string(), metadata);
+            }
+            Err(e) => debug!("Skipping unreadable metadata {:?}: {}", path, e),
+        }
+    }
+
+    let mut documents = Vec::new();
+    for (uuid, metadata) in &entries {
+        if metadata.deleted || metadata.kind.as_deref() == Some("CollectionType") {
+            continue;
+        }
+        // Names may contain slashes, which would make up folders
+        let mut names = vec![metadata.visible_name.replace('/', "_")];
+        let mut parent = metadata.parent.as_deref();
+        let mut in_trash = false;
+        while let Some(id) = parent.filter(|id| !id.is_empty()) {
+            if id == "trash" {
+                in_trash = true;
+                break;
+            }
+            let Some(folder) = entries.get(id) else {
+                break;
+            };
+            names.push(folder.visible_name.replace('/', "_"));
+            parent = folder.parent.as_deref();
+            // Guard against a cycle in broken metadata
+            if names.len() > entries.len() {
+                break;
+            }
+        }
+        if in_trash {
+            continue;
+        }
+        names.reverse();
+        let modified = metadata
+            .last_modified
+            .as_deref()
+            .and_then(|ts| ts.parse().ok());
+        documents.push((uuid.clone(), names.join("/"), modified));
+    }
+    Ok(documents)
+}
+
+/// A page of a document, from its `.content` file
+pub struct PageEntry {
+    pub id: String,
+    /// Last modification of the page, if the file format records it (v6)
+    pub modified: Option<chrono::DateTime<chrono::Utc>>,
+    /// Name of the page's template (e.g. "P Lines small"), if known
+    pub template: Option<String>,
+}
+
+/// Pages in document order from the `.content` file (v5 `pages` or v6 `cPages`)
+pub fn page_list(notebooks_dir: &Path, uuid: &str) -> Result<Vec<PageEntry>> {
+    #[derive(Deserialize)]
+    struct Content {
+        #[serde(default)]
+        pages: Vec<String>,
+        #[serde(rename = "cPages")]
+        c_pages: Option<CPages>,
+    }
+
+    #[derive(Deserialize)]
+    struct CPages {
+        #[serde(default)]
+        pages: Vec<CPage>,
+    }
+
+    #[derive(Deserialize)]
+    struct CPage {
+        id: String,
+        /// Present on pages deleted from the notebook
+        deleted: Option<serde_json::Value>,
+        /// Milliseconds since the epoch (misspelled by the tablet)
+        #[serde(alias = "modified")]
+        modifed: Option<String>,
+        template: Option<Value>,
+    }
+
+    #[derive(Deserialize)]
+    struct Value {
+        value: String,
+    }
+
+    let path = notebooks_dir.join(format!("{}.content", uuid));
+    let content: Content = serde_json::from_str(&std::fs::read_to_string(&path)?)
+        .map_err(|e| Error::Remarkable(format!("Invalid content file {:?}: {}", path, e)))?;
+
+    Ok(match content.c_pages {
+        Some(c_pages) => c_pages
+            .pages
+            .into_iter()
+            .filter(|page| page.deleted.is_none())
+            .map(|page| PageEntry {
+                modified: page
+                    .modifed
+                    .and_then(|ms| ms.parse().ok())
+                    .and_then(chrono::DateTime::from_timestamp_millis),
+                template: page.template.map(|template| template.value),
+                id: page.id,
+            })
+            .collect(),
+        None => {
+            // Older firmware lists each page's template on a line of `.pagedata`
+            let pagedata =
+                std::fs::read_to_string(notebooks_dir.join(format!("{}.pagedata", uuid)))
+                    .unwrap_or_default();
+            let mut templates = pagedata.lines().map(|line| line.trim().to_string());
+            content
+                .pages
+                .into_iter()
+                .map(|id| PageEntry {
+                    id,
+                    modified: None,
+                    template: templates.next().filter(|name| !name.is_empty()),
+                })
+                .collect()
+        }
+    })
+}
+
+/// Page IDs in document order
+pub fn page_ids(notebooks_dir: &Path, uuid: &str) -> Result<Vec<String>> {
+    Ok(page_list(notebooks_dir, uuid)?
+        .into_iter()
+        .map(|page| page.id)
+        .collect())
+}
+
+/// Log a child's output as it arrives, collecting it for inspection afterwards
+fn stream_output<R>(reader: Option<R>) -> JoinHandle<String>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut output = String::new();
+        let Some(reader) = reader else {
+            return output;
+        };
+
+        let mut lines = BufReader::new(reader).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            debug!("RemarkableSync: {}", line);
+            output.push_str(&line);
+            output.push('\n');
+        }
+        output
+    })
 }
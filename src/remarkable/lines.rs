@@ -0,0 +1,511 @@
+//! Native parser for the stroke data in `.rm` page files (v3, v5 and v6).
+//!
+//! Only pen strokes are read; text, glyph highlights and the CRDT structure
+//! of v6 files are skipped.
+
+use crate::error::{Error, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+const HEADER_PREFIX: &[u8] = b"reMarkable .lines file, version=";
+const HEADER_LEN: usize = 43;
+
+/// v6 block holding one stroke
+const SCENE_LINE_ITEM_BLOCK: u8 = 0x05;
+
+/// Strokes of one page, by layer
+#[derive(Debug, Serialize)]
+pub struct Page {
+    pub version: u8,
+    pub layers: Vec<Layer>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Layer {
+    /// Scene group of the layer (v6) or its position (older versions)
+    pub id: String,
+    pub strokes: Vec<Stroke>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Stroke {
+    pub pen: &'static str,
+    pub color: &'static str,
+    /// Base line width (thickness scale in v6)
+    pub width: f32,
+    /// Logical clock of the stroke (v6 only): orders strokes, it isn't a time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<String>,
+    /// Exact color as `0xAARRGGBB`, written by the Paper Pro next to `color`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub argb: Option<u32>,
+    pub points: Vec<Point>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Point {
+    pub x: f32,
+    pub y: f32,
+    pub speed: f32,
+    pub direction: f32,
+    pub width: f32,
+    pub pressure: f32,
+}
+
+/// Parse the strokes of a `.rm` file
+pub fn parse(data: &[u8]) -> Result<Page> {
+    if data.len() < HEADER_LEN || !data.starts_with(HEADER_PREFIX) {
+        return Err(invalid("not a reMarkable lines file"));
+    }
+    let version = data[HEADER_PREFIX.len()..HEADER_LEN]
+        .iter()
+        .take_while(|b| b.is_ascii_digit())
+        .fold(0u8, |v, b| v.saturating_mul(10).saturating_add(b - b'0'));
+
+    let mut reader = Reader::new(&data[HEADER_LEN..]);
+    let layers = match version {
+        3 | 5 => parse_v5(&mut reader, version)?,
+        6 => parse_v6(&mut reader)?,
+        other => return Err(invalid(&format!("unsupported version {}", other))),
+    };
+
+    Ok(Page { version, layers })
+}
+
+/// Whether any stroke on the page is drawn in a color rather than black,
+/// gray or white
+pub fn has_color(page: &Page) -> bool {
+    page.layers
+        .iter()
+        .flat_map(|layer| &layer.strokes)
+        .filter(|stroke| !stroke.pen.starts_with("eraser"))
+        .any(|stroke| match stroke.argb {
+            Some(argb) => {
+                let [_, r, g, b] = argb.to_be_bytes();
+                r != g || g != b
+            }
+            None => !matches!(stroke.color, "black" | "gray" | "white" | "unknown"),
+        })
+}
+
+fn parse_v5(reader: &mut Reader, version: u8) -> Result<Vec<Layer>> {
+    let layer_count = reader.u32()?;
+    let mut layers = Vec::new();
+
+    for layer_index in 0..layer_count {
+        let stroke_count = reader.u32()?;
+        let mut strokes = Vec::new();
+
+        for _ in 0..stroke_count {
+            let pen = reader.u32()?;
+            let color = reader.u32()?;
+            reader.u32()?; // unknown
+            let width = reader.f32()?;
+            if version == 5 {
+                reader.u32()?; // unknown
+            }
+
+            let point_count = reader.u32()?;
+            let mut points = Vec::new();
+            for _ in 0..point_count {
+                points.push(Point {
+                    x: reader.f32()?,
+                    y: reader.f32()?,
+                    speed: reader.f32()?,
+                    direction: reader.f32()?,
+                    width: reader.f32()?,
+                    pressure: reader.f32()?,
+                });
+            }
+
+            strokes.push(Stroke {
+                pen: pen_name(pen),
+                color: color_name(color),
+                width,
+                timestamp: None,
+                argb: None,
+                points,
+            });
+        }
+
+        layers.push(Layer {
+            id: (layer_index + 1).to_string(),
+            strokes,
+        });
+    }
+
+    Ok(layers)
+}
+
+fn parse_v6(reader: &mut Reader) -> Result<Vec<Layer>> {
+    let mut layers: BTreeMap<String, Vec<Stroke>> = BTreeMap::new();
+
+    while !reader.is_empty() {
+        let length = reader.u32()? as usize;
+        reader.u8()?; // unknown
+        reader.u8()?; // min version
+        let block_version = reader.u8()?;
+        let block_type = reader.u8()?;
+        let mut block = Reader::new(reader.take(length)?);
+
+        if block_type != SCENE_LINE_ITEM_BLOCK {
+            continue;
+        }
+        // Blocks we can't make sense of are skipped rather than failing the page
+        if let Ok(Some((parent, stroke))) = parse_line_item(&mut block, block_version) {
+            layers.entry(parent).or_default().push(stroke);
+        }
+    }
+
+    Ok(layers
+        .into_iter()
+        .map(|(id, strokes)| Layer { id, strokes })
+        .collect())
+}
+
+/// A stroke and the scene group it belongs to, or `None` for deleted items
+fn parse_line_item(block: &mut Reader, block_version: u8) -> Result<Option<(String, Stroke)>> {
+    let parent = block.tagged_id(1)?;
+    block.tagged_id(2)?; // item
+    block.tagged_id(3)?; // left
+    block.tagged_id(4)?; // right
+    block.tagged_u32(5)?; // deleted length
+    if block.is_empty() {
+        return Ok(None);
+    }
+
+    let mut value = Reader::new(block.tagged_subblock(6)?);
+    value.u8()?; // item type
+
+    let pen = value.tagged_u32(1)?;
+    let color = value.tagged_u32(2)?;
+    let width = value.tagged_f64(3)? as f32;
+    value.tagged_f32(4)?; // starting length
+
+    let mut data = Reader::new(value.tagged_subblock(5)?);
+    let mut points = Vec::new();
+    while !data.is_empty() {
+        points.push(if block_version >= 2 {
+            Point {
+                x: data.f32()?,
+                y: data.f32()?,
+                speed: data.u16()? as f32,
+                // Stored in quarter pixels
+                width: data.u16()? as f32 / 4.0,
+                direction: data.u8()? as f32,
+                pressure: data.u8()? as f32,
+            }
+        } else {
+            Point {
+                x: data.f32()?,
+                y: data.f32()?,
+                speed: data.f32()?,
+                direction: data.f32()?,
+                width: data.f32()?,
+                pressure: data.f32()?,
+            }
+        });
+    }
+
+    let timestamp = value.tagged_id(6).ok();
+    // Paper Pro files follow with a move ID and the exact color
+    value.optional(|value| value.tagged_id(7));
+    let argb = value.optional(|value| value.tagged_u32(8));
+
+    Ok(Some((
+        parent,
+        Stroke {
+            pen: pen_name(pen),
+            color: color_name(color),
+            width,
+            timestamp,
+            argb,
+            points,
+        },
+    )))
+}
+
+fn pen_name(id: u32) -> &'static str {
+    match id {
+        0 | 12 => "paintbrush",
+        1 | 14 => "pencil",
+        2 | 15 => "ballpoint",
+        3 | 16 => "marker",
+        4 | 17 => "fineliner",
+        5 | 18 => "highlighter",
+        6 => "eraser",
+        7 | 13 => "mechanical_pencil",
+        8 => "eraser_area",
+        21 => "calligraphy",
+        23 => "shader",
+        _ => "unknown",
+    }
+}
+
+fn color_name(id: u32) -> &'static str {
+    match id {
+        0 => "black",
+        1 | 8 => "gray",
+        2 => "white",
+        3 | 13 => "yellow",
+        4 | 10 => "green",
+        5 => "pink",
+        6 => "blue",
+        7 => "red",
+        9 => "highlight",
+        11 => "cyan",
+        12 => "magenta",
+        _ => "unknown",
+    }
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::Remarkable(format!("Invalid .rm file: {}", reason))
+}
+
+/// Little-endian cursor over a byte slice
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Read a value that may be missing, leaving the reader where it was if
+    /// it is
+    fn optional<T>(&mut self, read: impl FnOnce(&mut Self) -> Result<T>) -> Option<T> {
+        let mut ahead = Reader { data: self.data };
+        let value = read(&mut ahead).ok()?;
+        self.data = ahead.data;
+        Some(value)
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.data.len() < n {
+            return Err(invalid("unexpected end of file"));
+        }
+        let (head, tail) = self.data.split_at(n);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn f32(&mut self) -> Result<f32> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn f64(&mut self) -> Result<f64> {
+        Ok(f64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn varuint(&mut self) -> Result<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(invalid("varint too long"))
+    }
+
+    /// Check the next tag's index and type (v6 tags are `index << 4 | type`)
+    fn tag(&mut self, index: u64, tag_type: u64) -> Result<()> {
+        let tag = self.varuint()?;
+        if tag != (index << 4 | tag_type) {
+            return Err(invalid("unexpected tag"));
+        }
+        Ok(())
+    }
+
+    fn tagged_id(&mut self, index: u64) -> Result<String> {
+        self.tag(index, 0xf)?;
+        let part1 = self.u8()?;
+        let part2 = self.varuint()?;
+        Ok(format!("{}:{}", part1, part2))
+    }
+
+    fn tagged_u32(&mut self, index: u64) -> Result<u32> {
+        self.tag(index, 0x4)?;
+        self.u32()
+    }
+
+    fn tagged_f32(&mut self, index: u64) -> Result<f32> {
+        self.tag(index, 0x4)?;
+        self.f32()
+    }
+
+    fn tagged_f64(&mut self, index: u64) -> Result<f64> {
+        self.tag(index, 0x8)?;
+        self.f64()
+    }
+
+    fn tagged_subblock(&mut self, index: u64) -> Result<&'a [u8]> {
+        self.tag(index, 0xc)?;
+        let length = self.u32()? as usize;
+        self.take(length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(version: u8) -> Vec<u8> {
+        let mut data = format!("reMarkable .lines file, version={}", version).into_bytes();
+        data.resize(HEADER_LEN, b' ');
+        data
+    }
+
+    fn varuint(mut value: u64, out: &mut Vec<u8>) {
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                out.push(byte);
+                return;
+            }
+            out.push(byte | 0x80);
+        }
+    }
+
+    fn tagged_id(index: u64, part1: u8, part2: u64, out: &mut Vec<u8>) {
+        varuint(index << 4 | 0xf, out);
+        out.push(part1);
+        varuint(part2, out);
+    }
+
+    fn tagged_u32(index: u64, value: u32, out: &mut Vec<u8>) {
+        varuint(index << 4 | 0x4, out);
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn tagged_subblock(index: u64, content: &[u8], out: &mut Vec<u8>) {
+        varuint(index << 4 | 0xc, out);
+        out.extend_from_slice(&(content.len() as u32).to_le_bytes());
+        out.extend_from_slice(content);
+    }
+
+    fn v6_block(block_type: u8, content: &[u8], out: &mut Vec<u8>) {
+        out.extend_from_slice(&(content.len() as u32).to_le_bytes());
+        out.extend_from_slice(&[0, 1, 2, block_type]);
+        out.extend_from_slice(content);
+    }
+
+    /// A v6 line item in group 0:11; without `stroke` it's a deleted item
+    fn line_item(stroke: bool) -> Vec<u8> {
+        let mut item = Vec::new();
+        tagged_id(1, 0, 11, &mut item);
+        tagged_id(2, 0, 20, &mut item);
+        tagged_id(3, 0, 0, &mut item);
+        tagged_id(4, 0, 0, &mut item);
+        tagged_u32(5, 0, &mut item);
+        if !stroke {
+            return item;
+        }
+
+        let mut points = Vec::new();
+        for (x, y) in [(-100.0f32, 50.0f32), (-90.0, 55.0)] {
+            points.extend_from_slice(&x.to_le_bytes());
+            points.extend_from_slice(&y.to_le_bytes());
+            points.extend_from_slice(&3u16.to_le_bytes()); // speed
+            points.extend_from_slice(&8u16.to_le_bytes()); // width
+            points.extend_from_slice(&[1, 200]); // direction, pressure
+        }
+        let mut value = vec![3];
+        tagged_u32(1, 17, &mut value);
+        tagged_u32(2, 0, &mut value);
+        varuint(3 << 4 | 0x8, &mut value);
+        value.extend_from_slice(&2.0f64.to_le_bytes());
+        varuint(4 << 4 | 0x4, &mut value);
+        value.extend_from_slice(&0.0f32.to_le_bytes());
+        tagged_subblock(5, &points, &mut value);
+        tagged_id(6, 1, 5, &mut value);
+        tagged_id(7, 0, 0, &mut value);
+        tagged_u32(8, 0xff2f6fd6, &mut value);
+
+        tagged_subblock(6, &value, &mut item);
+        item
+    }
+
+    #[test]
+    fn parses_v5_strokes() {
+        let mut data = header(5);
+        for value in [1u32, 1, 2, 6, 0] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(&2.0f32.to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&1u32.to_le_bytes());
+        for value in [10.0f32, 20.0, 1.0, 0.5, 2.5, 0.8] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let page = parse(&data).unwrap();
+        assert_eq!(page.version, 5);
+        assert_eq!(page.layers.len(), 1);
+        assert_eq!(page.layers[0].id, "1");
+        let stroke = &page.layers[0].strokes[0];
+        assert_eq!(
+            (stroke.pen, stroke.color, stroke.width),
+            ("ballpoint", "blue", 2.0)
+        );
+        assert_eq!(stroke.points.len(), 1);
+        assert_eq!((stroke.points[0].x, stroke.points[0].width), (10.0, 2.5));
+        assert!(has_color(&page));
+    }
+
+    #[test]
+    fn parses_v6_line_items() {
+        let mut data = header(6);
+        // Other blocks and deleted items are skipped
+        v6_block(0x01, &[1, 2, 3], &mut data);
+        v6_block(SCENE_LINE_ITEM_BLOCK, &line_item(false), &mut data);
+        v6_block(SCENE_LINE_ITEM_BLOCK, &line_item(true), &mut data);
+
+        let page = parse(&data).unwrap();
+        assert_eq!(page.version, 6);
+        assert_eq!(page.layers.len(), 1);
+        assert_eq!(page.layers[0].id, "0:11");
+        let stroke = &page.layers[0].strokes[0];
+        assert_eq!(
+            (stroke.pen, stroke.color, stroke.width),
+            ("fineliner", "black", 2.0)
+        );
+        assert_eq!(stroke.timestamp.as_deref(), Some("1:5"));
+        assert_eq!(stroke.argb, Some(0xff2f6fd6));
+        assert_eq!(stroke.points.len(), 2);
+        assert_eq!((stroke.points[0].x, stroke.points[0].width), (-100.0, 2.0));
+        assert_eq!(stroke.points[1].pressure, 200.0);
+        // The exact color is blue, though the palette color is black
+        assert!(has_color(&page));
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(parse(b"%PDF-1.4").is_err());
+        assert!(parse(&header(4)).is_err());
+        // Truncated: a layer count but no layers
+        let mut data = header(5);
+        data.extend_from_slice(&1u32.to_le_bytes());
+        assert!(parse(&data).is_err());
+    }
+}
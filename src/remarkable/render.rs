@@ -0,0 +1,227 @@
+//! Renders pages parsed by `lines` as SVG or PDF, without external tools.
+//!
+//! Strokes are drawn as plain lines with their average width; pen textures
+//! (pencil grain, brush pressure) and page templates aren't reproduced.
+
+use crate::remarkable::lines::{Page, Point, Stroke};
+use std::fmt::Write;
+
+/// Page size in pixels of the tablet's screen
+pub const PAGE_WIDTH: f32 = 1404.0;
+pub const PAGE_HEIGHT: f32 = 1872.0;
+
+/// Screen resolution, for sizing PDF pages
+const SCREEN_DPI: f32 = 226.0;
+
+/// How a stroke is drawn
+struct Style {
+    /// `#rrggbb`
+    color: String,
+    opacity: f32,
+    width: f32,
+}
+
+/// A page as a standalone SVG document
+pub fn svg(page: &Page) -> String {
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n\
+         <rect width=\"100%\" height=\"100%\" fill=\"#ffffff\"/>\n",
+        w = PAGE_WIDTH,
+        h = PAGE_HEIGHT
+    );
+    for stroke in page.layers.iter().flat_map(|layer| &layer.strokes) {
+        let Some(style) = style(stroke) else {
+            continue;
+        };
+        let points: Vec<String> = stroke
+            .points
+            .iter()
+            .map(|point| {
+                let (x, y) = position(page, point);
+                format!("{:.1},{:.1}", x, y)
+            })
+            .collect();
+        let _ = writeln!(
+            svg,
+            "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-opacity=\"{}\" stroke-width=\"{:.1}\" stroke-linecap=\"round\" stroke-linejoin=\"round\"/>",
+            points.join(" "),
+            style.color,
+            style.opacity,
+            style.width
+        );
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// A PDF with one page per entry; `None` gives a blank page, so page
+/// numbers stay those of the notebook
+pub fn pdf(pages: &[Option<Page>]) -> Vec<u8> {
+    let scale = 72.0 / SCREEN_DPI;
+    let (width, height) = (PAGE_WIDTH * scale, PAGE_HEIGHT * scale);
+
+    // Objects 1 and 2 are the catalog and page tree, then a page and its
+    // content stream for every page
+    let mut objects = vec![
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            (0..pages.len())
+                .map(|i| format!("{} 0 R", 3 + i * 2))
+                .collect::<Vec<_>>()
+                .join(" "),
+            pages.len()
+        ),
+    ];
+    for (i, page) in pages.iter().enumerate() {
+        let content = page
+            .as_ref()
+            .map(|page| content_stream(page, scale))
+            .unwrap_or_default();
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {:.2} {:.2}] /Contents {} 0 R \
+             /Resources << /ExtGState << /Opaque << /CA 1 >> /Translucent << /CA 0.4 >> >> >> >>",
+            width,
+            height,
+            4 + i * 2
+        ));
+        objects.push(format!(
+            "<< /Length {} >>\nstream\n{}\nendstream",
+            content.len() + 1,
+            content
+        ));
+    }
+
+    let mut pdf = b"%PDF-1.4\n".to_vec();
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.extend_from_slice(format!("{} 0 obj\n{}\nendobj\n", i + 1, object).as_bytes());
+    }
+    let xref = pdf.len();
+    let mut trailer = format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+    for offset in offsets {
+        let _ = writeln!(trailer, "{:010} 00000 n ", offset);
+    }
+    let _ = write!(
+        trailer,
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+        objects.len() + 1,
+        xref
+    );
+    pdf.extend_from_slice(trailer.as_bytes());
+    pdf
+}
+
+/// Drawing operators for a page, in screen pixels flipped to PDF's
+/// bottom-up coordinates
+fn content_stream(page: &Page, scale: f32) -> String {
+    let mut content = format!(
+        "{:.4} 0 0 {:.4} 0 {:.2} cm 1 J 1 j\n",
+        scale,
+        -scale,
+        PAGE_HEIGHT * scale
+    );
+    for stroke in page.layers.iter().flat_map(|layer| &layer.strokes) {
+        let Some(style) = style(stroke) else {
+            continue;
+        };
+        let (r, g, b) = rgb(&style.color);
+        let state = if style.opacity < 1.0 {
+            "Translucent"
+        } else {
+            "Opaque"
+        };
+        let _ = writeln!(
+            content,
+            "/{} gs {:.3} {:.3} {:.3} RG {:.2} w",
+            state, r, g, b, style.width
+        );
+        for (i, point) in stroke.points.iter().enumerate() {
+            let (x, y) = position(page, point);
+            let op = if i == 0 { "m" } else { "l" };
+            let _ = writeln!(content, "{:.1} {:.1} {}", x, y, op);
+        }
+        // A single point still leaves a dot
+        if stroke.points.len() == 1 {
+            let (x, y) = position(page, &stroke.points[0]);
+            let _ = writeln!(content, "{:.1} {:.1} l", x, y);
+        }
+        content.push_str("S\n");
+    }
+    content
+}
+
+/// A point's position on the page; v6 pages put x = 0 in the middle
+fn position(page: &Page, point: &Point) -> (f32, f32) {
+    let offset = if page.version >= 6 {
+        PAGE_WIDTH / 2.0
+    } else {
+        0.0
+    };
+    (point.x + offset, point.y)
+}
+
+/// How to draw the stroke, or `None` for strokes that leave nothing visible
+fn style(stroke: &Stroke) -> Option<Style> {
+    if stroke.points.is_empty() || stroke.pen == "eraser_area" {
+        return None;
+    }
+    let average =
+        stroke.points.iter().map(|point| point.width).sum::<f32>() / stroke.points.len() as f32;
+    let width = if average > 0.0 { average } else { stroke.width };
+
+    let highlight = matches!(stroke.pen, "highlighter" | "shader");
+    Some(match (stroke.pen, stroke.argb) {
+        // Eraser strokes of older files paint over what's below
+        ("eraser", _) => Style {
+            color: "#ffffff".to_string(),
+            opacity: 1.0,
+            width,
+        },
+        // The Paper Pro's exact color
+        (_, Some(argb)) => {
+            let [_, r, g, b] = argb.to_be_bytes();
+            Style {
+                color: format!("#{:02x}{:02x}{:02x}", r, g, b),
+                opacity: if highlight { 0.4 } else { 1.0 },
+                width,
+            }
+        }
+        _ if highlight => Style {
+            color: match stroke.color {
+                "black" | "highlight" => "#fbf719",
+                color => hex(color),
+            }
+            .to_string(),
+            opacity: 0.4,
+            width,
+        },
+        _ => Style {
+            color: hex(stroke.color).to_string(),
+            opacity: 1.0,
+            width,
+        },
+    })
+}
+
+fn hex(color: &str) -> &'static str {
+    match color {
+        "gray" => "#7f7f7f",
+        "white" => "#ffffff",
+        "yellow" | "highlight" => "#fbf719",
+        "green" => "#00a651",
+        "pink" => "#ff80c0",
+        "blue" => "#2f6fd6",
+        "red" => "#d62f2f",
+        "cyan" => "#00b7eb",
+        "magenta" => "#c000c0",
+        _ => "#000000",
+    }
+}
+
+/// Components (0-1) of a `#rrggbb` color
+fn rgb(hex: &str) -> (f32, f32, f32) {
+    let component = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap_or(0) as f32 / 255.0;
+    (component(1), component(3), component(5))
+}
@@ -0,0 +1,179 @@
+use crate::error::{Error, Result};
+use crate::process;
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::Command;
+use tracing::debug;
+
+/// SSH destination of a tablet connected over USB
+pub const DEFAULT_SSH_HOST: &str = "root@10.11.99.1";
+
+/// Where the tablet UI (xochitl) keeps its documents
+pub const XOCHITL_DIR: &str = "/home/root/.local/share/remarkable/xochitl";
+
+/// How long quick commands (connection check, scp, ssh) may take
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Talks to the tablet directly over SSH (USB or Wi-Fi), with the system's
+/// `ssh`, `scp` and `tar`.
+///
+/// Authentication is left to SSH: install your key on the tablet, as
+/// password prompts can't be answered.
+#[derive(Debug, Clone)]
+pub struct SshClient {
+    host: String,
+}
+
+impl SshClient {
+    pub fn new(host: impl Into<String>) -> Self {
+        Self { host: host.into() }
+    }
+
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    fn ssh(&self) -> Command {
+        let mut cmd = Command::new("ssh");
+        cmd.arg("-o")
+            .arg("BatchMode=yes")
+            .arg("-o")
+            .arg("ConnectTimeout=10")
+            .arg(&self.host);
+        cmd
+    }
+
+    /// Check that the tablet is reachable and accepts our key
+    pub async fn check(&self) -> Result<()> {
+        debug!("Checking SSH connection to {}", self.host);
+        self.run(&format!("test -d {}", XOCHITL_DIR))
+            .await
+            .map_err(|e| {
+                Error::Remarkable(format!(
+                    "Can't reach the tablet at {}: {}. Connect it over USB or Wi-Fi and install your SSH key (ssh-copy-id {}).",
+                    self.host, e, self.host
+                ))
+            })
+    }
+
+    /// Run a shell command on the tablet
+    pub async fn run(&self, command: &str) -> Result<()> {
+        let output = process::output(self.ssh().arg(command), COMMAND_TIMEOUT)
+            .await
+            .map_err(|e| Error::Remarkable(format!("Failed to run ssh: {}", e)))?;
+
+        if !output.status.success() {
+            return Err(Error::Remarkable(format!(
+                "'{}' failed on the tablet: {}",
+                command,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Copy a local file into the tablet's document directory as `name`
+    pub async fn upload(&self, local: &Path, name: &str) -> Result<()> {
+        let destination = format!("{}:{}/{}", self.host, XOCHITL_DIR, name);
+        let output = process::output(
+            Command::new("scp")
+                .arg("-q")
+                .arg("-o")
+                .arg("BatchMode=yes")
+                .arg(local)
+                .arg(&destination),
+            COMMAND_TIMEOUT,
+        )
+        .await
+        .map_err(|e| Error::Remarkable(format!("Failed to run scp: {}", e)))?;
+
+        if !output.status.success() {
+            return Err(Error::Remarkable(format!(
+                "Failed to copy {} to the tablet: {}",
+                name,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Mirror the tablet's document directory into `notebooks_dir`.
+    ///
+    /// The files are streamed as a tar archive into a sibling directory that
+    /// replaces `notebooks_dir` once complete, so documents deleted on the
+    /// tablet disappear and an interrupted pull leaves the old copy intact.
+    pub async fn pull(&self, notebooks_dir: &Path, timeout: Duration) -> Result<()> {
+        let partial = notebooks_dir.with_extension("partial");
+        if partial.exists() {
+            std::fs::remove_dir_all(&partial)?;
+        }
+        std::fs::create_dir_all(&partial)?;
+
+        debug!("Pulling {} from {}", XOCHITL_DIR, self.host);
+        match tokio::time::timeout(timeout, self.pull_into(&partial)).await {
+            Ok(result) => result?,
+            Err(_) => {
+                std::fs::remove_dir_all(&partial).ok();
+                return Err(Error::Remarkable(format!(
+                    "Copying documents from the tablet did not finish within {}s. Check the connection or raise REMARKABLE_SYNC_TIMEOUT.",
+                    timeout.as_secs()
+                )));
+            }
+        }
+
+        if notebooks_dir.exists() {
+            std::fs::remove_dir_all(notebooks_dir)?;
+        }
+        std::fs::rename(&partial, notebooks_dir)?;
+        Ok(())
+    }
+
+    async fn pull_into(&self, dir: &Path) -> Result<()> {
+        let mut remote = self
+            .ssh()
+            .arg(format!("tar -cf - -C {} .", XOCHITL_DIR))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| Error::Remarkable(format!("Failed to run ssh: {}", e)))?;
+        let mut local = Command::new("tar")
+            .arg("-xf")
+            .arg("-")
+            .arg("-C")
+            .arg(dir)
+            .stdin(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| Error::Remarkable(format!("Failed to run tar: {}", e)))?;
+
+        let (Some(mut archive), Some(mut extract)) = (remote.stdout.take(), local.stdin.take())
+        else {
+            return Err(Error::Remarkable(
+                "Failed to connect ssh to tar".to_string(),
+            ));
+        };
+        let copied = tokio::io::copy(&mut archive, &mut extract).await;
+        drop(extract);
+
+        let remote = remote.wait_with_output().await?;
+        let local = local.wait_with_output().await?;
+        if !remote.status.success() {
+            return Err(Error::Remarkable(format!(
+                "Failed to read documents from the tablet: {}",
+                String::from_utf8_lossy(&remote.stderr).trim()
+            )));
+        }
+        if !local.status.success() {
+            return Err(Error::Remarkable(format!(
+                "Failed to unpack documents from the tablet: {}",
+                String::from_utf8_lossy(&local.stderr).trim()
+            )));
+        }
+        let bytes = copied?;
+        debug!("Pulled {} bytes from {}", bytes, self.host);
+        Ok(())
+    }
+}
@@ -0,0 +1,167 @@
+use regex::Regex;
+use std::collections::HashSet;
+use std::sync::OnceLock;
+
+/// A line of RemarkableSync output we understand
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyncEvent {
+    /// A document was downloaded or converted (name or UUID, without extension)
+    Updated(String),
+    /// A document was already current
+    Skipped(String),
+    Error(String),
+    /// Nothing changed on the tablet since the last backup
+    UpToDate,
+    /// The backup finished (possibly followed by template errors)
+    Completed,
+}
+
+/// What a RemarkableSync run did, parsed from its stdout
+#[derive(Debug, Default)]
+pub struct SyncOutput {
+    pub events: Vec<SyncEvent>,
+}
+
+impl SyncOutput {
+    pub fn parse(stdout: &str) -> Self {
+        Self {
+            events: stdout.lines().filter_map(parse_line).collect(),
+        }
+    }
+
+    /// Whether the backup itself went through, even if the run exited non-zero
+    pub fn backup_succeeded(&self) -> bool {
+        self.events
+            .iter()
+            .any(|event| matches!(event, SyncEvent::UpToDate | SyncEvent::Completed))
+    }
+
+    /// Documents exported this run, or `None` if the output said nothing
+    /// either way (an unfamiliar RemarkableSync version)
+    pub fn exported(&self) -> Option<HashSet<&str>> {
+        if self.events.is_empty() {
+            return None;
+        }
+        Some(
+            self.events
+                .iter()
+                .filter_map(|event| match event {
+                    SyncEvent::Updated(name) => Some(name.as_str()),
+                    _ => None,
+                })
+                .collect(),
+        )
+    }
+
+    pub fn skipped(&self) -> usize {
+        self.events
+            .iter()
+            .filter(|event| matches!(event, SyncEvent::Skipped(_)))
+            .count()
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &str> {
+        self.events.iter().filter_map(|event| match event {
+            SyncEvent::Error(message) => Some(message.as_str()),
+            _ => None,
+        })
+    }
+}
+
+fn parse_line(line: &str) -> Option<SyncEvent> {
+    static PATTERNS: OnceLock<[Regex; 3]> = OnceLock::new();
+    let [updated, skipped, error] = PATTERNS.get_or_init(|| {
+        [
+            Regex::new(r"(?i)^\W*(?:updated|downloaded|downloading|converted|converting|exported|exporting)\b:?\s+(.+)$").unwrap(),
+            Regex::new(r"(?i)^\W*(?:skipped|skipping|unchanged)\b:?\s+(.+)$").unwrap(),
+            Regex::new(r"(?i)^\W*(?:error|failed)\b:?\s*(.+)$").unwrap(),
+        ]
+    });
+
+    let line = line.trim();
+    if line.contains("All files are up to date") {
+        return Some(SyncEvent::UpToDate);
+    }
+    if line.contains("Backup completed") {
+        return Some(SyncEvent::Completed);
+    }
+    if let Some(caps) = updated.captures(line) {
+        return Some(SyncEvent::Updated(document_name(&caps[1])));
+    }
+    if let Some(caps) = skipped.captures(line) {
+        return Some(SyncEvent::Skipped(document_name(&caps[1])));
+    }
+    error
+        .captures(line)
+        .map(|caps| SyncEvent::Error(caps[1].to_string()))
+}
+
+/// Strip quotes, directories and file extensions from a reported document
+fn document_name(raw: &str) -> String {
+    let name = raw.trim().trim_matches(|c| c == '"' || c == '\'');
+    let name = name.rsplit('/').next().unwrap_or(name);
+    [".pdf", ".rm", ".metadata", ".content"]
+        .iter()
+        .find_map(|ext| name.strip_suffix(ext))
+        .unwrap_or(name)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_document_events() {
+        let output = SyncOutput::parse(
+            "Connecting to tablet...\n\
+             ✓ Downloaded: \"Notebooks/Meeting Notes.pdf\"\n\
+             Converting 0a1b2c3d.rm\n\
+             Skipping Journal\n\
+             unchanged: Sketches.pdf\n\
+             ERROR: Failed to convert Broken\n\
+             Backup completed in 12s\n",
+        );
+        assert_eq!(
+            output.events,
+            vec![
+                SyncEvent::Updated("Meeting Notes".to_string()),
+                SyncEvent::Updated("0a1b2c3d".to_string()),
+                SyncEvent::Skipped("Journal".to_string()),
+                SyncEvent::Skipped("Sketches".to_string()),
+                SyncEvent::Error("Failed to convert Broken".to_string()),
+                SyncEvent::Completed,
+            ]
+        );
+        assert!(output.backup_succeeded());
+        assert_eq!(
+            output.exported(),
+            Some(HashSet::from(["Meeting Notes", "0a1b2c3d"]))
+        );
+        assert_eq!(output.skipped(), 2);
+        assert_eq!(
+            output.errors().collect::<Vec<_>>(),
+            ["Failed to convert Broken"]
+        );
+    }
+
+    #[test]
+    fn up_to_date_exports_nothing() {
+        let output = SyncOutput::parse("All files are up to date\n");
+        assert!(output.backup_succeeded());
+        assert_eq!(output.exported(), Some(HashSet::new()));
+    }
+
+    #[test]
+    fn unfamiliar_output_says_nothing() {
+        let output = SyncOutput::parse("Some other tool\nprinting things\n");
+        assert!(!output.backup_succeeded());
+        assert_eq!(output.exported(), None);
+    }
+
+    #[test]
+    fn words_inside_names_are_not_events() {
+        assert_eq!(parse_line("Errors and omissions"), None);
+        assert_eq!(parse_line("Downloads folder scanned"), None);
+    }
+}
@@ -0,0 +1,411 @@
+use crate::error::Result;
+use crate::google_drive::GoogleDriveClient;
+use crate::notion::models::{plain_text, Property};
+use crate::notion::{
+    is_sync_warning, Block, NotionClient, Page, ACTION_ITEMS_HEADING, CONTENT_HEADING,
+    PDF_REFERENCE_PREFIX,
+};
+use crate::state::SyncState;
+use regex::Regex;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+use tracing::{debug, info, warn};
+
+/// Remove duplicated text sections and image sets left behind by interrupted runs
+pub async fn dedupe(notion: &NotionClient, dry_run: bool) -> Result<()> {
+    info!("Scanning pages for duplicate blocks...");
+    let pages = notion.get_all_pages().await?;
+    let mut state = SyncState::load()?;
+
+    let mut repaired = 0;
+    let mut removed = 0;
+    for page in &pages {
+        let blocks = match notion.list_blocks(&page.id).await {
+            Ok(blocks) => blocks,
+            Err(e) => {
+                warn!("Failed to read '{}': {}", page.title, e);
+                continue;
+            }
+        };
+
+        let duplicates = duplicate_blocks(&blocks);
+        if duplicates.is_empty() {
+            debug!("'{}' is clean", page.title);
+            continue;
+        }
+
+        if dry_run {
+            info!(
+                "[DRY RUN] Would remove {} duplicate blocks from '{}'",
+                duplicates.len(),
+                page.title
+            );
+            repaired += 1;
+            removed += duplicates.len();
+            continue;
+        }
+
+        let mut deleted = Vec::new();
+        for block_id in &duplicates {
+            match notion.delete_block(block_id).await {
+                Ok(()) => deleted.push(block_id.as_str()),
+                Err(e) => warn!("Failed to delete block in '{}': {}", page.title, e),
+            }
+        }
+
+        // Forget deleted images so the next sync uploads them again if needed
+        if let Some(page_state) = state.pages.get_mut(&page.id) {
+            page_state
+                .images
+                .retain(|_, image| !deleted.contains(&image.block_id.as_str()));
+        }
+
+        info!(
+            "🧹 {} - removed {} duplicate blocks",
+            page.title,
+            deleted.len()
+        );
+        repaired += 1;
+        removed += deleted.len();
+    }
+
+    if !dry_run {
+        state.save()?;
+    }
+
+    info!(
+        "Dedupe complete: {} blocks removed from {} of {} pages",
+        removed,
+        repaired,
+        pages.len()
+    );
+    Ok(())
+}
+
+/// Merge pages that share a title, as left behind by past title-matching bugs.
+///
+/// Of every group the most recently edited page is kept. Tags, attendees and
+/// relations of the others are added to it, and properties it has no value
+/// for are filled in from them (newest first). The other pages are archived.
+pub async fn merge_duplicates(notion: &NotionClient, dry_run: bool) -> Result<()> {
+    info!("Scanning database for pages with the same title...");
+    let pages = notion.query_pages().await?;
+    let mut state = SyncState::load()?;
+
+    let mut groups: BTreeMap<String, Vec<&Page>> = BTreeMap::new();
+    for page in pages.iter().filter(|page| !page.archived) {
+        if let Some(title) = page.title() {
+            groups.entry(title).or_default().push(page);
+        }
+    }
+
+    let mut merged = 0;
+    let mut archived = 0;
+    for (title, mut group) in groups {
+        if group.len() < 2 {
+            continue;
+        }
+        // Newest first; ISO 8601 timestamps sort chronologically
+        group.sort_by(|a, b| b.last_edited_time.cmp(&a.last_edited_time));
+        let (keep, duplicates) = group.split_first().unwrap();
+        let properties = merged_properties(keep, duplicates);
+
+        if dry_run {
+            info!(
+                "[DRY RUN] Would merge {} duplicates of '{}' into {} ({} properties updated)",
+                duplicates.len(),
+                title,
+                keep.id,
+                properties.len()
+            );
+            merged += 1;
+            archived += duplicates.len();
+            continue;
+        }
+
+        if !properties.is_empty() {
+            if let Err(e) = notion
+                .update_properties(&keep.id, serde_json::Value::Object(properties))
+                .await
+            {
+                // Archiving now would lose the values, so leave the group alone
+                warn!("Failed to merge properties into '{}': {}", title, e);
+                continue;
+            }
+        }
+
+        let mut removed = 0;
+        for duplicate in duplicates {
+            match notion.delete_page(&duplicate.id).await {
+                Ok(()) => {
+                    state.pages.remove(&duplicate.id);
+                    removed += 1;
+                }
+                Err(e) => warn!("Failed to archive duplicate of '{}': {}", title, e),
+            }
+        }
+
+        info!("🔀 {} - archived {} duplicates", title, removed);
+        merged += 1;
+        archived += removed;
+    }
+
+    if !dry_run {
+        state.save()?;
+    }
+
+    info!(
+        "Merge complete: {} duplicate pages archived across {} titles",
+        archived, merged
+    );
+    Ok(())
+}
+
+/// Property values to write to `keep` so it carries what its duplicates had
+pub fn merged_properties(
+    keep: &Page,
+    duplicates: &[&Page],
+) -> serde_json::Map<String, serde_json::Value> {
+    let mut properties = serde_json::Map::new();
+
+    for (name, property) in &keep.properties {
+        let others = duplicates
+            .iter()
+            .filter_map(|page| page.properties.get(name));
+
+        let value = match property {
+            Property::MultiSelect { multi_select } => {
+                let mut names: Vec<&str> = multi_select.iter().map(|o| o.name.as_str()).collect();
+                let before = names.len();
+                for other in others {
+                    if let Property::MultiSelect { multi_select } = other {
+                        for option in multi_select {
+                            if !names.contains(&option.name.as_str()) {
+                                names.push(&option.name);
+                            }
+                        }
+                    }
+                }
+                (names.len() > before).then(|| {
+                    json!({ "multi_select": names.iter().map(|name| json!({"name": name})).collect::<Vec<_>>() })
+                })
+            }
+            Property::Relation { relation } => {
+                let mut ids: Vec<&str> = relation.iter().map(|r| r.id.as_str()).collect();
+                let before = ids.len();
+                for other in others {
+                    if let Property::Relation { relation } = other {
+                        for reference in relation {
+                            if !ids.contains(&reference.id.as_str()) {
+                                ids.push(&reference.id);
+                            }
+                        }
+                    }
+                }
+                (ids.len() > before).then(|| {
+                    json!({ "relation": ids.iter().map(|id| json!({"id": id})).collect::<Vec<_>>() })
+                })
+            }
+            property if property.is_empty() => others
+                .filter(|other| !other.is_empty())
+                .find_map(property_value),
+            _ => None,
+        };
+
+        if let Some(value) = value {
+            properties.insert(name.clone(), value);
+        }
+    }
+
+    properties
+}
+
+/// Update payload setting a single-valued property to `property`'s value
+fn property_value(property: &Property) -> Option<serde_json::Value> {
+    match property {
+        Property::RichText { rich_text } => Some(json!({
+            "rich_text": [{ "text": { "content": plain_text(rich_text) } }]
+        })),
+        Property::Select { select } => select
+            .as_ref()
+            .map(|option| json!({ "select": { "name": option.name } })),
+        Property::Date { date } => date
+            .as_ref()
+            .map(|date| json!({ "date": { "start": date.start, "end": date.end } })),
+        Property::Url { url } => url.as_ref().map(|url| json!({ "url": url })),
+        Property::Number { number } => number.map(|number| json!({ "number": number })),
+        _ => None,
+    }
+}
+
+/// Delete temp files and Drive uploads left behind by interrupted runs.
+///
+/// Drive files are recognised by the marker the tool sets in their
+/// appProperties; any that no notebook in the sync state links to is an
+/// orphan. Don't run this while a sync is in progress.
+pub async fn storage(
+    drive: Option<&GoogleDriveClient>,
+    work_dir: &Path,
+    dry_run: bool,
+) -> Result<()> {
+    let mut temp_files = Vec::new();
+    if work_dir.exists() {
+        // Per-notebook directories, and files of older versions
+        for entry in std::fs::read_dir(work_dir)? {
+            temp_files.push(entry?.path());
+        }
+    }
+
+    // Page renders of older versions and `test ocr` live directly in the
+    // system temp dir
+    let render = Regex::new(r"_(page-\d+(_hires_\d+dpi)?|hires_\d+dpi-\d+)(_crop)?\.png$").unwrap();
+    for entry in std::fs::read_dir(std::env::temp_dir())? {
+        let path = entry?.path();
+        let is_render = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|name| render.is_match(name));
+        if is_render && path.is_file() {
+            temp_files.push(path);
+        }
+    }
+
+    for path in &temp_files {
+        if dry_run {
+            info!("[DRY RUN] Would delete {:?}", path);
+        } else if let Err(e) = if path.is_dir() {
+            std::fs::remove_dir_all(path)
+        } else {
+            std::fs::remove_file(path)
+        } {
+            warn!("Failed to delete {:?}: {}", path, e);
+        } else {
+            debug!("Deleted {:?}", path);
+        }
+    }
+    info!("{} orphaned temp files", temp_files.len());
+
+    let Some(drive) = drive else {
+        info!("Google Drive not configured, skipping Drive cleanup");
+        return Ok(());
+    };
+
+    let state = SyncState::load()?;
+    let linked: HashSet<&str> = state
+        .drive_files
+        .values()
+        .chain(state.drive_pages.values().flat_map(|pages| pages.values()))
+        .map(String::as_str)
+        .collect();
+
+    let files = drive.list_own_files().await?;
+    let orphans: Vec<_> = files
+        .iter()
+        .filter(|file| !linked.contains(file.id.as_str()))
+        .collect();
+
+    for file in &orphans {
+        if dry_run {
+            info!(
+                "[DRY RUN] Would delete Drive file '{}' ({}, uploaded by run {})",
+                file.name,
+                file.id,
+                file.run_id().unwrap_or("unknown")
+            );
+            continue;
+        }
+        match drive.delete_file(&file.id).await {
+            Ok(()) => info!("🗑️  Drive file '{}'", file.name),
+            Err(e) => warn!("Failed to delete Drive file '{}': {}", file.name, e),
+        }
+    }
+
+    info!(
+        "{} of {} Drive files uploaded by remarkable2notion are orphaned",
+        orphans.len(),
+        files.len()
+    );
+    Ok(())
+}
+
+/// Blocks that break the canonical page layout.
+///
+/// A synced page holds one "OCR Extracted Text" section, one image per
+/// notebook page, at most one PDF reference and one "Action Items" section.
+/// Of every repeated section or image, the last one (the most recent write)
+/// is kept.
+fn duplicate_blocks(blocks: &[Block]) -> Vec<String> {
+    let mut content_sections: Vec<Vec<&str>> = Vec::new();
+    let mut action_sections: Vec<Vec<&str>> = Vec::new();
+    let mut images: HashMap<String, Vec<&str>> = HashMap::new();
+    let mut pdf_references: Vec<&str> = Vec::new();
+
+    // Section the previous block belonged to, if any
+    let mut current: Option<Section> = None;
+    for block in blocks {
+        let text = block.text();
+        let is_pdf_reference = block.kind == "paragraph"
+            && text
+                .as_deref()
+                .is_some_and(|t| t.starts_with(PDF_REFERENCE_PREFIX));
+
+        if block.is_heading(CONTENT_HEADING) {
+            content_sections.push(vec![block.id.as_str()]);
+            current = Some(Section::Content);
+            continue;
+        }
+        if block.is_heading(ACTION_ITEMS_HEADING) {
+            action_sections.push(vec![block.id.as_str()]);
+            current = Some(Section::ActionItems);
+            continue;
+        }
+
+        // Paragraphs (and page headings or toggles) belong to the text
+        // section, to-dos to the action items
+        let section = match current {
+            Some(Section::Content)
+                if (block.is_text() || is_sync_warning(block)) && !is_pdf_reference =>
+            {
+                content_sections.last_mut()
+            }
+            Some(Section::ActionItems) if block.kind == "to_do" => action_sections.last_mut(),
+            _ => None,
+        };
+        if let Some(section) = section {
+            section.push(block.id.as_str());
+            continue;
+        }
+        current = None;
+
+        if is_pdf_reference {
+            pdf_references.push(block.id.as_str());
+        } else if let Some(caption) = block.caption().filter(|c| c.starts_with("Page ")) {
+            // Captions may carry the page's date: "Page 3 · 2024-05-02"
+            let page = caption.split(" · ").next().unwrap_or_default().to_string();
+            images.entry(page).or_default().push(block.id.as_str());
+        }
+    }
+
+    let mut duplicates: Vec<String> = Vec::new();
+    for sections in [&content_sections, &action_sections] {
+        if let Some((_, older)) = sections.split_last() {
+            duplicates.extend(older.iter().flatten().map(|id| id.to_string()));
+        }
+    }
+    for ids in images.values().chain(std::iter::once(&pdf_references)) {
+        if let Some((_, older)) = ids.split_last() {
+            duplicates.extend(older.iter().map(|id| id.to_string()));
+        }
+    }
+
+    // Delete in page order
+    duplicates.sort_by_key(|id| blocks.iter().position(|block| &block.id == id));
+    duplicates
+}
+
+#[derive(Clone, Copy)]
+enum Section {
+    Content,
+    ActionItems,
+}
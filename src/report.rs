@@ -0,0 +1,76 @@
+use crate::error::ErrorContext;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Summary of a sync run, printed as JSON with `sync --json`
+#[derive(Debug, Default, Serialize)]
+pub struct SyncReport {
+    pub run_id: String,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub deleted: usize,
+    pub stale: usize,
+    /// Notebooks skipped because their PDF didn't change since the last sync,
+    /// or by `--changed-only` because RemarkableSync didn't export them
+    pub unchanged: usize,
+    /// Notebooks skipped by `--only`, `--include-tag` or `--exclude-tag`
+    pub filtered: usize,
+    /// Notebooks without text that were skipped or archived (`EMPTY_NOTEBOOKS`)
+    pub empty: usize,
+    /// Notebooks whose page couldn't be written, skipped per `ON_NOTION_ERROR`
+    pub skipped: usize,
+    /// Notebooks left for the next run because the run was cancelled, or
+    /// stopped by `--fail-fast` or `--max-errors`
+    pub cancelled: usize,
+    /// Notebooks left for a later run by `--limit`
+    pub deferred: usize,
+    /// Notebooks written without text, their OCR queued for
+    /// `sync --ocr-backlog` (`DEFER_OCR`)
+    pub queued: usize,
+    /// Whether the run stopped early because too many notebooks failed
+    pub aborted: bool,
+    /// Time spent running RemarkableSync and scanning the backup
+    pub device_sync_ms: u64,
+    /// Notebooks not synced because their page was archived in Notion
+    /// (`RESPECT_NOTION_ARCHIVE`)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub archived_in_notion: Vec<String>,
+    /// Tags Notion would reject (e.g. containing commas), left off the pages
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub rejected_tags: Vec<String>,
+    /// Kinds of notebook data the run's settings send off this machine
+    /// (metadata, text, images, pdf, svg, errors), by destination
+    pub transmitted: BTreeMap<String, Vec<String>>,
+    pub notebooks: Vec<NotebookReport>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NotebookReport {
+    pub name: String,
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Stage and API details of `error`: HTTP status, error code, request ID
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_context: Option<ErrorContext>,
+    /// Differences found by `--verify` between what was sent and what Notion stored
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub verification_issues: Vec<String>,
+    pub timings: StageTimings,
+}
+
+/// Wall-clock time spent per pipeline stage for one notebook, in milliseconds
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct StageTimings {
+    pub render_ms: u64,
+    pub ocr_ms: u64,
+    pub correction_ms: u64,
+    pub drive_upload_ms: u64,
+    pub notion_ms: u64,
+    pub total_ms: u64,
+}
+
+pub fn millis(duration: Duration) -> u64 {
+    duration.as_millis() as u64
+}
@@ -0,0 +1,214 @@
+use crate::error::Result;
+use crate::rate_limit::RateLimiter;
+use crate::redact::redact;
+use rand::Rng;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use std::convert::Infallible;
+use std::future::Future;
+use std::time::Duration;
+use tracing::debug;
+
+/// Attempts per request, including the first
+const MAX_ATTEMPTS: u32 = 5;
+
+/// Delay before the first retry; doubled for every further one
+const BASE_DELAY: Duration = Duration::from_millis(500);
+
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Send requests again when they fail transiently: rate limits (429), server
+/// errors (5xx) and connection problems. Retries back off exponentially
+/// with jitter, or wait as long as a `Retry-After` header asks.
+pub trait RetryExt {
+    fn send_retrying(self) -> impl Future<Output = Result<Response>> + Send;
+
+    /// Like `send_retrying`, waiting for a slot from `limiter` before every
+    /// attempt
+    fn send_limited(self, limiter: &RateLimiter) -> impl Future<Output = Result<Response>> + Send;
+}
+
+impl RetryExt for RequestBuilder {
+    async fn send_retrying(self) -> Result<Response> {
+        send_request(self, None).await
+    }
+
+    async fn send_limited(self, limiter: &RateLimiter) -> Result<Response> {
+        send_request(self, Some(limiter)).await
+    }
+}
+
+async fn send_request(request: RequestBuilder, limiter: Option<&RateLimiter>) -> Result<Response> {
+    // Streaming bodies can't be replayed, so those requests go out once
+    let Some(retry) = request.try_clone() else {
+        if let Some(limiter) = limiter {
+            limiter.acquire().await;
+        }
+        return Ok(request.send().await?);
+    };
+    let mut retry = Some(retry);
+    send_with_limit(
+        move || {
+            let request = retry
+                .take()
+                .expect("request is cloned before every attempt");
+            retry = request.try_clone();
+            Ok(request)
+        },
+        limiter,
+    )
+    .await
+}
+
+/// Like `send_retrying`, for requests that have to be built again for every
+/// attempt, e.g. multipart uploads
+pub async fn send_with<F>(build: F) -> Result<Response>
+where
+    F: FnMut() -> Result<RequestBuilder>,
+{
+    send_with_limit(build, None).await
+}
+
+/// `send_with` for an API with a rate limit. A 429 response holds back
+/// every request sharing the limiter, not just this one.
+pub async fn send_with_limit<F>(build: F, limiter: Option<&RateLimiter>) -> Result<Response>
+where
+    F: FnMut() -> Result<RequestBuilder>,
+{
+    let never_applied = || async { Ok(None::<Infallible>) };
+    match send_attempts(build, limiter, never_applied).await? {
+        Sent::Response(response) => Ok(response),
+        Sent::Applied(never) => match never {},
+    }
+}
+
+/// Outcome of `send_checked`
+pub enum Sent<T> {
+    /// The response to the last attempt
+    Response(Response),
+    /// What `applied` found of an earlier attempt that went through
+    Applied(T),
+}
+
+/// Like `send_limited`, for writes that mustn't be repeated once they went
+/// through. A timeout or server error can come after the write was done, so
+/// before retrying one `applied` is asked for what an earlier attempt
+/// created; if it finds something that's returned instead of writing again.
+pub async fn send_checked<T, C, Fut>(
+    request: RequestBuilder,
+    limiter: &RateLimiter,
+    applied: C,
+) -> Result<Sent<T>>
+where
+    C: FnMut() -> Fut,
+    Fut: Future<Output = Result<Option<T>>>,
+{
+    let Some(retry) = request.try_clone() else {
+        limiter.acquire().await;
+        return Ok(Sent::Response(request.send().await?));
+    };
+    let mut retry = Some(retry);
+    send_attempts(
+        move || {
+            let request = retry
+                .take()
+                .expect("request is cloned before every attempt");
+            retry = request.try_clone();
+            Ok(request)
+        },
+        Some(limiter),
+        applied,
+    )
+    .await
+}
+
+async fn send_attempts<T, F, C, Fut>(
+    mut build: F,
+    limiter: Option<&RateLimiter>,
+    mut applied: C,
+) -> Result<Sent<T>>
+where
+    F: FnMut() -> Result<RequestBuilder>,
+    C: FnMut() -> Fut,
+    Fut: Future<Output = Result<Option<T>>>,
+{
+    let mut attempt = 1;
+    loop {
+        if let Some(limiter) = limiter {
+            limiter.acquire().await;
+        }
+        let result = build()?.send().await;
+        let retry_after = match result {
+            Ok(ref response) if is_transient(response.status()) => retry_after(response),
+            Err(ref e) if e.is_connect() || e.is_timeout() => None,
+            _ => return Ok(Sent::Response(result?)),
+        };
+        if attempt == MAX_ATTEMPTS {
+            return Ok(Sent::Response(result?));
+        }
+        // Rate limited and unconnected requests weren't processed
+        let uncertain = match result {
+            Ok(ref response) => response.status() != StatusCode::TOO_MANY_REQUESTS,
+            Err(ref e) => !e.is_connect(),
+        };
+
+        let delay = retry_after
+            .unwrap_or_else(|| backoff(attempt))
+            .min(MAX_DELAY);
+        if let (Some(limiter), Ok(response)) = (limiter, &result) {
+            if response.status() == StatusCode::TOO_MANY_REQUESTS {
+                limiter.pause(delay);
+            }
+        }
+        match result {
+            Ok(ref response) => debug!(
+                "{} from {}, retrying in {:?} ({}/{})",
+                response.status(),
+                response.url().path(),
+                delay,
+                attempt,
+                MAX_ATTEMPTS - 1
+            ),
+            Err(ref e) => debug!(
+                "Request failed, retrying in {:?} ({}/{}): {}",
+                delay,
+                attempt,
+                MAX_ATTEMPTS - 1,
+                redact(&e.to_string())
+            ),
+        }
+        tokio::time::sleep(delay).await;
+        if uncertain {
+            if let Some(found) = applied().await? {
+                debug!("The failed attempt went through, not sending it again");
+                return Ok(Sent::Applied(found));
+            }
+        }
+        attempt += 1;
+    }
+}
+
+fn is_transient(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Seconds asked for by a `Retry-After` header
+fn retry_after(response: &Response) -> Option<Duration> {
+    response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+        .map(Duration::from_secs_f64)
+}
+
+/// Exponential backoff with jitter: between half and all of the doubled base delay
+fn backoff(attempt: u32) -> Duration {
+    let ceiling = BASE_DELAY
+        .saturating_mul(1 << (attempt - 1).min(16))
+        .min(MAX_DELAY);
+    ceiling.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+}
@@ -0,0 +1,132 @@
+use crate::error::Result;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+/// A Notion page as a sync would write it, for `sync --review-dir`
+pub struct ReviewPage {
+    pub title: String,
+    /// Property values by name, as text
+    pub properties: Vec<(String, String)>,
+    /// Block payloads in the order they'd be appended
+    pub blocks: Vec<Value>,
+}
+
+/// Write the pages of one notebook to `<dir>/<notebook>.md`, with the page
+/// images copied to `<dir>/<notebook>/` so the markdown can show them.
+///
+/// `images` are the rendered pages by the file name image blocks refer to.
+pub fn write(
+    dir: &Path,
+    notebook: &str,
+    pages: &[ReviewPage],
+    images: &[(String, &Path)],
+) -> Result<PathBuf> {
+    let name = file_name(notebook);
+    let image_dir = dir.join(&name);
+    std::fs::create_dir_all(&image_dir)?;
+    for (file_name, path) in images {
+        std::fs::copy(path, image_dir.join(file_name))?;
+    }
+
+    let mut markdown = String::new();
+    for page in pages {
+        markdown.push_str(&format!("# {}\n\n", page.title));
+        if !page.properties.is_empty() {
+            markdown.push_str("| Property | Value |\n|----------|-------|\n");
+            for (property, value) in &page.properties {
+                markdown.push_str(&format!(
+                    "| {} | {} |\n",
+                    property,
+                    value.replace('|', "\\|")
+                ));
+            }
+            markdown.push('\n');
+        }
+        markdown.push_str(&to_markdown(&page.blocks));
+    }
+
+    let path = dir.join(format!("{}.md", name));
+    std::fs::write(&path, markdown.trim_end().to_string() + "\n")?;
+    debug!("Wrote review of '{}' to {:?}", notebook, path);
+    Ok(path)
+}
+
+/// Notion-flavored markdown approximation of block payloads
+pub fn to_markdown(blocks: &[Value]) -> String {
+    let mut markdown = String::new();
+    for block in blocks {
+        let kind = block["type"].as_str().unwrap_or_default();
+        let body = &block[kind];
+        let text = plain_text(&body["rich_text"]);
+        let children = body["children"]
+            .as_array()
+            .map(|children| to_markdown(children));
+
+        let rendered = match kind {
+            "paragraph" => text,
+            "heading_1" | "heading_2" | "heading_3" => {
+                let level: usize = kind.trim_start_matches("heading_").parse().unwrap_or(1);
+                match children {
+                    Some(children) => toggle(&format!("<h{0}>{1}</h{0}>", level, text), &children),
+                    None => format!("{} {}", "#".repeat(level), text),
+                }
+            }
+            "toggle" => toggle(&text, &children.unwrap_or_default()),
+            "to_do" => {
+                let checked = if body["checked"].as_bool() == Some(true) {
+                    "x"
+                } else {
+                    " "
+                };
+                format!("- [{}] {}", checked, text)
+            }
+            "callout" => format!(
+                "> {} {}",
+                body["icon"]["emoji"].as_str().unwrap_or_default(),
+                text
+            ),
+            "equation" => format!(
+                "$$\n{}\n$$",
+                body["expression"].as_str().unwrap_or_default()
+            ),
+            "image" => {
+                let url = body["external"]["url"].as_str().unwrap_or_default();
+                format!("![{}](<{}>)", plain_text(&body["caption"]), url)
+            }
+            other => format!("<!-- {} block -->", other),
+        };
+        markdown.push_str(&rendered);
+        markdown.push_str("\n\n");
+    }
+    markdown
+}
+
+fn toggle(summary: &str, children: &str) -> String {
+    format!(
+        "<details>\n<summary>{}</summary>\n\n{}</details>",
+        summary, children
+    )
+}
+
+fn plain_text(rich_text: &Value) -> String {
+    rich_text
+        .as_array()
+        .map(|parts| {
+            parts
+                .iter()
+                .filter_map(|part| part["text"]["content"].as_str())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Notebook name usable as a file name
+pub fn file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c => c,
+        })
+        .collect()
+}
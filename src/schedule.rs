@@ -0,0 +1,289 @@
+use crate::error::{Error, Result};
+use chrono::{
+    DateTime, Datelike, Duration as ChronoDuration, Local, NaiveDateTime, TimeZone, Timelike,
+};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// When `daemon --schedule` runs a sync: a five-field cron expression in
+/// local time (`0 */2 * * *`), or a fixed interval (`30m`, `2h`)
+#[derive(Debug, Clone)]
+pub enum Schedule {
+    Interval(Duration),
+    Cron(Box<Cron>),
+}
+
+impl Schedule {
+    /// The first run time after `after`
+    pub fn next_after(&self, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        match self {
+            Schedule::Interval(interval) => Some(after + ChronoDuration::from_std(*interval).ok()?),
+            Schedule::Cron(cron) => cron.next_after(after),
+        }
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let expression = match s {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            _ => s,
+        };
+        if expression.split_whitespace().count() == 5 {
+            return Ok(Schedule::Cron(Box::new(expression.parse()?)));
+        }
+        parse_interval(s.strip_prefix("every ").unwrap_or(s)).map(Schedule::Interval)
+    }
+}
+
+/// `90s`, `15m`, `2h` or `1d`
+fn parse_interval(s: &str) -> Result<Duration> {
+    let invalid = || {
+        Error::Config(format!(
+            "Invalid schedule '{}' (expected a cron expression like \"0 * * * *\" or an interval like 30m)",
+            s
+        ))
+    };
+    let split = s.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+    let (number, unit) = s.split_at(split);
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+    let seconds = match unit.trim() {
+        "s" => number,
+        "m" => number * 60,
+        "h" => number * 3600,
+        "d" => number * 86400,
+        _ => return Err(invalid()),
+    };
+    if seconds == 0 {
+        return Err(invalid());
+    }
+    Ok(Duration::from_secs(seconds))
+}
+
+/// Five-field cron expression: minute, hour, day of month, month, day of
+/// week. Fields take `*`, values, ranges (`1-5`), steps (`*/15`, `0-30/10`)
+/// and lists of those.
+#[derive(Debug, Clone)]
+pub struct Cron {
+    minutes: Vec<bool>,
+    hours: Vec<bool>,
+    days: Vec<bool>,
+    months: Vec<bool>,
+    weekdays: Vec<bool>,
+    /// Whether day of month and day of week were both restricted, in which
+    /// case either matching is enough (as in cron)
+    either_day: bool,
+}
+
+impl Cron {
+    fn matches_day(&self, date: NaiveDateTime) -> bool {
+        let day = self.days[date.day() as usize];
+        let weekday = self.weekdays[date.weekday().num_days_from_sunday() as usize];
+        if self.either_day {
+            day || weekday
+        } else {
+            day && weekday
+        }
+    }
+
+    fn next_after(&self, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        let mut time =
+            after.naive_local().with_second(0)?.with_nanosecond(0)? + ChronoDuration::minutes(1);
+
+        // Four years covers every combination of days, months and weekdays
+        let limit = time + ChronoDuration::days(4 * 366);
+        while time < limit {
+            if !self.months[time.month() as usize] || !self.matches_day(time) {
+                time = (time.date() + ChronoDuration::days(1)).and_hms_opt(0, 0, 0)?;
+                continue;
+            }
+            if !self.hours[time.hour() as usize] {
+                time = time.with_minute(0)? + ChronoDuration::hours(1);
+                continue;
+            }
+            if !self.minutes[time.minute() as usize] {
+                time += ChronoDuration::minutes(1);
+                continue;
+            }
+            // Times skipped by a DST change don't exist locally
+            if let Some(local) = Local.from_local_datetime(&time).earliest() {
+                return Some(local);
+            }
+            time += ChronoDuration::minutes(1);
+        }
+        None
+    }
+}
+
+impl FromStr for Cron {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(Error::Config(format!(
+                "Invalid cron expression '{}': expected 5 fields",
+                s
+            )));
+        };
+
+        let mut weekdays = parse_field(weekday, 0, 7, s)?;
+        // Both 0 and 7 are Sunday
+        weekdays[0] |= weekdays[7];
+        Ok(Cron {
+            minutes: parse_field(minute, 0, 59, s)?,
+            hours: parse_field(hour, 0, 23, s)?,
+            days: parse_field(day, 1, 31, s)?,
+            months: parse_field(month, 1, 12, s)?,
+            weekdays,
+            either_day: !day.starts_with('*') && !weekday.starts_with('*'),
+        })
+    }
+}
+
+/// Values a cron field selects, as flags indexed by value
+fn parse_field(field: &str, min: u32, max: u32, expression: &str) -> Result<Vec<bool>> {
+    let invalid = || {
+        Error::Config(format!(
+            "Invalid cron field '{}' in '{}' (values {} to {})",
+            field, expression, min, max
+        ))
+    };
+    let number = |s: &str| -> Result<u32> {
+        s.parse()
+            .ok()
+            .filter(|n| (min..=max).contains(n))
+            .ok_or_else(invalid)
+    };
+
+    let mut selected = vec![false; max as usize + 1];
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().map_err(|_| invalid())?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            return Err(invalid());
+        }
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (number(start)?, number(end)?),
+                // `5/10` means from 5 to the end in steps of 10
+                None if step > 1 => (number(range)?, max),
+                None => (number(range)?, number(range)?),
+            },
+        };
+        if start > end {
+            return Err(invalid());
+        }
+        for value in (start..=end).step_by(step as usize) {
+            selected[value as usize] = true;
+        }
+    }
+    Ok(selected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local(y: i32, mo: u32, d: u32, h: u32, mi: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(y, mo, d, h, mi, 0).unwrap()
+    }
+
+    fn next(schedule: &str, after: DateTime<Local>) -> DateTime<Local> {
+        schedule
+            .parse::<Schedule>()
+            .unwrap()
+            .next_after(after)
+            .unwrap()
+    }
+
+    #[test]
+    fn parses_intervals() {
+        let interval = |s: &str| match s.parse::<Schedule>().unwrap() {
+            Schedule::Interval(interval) => interval.as_secs(),
+            Schedule::Cron(_) => panic!("'{}' parsed as cron", s),
+        };
+        assert_eq!(interval("90s"), 90);
+        assert_eq!(interval("30m"), 1800);
+        assert_eq!(interval("every 2h"), 7200);
+        assert_eq!(interval("1d"), 86400);
+        for invalid in ["0m", "5x", "m", "", "* * * *"] {
+            assert!(invalid.parse::<Schedule>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn interval_runs_after_the_last_run() {
+        assert_eq!(
+            next("45m", local(2025, 1, 10, 10, 7)),
+            local(2025, 1, 10, 10, 52)
+        );
+    }
+
+    #[test]
+    fn cron_steps_and_ranges() {
+        assert_eq!(
+            next("*/15 * * * *", local(2025, 1, 10, 10, 7)),
+            local(2025, 1, 10, 10, 15)
+        );
+        // Friday after the run goes to Monday
+        assert_eq!(
+            next("0 9 * * 1-5", local(2025, 1, 10, 9, 0)),
+            local(2025, 1, 13, 9, 0)
+        );
+        assert_eq!(
+            next("@daily", local(2025, 1, 10, 9, 0)),
+            local(2025, 1, 11, 0, 0)
+        );
+        assert_eq!(
+            next("5/20 * * * *", local(2025, 1, 10, 9, 30)),
+            local(2025, 1, 10, 9, 45)
+        );
+    }
+
+    #[test]
+    fn cron_days() {
+        // Sunday is 0 or 7
+        assert_eq!(
+            next("0 12 * * 7", local(2025, 1, 10, 9, 0)),
+            local(2025, 1, 12, 12, 0)
+        );
+        // Day of month or day of week when both are given: the 13th or a Friday
+        assert_eq!(
+            next("0 0 13 * 5", local(2025, 1, 10, 0, 0)),
+            local(2025, 1, 13, 0, 0)
+        );
+        assert_eq!(
+            next("0 0 13 * 5", local(2025, 1, 13, 0, 0)),
+            local(2025, 1, 17, 0, 0)
+        );
+        // The next 31 February never comes
+        assert!("0 0 31 2 *"
+            .parse::<Schedule>()
+            .unwrap()
+            .next_after(local(2025, 1, 10, 0, 0))
+            .is_none());
+    }
+
+    #[test]
+    fn rejects_invalid_cron_fields() {
+        for invalid in [
+            "60 * * * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "* * 0 * *",
+            "* * * 13 *",
+        ] {
+            assert!(invalid.parse::<Schedule>().is_err(), "{}", invalid);
+        }
+    }
+}
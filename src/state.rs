@@ -0,0 +1,150 @@
+use crate::error::{Error, Result};
+use crate::ocr::PageText;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+/// Bookkeeping carried over between runs, keyed by Notion page ID
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SyncState {
+    #[serde(default)]
+    pub pages: HashMap<String, PageState>,
+    /// Google Drive file currently linked for each notebook, by notebook path
+    #[serde(default)]
+    pub drive_files: HashMap<String, String>,
+    /// Per-page Google Drive files (SVG exports) by notebook path and page number
+    #[serde(default)]
+    pub drive_pages: HashMap<String, BTreeMap<usize, String>>,
+    /// Archived pages of notebooks without text (`EMPTY_NOTEBOOKS=archive`),
+    /// by notebook path, so they aren't created again on every run
+    #[serde(default)]
+    pub archived_empty: HashMap<String, String>,
+    /// SHA-256 of each notebook's PDF when it was last synced, by notebook path
+    #[serde(default)]
+    pub content_hashes: HashMap<String, String>,
+    /// Page last written for each notebook, by notebook path (not kept for
+    /// journals, which have a page per day)
+    #[serde(default)]
+    pub notebook_pages: HashMap<String, String>,
+    /// The same pages by the notebook's document UUID, which survives renames
+    /// and moves on the tablet
+    #[serde(default)]
+    pub uuid_pages: HashMap<String, String>,
+    /// Notebooks not synced because their page was archived in Notion
+    /// (`RESPECT_NOTION_ARCHIVE`), by notebook path
+    #[serde(default)]
+    pub archived_in_notion: HashMap<String, String>,
+    /// Notebooks written without OCR (`DEFER_OCR`) that `sync --ocr-backlog`
+    /// still has to fill in, by notebook path
+    #[serde(default)]
+    pub ocr_backlog: BTreeMap<String, QueuedOcr>,
+    /// Text of each notebook's pages from the last OCR, by notebook path, so
+    /// only pages that changed are OCR'd again
+    #[serde(default)]
+    pub page_texts: HashMap<String, PageTexts>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct PageState {
+    /// Image blocks on the page by notebook page number
+    #[serde(default)]
+    pub images: BTreeMap<usize, ImageState>,
+    /// The page's `last_edited_time` right after the sync last wrote it, to
+    /// tell edits made in Notion since
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub synced_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PageTexts {
+    /// OCR settings the texts were recognized with (`OcrEngine::settings`)
+    pub settings: String,
+    /// Page texts by `ocr::page_key`
+    pub pages: HashMap<String, PageText>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedOcr {
+    /// When the notebook was queued (RFC 3339)
+    pub queued_at: String,
+    /// The page written for it, with a placeholder for the text
+    pub page_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageState {
+    /// SHA-256 of the rendered page image
+    pub hash: String,
+    pub block_id: String,
+    /// Caption the image was uploaded with (`None` for "Page N")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub caption: Option<String>,
+}
+
+impl ImageState {
+    /// Whether the block shows this image with this caption
+    pub fn matches(&self, page_num: usize, hash: &str, caption: &str) -> bool {
+        let current = match &self.caption {
+            Some(caption) => caption.clone(),
+            None => format!("Page {}", page_num),
+        };
+        self.hash == hash && current == caption
+    }
+}
+
+impl SyncState {
+    /// Load the state file (`REMARKABLE2NOTION_STATE` or `state.json` next to the config file)
+    pub fn load() -> Result<Self> {
+        let path = match std::env::var("REMARKABLE2NOTION_STATE") {
+            Ok(path) => PathBuf::from(path),
+            Err(_) => dirs::config_dir()
+                .unwrap_or_else(|| PathBuf::from("."))
+                .join("remarkable2notion")
+                .join("state.json"),
+        };
+
+        if !path.exists() {
+            return Ok(Self {
+                path,
+                ..Self::default()
+            });
+        }
+
+        let content = std::fs::read_to_string(&path)?;
+        let mut state: SyncState = serde_json::from_str(&content)
+            .map_err(|e| Error::Config(format!("Invalid state file {:?}: {}", path, e)))?;
+        debug!(
+            "Loaded state for {} pages from {:?}",
+            state.pages.len(),
+            path
+        );
+        state.path = path;
+        Ok(state)
+    }
+
+    /// Write the state file, replacing it atomically
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        std::fs::write(&tmp_path, serde_json::to_string_pretty(self)?)?;
+        std::fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+/// Hex-encoded SHA-256 of a file's contents
+pub fn file_hash(path: &Path) -> Result<String> {
+    let bytes = std::fs::read(path)?;
+    Ok(Sha256::digest(&bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
@@ -1,71 +1,260 @@
-use crate::config::Config;
-use crate::error::{Error, Result};
+use crate::alerts::ErrorReporter;
+use crate::cache::StageCache;
+use crate::config::{
+    self, ArchivedPages, Config, DeleteMode, EmptyNotebookAction, ManualEdits, NotebookOrder,
+    PdfLinkMode, PrivacyMode, RelationConfig, RelationSource, TemplateConfig,
+};
+use crate::correction::{OllamaCorrector, DEFAULT_OLLAMA_URL, DEFAULT_PROMPT};
+use crate::error::{Error, Result, StageExt};
+use crate::events::SyncEvent;
+use crate::exporter::Exporter;
+use crate::extract::{self, TemplateFields};
+use crate::glob::Glob;
 use crate::google_drive::GoogleDriveClient;
-use crate::google_vision::GoogleVisionClient;
-use crate::notion::NotionClient;
+use crate::notion::blocks::BlockBuilder;
+use crate::notion::models::{plain_text, Property};
+use crate::notion::{self, NotionClient, NotionPage, Page, SourceProperties, SyncStatus};
 use crate::oauth::GoogleOAuthClient;
+use crate::ocr::{self, OcrBackendKind, OcrEngine};
+use crate::rate_limit::RateLimiter;
+use crate::redact::redact;
 use crate::remarkable::{Notebook, RemarkableClient};
-use std::path::Path;
-use std::sync::Arc;
+use crate::repair;
+use crate::report::{millis, NotebookReport, StageTimings, SyncReport};
+use crate::review::{self, ReviewPage};
+use crate::state::{self, ImageState, PageState, PageTexts, QueuedOcr, SyncState};
+use crate::vocabulary::Vocabulary;
+use futures_util::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::cmp::Reverse;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::{broadcast, OnceCell};
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, warn};
 
+/// Events kept for subscribers that fall behind
+const EVENT_BUFFER: usize = 256;
+
+/// Notebooks with this tag are split into one page per dated entry
+const JOURNAL_TAG: &str = "journal";
+
+/// Notebooks with this tag get meeting fields and action items extracted
+const MEETING_TAG: &str = "meeting";
+
+/// Notebooks with this tag, like starred ones, are processed before the rest
+const PRIORITY_TAG: &str = "priority";
+
+/// Tag on the pages of notebooks in the tablet's trash (`--include-trash`)
+const TRASH_TAG: &str = "Trash";
+
+/// Text of pages written before their OCR ran (`DEFER_OCR`)
+const OCR_PENDING_TEXT: &str = "Text recognition is queued and will be added here.";
+
 pub struct SyncEngine {
     config: Config,
+    /// Identifies the current run in the report and on uploaded Drive files
+    run_id: Mutex<String>,
     remarkable: RemarkableClient,
-    google_vision: GoogleVisionClient,
+    /// OCR engines for the default backend and every backend used by an OCR rule
+    ocr_engines: HashMap<OcrBackendKind, OcrEngine>,
+    corrector: Option<OllamaCorrector>,
+    error_reporter: Option<ErrorReporter>,
+    /// Terms near misses in the OCR text are corrected to
+    vocabulary: Vocabulary,
     google_drive: Option<GoogleDriveClient>,
+    /// Exports pages as SVG for Drive and Notion (`SVG_EXPORT`)
+    svg_exporter: Option<Exporter>,
     notion: NotionClient,
+    /// Relation configs with a client for each target database
+    relations: Vec<(RelationConfig, NotionClient)>,
+    /// Pages of each relation's target database, loaded on first use
+    relation_targets: OnceCell<Vec<Vec<NotionPage>>>,
+    /// IDs of pages written during this run
+    synced_pages: Mutex<HashSet<String>>,
+    /// Persisted per-page bookkeeping (image hashes)
+    state: Mutex<SyncState>,
+    /// OCR output of notebooks whose sync failed after OCR
+    stage_cache: StageCache,
+    /// Whether tags were written back to the tablet, which needs a UI restart
+    tags_written_back: AtomicBool,
+    events: broadcast::Sender<SyncEvent>,
 }
 
 impl SyncEngine {
     pub async fn new(config: Config) -> Result<Self> {
+        // Privacy modes don't send page images to a cloud OCR service, nor
+        // PDFs and SVG pages to Drive
+        let private = config.privacy_mode != PrivacyMode::Off;
+        if private {
+            check_local_ocr(&config)?;
+            info!(
+                "Privacy mode {:?}: OCR runs locally, nothing is uploaded to Drive",
+                config.privacy_mode
+            );
+        }
+
+        let exporter = Exporter::new(
+            config.exporter,
+            config.exporter_command.as_deref(),
+            config.exporter_format,
+        )?;
+        // SVG pages are images, so they're only held back from Notion in text mode
+        let svg_exporter = ((config.svg_export.to_drive() && !private)
+            || (config.svg_export.to_notion() && config.privacy_mode != PrivacyMode::Text))
+            .then(|| Exporter::svg(exporter.as_ref()));
+
         let remarkable = RemarkableClient::new(
             config.remarkable_backup_dir.clone(),
             config.remarkable_password.clone(),
         )
-        .await?;
+        .await?
+        .with_ssh_host(config.remarkable_ssh_host.clone())
+        .with_transport(config.remarkable_transport)
+        .with_device_sync(config.device_sync)
+        .with_favorites_only(config.favorites_only)
+        .with_sync_timeout(config.remarkable_sync_timeout)
+        .with_exporter(exporter);
 
-        // Google Cloud Vision is required
-        let google_vision = if let Some(ref api_key) = config.google_vision_api_key {
-            debug!("Using Google Cloud Vision for OCR");
-            GoogleVisionClient::new(api_key.clone())
-        } else {
-            return Err(Error::Config(
-                "Google Cloud Vision API key is required. Set GOOGLE_VISION_API_KEY in .env file."
-                    .to_string(),
-            ));
-        };
+        // An OCR backend is required (Google Cloud Vision unless OCR_BACKEND says otherwise),
+        // plus any backend that OCR rules route notebooks to
+        let mut ocr_engines = HashMap::new();
+        let rule_backends = config.file.ocr_rules.iter().map(|rule| rule.backend);
+        for kind in std::iter::once(config.ocr_backend).chain(rule_backends) {
+            if let Entry::Vacant(entry) = ocr_engines.entry(kind) {
+                let backend = ocr::create_backend(kind, &config)?;
+                entry.insert(
+                    OcrEngine::new(backend)
+                        .with_dpi(config.ocr_dpi, config.adaptive_dpi)
+                        .with_error_policy(config.on_ocr_error),
+                );
+            }
+        }
+
+        // Optional local LLM pass that fixes OCR artifacts; there's no text to
+        // correct when only images are kept
+        let corrector = config
+            .ollama_model
+            .as_ref()
+            .filter(|_| config.privacy_mode != PrivacyMode::Images)
+            .map(|model| {
+                debug!("Using Ollama model {} for OCR correction", model);
+                OllamaCorrector::new(
+                    config
+                        .ollama_url
+                        .clone()
+                        .unwrap_or_else(|| DEFAULT_OLLAMA_URL.to_string()),
+                    model.clone(),
+                    config
+                        .ollama_prompt
+                        .clone()
+                        .unwrap_or_else(|| DEFAULT_PROMPT.to_string()),
+                )
+            });
+
+        let vocabulary = Vocabulary::new(config.file.vocabulary.iter().cloned());
+        if !vocabulary.is_empty() {
+            debug!(
+                "Correcting OCR text towards {} vocabulary terms",
+                vocabulary.len()
+            );
+        }
 
         // Setup Google Drive if OAuth credentials are provided
-        let google_drive = if let (Some(client_id), Some(client_secret)) = (
-            &config.google_oauth_client_id,
-            &config.google_oauth_client_secret,
-        ) {
+        let google_drive = if private {
+            None
+        } else if let Some(ref client_id) = config.google_oauth_client_id {
             debug!("Google Drive integration enabled");
-            let oauth_client = Arc::new(GoogleOAuthClient::new(
-                client_id.clone(),
-                client_secret.clone(),
-            )?);
+            let oauth_client = Arc::new(
+                GoogleOAuthClient::new(
+                    client_id.clone(),
+                    config.google_oauth_client_secret.clone(),
+                )?
+                .with_callback_port(config.oauth_callback_port),
+            );
             Some(GoogleDriveClient::new(oauth_client, config.google_drive_folder_id.clone()).await?)
         } else {
             warn!("Google Drive not configured - PDFs will be linked locally");
             None
         };
 
-        let notion = NotionClient::new(
+        let error_reporter = ErrorReporter::new(
+            config.error_webhook_url.clone(),
+            config.sentry_dsn.as_deref(),
+        )?;
+
+        // One limiter for every client, as Notion limits the integration
+        let limiter = Arc::new(RateLimiter::per_second(config.notion_rate_limit));
+        let mut notion = NotionClient::new(
             config.notion_token.clone(),
             config.notion_database_id.clone(),
-        );
+        )
+        .with_rate_limiter(limiter.clone())
+        .with_batch_size(config.notion_batch_size)
+        .with_layout(config.file.layout.clone())
+        .with_source_id_property(config.source_id_property.clone())
+        .with_relations(config.file.relation_properties());
+        if let Some(ref page_id) = config.notion_parent_page_id {
+            notion = notion.with_parent_page(page_id.clone());
+        }
+
+        let relations = config
+            .file
+            .relations
+            .iter()
+            .map(|relation| {
+                let client =
+                    NotionClient::new(config.notion_token.clone(), relation.database_id.clone())
+                        .with_rate_limiter(limiter.clone());
+                (relation.clone(), client)
+            })
+            .collect();
 
         Ok(Self {
             config,
+            run_id: Mutex::new(String::new()),
             remarkable,
-            google_vision,
+            ocr_engines,
+            corrector,
+            error_reporter,
+            vocabulary,
             google_drive,
+            svg_exporter,
             notion,
+            relations,
+            relation_targets: OnceCell::new(),
+            synced_pages: Mutex::new(HashSet::new()),
+            state: Mutex::new(SyncState::load()?),
+            stage_cache: StageCache::new(config::cache_dir()),
+            tags_written_back: AtomicBool::new(false),
+            events: broadcast::channel(EVENT_BUFFER).0,
         })
     }
 
+    /// Receive progress events of the runs that follow
+    pub fn subscribe(&self) -> broadcast::Receiver<SyncEvent> {
+        self.events.subscribe()
+    }
+
+    fn emit(&self, event: SyncEvent) {
+        // Without subscribers there's nobody to tell
+        let _ = self.events.send(event);
+    }
+
+    /// Directory RemarkableSync backs the tablet up to
+    pub fn backup_dir(&self) -> &Path {
+        self.remarkable.backup_dir()
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
     pub async fn verify_prerequisites(&self) -> Result<()> {
         debug!("Verifying prerequisites...");
 
@@ -73,87 +262,271 @@ impl SyncEngine {
 
         self.notion.verify_connection().await?;
 
-        // Ensure database has required properties
-        self.notion.ensure_database_properties().await?;
+        // Schema changes are left to `schema check --fix`
+        self.notion.report_schema_issues().await?;
+
+        if self.notion.uses_parent_page() && !self.relations.is_empty() {
+            warn!("Relations need a database, skipped when syncing to a parent page");
+        }
+
+        let problems = self
+            .notion
+            .audit_permissions(self.config.sync_comments)
+            .await?;
+        if !problems.is_empty() {
+            return Err(Error::Config(format!(
+                "The Notion integration can't sync to the target:\n  - {}\nSee SETUP.md for the capabilities it needs; `schema check --fix` fixes the properties",
+                problems.join("\n  - ")
+            )));
+        }
 
         debug!("All prerequisites verified");
         Ok(())
     }
 
-    pub async fn sync(&self) -> Result<()> {
+    pub async fn sync(&self, cancel: &CancellationToken) -> Result<SyncReport> {
+        self.sync_notebooks(None, cancel).await
+    }
+
+    /// Run a sync, processing only the named notebooks when `only` is given.
+    ///
+    /// Deletions and stale pages are handled for all notebooks either way.
+    /// Once `cancel` is triggered no more notebooks are started. Notebooks in
+    /// progress stop before anything is uploaded, or are finished if their
+    /// upload already began, so no page is left half-written; the rest of the
+    /// run (deletions, stale pages) is skipped.
+    pub async fn sync_notebooks(
+        &self,
+        only: Option<&HashSet<String>>,
+        cancel: &CancellationToken,
+    ) -> Result<SyncReport> {
+        let run_id = chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        *self.run_id.lock().unwrap() = run_id.clone();
+        self.synced_pages.lock().unwrap().clear();
+
+        let mut report = SyncReport {
+            run_id,
+            transmitted: self.transmitted(),
+            ..SyncReport::default()
+        };
+        if self.config.privacy_mode != PrivacyMode::Off {
+            for (destination, data) in &report.transmitted {
+                info!("Sending {} to {}", data.join(", "), destination);
+            }
+        }
+
+        let device_sync_start = Instant::now();
         let notebooks = self.remarkable.list_notebooks().await?;
+        // Ignored notebooks are left out, but still count as on the tablet
+        // when looking for stale pages
+        let (ignored, mut notebooks): (Vec<_>, Vec<_>) =
+            notebooks.into_iter().partition(|notebook| notebook.ignored);
+        report.device_sync_ms = millis(device_sync_start.elapsed());
+        debug!("Device sync took {}ms", report.device_sync_ms);
 
         if notebooks.is_empty() {
             warn!("No notebooks found");
-            return Ok(());
+            return Ok(report);
         }
 
         info!("Syncing {} notebooks", notebooks.len());
 
-        let mut success_count = 0;
-        let mut error_count = 0;
+        if self.config.include_trash {
+            for notebook in notebooks.iter_mut().filter(|notebook| notebook.is_deleted) {
+                notebook.tags.push(TRASH_TAG.to_string());
+            }
+        }
+
+        if !self.config.dry_run {
+            report.rejected_tags = self.register_tags(&notebooks).await;
+        }
 
-        for (idx, notebook) in notebooks.iter().enumerate() {
+        let mut pending = Vec::new();
+        for notebook in &notebooks {
             // Skip deleted notebooks, they'll be handled separately
-            if notebook.is_deleted {
+            if notebook.is_deleted && !self.config.include_trash {
+                continue;
+            }
+            if only.is_some_and(|names| !names.contains(&notebook.name)) {
+                continue;
+            }
+            if !self.selected(notebook) {
+                debug!("Skipping {}: not selected", notebook.name);
+                report.filtered += 1;
+                continue;
+            }
+            let queued = self
+                .state
+                .lock()
+                .unwrap()
+                .ocr_backlog
+                .contains_key(&notebook.path);
+            if self.config.ocr_backlog && !queued {
+                continue;
+            }
+            if !self.modified_since(notebook) {
+                debug!("Skipping {}: not modified since --since", notebook.name);
+                report.filtered += 1;
                 continue;
             }
+            if self.config.changed_only && notebook.exported == Some(false) {
+                debug!("Skipping {}: not exported this run", notebook.name);
+                report.unchanged += 1;
+                continue;
+            }
+            // Queued notebooks were never OCR'd, changed or not
+            if !self.config.force && !self.config.ocr_backlog && self.unchanged_since_sync(notebook)
+            {
+                debug!(
+                    "Skipping {}: PDF unchanged since the last sync",
+                    notebook.name
+                );
+                report.unchanged += 1;
+                continue;
+            }
+            pending.push(notebook);
+        }
+        if !self.config.only.is_empty()
+            && !notebooks.iter().any(|notebook| {
+                self.config
+                    .only
+                    .iter()
+                    .any(|glob| matches_glob(glob, notebook))
+            })
+        {
+            let patterns: Vec<&str> = self.config.only.iter().map(Glob::as_str).collect();
+            warn!("No notebook matches --only {}", patterns.join(", "));
+        }
+        for entry in self.config.listed.iter().flatten() {
+            if !notebooks.iter().any(|notebook| is_listed(entry, notebook)) {
+                warn!("No notebook named '{}' (from stdin)", entry);
+            }
+        }
+        if self.config.respect_notion_archive {
+            let archived = self.archived_in_notion(&pending).await;
+            pending.retain(|notebook| !archived.contains(&notebook.path));
+            report.archived_in_notion = notebooks
+                .iter()
+                .filter(|notebook| archived.contains(&notebook.path))
+                .map(|notebook| notebook.name.clone())
+                .collect();
+        }
 
-            debug!(
-                "Processing {}/{}: {}",
-                idx + 1,
-                notebooks.len(),
-                notebook.name
+        // Starred and priority-tagged notebooks first, so they show up in
+        // Notion early in a long run
+        pending.sort_by_key(|notebook| !is_priority(notebook));
+
+        if let Some(limit) = self.config.limit.filter(|limit| pending.len() > *limit) {
+            // Notebooks that were never synced go first, oldest first, so a
+            // large first sync can be spread over several runs
+            let state = self.state.lock().unwrap();
+            pending.sort_by_cached_key(|notebook| {
+                (
+                    !is_priority(notebook),
+                    state.content_hashes.contains_key(&notebook.path),
+                    notebook.metadata.modified_time.clone(),
+                )
+            });
+            drop(state);
+            report.deferred = pending.len() - limit;
+            pending.truncate(limit);
+            info!(
+                "Processing {} notebooks, {} left for later runs (--limit)",
+                limit, report.deferred
             );
+        }
 
-            match self.process_notebook(notebook).await {
-                Ok(_) => {
-                    success_count += 1;
-                    info!("✓ {}", notebook.name);
+        // Up to `concurrency` notebooks are in flight; results keep their order.
+        // Too many failures stop the run the way Ctrl-C does.
+        let total = pending.len();
+        let mut pending: Vec<(usize, &Notebook)> = pending.into_iter().enumerate().collect();
+        if self.config.concurrency > 1 && self.config.notebook_order == NotebookOrder::LargestFirst
+        {
+            // The biggest notebooks take longest; starting them first keeps
+            // one from running on alone at the end. Priority notebooks still
+            // start before all others.
+            pending.sort_by_cached_key(|(_, notebook)| {
+                (
+                    !is_priority(notebook),
+                    Reverse(
+                        std::fs::metadata(self.remarkable.backup_pdf(notebook))
+                            .map(|metadata| metadata.len())
+                            .unwrap_or(0),
+                    ),
+                )
+            });
+        }
+        let abort = cancel.child_token();
+        let failures = AtomicUsize::new(0);
+        let mut results: Vec<_> = if self.config.dry_run && self.config.review_dir.is_none() {
+            let pages = self.notion.query_pages().await?;
+            pending
+                .into_iter()
+                .map(|(idx, notebook)| (idx, self.plan_notebook(notebook, &pages)))
+                .collect()
+        } else {
+            stream::iter(pending)
+                .map(|(idx, notebook)| {
+                    debug!("Processing {}/{}: {}", idx + 1, total, notebook.name);
+                    let (abort, failures) = (&abort, &failures);
+                    async move {
+                        let result = self.sync_notebook(notebook, abort).await;
+                        if result.1.is_none() {
+                            let failed = failures.fetch_add(1, Ordering::SeqCst) + 1;
+                            if self.config.max_errors.is_some_and(|max| failed >= max) {
+                                abort.cancel();
+                            }
+                        }
+                        (idx, result)
+                    }
+                })
+                .buffered(self.config.concurrency.max(1))
+                .collect()
+                .await
+        };
+        results.sort_by_key(|(idx, _)| *idx);
+
+        for (_, (notebook_report, processed)) in results {
+            match processed {
+                Some(Processed::Written) => report.succeeded += 1,
+                Some(Processed::Empty) => {
+                    report.succeeded += 1;
+                    report.empty += 1;
                 }
-                Err(e) => {
-                    error_count += 1;
-                    error!("✗ {} - {}", notebook.name, e);
+                Some(Processed::Skipped) => report.skipped += 1,
+                Some(Processed::Queued) => {
+                    report.succeeded += 1;
+                    report.queued += 1;
+                }
+                Some(Processed::Cancelled) => {
+                    report.cancelled += 1;
+                    continue;
                 }
+                None => report.failed += 1,
             }
+            report.notebooks.push(notebook_report);
         }
 
-        // Delete notebooks from Notion that are deleted on the tablet (parent="trash")
-        let mut deleted_count = 0;
-        for notebook in &notebooks {
-            if notebook.is_deleted {
-                debug!(
-                    "Notebook '{}' is in trash, deleting from Notion",
-                    notebook.name
+        if abort.is_cancelled() {
+            self.restart_ui_if_needed().await;
+            if cancel.is_cancelled() {
+                warn!(
+                    "Cancelled: {} succeeded, {} failed, {} left for the next run",
+                    report.succeeded, report.failed, report.cancelled
+                );
+            } else {
+                report.aborted = true;
+                error!(
+                    "Stopped after {} failed notebooks: {} succeeded, {} left for the next run",
+                    report.failed, report.succeeded, report.cancelled
                 );
-                match self.notion.find_page_by_title(&notebook.name).await {
-                    Ok(Some(page)) => {
-                        if let Err(e) = self.notion.delete_page(&page.id).await {
-                            warn!("Failed to delete '{}': {}", notebook.name, e);
-                        } else {
-                            deleted_count += 1;
-                            info!("🗑️  {}", notebook.name);
-                        }
-                    }
-                    Ok(None) => {
-                        debug!(
-                            "No Notion page found for deleted notebook '{}', skipping delete",
-                            notebook.name
-                        );
-                    }
-                    Err(e) => {
-                        warn!(
-                            "Failed to check if page exists for deleted notebook '{}': {}",
-                            notebook.name, e
-                        );
-                    }
-                }
             }
+            return Ok(report);
         }
 
         // Fetch all pages from Notion using a paginated API to ensure we see
         // pages beyond the first page of results.
-        let all_pages = match self.notion.get_all_pages().await {
+        let pages = match self.notion.query_pages().await {
             Ok(pages) => pages,
             Err(e) => {
                 warn!(
@@ -164,129 +537,3098 @@ impl SyncEngine {
             }
         };
 
-        for notebook in &notebooks {
-            if notebook.is_deleted {
-                debug!(
-                    "Notebook '{}' is in trash, attempting to delete from Notion",
-                    notebook.name
-                );
+        // Notebooks in the tablet's trash (parent="trash")
+        report.deleted = self.propagate_deletions(&notebooks, &pages).await;
+
+        // The notebooks that aren't starred weren't looked at, rather than gone
+        if !self.config.dry_run && !self.config.favorites_only {
+            let present: Vec<&Notebook> = notebooks.iter().chain(&ignored).collect();
+            report.stale = self.mark_stale(&pages, &present).await;
+        }
+
+        self.restart_ui_if_needed().await;
+
+        info!(
+            "Complete: {} succeeded, {} failed, {} deleted, {} stale",
+            report.succeeded, report.failed, report.deleted, report.stale
+        );
+
+        Ok(report)
+    }
+
+    /// The page last written for the notebook, found by its document UUID
+    /// so it's still found after the notebook was renamed or moved; its title
+    /// is changed to `title` if it differs. Journals have a page per day and
+    /// are always found by title.
+    async fn page_by_uuid(&self, notebook: &Notebook, title: &str) -> Result<Option<NotionPage>> {
+        let Some(ref uuid) = notebook.uuid else {
+            return Ok(None);
+        };
+        if has_tag(notebook, JOURNAL_TAG) {
+            return Ok(None);
+        }
+
+        let known = self.state.lock().unwrap().uuid_pages.get(uuid).cloned();
+        let page = match known {
+            // Pages archived or deleted in Notion are handled as before
+            Some(page_id) => match self.notion.get_page(&page_id).await {
+                Ok(page) if !page.archived => NotionPage::from_page(&page),
+                Ok(_) => None,
+                Err(e) => {
+                    debug!("Page {} of '{}' not found: {}", page_id, notebook.name, e);
+                    None
+                }
+            },
+            None => self.notion.find_page_by_source_id(uuid).await?,
+        };
+        let Some(mut page) = page else {
+            return Ok(None);
+        };
+
+        if page.title != title {
+            info!("'{}' was renamed to '{}'", page.title, title);
+            self.notion.rename_page(&page.id, title).await?;
+            page.title = title.to_string();
+        }
+        Ok(Some(page))
+    }
+
+    /// Key of this run's write of the page titled `title`, unique across runs
+    /// and the pages of a notebook (journals have one per day)
+    fn write_key(&self, notebook: &Notebook, title: &str) -> String {
+        let digest = Sha256::digest(format!("{}\n{}", notebook_id(notebook), title));
+        let hash: String = digest[..8].iter().map(|b| format!("{:02x}", b)).collect();
+        format!("{}-{}", self.run_id.lock().unwrap(), hash)
+    }
+
+    /// Kinds of notebook data sent off this machine, by destination
+    fn transmitted(&self) -> BTreeMap<String, Vec<String>> {
+        let mut transmitted: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        let mut send = |destination: &str, data: &str| {
+            transmitted
+                .entry(destination.to_string())
+                .or_default()
+                .push(data.to_string());
+        };
+
+        send("Notion", "metadata");
+        if self.config.privacy_mode != PrivacyMode::Images {
+            send("Notion", "text");
+        }
+        if self.config.privacy_mode != PrivacyMode::Text {
+            send("Notion", "images");
+        }
+        for kind in self.ocr_engines.keys() {
+            match kind {
+                OcrBackendKind::Vision => send("Google Cloud Vision", "images"),
+                OcrBackendKind::Gemini => send("Gemini", "images"),
+                OcrBackendKind::OpenAi => send("OpenAI", "images"),
+                OcrBackendKind::Apple => {}
+            }
+        }
+        if self.google_drive.is_some() {
+            send("Google Drive", "pdf");
+            if self.svg_exporter.is_some() && self.config.svg_export.to_drive() {
+                send("Google Drive", "svg");
+            }
+        }
+        if self.corrector.is_some() {
+            let url = self
+                .config
+                .ollama_url
+                .as_deref()
+                .unwrap_or(DEFAULT_OLLAMA_URL);
+            let host = url::Url::parse(url)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_string))
+                .unwrap_or_else(|| url.to_string());
+            send(&format!("Ollama ({})", host), "text");
+        }
+        if self.config.error_webhook_url.is_some() {
+            send("Error webhook", "errors");
+        }
+        if self.config.sentry_dsn.is_some() {
+            send("Sentry", "errors");
+        }
+        transmitted
+    }
+
+    /// Paths of the notebooks whose page was archived in Notion.
+    ///
+    /// Pages missing from the database query are looked up one by one, so
+    /// this costs one query per run. Notebooks whose page has been restored
+    /// since are synced again.
+    async fn archived_in_notion(&self, notebooks: &[&Notebook]) -> HashSet<String> {
+        let known: Vec<(&Notebook, String)> = {
+            let state = self.state.lock().unwrap();
+            notebooks
+                .iter()
+                // Pages archived for being empty are the sync's own doing
+                .filter(|notebook| !state.archived_empty.contains_key(&notebook.path))
+                .filter_map(|notebook| {
+                    let page_id = state.notebook_pages.get(&notebook.path)?;
+                    Some((*notebook, page_id.clone()))
+                })
+                .collect()
+        };
+        if known.is_empty() {
+            return HashSet::new();
+        }
+
+        let live: HashSet<String> = match self.notion.query_pages().await {
+            Ok(pages) => pages.into_iter().map(|page| page.id).collect(),
+            Err(e) => {
+                warn!("Failed to list pages to check for archived ones: {}", e);
+                return HashSet::new();
+            }
+        };
 
-                // Find the corresponding page by title among all pages.
-                if let Some(page) = all_pages.iter().find(|page| page.title == notebook.name) {
-                    if let Err(e) = self.notion.delete_page(&page.id).await {
-                        warn!("Failed to delete '{}': {}", notebook.name, e);
-                    } else {
-                        deleted_count += 1;
-                        info!("🗑️  {}", notebook.name);
+        let mut archived = HashSet::new();
+        for (notebook, page_id) in known {
+            let was_archived = self
+                .state
+                .lock()
+                .unwrap()
+                .archived_in_notion
+                .contains_key(&notebook.path);
+            let is_archived = !live.contains(&page_id)
+                && match self.notion.get_page(&page_id).await {
+                    Ok(page) => page.archived,
+                    Err(e) => {
+                        debug!("Failed to look up page of '{}': {}", notebook.name, e);
+                        was_archived
                     }
-                } else {
-                    debug!(
-                        "No Notion page found with title '{}' for deletion",
+                };
+
+            let mut state = self.state.lock().unwrap();
+            if is_archived {
+                if !was_archived {
+                    warn!(
+                        "'{}' was archived in Notion, not syncing it until the page is restored",
                         notebook.name
                     );
                 }
+                state
+                    .archived_in_notion
+                    .insert(notebook.path.clone(), page_id);
+                archived.insert(notebook.path.clone());
+            } else if was_archived {
+                info!(
+                    "'{}' was restored in Notion, syncing it again",
+                    notebook.name
+                );
+                state.archived_in_notion.remove(&notebook.path);
             }
         }
 
-        info!(
-            "Complete: {} succeeded, {} failed, {} deleted",
-            success_count, error_count, deleted_count
-        );
+        if !self.config.dry_run {
+            if let Err(e) = self.state.lock().unwrap().save() {
+                warn!("Failed to save sync state: {}", e);
+            }
+        }
+        archived
+    }
 
-        Ok(())
+    /// Whether the notebook's PDF in the backup is the one last synced.
+    ///
+    /// RemarkableSync rewrites PDFs on every backup, so their timestamps
+    /// don't tell whether a notebook changed; their contents do.
+    fn unchanged_since_sync(&self, notebook: &Notebook) -> bool {
+        let state = self.state.lock().unwrap();
+        let Some(synced) = state.content_hashes.get(&notebook.path) else {
+            return false;
+        };
+        state::file_hash(&self.remarkable.backup_pdf(notebook)).is_ok_and(|hash| hash == *synced)
     }
 
-    async fn process_notebook(&self, notebook: &Notebook) -> Result<()> {
-        if self.config.dry_run {
-            debug!("[DRY RUN] Would process: {}", notebook.name);
-            return Ok(());
+    /// Remember the hash of the notebook's PDF after syncing it
+    fn record_content_hash(&self, notebook: &Notebook) {
+        if let Ok(hash) = state::file_hash(&self.remarkable.backup_pdf(notebook)) {
+            self.state
+                .lock()
+                .unwrap()
+                .content_hashes
+                .insert(notebook.path.clone(), hash);
         }
+    }
 
-        let pdf_path = self
-            .remarkable
-            .download_notebook(notebook, &self.config.temp_dir)
-            .await?;
-
-        // Extract text and images using Google Cloud Vision
-        let (text_content, page_images) = self
-            .google_vision
-            .extract_text_and_images_from_pdf(&pdf_path)
-            .await?;
+    /// Whether the notebook passes `--only`, `--include-tag` and `--exclude-tag`.
+    ///
+    /// Patterns match the name or the path including folders. Tags compare
+    /// case-insensitively; an excluded tag wins over an included one.
+    fn selected(&self, notebook: &Notebook) -> bool {
+        if !self.matches_only(notebook) {
+            return false;
+        }
 
-        // Prepare image paths for direct upload to Notion
-        let image_paths: Vec<(usize, &Path)> = page_images
-            .iter()
-            .enumerate()
-            .map(|(idx, path)| (idx + 1, path.as_path()))
-            .collect();
+        let has_any = |wanted: &[String]| {
+            wanted
+                .iter()
+                .any(|tag| notebook.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)))
+        };
+        if has_any(&self.config.exclude_tags) {
+            return false;
+        }
+        self.config.include_tags.is_empty() || has_any(&self.config.include_tags)
+    }
 
-        // Upload PDF to Google Drive if configured
-        let pdf_url = if let Some(ref drive) = self.google_drive {
-            Some(drive.upload_pdf(&pdf_path, &notebook.name).await?)
-        } else {
-            None
+    /// Whether the notebook was modified after the `--since` cutoff; notebooks
+    /// without a modification time are always processed
+    fn modified_since(&self, notebook: &Notebook) -> bool {
+        let Some(since) = self.config.since else {
+            return true;
         };
+        notebook
+            .metadata
+            .modified_time
+            .as_deref()
+            .and_then(|time| chrono::DateTime::parse_from_rfc3339(time).ok())
+            .is_none_or(|modified| modified > since)
+    }
 
-        let existing_page = self.notion.find_page_by_title(&notebook.name).await?;
+    fn matches_only(&self, notebook: &Notebook) -> bool {
+        let only = &self.config.only;
+        let listed = &self.config.listed;
+        (only.is_empty() && listed.is_none())
+            || only.iter().any(|glob| matches_glob(glob, notebook))
+            || listed
+                .iter()
+                .flatten()
+                .any(|entry| is_listed(entry, notebook))
+    }
 
-        match existing_page {
-            Some(page) => {
-                debug!("Updating existing page: {}", notebook.name);
-                self.notion
-                    .update_page(&page.id, &text_content, &notebook.metadata, &notebook.tags)
-                    .await?;
+    async fn restart_ui_if_needed(&self) {
+        if self.tags_written_back.load(Ordering::Relaxed) {
+            info!("Restarting tablet UI to load tags added in Notion");
+            if let Err(e) = self.remarkable.restart_ui().await {
+                warn!("{}", e);
+            }
+        }
+    }
 
-                // Add images if available (upload directly to Notion)
-                if !image_paths.is_empty() {
-                    self.notion
-                        .add_uploaded_images(&page.id, &image_paths)
-                        .await?;
-                }
+    /// Process one notebook, keeping its errors out of the rest of the run.
+    ///
+    /// Returns the notebook's report and, if it succeeded, what was done.
+    async fn sync_notebook(
+        &self,
+        notebook: &Notebook,
+        cancel: &CancellationToken,
+    ) -> (NotebookReport, Option<Processed>) {
+        let mut timings = StageTimings::default();
+        let mut verification_issues = Vec::new();
+        if cancel.is_cancelled() {
+            let notebook_report = NotebookReport {
+                name: notebook.name.clone(),
+                success: false,
+                error: None,
+                error_context: None,
+                verification_issues,
+                timings,
+            };
+            return (notebook_report, Some(Processed::Cancelled));
+        }
 
-                // Set PDF URL (Google Drive link or local path)
-                if let Some(ref url) = pdf_url {
-                    self.notion.set_pdf_url(&page.id, url).await?;
-                } else {
-                    self.notion.upload_pdf(&page.id, &pdf_path).await?;
-                    self.notion.set_pdf_link(&page.id, &pdf_path).await?;
+        self.emit(SyncEvent::NotebookStarted {
+            notebook: notebook.name.clone(),
+        });
+
+        let start = Instant::now();
+        let result = self
+            .process_notebook(notebook, cancel, &mut timings, &mut verification_issues)
+            .await;
+        timings.total_ms = millis(start.elapsed());
+        // The cached OCR is only needed to retry a failed or cancelled notebook
+        if matches!(result, Ok(ref processed) if !matches!(processed, Processed::Cancelled)) {
+            self.stage_cache.remove(notebook_id(notebook));
+        }
+
+        debug!(
+            "Timings for {}: render {}ms, OCR {}ms, correction {}ms, Drive {}ms, Notion {}ms, total {}ms",
+            notebook.name,
+            timings.render_ms,
+            timings.ocr_ms,
+            timings.correction_ms,
+            timings.drive_upload_ms,
+            timings.notion_ms,
+            timings.total_ms
+        );
+
+        let (processed, error, error_context) = match result {
+            Ok(processed) => {
+                let name = notebook.name.clone();
+                let event = match processed {
+                    Processed::Written => {
+                        info!("✓ {}", notebook.name);
+                        SyncEvent::NotebookCompleted { notebook: name }
+                    }
+                    Processed::Empty => {
+                        info!("○ {} - no text", notebook.name);
+                        SyncEvent::NotebookCompleted { notebook: name }
+                    }
+                    Processed::Cancelled => {
+                        info!("⏸ {} - cancelled", notebook.name);
+                        SyncEvent::NotebookCancelled { notebook: name }
+                    }
+                    Processed::Skipped => {
+                        warn!("○ {} - not written", notebook.name);
+                        SyncEvent::NotebookSkipped { notebook: name }
+                    }
+                    Processed::Queued => {
+                        info!("⏳ {} - OCR queued", notebook.name);
+                        SyncEvent::NotebookCompleted { notebook: name }
+                    }
+                };
+                self.emit(event);
+                (Some(processed), None, None)
+            }
+            Err(e) => {
+                error!("✗ {} - {}", notebook.name, e);
+                if let Some(ref reporter) = self.error_reporter {
+                    let run_id = self.run_id.lock().unwrap().clone();
+                    reporter.report(&run_id, &notebook.name, &e).await;
                 }
+                self.mark_failed(notebook).await;
+                self.emit(SyncEvent::NotebookFailed {
+                    notebook: notebook.name.clone(),
+                    reason: redact(&e.to_string()),
+                });
+                (None, Some(redact(&e.to_string())), Some(e.context()))
             }
-            None => {
-                debug!("Creating new page: {}", notebook.name);
-                let page = self
-                    .notion
-                    .create_page(
-                        &notebook.name,
-                        &text_content,
-                        &notebook.metadata,
-                        &notebook.tags,
-                    )
-                    .await?;
+        };
 
-                // Add images if available (upload directly to Notion)
-                if !image_paths.is_empty() {
-                    self.notion
-                        .add_uploaded_images(&page.id, &image_paths)
-                        .await?;
-                }
+        if !self.config.dry_run {
+            if let Err(e) = self.state.lock().unwrap().save() {
+                warn!("Failed to save sync state: {}", e);
+            }
+        }
 
-                // Set PDF URL (Google Drive link or local path)
-                if let Some(ref url) = pdf_url {
-                    self.notion.set_pdf_url(&page.id, url).await?;
-                } else {
-                    self.notion.upload_pdf(&page.id, &pdf_path).await?;
-                    self.notion.set_pdf_link(&page.id, &pdf_path).await?;
+        let notebook_report = NotebookReport {
+            name: notebook.name.clone(),
+            success: error.is_none() && !matches!(processed, Some(Processed::Skipped)),
+            error,
+            error_context,
+            verification_issues,
+            timings,
+        };
+        (notebook_report, processed)
+    }
+
+    /// Fill in source properties missing on existing pages from the current backup.
+    ///
+    /// For pages created before these properties existed. Pages are matched to
+    /// notebooks by Source UUID when they have one and by title otherwise, so
+    /// pages titled from their text (`SMART_TITLE`) can't be matched.
+    pub async fn backfill(&self) -> Result<()> {
+        let mut notebooks = self.remarkable.list_notebooks().await?;
+        notebooks.retain(|notebook| !notebook.ignored);
+        let pages = self.notion.query_pages().await?;
+        info!(
+            "Backfilling {} pages from {} notebooks",
+            pages.len(),
+            notebooks.len()
+        );
+
+        let mut updated = 0;
+        let mut unmatched = 0;
+        for page in pages.iter().filter(|page| !page.archived) {
+            let Some(title) = page.title() else {
+                continue;
+            };
+            let Some(notebook) =
+                source_notebook(page, &title, &notebooks, &self.config.source_id_property)
+            else {
+                debug!("No notebook in the backup for '{}'", title);
+                unmatched += 1;
+                continue;
+            };
+
+            let missing: serde_json::Map<_, _> = self
+                .source_properties(notebook)
+                .values(&self.config.source_id_property)
+                .into_iter()
+                .filter(|(name, _)| page.properties.get(name).is_none_or(Property::is_empty))
+                // Nothing to add for notebooks at the root
+                .filter(|(_, value)| value["rich_text"] != serde_json::json!([]))
+                .collect();
+            if missing.is_empty() {
+                debug!("'{}' is up to date", title);
+                continue;
+            }
+
+            let names = missing.keys().cloned().collect::<Vec<_>>().join(", ");
+            if self.config.dry_run {
+                info!("[DRY RUN] Would set {} on '{}'", names, title);
+                updated += 1;
+                continue;
+            }
+            let edited =
+                NotionPage::from_page(page).is_some_and(|page| self.edited_since_sync(&page));
+            match self
+                .notion
+                .update_properties(&page.id, serde_json::Value::Object(missing))
+                .await
+            {
+                Ok(()) => {
+                    info!("➕ {} - {}", title, names);
+                    updated += 1;
+                    if !edited {
+                        self.record_synced_at(&page.id).await;
+                    }
                 }
+                Err(e) => warn!("Failed to backfill '{}': {}", title, e),
             }
         }
 
-        // Clean up temporary image files
-        for (_, image_path) in &image_paths {
-            std::fs::remove_file(image_path).ok();
+        if !self.config.dry_run {
+            self.state.lock().unwrap().save()?;
         }
 
-        std::fs::remove_file(&pdf_path)?;
-
+        info!(
+            "Backfill complete: {} pages updated, {} without a notebook in the backup",
+            updated, unmatched
+        );
         Ok(())
     }
+
+    /// Find pages claiming the same notebook UUID, e.g. duplicated by hand in
+    /// Notion, which would keep the sync from knowing which one to update.
+    /// Returns the number of notebooks with more than one page left.
+    ///
+    /// Split journal pages share their notebook's UUID and are told apart by
+    /// their date. With `fix` the page the sync last wrote is kept (else the
+    /// most recently edited one), gets the tags, relations and missing
+    /// property values of the others, and the others are archived.
+    pub async fn verify_unique(&self, fix: bool) -> Result<usize> {
+        let id_property = &self.config.source_id_property;
+        let pages = self.notion.query_pages().await?;
+        info!(
+            "Checking {} pages for duplicate {} values",
+            pages.len(),
+            id_property
+        );
+
+        let mut groups: BTreeMap<(String, String), Vec<&Page>> = BTreeMap::new();
+        for page in pages.iter().filter(|page| !page.archived) {
+            let uuid = match page.properties.get(id_property.as_str()) {
+                Some(Property::RichText { rich_text }) => plain_text(rich_text),
+                _ => continue,
+            };
+            if uuid.is_empty() {
+                continue;
+            }
+            let date = match page.properties.get("Date") {
+                Some(Property::Date { date: Some(date) }) => date.start.clone(),
+                _ => String::new(),
+            };
+            groups.entry((uuid, date)).or_default().push(page);
+        }
+
+        let tracked: HashSet<String> = self
+            .state
+            .lock()
+            .unwrap()
+            .notebook_pages
+            .values()
+            .cloned()
+            .collect();
+        let mut duplicated = 0;
+        for ((uuid, _), mut group) in groups {
+            if group.len() < 2 {
+                continue;
+            }
+            // The tracked page first, then newest first
+            group.sort_by(|a, b| {
+                tracked
+                    .contains(&b.id)
+                    .cmp(&tracked.contains(&a.id))
+                    .then_with(|| b.last_edited_time.cmp(&a.last_edited_time))
+            });
+            let (keep, duplicates) = group.split_first().unwrap();
+            let title = keep.title().unwrap_or_default();
+            if !fix {
+                warn!(
+                    "'{}' ({}) has {} pages: {}",
+                    title,
+                    uuid,
+                    group.len(),
+                    group
+                        .iter()
+                        .map(|page| page.id.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+                duplicated += 1;
+                continue;
+            }
+
+            let properties = repair::merged_properties(keep, duplicates);
+            if !properties.is_empty() {
+                if let Err(e) = self
+                    .notion
+                    .update_properties(&keep.id, serde_json::Value::Object(properties))
+                    .await
+                {
+                    // Archiving now would lose the values
+                    warn!("Failed to merge properties into '{}': {}", title, e);
+                    duplicated += 1;
+                    continue;
+                }
+            }
+            let mut removed = 0;
+            for duplicate in duplicates {
+                match self.notion.delete_page(&duplicate.id).await {
+                    Ok(()) => {
+                        self.state.lock().unwrap().pages.remove(&duplicate.id);
+                        removed += 1;
+                    }
+                    Err(e) => warn!("Failed to archive duplicate of '{}': {}", title, e),
+                }
+            }
+            if removed < duplicates.len() {
+                duplicated += 1;
+            }
+            info!(
+                "🔀 {} - kept {}, archived {} duplicates",
+                title, keep.id, removed
+            );
+        }
+
+        if fix {
+            self.state.lock().unwrap().save()?;
+        }
+        if duplicated == 0 {
+            info!("Every notebook has a single page");
+        }
+        Ok(duplicated)
+    }
+
+    /// Source properties of a notebook, from the current backup
+    fn source_properties(&self, notebook: &Notebook) -> SourceProperties {
+        SourceProperties {
+            uuid: notebook.uuid.clone(),
+            folder: notebook.metadata.folder_path.clone(),
+            sync_hash: state::file_hash(&self.remarkable.backup_pdf(notebook)).ok(),
+            page_count: self.remarkable.page_count(notebook),
+            pdf_size_mb: std::fs::metadata(self.remarkable.backup_pdf(notebook))
+                .ok()
+                .map(|metadata| pdf_size_mb(metadata.len())),
+            document_type: self.remarkable.document_type(notebook).map(str::to_string),
+        }
+    }
+
+    /// Pre-register every tag of this run on the Tags property, returning rejected tags
+    async fn register_tags(&self, notebooks: &[Notebook]) -> Vec<String> {
+        let tags: Vec<String> = notebooks
+            .iter()
+            .filter(|n| !n.is_deleted)
+            .flat_map(|n| n.tags.iter().cloned())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+
+        if tags.is_empty() {
+            return Vec::new();
+        }
+
+        match self.notion.register_tags(&tags).await {
+            Ok(rejected) => {
+                for tag in &rejected {
+                    warn!(
+                        "Tag '{}' rejected by Notion (commas or over 100 characters), skipping",
+                        tag
+                    );
+                }
+                rejected
+            }
+            Err(e) => {
+                warn!("Failed to pre-register tags: {}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Best-effort: flag the notebook's page as failed so dashboards can pick it up
+    async fn mark_failed(&self, notebook: &Notebook) {
+        if self.config.dry_run {
+            return;
+        }
+        if let Ok(Some(page)) = self.existing_page(notebook).await {
+            let edited = self.edited_since_sync(&page);
+            if let Err(e) = self
+                .notion
+                .set_sync_status(&page.id, SyncStatus::Failed)
+                .await
+            {
+                debug!("Failed to mark '{}' as failed: {}", notebook.name, e);
+            }
+            // The status change isn't an edit made in Notion
+            if !edited {
+                self.record_synced_at(&page.id).await;
+            }
+        }
+    }
+
+    /// The notebook's page, looked up like `find_notebook_page` does but
+    /// without listing the database. Unlike `page_by_uuid` it's left as it is.
+    async fn existing_page(&self, notebook: &Notebook) -> Result<Option<NotionPage>> {
+        if let Some(page_id) = self.known_page_id(notebook) {
+            match self.notion.get_page(&page_id).await {
+                Ok(page) if !page.archived => return Ok(NotionPage::from_page(&page)),
+                Ok(_) => {}
+                Err(e) => debug!("Page {} of '{}' not found: {}", page_id, notebook.name, e),
+            }
+        }
+        if let Some(ref uuid) = notebook.uuid {
+            if let Some(page) = self.notion.find_page_by_source_id(uuid).await? {
+                return Ok(Some(page));
+            }
+        }
+        self.notion.find_page_by_title(&notebook.name).await
+    }
+
+    /// Whether the page was edited in Notion after the sync last wrote it.
+    ///
+    /// Notion reports edit times to the minute, so edits made within the
+    /// minute of a sync go unnoticed. Pages without a recorded sync time
+    /// count as unedited.
+    fn edited_since_sync(&self, page: &NotionPage) -> bool {
+        if self.config.manual_edits == ManualEdits::Overwrite {
+            return false;
+        }
+        let state = self.state.lock().unwrap();
+        let synced_at = state
+            .pages
+            .get(&page.id)
+            .and_then(|page_state| page_state.synced_at.as_deref());
+        match (synced_at, page.last_edited_time.as_deref()) {
+            (Some(synced_at), Some(edited)) => {
+                let parse = |time| chrono::DateTime::parse_from_rfc3339(time).ok();
+                match (parse(synced_at), parse(edited)) {
+                    (Some(synced_at), Some(edited)) => edited > synced_at,
+                    _ => false,
+                }
+            }
+            _ => false,
+        }
+    }
+
+    /// Remember the page's edit time after the sync wrote it, so later edits
+    /// in Notion can be told apart
+    async fn record_synced_at(&self, page_id: &str) {
+        if self.config.manual_edits == ManualEdits::Overwrite {
+            return;
+        }
+        match self.notion.get_page(page_id).await {
+            Ok(page) => {
+                if let Some(page_state) = self.state.lock().unwrap().pages.get_mut(page_id) {
+                    page_state.synced_at = page.last_edited_time;
+                }
+            }
+            Err(e) => debug!("Failed to read back edit time of {}: {}", page_id, e),
+        }
+    }
+
+    /// Mark pages as stale when they weren't written this run and their notebook is gone.
+    ///
+    /// Pages are tied to notebooks by their Source ID or the pages recorded
+    /// in the sync state, so renamed notebooks and titles other than the
+    /// notebook's name (smart titles, journal days) aren't mistaken for
+    /// stale. Pages the sync didn't write are left alone.
+    async fn mark_stale(&self, pages: &[Page], notebooks: &[&Notebook]) -> usize {
+        let synced_pages = self.synced_pages.lock().unwrap().clone();
+        // Trashed notebooks are archived rather than marked stale, so they
+        // count as present
+        let uuids: HashSet<&str> = notebooks.iter().filter_map(|n| n.uuid.as_deref()).collect();
+        let (present, known): (HashSet<String>, HashSet<String>) = {
+            let state = self.state.lock().unwrap();
+            let present = notebooks
+                .iter()
+                .filter_map(|n| state.notebook_pages.get(&n.path))
+                .chain(uuids.iter().filter_map(|uuid| state.uuid_pages.get(*uuid)))
+                .cloned()
+                .collect();
+            let known = state
+                .notebook_pages
+                .values()
+                .chain(state.uuid_pages.values())
+                .chain(state.pages.keys())
+                .cloned()
+                .collect();
+            (present, known)
+        };
+        let mut stale_count = 0;
+
+        for page in pages {
+            if synced_pages.contains(&page.id)
+                || present.contains(&page.id)
+                || notion::page_sync_status(page) == Some(SyncStatus::Stale.as_str())
+            {
+                continue;
+            }
+            let has_notebook = match self.notion.page_source_id(page) {
+                Some(uuid) => uuids.contains(uuid.as_str()),
+                // Without a Source ID only pages the sync recorded are its own
+                None => !known.contains(&page.id),
+            };
+            if has_notebook {
+                continue;
+            }
+
+            let title = page.title().unwrap_or_default();
+            match self
+                .notion
+                .set_sync_status(&page.id, SyncStatus::Stale)
+                .await
+            {
+                Ok(_) => stale_count += 1,
+                Err(e) => debug!("Failed to mark '{}' as stale: {}", title, e),
+            }
+        }
+
+        stale_count
+    }
+
+    /// Apply `--delete-mode` to the pages of notebooks moved to the tablet's trash.
+    ///
+    /// Returns the number of pages marked archived or moved to Notion's trash.
+    async fn propagate_deletions(&self, notebooks: &[Notebook], pages: &[Page]) -> usize {
+        let mode = self.config.delete_mode;
+        // With --include-trash the pages are kept and tagged instead
+        if mode == DeleteMode::Ignore || self.config.include_trash {
+            return 0;
+        }
+
+        let mut deleted = 0;
+        for notebook in notebooks.iter().filter(|notebook| notebook.is_deleted) {
+            debug!("Notebook '{}' is in trash", notebook.name);
+            let Some(page) = self.find_notebook_page(notebook, pages) else {
+                debug!(
+                    "No Notion page found for deleted notebook '{}'",
+                    notebook.name
+                );
+                continue;
+            };
+
+            // Archived pages stay in the database, so only mark them once
+            if mode == DeleteMode::Archive
+                && notion::page_sync_status(page) == Some(SyncStatus::Archived.as_str())
+            {
+                continue;
+            }
+            if self.config.dry_run {
+                info!("[DRY RUN] Would {} '{}'", mode.as_str(), notebook.name);
+                continue;
+            }
+
+            let result = match mode {
+                DeleteMode::Archive => {
+                    self.notion
+                        .set_sync_status(&page.id, SyncStatus::Archived)
+                        .await
+                }
+                _ => self.archive_page(&page.id).await,
+            };
+            match result {
+                Ok(()) => {
+                    deleted += 1;
+                    info!("🗑️  {}", notebook.name);
+                }
+                Err(e) => warn!("Failed to {} '{}': {}", mode.as_str(), notebook.name, e),
+            }
+        }
+        deleted
+    }
+
+    /// The notebook's page among `pages`: the one recorded in the sync state,
+    /// else the one with its Source ID, and only then one titled like it
+    fn find_notebook_page<'a>(&self, notebook: &Notebook, pages: &'a [Page]) -> Option<&'a Page> {
+        let known = self.known_page_id(notebook);
+        let uuid = notebook.uuid.as_deref();
+        known
+            .and_then(|id| pages.iter().find(|page| page.id == id))
+            .or_else(|| {
+                uuid.and_then(|uuid| {
+                    pages
+                        .iter()
+                        .find(|page| self.notion.page_source_id(page).as_deref() == Some(uuid))
+                })
+            })
+            .or_else(|| {
+                pages
+                    .iter()
+                    .find(|page| page.title().as_deref() == Some(notebook.name.as_str()))
+            })
+    }
+
+    /// The page last written for the notebook according to the sync state
+    fn known_page_id(&self, notebook: &Notebook) -> Option<String> {
+        let state = self.state.lock().unwrap();
+        notebook
+            .uuid
+            .as_ref()
+            .and_then(|uuid| state.uuid_pages.get(uuid))
+            .or_else(|| state.notebook_pages.get(&notebook.path))
+            .cloned()
+    }
+
+    /// Set the status to Archived, then archive the page
+    async fn archive_page(&self, page_id: &str) -> Result<()> {
+        if let Err(e) = self
+            .notion
+            .set_sync_status(page_id, SyncStatus::Archived)
+            .await
+        {
+            debug!("Failed to set Archived status: {}", e);
+        }
+        self.notion.delete_page(page_id).await
+    }
+
+    /// Log what syncing the notebook would do, from the backup and the pages
+    /// already in Notion, without running OCR or changing anything
+    fn plan_notebook(
+        &self,
+        notebook: &Notebook,
+        pages: &[Page],
+    ) -> (NotebookReport, Option<Processed>) {
+        // Renamed notebooks keep their page, found by document UUID
+        let live_pages = || pages.iter().filter(|page| !page.archived);
+        let by_uuid = notebook
+            .uuid
+            .as_ref()
+            .filter(|_| !has_tag(notebook, JOURNAL_TAG))
+            .and_then(|uuid| {
+                live_pages().find(|page| {
+                    matches!(
+                        page.properties.get(&self.config.source_id_property),
+                        Some(Property::RichText { rich_text }) if plain_text(rich_text) == *uuid
+                    )
+                })
+            });
+        let page = by_uuid.or_else(|| {
+            live_pages().find(|page| page.title().as_deref() == Some(notebook.name.as_str()))
+        });
+
+        let page_count = match self.remarkable.page_count(notebook) {
+            Some(count) => format!("{} pages", count),
+            None => "unknown page count".to_string(),
+        };
+        let pdf = if self.google_drive.is_some() {
+            "PDF to Google Drive"
+        } else {
+            "PDF attached to the page"
+        };
+        let action = if page.is_some() { "update" } else { "create" };
+        info!(
+            "[DRY RUN] Would {} '{}' ({}, {})",
+            action, notebook.name, page_count, pdf
+        );
+        if let Some(title) = page
+            .and_then(Page::title)
+            .filter(|title| *title != notebook.name)
+        {
+            info!("    title: '{}' -> '{}'", title, notebook.name);
+        }
+
+        let current = page.map(notion::page_tags).unwrap_or_default();
+        let changes = tag_changes(&current, &notebook.tags, self.config.two_way_tags);
+        if !changes.is_empty() {
+            info!("    tags: {}", changes.join(", "));
+        }
+
+        if let Some(page) = page {
+            let changed = changed_properties(
+                page,
+                &self.source_properties(notebook),
+                &self.config.source_id_property,
+            );
+            if !changed.is_empty() {
+                info!("    properties: {}", changed.join(", "));
+            }
+            if NotionPage::from_page(page).is_some_and(|page| self.edited_since_sync(&page)) {
+                info!("    edited in Notion since the last sync");
+            }
+        }
+        if has_tag(notebook, JOURNAL_TAG) {
+            info!("    journal: split into daily pages after OCR");
+        }
+        if self.config.smart_title {
+            info!("    title: taken from the text after OCR, may differ");
+        }
+
+        let notebook_report = NotebookReport {
+            name: notebook.name.clone(),
+            success: true,
+            error: None,
+            error_context: None,
+            verification_issues: Vec::new(),
+            timings: StageTimings::default(),
+        };
+        (notebook_report, Some(Processed::Written))
+    }
+
+    /// Sync one notebook in stages: export its PDF, render and OCR the pages
+    /// (see `ocr_stages`), clean up the text, upload the PDF to Drive and
+    /// write the Notion page.
+    async fn process_notebook(
+        &self,
+        notebook: &Notebook,
+        cancel: &CancellationToken,
+        timings: &mut StageTimings,
+        verification_issues: &mut Vec<String>,
+    ) -> Result<Processed> {
+        // A directory of its own, so notebooks with the same name processed
+        // at the same time don't overwrite each other's PDF and page images.
+        // Leftovers of an interrupted run are cleared first.
+        let work_dir = self
+            .config
+            .temp_dir
+            .join(notebook.uuid.clone().unwrap_or_else(random_uuid));
+        if work_dir.exists() {
+            remove_work_dir(&work_dir)?;
+        }
+        std::fs::create_dir_all(&work_dir)?;
+
+        // The directory goes whether the sync succeeded or not
+        let result = self
+            .process_notebook_in(notebook, &work_dir, cancel, timings, verification_issues)
+            .await;
+        let removed = remove_work_dir(&work_dir);
+        let processed = result?;
+        removed?;
+        Ok(processed)
+    }
+
+    /// The stages of `process_notebook`, with the files in `work_dir`
+    async fn process_notebook_in(
+        &self,
+        notebook: &Notebook,
+        work_dir: &Path,
+        cancel: &CancellationToken,
+        timings: &mut StageTimings,
+        verification_issues: &mut Vec<String>,
+    ) -> Result<Processed> {
+        let pdf_path = self
+            .remarkable
+            .download_notebook(notebook, work_dir)
+            .await
+            .stage("download")?;
+
+        if self.config.stroke_sidecars {
+            if let Err(e) = self.remarkable.write_stroke_sidecars(notebook) {
+                warn!("Failed to write stroke data for '{}': {}", notebook.name, e);
+            }
+        }
+
+        // Journals are split into pages by the dates in their text, so they
+        // can't be written before OCR
+        if self.config.defer_ocr
+            && self.config.review_dir.is_none()
+            && !has_tag(notebook, JOURNAL_TAG)
+        {
+            if cancel.is_cancelled() {
+                return Ok(Processed::Cancelled);
+            }
+            let stage = Instant::now();
+            let queued = self.queue_ocr(notebook, &pdf_path).await.stage("notion");
+            timings.notion_ms = millis(stage.elapsed());
+            return queued;
+        }
+
+        let Some((output, page_images)) = self
+            .ocr_stages(notebook, &pdf_path, work_dir, cancel, timings)
+            .await?
+        else {
+            return Ok(Processed::Cancelled);
+        };
+        let OcrOutput {
+            text: text_content,
+            confidence,
+            failed_pages,
+            summary_pages,
+            fields,
+        } = output;
+        self.emit(SyncEvent::OcrCompleted {
+            notebook: notebook.name.clone(),
+            pages: page_images.len(),
+            characters: text_content.chars().count(),
+        });
+
+        let empty = self.config.empty_notebooks != EmptyNotebookAction::Keep
+            && (page_images.is_empty() || text_content == ocr::NO_TEXT)
+            && page_images.len() < self.config.empty_notebook_pages;
+        if empty {
+            let archived = self
+                .state
+                .lock()
+                .unwrap()
+                .archived_empty
+                .contains_key(&notebook.path);
+            if self.config.empty_notebooks == EmptyNotebookAction::Skip || archived {
+                debug!("No text in '{}', not writing a page", notebook.name);
+                self.record_content_hash(notebook);
+                return Ok(Processed::Empty);
+            }
+        } else {
+            // Written normally from now on
+            self.state
+                .lock()
+                .unwrap()
+                .archived_empty
+                .remove(&notebook.path);
+        }
+
+        // Snap near misses of vocabulary terms to their spelling; the LLM
+        // correction is told about the terms as well
+        let vocabulary = self.vocabulary_for(notebook);
+        let text_content = vocabulary.correct(&text_content);
+        let text_content = match self.corrector {
+            Some(ref corrector) if !page_images.is_empty() => {
+                let stage = Instant::now();
+                let corrected = corrector.correct(&text_content, &vocabulary).await;
+                timings.correction_ms = millis(stage.elapsed());
+                corrected
+            }
+            _ => text_content,
+        };
+
+        // Use the template's title region, or the first heading-like line if
+        // enabled, otherwise the visibleName
+        let title = if let Some(ref title) = fields.title {
+            title.clone()
+        } else if self.config.smart_title {
+            derive_title(&text_content).unwrap_or_else(|| notebook.name.clone())
+        } else {
+            notebook.name.clone()
+        };
+
+        // Label page sections with the day each page was written, unless the
+        // page list doesn't line up with the rendered pages (a summary's
+        // pages come from the page list)
+        let page_dates: Vec<Option<String>> = self
+            .remarkable
+            .page_times(notebook)
+            .into_iter()
+            .map(|time| {
+                time.map(|t| {
+                    t.with_timezone(&chrono::Local)
+                        .format("%Y-%m-%d")
+                        .to_string()
+                })
+            })
+            .collect();
+        let page_dates = if page_dates.len() == page_images.len() || summary_pages.is_some() {
+            page_dates
+        } else {
+            Vec::new()
+        };
+        let text_content = label_page_separators(&text_content, &page_dates);
+
+        let svg_pages = self.export_svg_pages(notebook, &work_dir.join("svg")).await;
+        let notion_svgs = if self.config.svg_export.to_notion() {
+            &svg_pages
+        } else {
+            &BTreeMap::new()
+        };
+
+        // Prepare page images for direct upload to Notion, unless they're
+        // kept on this machine. Pages with strokes show their SVG if exported.
+        let image_paths: Vec<PageImage> = page_images
+            .iter()
+            .filter(|_| self.config.privacy_mode != PrivacyMode::Text)
+            .enumerate()
+            .map(|(idx, path)| {
+                let num = summary_pages.as_ref().map_or(idx + 1, |pages| pages[idx]);
+                PageImage {
+                    num,
+                    path: notion_svgs
+                        .get(&num)
+                        .map_or(path.as_path(), PathBuf::as_path),
+                    caption: match page_dates.get(num - 1) {
+                        Some(Some(date)) => format!("Page {} · {}", num, date),
+                        _ => format!("Page {}", num),
+                    },
+                }
+            })
+            .collect();
+
+        let journal_entries = if has_tag(notebook, JOURNAL_TAG) {
+            let year = extract::default_year(notebook.metadata.modified_time.as_deref());
+            extract::split_journal(&text_content, year, self.config.date_locale)
+        } else {
+            Vec::new()
+        };
+
+        if let Some(ref dir) = self.config.review_dir {
+            let pages = self.review_pages(
+                notebook,
+                &title,
+                &text_content,
+                &journal_entries,
+                &image_paths,
+                &fields,
+                &pdf_path,
+            )?;
+            let images: Vec<(String, &Path)> = image_paths
+                .iter()
+                .map(|image| (review_image_name(image), image.path))
+                .collect();
+            let path = review::write(dir, &notebook.name, &pages, &images)?;
+            info!("📝 {} - {}", notebook.name, path.display());
+            return Ok(Processed::Written);
+        }
+
+        // Last point to stop without leaving anything half-uploaded
+        if cancel.is_cancelled() {
+            return Ok(Processed::Cancelled);
+        }
+
+        // Upload PDF to Google Drive if configured
+        let stage = Instant::now();
+        // A skipped Drive upload attaches the PDF to the Notion page instead
+        let drive_file = if let Some(ref drive) = self.google_drive {
+            let notebook_id = notebook_id(notebook);
+            let run_id = self.run_id.lock().unwrap().clone();
+            self.config
+                .on_upload_error
+                .run("Drive upload", || {
+                    drive.upload_pdf(&pdf_path, &notebook.name, notebook_id, &run_id)
+                })
+                .await
+                .stage("drive_upload")?
+        } else {
+            None
+        };
+        let pdf_url = drive_file.as_ref().map(|file| file.url.clone());
+        let svg_files = self.upload_svg_pages(notebook, &svg_pages).await;
+        timings.drive_upload_ms = millis(stage.elapsed());
+
+        let stage = Instant::now();
+        let policy = self.config.on_notion_error;
+        // Whether everything was written, so the notebook counts as synced
+        let mut complete = true;
+        if journal_entries.is_empty() {
+            let written = policy
+                .run("Notion write", || {
+                    self.write_page(
+                        &title,
+                        &text_content,
+                        notebook,
+                        &image_paths,
+                        pdf_url.as_deref(),
+                        &pdf_path,
+                    )
+                })
+                .await
+                .stage("notion")?;
+            let Some(page) = written.flatten() else {
+                return Ok(Processed::Skipped);
+            };
+            self.replace_placeholder(notebook, &page.id).await;
+
+            // Meeting and template action items share one section
+            let mut action_items = fields.action_items.clone();
+            if has_tag(notebook, MEETING_TAG) {
+                let meeting_items = self
+                    .apply_meeting_fields(&page.id, notebook, &text_content)
+                    .await
+                    .stage("notion")?;
+                for item in meeting_items {
+                    if !action_items.contains(&item) {
+                        action_items.push(item);
+                    }
+                }
+            }
+            self.apply_template_fields(&page.id, &fields)
+                .await
+                .stage("notion")?;
+            self.notion
+                .add_action_items(&page.id, &action_items)
+                .await
+                .stage("notion")?;
+
+            if empty {
+                debug!("No text in '{}', archiving its page", notebook.name);
+                self.archive_page(&page.id).await.stage("notion")?;
+                let mut state = self.state.lock().unwrap();
+                state
+                    .archived_empty
+                    .insert(notebook.path.clone(), page.id.clone());
+                // The sync archived this page itself, so it isn't looked up
+                state.notebook_pages.remove(&notebook.path);
+                if let Some(ref uuid) = notebook.uuid {
+                    state.uuid_pages.remove(uuid);
+                }
+            } else {
+                let mut state = self.state.lock().unwrap();
+                state
+                    .notebook_pages
+                    .insert(notebook.path.clone(), page.id.clone());
+                if let Some(ref uuid) = notebook.uuid {
+                    state.uuid_pages.insert(uuid.clone(), page.id.clone());
+                }
+            }
+            self.record_synced_at(&page.id).await;
+            self.post_sync_comment(&page, confidence).await;
+
+            let issues = if self.config.verify {
+                self.verify_page(&page, &title, &text_content).await
+            } else {
+                Vec::new()
+            };
+            // Incomplete notebooks are tried again next run
+            if self.flag_incomplete(&page, &failed_pages, &issues).await {
+                complete = false;
+            }
+            verification_issues.extend(issues);
+        } else {
+            debug!(
+                "Splitting journal '{}' into {} daily pages",
+                notebook.name,
+                journal_entries.len()
+            );
+            for entry in &journal_entries {
+                let date = entry.date.format("%Y-%m-%d").to_string();
+                let entry_images: Vec<PageImage> = image_paths
+                    .iter()
+                    .filter(|image| entry.pages.contains(&image.num))
+                    .cloned()
+                    .collect();
+
+                let entry_title = format!("{} {}", title, date);
+                let written = policy
+                    .run("Notion write", || {
+                        self.write_page(
+                            &entry_title,
+                            &entry.content,
+                            notebook,
+                            &entry_images,
+                            pdf_url.as_deref(),
+                            &pdf_path,
+                        )
+                    })
+                    .await
+                    .stage("notion")?;
+                let Some(page) = written.flatten() else {
+                    complete = false;
+                    continue;
+                };
+                self.notion
+                    .set_date(&page.id, &date)
+                    .await
+                    .stage("notion")?;
+                self.record_synced_at(&page.id).await;
+                self.post_sync_comment(&page, confidence).await;
+
+                let issues = if self.config.verify {
+                    self.verify_page(&page, &entry_title, &entry.content).await
+                } else {
+                    Vec::new()
+                };
+                let entry_failed: Vec<usize> = failed_pages
+                    .iter()
+                    .copied()
+                    .filter(|num| entry.pages.contains(num))
+                    .collect();
+                if self.flag_incomplete(&page, &entry_failed, &issues).await {
+                    complete = false;
+                }
+                verification_issues.extend(issues);
+            }
+        }
+        timings.notion_ms = millis(stage.elapsed());
+
+        // Files no longer referenced here are reclaimed by `repair --storage`
+        if let Some(file) = drive_file {
+            self.state
+                .lock()
+                .unwrap()
+                .drive_files
+                .insert(notebook.path.clone(), file.id);
+        }
+        if !svg_files.is_empty() {
+            self.state
+                .lock()
+                .unwrap()
+                .drive_pages
+                .insert(notebook.path.clone(), svg_files);
+        }
+
+        if complete {
+            self.record_content_hash(notebook);
+        }
+
+        Ok(if empty {
+            Processed::Empty
+        } else {
+            Processed::Written
+        })
+    }
+
+    /// Write the notebook's page with its properties and PDF link but a
+    /// placeholder for the text, and queue the OCR for `sync --ocr-backlog`.
+    ///
+    /// An existing page keeps its content and only gets new properties, so
+    /// text from an earlier sync stays visible until the backlog runs. The
+    /// content hash isn't recorded, so any later full sync OCRs the notebook
+    /// as well.
+    async fn queue_ocr(&self, notebook: &Notebook, pdf_path: &Path) -> Result<Processed> {
+        let known = self
+            .state
+            .lock()
+            .unwrap()
+            .notebook_pages
+            .get(&notebook.path)
+            .cloned();
+        let existing = match known {
+            Some(page_id) => Some(page_id),
+            None => match self.page_by_uuid(notebook, &notebook.name).await? {
+                Some(page) => Some(page.id),
+                None => self
+                    .notion
+                    .find_page_by_title(&notebook.name)
+                    .await?
+                    .map(|page| page.id),
+            },
+        };
+        let page_id = match existing {
+            Some(page_id) => {
+                debug!("Updating properties of '{}' before OCR", notebook.name);
+                self.notion
+                    .update_notebook_properties(&page_id, &notebook.metadata, &notebook.tags)
+                    .await?;
+                page_id
+            }
+            None => {
+                debug!("Creating '{}' before OCR", notebook.name);
+                let page = self
+                    .notion
+                    .create_page(
+                        &notebook.name,
+                        OCR_PENDING_TEXT,
+                        &notebook.metadata,
+                        &notebook.tags,
+                        &self.write_key(notebook, &notebook.name),
+                    )
+                    .await?;
+                self.apply_relations(&page.id, notebook).await?;
+                let link = self.local_pdf_link(notebook, pdf_path)?;
+                self.notion
+                    .add_pdf_reference(&page.id, pdf_path, link.as_deref())
+                    .await?;
+                page.id
+            }
+        };
+        self.notion
+            .set_source_properties(&page_id, &self.source_properties(notebook))
+            .await?;
+        self.notion
+            .set_sync_status(&page_id, SyncStatus::Queued)
+            .await?;
+        self.synced_pages.lock().unwrap().insert(page_id.clone());
+
+        let mut state = self.state.lock().unwrap();
+        state
+            .notebook_pages
+            .insert(notebook.path.clone(), page_id.clone());
+        state.ocr_backlog.insert(
+            notebook.path.clone(),
+            QueuedOcr {
+                queued_at: chrono::Utc::now().to_rfc3339(),
+                page_id,
+            },
+        );
+        Ok(Processed::Queued)
+    }
+
+    /// Take the notebook off the OCR backlog once its text was written,
+    /// archiving the placeholder page if the text went to another page (a
+    /// title from the text or the template)
+    async fn replace_placeholder(&self, notebook: &Notebook, page_id: &str) {
+        let Some(queued) = self
+            .state
+            .lock()
+            .unwrap()
+            .ocr_backlog
+            .remove(&notebook.path)
+        else {
+            return;
+        };
+        if queued.page_id != page_id {
+            debug!("Archiving placeholder page of '{}'", notebook.name);
+            if let Err(e) = self.archive_page(&queued.page_id).await {
+                warn!(
+                    "Failed to archive placeholder page of '{}': {}",
+                    notebook.name, e
+                );
+            }
+        }
+    }
+
+    /// Export the notebook's pages as SVG into `dir`, returning the files by
+    /// page number. Pages without strokes have none.
+    ///
+    /// Best-effort: the vector copies are extras, so failures only warn.
+    async fn export_svg_pages(&self, notebook: &Notebook, dir: &Path) -> BTreeMap<usize, PathBuf> {
+        let (Some(exporter), Some(uuid)) = (&self.svg_exporter, &notebook.uuid) else {
+            return BTreeMap::new();
+        };
+        if let Err(e) = std::fs::create_dir_all(dir) {
+            warn!("Failed to create {:?}: {}", dir, e);
+            return BTreeMap::new();
+        }
+
+        let notebooks_dir = self.remarkable.backup_dir().join("Notebooks");
+        match exporter.export_pages(&notebooks_dir, uuid, dir).await {
+            Ok(pages) => pages.into_iter().collect(),
+            Err(e) => {
+                warn!("Failed to export '{}' as SVG: {}", notebook.name, e);
+                BTreeMap::new()
+            }
+        }
+    }
+
+    /// Upload the notebook's SVG pages to Drive, returning the file IDs by
+    /// page number
+    async fn upload_svg_pages(
+        &self,
+        notebook: &Notebook,
+        svg_pages: &BTreeMap<usize, PathBuf>,
+    ) -> BTreeMap<usize, String> {
+        let mut files = BTreeMap::new();
+        let (true, Some(drive), Some(uuid)) = (
+            self.config.svg_export.to_drive(),
+            &self.google_drive,
+            &notebook.uuid,
+        ) else {
+            return files;
+        };
+
+        let run_id = self.run_id.lock().unwrap().clone();
+        for (&page_num, svg_path) in svg_pages {
+            match drive
+                .upload_svg_page(svg_path, &notebook.name, uuid, page_num, &run_id)
+                .await
+            {
+                Ok(file) => {
+                    files.insert(page_num, file.id);
+                }
+                Err(e) => warn!(
+                    "Failed to upload SVG of page {} of '{}': {}",
+                    page_num, notebook.name, e
+                ),
+            }
+        }
+        files
+    }
+
+    /// The global vocabulary plus the sets named after the notebook's tags
+    fn vocabulary_for(&self, notebook: &Notebook) -> Vocabulary {
+        let mut vocabulary = self.vocabulary.clone();
+        for (tag, terms) in &self.config.file.vocabularies {
+            if has_tag(notebook, tag) {
+                debug!("Using '{}' vocabulary for '{}'", tag, notebook.name);
+                vocabulary.extend(terms.iter().cloned());
+            }
+        }
+        vocabulary
+    }
+
+    /// Pick the OCR engine from the first matching OCR rule, or the default backend
+    fn ocr_for(&self, notebook: &Notebook) -> &OcrEngine {
+        let kind = self
+            .config
+            .file
+            .ocr_rules
+            .iter()
+            .find(|rule| {
+                rule.matcher
+                    .matches(&notebook.tags, &notebook.metadata.folder_path)
+            })
+            .map(|rule| rule.backend)
+            .unwrap_or(self.config.ocr_backend);
+
+        debug!("OCR backend for '{}': {:?}", notebook.name, kind);
+        &self.ocr_engines[&kind]
+    }
+
+    /// Populate meeting properties from the OCR text, returning its action items
+    async fn apply_meeting_fields(
+        &self,
+        page_id: &str,
+        notebook: &Notebook,
+        text_content: &str,
+    ) -> Result<Vec<String>> {
+        let year = extract::default_year(notebook.metadata.modified_time.as_deref());
+        let meeting = extract::extract_meeting(
+            text_content,
+            &self.config.file.meeting,
+            year,
+            self.config.date_locale,
+        )?;
+
+        debug!(
+            "Meeting '{}': date {:?}, {} attendees, {} action items",
+            notebook.name,
+            meeting.date,
+            meeting.attendees.len(),
+            meeting.action_items.len()
+        );
+
+        if let Some(date) = meeting.date {
+            self.notion
+                .set_date(page_id, &date.format("%Y-%m-%d").to_string())
+                .await?;
+        }
+        if !meeting.attendees.is_empty() {
+            self.notion
+                .set_attendees(page_id, &meeting.attendees)
+                .await?;
+        }
+
+        Ok(meeting.action_items)
+    }
+
+    /// The pages `write_page` and the field updates after it would produce,
+    /// for `--review-dir`
+    #[allow(clippy::too_many_arguments)]
+    fn review_pages(
+        &self,
+        notebook: &Notebook,
+        title: &str,
+        text_content: &str,
+        journal_entries: &[extract::JournalEntry],
+        image_paths: &[PageImage<'_>],
+        fields: &TemplateFields,
+        pdf_path: &Path,
+    ) -> Result<Vec<ReviewPage>> {
+        let metadata = &notebook.metadata;
+        let mut properties = vec![
+            ("Tags".to_string(), notebook.tags.join(", ")),
+            ("Folder".to_string(), metadata.folder_path.clone()),
+        ];
+        for (name, value) in [
+            ("Created", &metadata.created_time),
+            ("Last Modified", &metadata.modified_time),
+        ] {
+            if let Some(value) = value {
+                properties.push((name.to_string(), value.clone()));
+            }
+        }
+        let pdf = if self.google_drive.is_some() {
+            Some("Google Drive".to_string())
+        } else {
+            self.local_pdf_path(notebook, pdf_path)
+                .map(|path| self.pdf_link(notebook, &path))
+        };
+        if let Some(pdf) = pdf {
+            properties.push(("PDF Link".to_string(), pdf));
+        }
+
+        // Page images after the text, then the PDF reference
+        let page_blocks = |content: &str, images: &mut dyn Iterator<Item = &PageImage>| {
+            let mut builder = BlockBuilder::new();
+            for image in images {
+                builder = builder.external_image(
+                    &format!(
+                        "{}/{}",
+                        review::file_name(&notebook.name),
+                        review_image_name(image)
+                    ),
+                    &image.caption,
+                );
+            }
+            if self.google_drive.is_none() {
+                let pdf_name = pdf_path
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .unwrap_or("notebook.pdf");
+                builder =
+                    builder.paragraph(&format!("{}{}", notion::PDF_REFERENCE_PREFIX, pdf_name));
+            }
+            let mut blocks = self.notion.content_blocks(content);
+            blocks.extend(builder.build());
+            blocks
+        };
+
+        if !journal_entries.is_empty() {
+            return Ok(journal_entries
+                .iter()
+                .map(|entry| {
+                    let date = entry.date.format("%Y-%m-%d").to_string();
+                    let mut properties = properties.clone();
+                    properties.push(("Date".to_string(), date.clone()));
+                    ReviewPage {
+                        title: format!("{} {}", title, date),
+                        properties,
+                        blocks: page_blocks(
+                            &entry.content,
+                            &mut image_paths
+                                .iter()
+                                .filter(|image| entry.pages.contains(&image.num)),
+                        ),
+                    }
+                })
+                .collect());
+        }
+
+        let mut action_items = fields.action_items.clone();
+        if has_tag(notebook, MEETING_TAG) {
+            let year = extract::default_year(metadata.modified_time.as_deref());
+            let meeting = extract::extract_meeting(
+                text_content,
+                &self.config.file.meeting,
+                year,
+                self.config.date_locale,
+            )?;
+            if let Some(date) = meeting.date {
+                properties.push(("Date".to_string(), date.format("%Y-%m-%d").to_string()));
+            }
+            if !meeting.attendees.is_empty() {
+                properties.push(("Attendees".to_string(), meeting.attendees.join(", ")));
+            }
+            for item in meeting.action_items {
+                if !action_items.contains(&item) {
+                    action_items.push(item);
+                }
+            }
+        }
+        if let Some(date) = fields.date {
+            properties.push(("Date".to_string(), date.format("%Y-%m-%d").to_string()));
+        }
+        properties.extend(fields.properties.clone());
+
+        let mut blocks = page_blocks(text_content, &mut image_paths.iter());
+        blocks.extend(self.notion.action_item_blocks(&action_items));
+        Ok(vec![ReviewPage {
+            title: title.to_string(),
+            properties,
+            blocks,
+        }])
+    }
+
+    /// Render the notebook's pages and OCR them, or reuse what an earlier run
+    /// got for the same PDF and OCR settings before failing in a later stage.
+    ///
+    /// Returns None when the run is cancelled in the meantime.
+    async fn ocr_stages(
+        &self,
+        notebook: &Notebook,
+        pdf_path: &Path,
+        work_dir: &Path,
+        cancel: &CancellationToken,
+        timings: &mut StageTimings,
+    ) -> Result<Option<(OcrOutput, Vec<PathBuf>)>> {
+        let ocr = self.ocr_for(notebook);
+        let summary_pages = self.summary_pages(notebook);
+        let cache_key = format!(
+            "{} {} {:?} {:?}",
+            state::file_hash(pdf_path)?,
+            ocr.settings(),
+            summary_pages,
+            self.config.privacy_mode
+        );
+        if let Some(cached) = self.stage_cache.load(notebook_id(notebook), &cache_key) {
+            info!(
+                "Reusing OCR of '{}' from an earlier run that didn't finish",
+                notebook.name
+            );
+            return Ok(Some(cached));
+        }
+
+        // Low-confidence pages are re-rendered during OCR when adaptive DPI
+        // is on. Images are named by notebook and run rather than by notebook
+        // name, which other notebooks and runs can share.
+        let image_prefix = format!(
+            "{}-{}",
+            notebook.uuid.clone().unwrap_or_else(random_uuid),
+            self.run_id.lock().unwrap()
+        );
+        let stage = Instant::now();
+        let mut page_images = match summary_pages {
+            Some(ref pages) => {
+                ocr.extract_page_images(pdf_path, work_dir, &image_prefix, pages)
+                    .await
+            }
+            None => {
+                ocr.extract_images_from_pdf(pdf_path, work_dir, &image_prefix)
+                    .await
+            }
+        }
+        .stage("render")?;
+        timings.render_ms = millis(stage.elapsed());
+        if cancel.is_cancelled() {
+            return Ok(None);
+        }
+
+        // Settings of the pages' templates, unless the page list doesn't line
+        // up with the rendered pages
+        let templates = self.remarkable.page_templates(notebook);
+        let templates: Vec<Option<&TemplateConfig>> = if templates.len() == page_images.len() {
+            templates
+                .iter()
+                .map(|name| self.config.file.template(name.as_deref()?))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let crops: Vec<_> = templates
+            .iter()
+            .map(|template| template.and_then(|t| t.crop))
+            .collect();
+
+        // Pages that look the same as when they were last OCR'd keep their
+        // text, unless everything is redone with --force
+        let settings = ocr.settings();
+        let known = if self.config.force {
+            HashMap::new()
+        } else {
+            self.state
+                .lock()
+                .unwrap()
+                .page_texts
+                .get(&notebook.path)
+                .filter(|texts| texts.settings == settings)
+                .map(|texts| texts.pages.clone())
+                .unwrap_or_default()
+        };
+
+        // Only the images are synced in the images privacy mode, so there's
+        // nothing to OCR
+        let images_only = self.config.privacy_mode == PrivacyMode::Images;
+        let stage = Instant::now();
+        let (text, confidence, failed_pages) = if page_images.is_empty() {
+            ("(No pages found in PDF)".to_string(), None, Vec::new())
+        } else if images_only {
+            (String::new(), None, Vec::new())
+        } else {
+            let document = ocr
+                .extract_text_from_pages(
+                    pdf_path,
+                    &mut page_images,
+                    &crops,
+                    summary_pages.as_deref().unwrap_or_default(),
+                    &known,
+                )
+                .await
+                .stage("ocr")?;
+            if !self.config.dry_run {
+                self.state.lock().unwrap().page_texts.insert(
+                    notebook.path.clone(),
+                    PageTexts {
+                        settings,
+                        pages: document.pages,
+                    },
+                );
+            }
+            (document.text, document.confidence, document.failed_pages)
+        };
+        let fields = if images_only {
+            TemplateFields::default()
+        } else {
+            self.template_fields(ocr, notebook, &page_images, &templates)
+                .await
+        };
+        timings.ocr_ms = millis(stage.elapsed());
+
+        let output = OcrOutput {
+            text,
+            confidence,
+            failed_pages,
+            summary_pages,
+            fields,
+        };
+        if let Err(e) =
+            self.stage_cache
+                .store(notebook_id(notebook), &cache_key, &output, &mut page_images)
+        {
+            warn!("Failed to cache the OCR of '{}': {}", notebook.name, e);
+        }
+        Ok(Some((output, page_images)))
+    }
+
+    /// The marked pages to sync of an imported document too large to
+    /// transcribe in full, or None to sync every page
+    fn summary_pages(&self, notebook: &Notebook) -> Option<Vec<usize>> {
+        let threshold = self.config.summary_only_pages?;
+        if !self.remarkable.is_imported(notebook) {
+            return None;
+        }
+        let page_count = self.remarkable.page_count(notebook)?;
+        if page_count <= threshold {
+            return None;
+        }
+        let pages = self.remarkable.marked_pages(notebook);
+        info!(
+            "'{}' has {} pages, only syncing the {} marked ones",
+            notebook.name,
+            page_count,
+            pages.len()
+        );
+        Some(pages)
+    }
+
+    /// OCR the regions of templated pages into fields for the Notion page
+    async fn template_fields(
+        &self,
+        ocr: &OcrEngine,
+        notebook: &Notebook,
+        page_images: &[PathBuf],
+        templates: &[Option<&TemplateConfig>],
+    ) -> TemplateFields {
+        let year = extract::default_year(notebook.metadata.modified_time.as_deref());
+        let mut fields = TemplateFields::default();
+
+        for (i, (image, template)) in page_images.iter().zip(templates).enumerate() {
+            let Some(template) = template else {
+                continue;
+            };
+            for region in &template.regions {
+                match ocr.recognize_area(image, region.area).await {
+                    Ok(text) => fields.add(region, &text, year, self.config.date_locale),
+                    Err(e) => warn!(
+                        "Failed to read {:?} region on page {} of '{}': {}",
+                        region.field,
+                        i + 1,
+                        notebook.name,
+                        e
+                    ),
+                }
+            }
+        }
+
+        fields
+    }
+
+    /// Set the date and text properties read from template regions
+    async fn apply_template_fields(&self, page_id: &str, fields: &TemplateFields) -> Result<()> {
+        if let Some(date) = fields.date {
+            self.notion
+                .set_date(page_id, &date.format("%Y-%m-%d").to_string())
+                .await?;
+        }
+
+        if !fields.properties.is_empty() {
+            let properties = fields
+                .properties
+                .iter()
+                .map(|(name, text)| (name.clone(), notion::rich_text_value(text)))
+                .collect();
+            self.notion
+                .update_properties(page_id, serde_json::Value::Object(properties))
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Link the page to related pages whose title matches a tag or folder of the notebook
+    async fn apply_relations(&self, page_id: &str, notebook: &Notebook) -> Result<()> {
+        if self.relations.is_empty() {
+            return Ok(());
+        }
+
+        let targets = self
+            .relation_targets
+            .get_or_try_init(|| async {
+                let mut targets = Vec::with_capacity(self.relations.len());
+                for (relation, client) in &self.relations {
+                    let pages = client.get_all_pages().await?;
+                    debug!(
+                        "Loaded {} pages for relation '{}'",
+                        pages.len(),
+                        relation.property
+                    );
+                    targets.push(pages);
+                }
+                Ok::<_, Error>(targets)
+            })
+            .await?;
+
+        for ((relation, _), pages) in self.relations.iter().zip(targets) {
+            let mut candidates: Vec<&str> = Vec::new();
+            if relation.match_on.contains(&RelationSource::Tag) {
+                candidates.extend(notebook.tags.iter().map(String::as_str));
+            }
+            if relation.match_on.contains(&RelationSource::Folder) {
+                candidates.extend(
+                    notebook
+                        .metadata
+                        .folder_path
+                        .split('/')
+                        .filter(|s| !s.is_empty()),
+                );
+            }
+
+            let related: Vec<String> = pages
+                .iter()
+                .filter(|page| {
+                    candidates
+                        .iter()
+                        .any(|c| c.eq_ignore_ascii_case(page.title.trim()))
+                })
+                .map(|page| page.id.clone())
+                .collect();
+
+            if !related.is_empty() {
+                self.notion
+                    .set_relation(page_id, &relation.property, &related)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Tags of the notebook plus those added on the Notion page, which are
+    /// written back to the tablet. Removing a tag still has to happen on the tablet.
+    async fn merge_notion_tags(&self, page_id: &str, notebook: &Notebook) -> Vec<String> {
+        let mut tags = notebook.tags.clone();
+
+        let notion_tags = match self.notion.get_page_tags(page_id).await {
+            Ok(notion_tags) => notion_tags,
+            Err(e) => {
+                warn!("Failed to read tags of '{}': {}", notebook.name, e);
+                return tags;
+            }
+        };
+
+        // The trash tag comes from the tablet's trash, not from the user
+        let added: Vec<String> = notion_tags
+            .into_iter()
+            .filter(|tag| !notebook.tags.contains(tag) && tag != TRASH_TAG)
+            .collect();
+        if added.is_empty() {
+            return tags;
+        }
+
+        match self.remarkable.add_tags(notebook, &added).await {
+            Ok(()) => {
+                info!("🏷️  {} ← {}", notebook.name, added.join(", "));
+                self.tags_written_back.store(true, Ordering::Relaxed);
+            }
+            Err(e) => warn!("Failed to write tags back to '{}': {}", notebook.name, e),
+        }
+
+        // Keep them on the page either way rather than clearing the user's edit
+        tags.extend(added);
+        tags
+    }
+
+    /// Where the PDF Link points without Drive, per `PDF_LINK_MODE`
+    fn local_pdf_path(&self, notebook: &Notebook, pdf_path: &Path) -> Option<PathBuf> {
+        match self.config.pdf_link_mode {
+            PdfLinkMode::Local => Some(pdf_path.to_path_buf()),
+            PdfLinkMode::Archive => self
+                .config
+                .pdf_archive_dir
+                .as_ref()
+                .map(|dir| dir.join(format!("{}.pdf", notebook.path))),
+            PdfLinkMode::None => None,
+        }
+    }
+
+    /// The PDF Link without Drive, copying the PDF into the archive first
+    /// when that's where it points
+    fn local_pdf_link(&self, notebook: &Notebook, pdf_path: &Path) -> Result<Option<String>> {
+        let Some(link) = self.local_pdf_path(notebook, pdf_path) else {
+            return Ok(None);
+        };
+        if link != pdf_path {
+            if let Some(parent) = link.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::copy(pdf_path, &link)?;
+            debug!("Archived PDF of '{}' to {:?}", notebook.name, link);
+        }
+        Ok(Some(self.pdf_link(notebook, &link)))
+    }
+
+    /// URL of the archived PDF when the archive is served (`PDF_BASE_URL`),
+    /// a `file://` link to `path` otherwise
+    fn pdf_link(&self, notebook: &Notebook, path: &Path) -> String {
+        if let Some(base) = &self.config.pdf_base_url {
+            let file_name = format!("{}.pdf", notebook.path);
+            let mut url = base.clone();
+            // Config only accepts URLs with a path
+            if let Ok(mut segments) = url.path_segments_mut() {
+                segments.pop_if_empty().extend(file_name.split('/'));
+            }
+            return url.to_string();
+        }
+        let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        format!("file://{}", path.display())
+    }
+
+    /// Create or update the Notion page with the given title, returning its ID
+    async fn write_page(
+        &self,
+        title: &str,
+        content: &str,
+        notebook: &Notebook,
+        image_paths: &[PageImage<'_>],
+        pdf_url: Option<&str>,
+        pdf_path: &Path,
+    ) -> Result<Option<WrittenPage>> {
+        // Hash the rendered pages so unchanged images can stay on the page
+        let hashes: Vec<String> = image_paths
+            .iter()
+            .map(|image| state::file_hash(image.path).unwrap_or_default())
+            .collect();
+
+        let existing = match self.page_by_uuid(notebook, title).await? {
+            Some(page) => Some(page),
+            None => self.notion.find_page_by_title(title).await?,
+        };
+        let existing = match existing {
+            Some(page) => Some(page),
+            None => match self.archived_page(notebook, title).await? {
+                ArchivedLookup::Restored(page) => Some(page),
+                ArchivedLookup::Skip => return Ok(None),
+                ArchivedLookup::None => None,
+            },
+        };
+        let (page_id, tags, previous, update) = match existing {
+            Some(page) => {
+                debug!("Updating existing page: {}", title);
+                let manual_edits = self.config.manual_edits;
+                if manual_edits != ManualEdits::Overwrite && self.edited_since_sync(&page) {
+                    match manual_edits {
+                        ManualEdits::Skip => {
+                            warn!(
+                                "'{}' was edited in Notion since the last sync, not updating it",
+                                title
+                            );
+                            return Ok(None);
+                        }
+                        ManualEdits::Managed => {
+                            info!(
+                                "'{}' was edited in Notion since the last sync, keeping the edits",
+                                title
+                            );
+                        }
+                        _ => warn!(
+                            "'{}' was edited in Notion since the last sync, overwriting the edits",
+                            title
+                        ),
+                    }
+                }
+
+                let tags = if self.config.two_way_tags {
+                    self.merge_notion_tags(&page.id, notebook).await
+                } else {
+                    notebook.tags.clone()
+                };
+
+                let previous = self
+                    .state
+                    .lock()
+                    .unwrap()
+                    .pages
+                    .get(&page.id)
+                    .map(|page_state| page_state.images.clone())
+                    .unwrap_or_default();
+                let keep: HashSet<String> = image_paths
+                    .iter()
+                    .zip(&hashes)
+                    .filter_map(|(page, hash)| {
+                        previous
+                            .get(&page.num)
+                            .filter(|image| {
+                                !hash.is_empty() && image.matches(page.num, hash, &page.caption)
+                            })
+                            .map(|image| image.block_id.clone())
+                    })
+                    .collect();
+
+                let update = self
+                    .notion
+                    .update_page(
+                        &page.id,
+                        content,
+                        &notebook.metadata,
+                        &tags,
+                        &keep,
+                        manual_edits == ManualEdits::Managed,
+                    )
+                    .await?;
+                (page.id, tags, previous, Some(update))
+            }
+            None => {
+                debug!("Creating new page: {}", title);
+                let page = self
+                    .notion
+                    .create_page(
+                        title,
+                        content,
+                        &notebook.metadata,
+                        &notebook.tags,
+                        &self.write_key(notebook, title),
+                    )
+                    .await?;
+                (page.id, notebook.tags.clone(), BTreeMap::new(), None)
+            }
+        };
+
+        self.apply_relations(&page_id, notebook).await?;
+
+        // Upload changed and new images in page order around the kept ones
+        let mut images = BTreeMap::new();
+        let mut anchor = update.as_ref().map(|u| u.content_end.clone());
+        let mut pending: Vec<(&PageImage, &str)> = Vec::new();
+        let mut changed = 0;
+        for (page, hash) in image_paths.iter().zip(&hashes) {
+            let unchanged = previous.get(&page.num).filter(|image| {
+                image.matches(page.num, hash, &page.caption)
+                    && update
+                        .as_ref()
+                        .is_some_and(|u| u.kept.contains(&image.block_id))
+            });
+
+            match unchanged {
+                Some(image) => {
+                    self.upload_images(notebook, &page_id, &pending, anchor, &mut images)
+                        .await?;
+                    pending.clear();
+                    images.insert(page.num, image.clone());
+                    anchor = Some(image.block_id.clone());
+                }
+                None => {
+                    pending.push((page, hash));
+                    changed += 1;
+                }
+            }
+        }
+        self.upload_images(notebook, &page_id, &pending, anchor, &mut images)
+            .await?;
+
+        let image_count = images.len();
+        self.state.lock().unwrap().pages.insert(
+            page_id.clone(),
+            PageState {
+                images,
+                synced_at: None,
+            },
+        );
+
+        // Set PDF URL (Google Drive link or local path)
+        if let Some(url) = pdf_url {
+            self.notion.set_pdf_url(&page_id, url).await?;
+        } else {
+            let link = self.local_pdf_link(notebook, pdf_path)?;
+            self.notion
+                .add_pdf_reference(&page_id, pdf_path, link.as_deref())
+                .await?;
+        }
+
+        self.notion
+            .set_source_properties(&page_id, &self.source_properties(notebook))
+            .await?;
+
+        self.notion
+            .set_sync_status(&page_id, SyncStatus::Synced)
+            .await?;
+        self.synced_pages.lock().unwrap().insert(page_id.clone());
+
+        Ok(Some(WrittenPage {
+            id: page_id,
+            tags,
+            images: image_count,
+            pages: image_paths.len(),
+            changed,
+        }))
+    }
+
+    /// Warn on the page itself when part of the notebook is missing from it:
+    /// pages OCR failed on, page images that couldn't be uploaded, or what
+    /// reading the page back found wrong.
+    ///
+    /// A later update replaces the content and the callout with it, so it
+    /// disappears once a sync succeeds fully. Returns whether anything was
+    /// missing.
+    async fn flag_incomplete(
+        &self,
+        page: &WrittenPage,
+        failed_pages: &[usize],
+        issues: &[String],
+    ) -> bool {
+        let mut warnings = Vec::new();
+        match failed_pages {
+            [] => {}
+            [num] => warnings.push(format!("OCR failed on page {}, its text is missing.", num)),
+            nums => warnings.push(format!(
+                "OCR failed on pages {}, their text is missing.",
+                nums.iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        }
+        let missing_images = page.pages.saturating_sub(page.images);
+        if missing_images > 0 {
+            warnings.push(format!(
+                "{} of {} page images couldn't be uploaded.",
+                missing_images, page.pages
+            ));
+        }
+        if !issues.is_empty() {
+            warnings.push(format!(
+                "Reading the page back found: {}.",
+                issues.join("; ")
+            ));
+        }
+        if warnings.is_empty() {
+            return false;
+        }
+
+        if let Err(e) = self
+            .notion
+            .add_sync_warning(&page.id, &warnings.join(" "))
+            .await
+        {
+            warn!("Failed to add sync warning to page {}: {}", page.id, e);
+        }
+        true
+    }
+
+    /// Note what the sync did in a comment on the page (`SYNC_COMMENTS`).
+    ///
+    /// Best-effort: the comment is only an audit trail, so failures warn.
+    async fn post_sync_comment(&self, page: &WrittenPage, confidence: Option<f32>) {
+        if !self.config.sync_comments {
+            return;
+        }
+        let mut comment = format!(
+            "Synced {} page{}, {} changed",
+            page.pages,
+            if page.pages == 1 { "" } else { "s" },
+            page.changed
+        );
+        if let Some(confidence) = confidence {
+            comment.push_str(&format!(", OCR confidence {:.0}%", confidence * 100.0));
+        }
+        if let Err(e) = self.notion.add_comment(&page.id, &comment).await {
+            warn!("Failed to comment on page {}: {}", page.id, e);
+        }
+    }
+
+    /// Look up the page the notebook was last written to when the database
+    /// query doesn't find it, which happens when it was archived in Notion,
+    /// and deal with it per `ARCHIVED_PAGES`
+    async fn archived_page(&self, notebook: &Notebook, title: &str) -> Result<ArchivedLookup> {
+        let mode = self.config.archived_pages;
+        if mode == ArchivedPages::Create || has_tag(notebook, JOURNAL_TAG) {
+            return Ok(ArchivedLookup::None);
+        }
+        let page_id = self
+            .state
+            .lock()
+            .unwrap()
+            .notebook_pages
+            .get(&notebook.path)
+            .cloned();
+        let Some(page_id) = page_id else {
+            return Ok(ArchivedLookup::None);
+        };
+
+        let page = match self.notion.get_page(&page_id).await {
+            Ok(page) if page.archived => page,
+            Ok(_) => return Ok(ArchivedLookup::None),
+            // Deleted for good, or no longer shared with the integration
+            Err(e) => {
+                debug!("Page of '{}' can't be looked up: {}", notebook.name, e);
+                return Ok(ArchivedLookup::None);
+            }
+        };
+
+        match mode {
+            ArchivedPages::Unarchive => {
+                info!("Restoring archived page of '{}'", notebook.name);
+                self.notion.restore_page(&page.id).await?;
+                Ok(ArchivedLookup::Restored(NotionPage {
+                    id: page.id,
+                    title: title.to_string(),
+                    last_edited_time: page.last_edited_time,
+                }))
+            }
+            _ => {
+                warn!(
+                    "The page of '{}' is archived in Notion, not writing it",
+                    notebook.name
+                );
+                Ok(ArchivedLookup::Skip)
+            }
+        }
+    }
+
+    /// Upload page images after `anchor` and record them in `images`
+    async fn upload_images(
+        &self,
+        notebook: &Notebook,
+        page_id: &str,
+        pending: &[(&PageImage<'_>, &str)],
+        anchor: Option<String>,
+        images: &mut BTreeMap<usize, ImageState>,
+    ) -> Result<()> {
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        let paths: Vec<(usize, &Path, &str)> = pending
+            .iter()
+            .map(|(page, _)| (page.num, page.path, page.caption.as_str()))
+            .collect();
+        let added = self
+            .notion
+            .add_uploaded_images(
+                page_id,
+                &paths,
+                anchor.as_deref(),
+                self.config.on_upload_error,
+            )
+            .await?;
+
+        for (page_num, block_id) in added {
+            self.emit(SyncEvent::PageUploaded {
+                notebook: notebook.name.clone(),
+                page: page_num,
+            });
+            if let Some((page, hash)) = pending.iter().find(|(page, _)| page.num == page_num) {
+                images.insert(
+                    page_num,
+                    ImageState {
+                        hash: hash.to_string(),
+                        block_id,
+                        caption: Some(page.caption.clone()),
+                    },
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Read a written page back and describe every way it differs from what was sent
+    async fn verify_page(&self, page: &WrittenPage, title: &str, content: &str) -> Vec<String> {
+        let mut issues = Vec::new();
+
+        match self.notion.get_page(&page.id).await {
+            Ok(stored) => {
+                let stored_title = stored.title().unwrap_or_default();
+                if stored_title != title {
+                    issues.push(format!(
+                        "{}: title is '{}', expected '{}'",
+                        title, stored_title, title
+                    ));
+                }
+
+                // Pages under a parent page keep these in a table instead
+                if !self.notion.uses_parent_page() {
+                    let mut expected_tags: Vec<&str> = page
+                        .tags
+                        .iter()
+                        .map(String::as_str)
+                        .filter(|tag| notion::is_valid_tag(tag))
+                        .collect();
+                    let stored_tags = notion::page_tags(&stored);
+                    let mut stored_tags: Vec<&str> =
+                        stored_tags.iter().map(String::as_str).collect();
+                    expected_tags.sort_unstable();
+                    expected_tags.dedup();
+                    stored_tags.sort_unstable();
+                    if stored_tags != expected_tags {
+                        issues.push(format!(
+                            "{}: tags are {:?}, expected {:?}",
+                            title, stored_tags, expected_tags
+                        ));
+                    }
+
+                    let status = notion::page_sync_status(&stored);
+                    if status != Some(SyncStatus::Synced.as_str()) {
+                        issues.push(format!(
+                            "{}: sync status is {:?}, expected Synced",
+                            title, status
+                        ));
+                    }
+                }
+            }
+            Err(e) => issues.push(format!("{}: failed to read page back: {}", title, e)),
+        }
+
+        match self.notion.list_blocks(&page.id).await {
+            Ok(blocks) => {
+                let expected = self.notion.content_chunks(content);
+                let expected_chars: usize = expected.iter().map(|c| c.chars().count()).sum();
+
+                // The text blocks directly follow the content heading
+                let heading = blocks
+                    .iter()
+                    .position(|block| block.is_heading(notion::CONTENT_HEADING));
+                match heading {
+                    Some(index) => {
+                        let paragraphs: Vec<String> = blocks[index + 1..]
+                            .iter()
+                            .skip_while(|block| notion::is_sync_warning(block))
+                            .take_while(|block| block.is_text())
+                            .take(expected.len())
+                            .filter_map(|block| block.text())
+                            .collect();
+                        let stored_chars: usize =
+                            paragraphs.iter().map(|p| p.chars().count()).sum();
+
+                        if paragraphs.len() != expected.len() {
+                            issues.push(format!(
+                                "{}: {} text blocks stored, expected {}",
+                                title,
+                                paragraphs.len(),
+                                expected.len()
+                            ));
+                        }
+                        if stored_chars != expected_chars {
+                            issues.push(format!(
+                                "{}: {} characters of text stored, expected {}",
+                                title, stored_chars, expected_chars
+                            ));
+                        }
+                    }
+                    None => issues.push(format!(
+                        "{}: '{}' section is missing",
+                        title,
+                        notion::CONTENT_HEADING
+                    )),
+                }
+
+                let images = blocks.iter().filter(|block| block.kind == "image").count();
+                if images != page.images {
+                    issues.push(format!(
+                        "{}: {} images stored, expected {}",
+                        title, images, page.images
+                    ));
+                }
+            }
+            Err(e) => issues.push(format!("{}: failed to read blocks back: {}", title, e)),
+        }
+
+        for issue in &issues {
+            warn!("Verification: {}", issue);
+        }
+        issues
+    }
+}
+
+/// A rendered notebook page
+#[derive(Clone)]
+struct PageImage<'a> {
+    /// 1-based page number
+    num: usize,
+    path: &'a Path,
+    /// "Page N", with the day the page was written when known
+    caption: String,
+}
+
+/// Add the day each page was written to the OCR page separators
+/// (`--- Page 3 ---` becomes `--- Page 3 · 2024-05-02 ---`)
+fn label_page_separators(text: &str, page_dates: &[Option<String>]) -> String {
+    if page_dates.is_empty() {
+        return text.to_string();
+    }
+
+    text.split('\n')
+        .map(|line| {
+            let num = line
+                .strip_prefix("--- Page ")
+                .and_then(|rest| rest.strip_suffix(" ---"))
+                .and_then(|num| num.parse::<usize>().ok());
+            let date = num
+                .and_then(|num| page_dates.get(num.checked_sub(1)?))
+                .and_then(Option::as_ref);
+            match (num, date) {
+                (Some(num), Some(date)) => format!("--- Page {} · {} ---", num, date),
+                _ => line.to_string(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// What the render and OCR stages produced for a notebook
+#[derive(Serialize, Deserialize)]
+struct OcrOutput {
+    /// Page texts joined with "--- Page N ---" separators
+    text: String,
+    confidence: Option<f32>,
+    /// Pages skipped after OCR failed
+    failed_pages: Vec<usize>,
+    /// The pages rendered of a document synced as a summary
+    summary_pages: Option<Vec<usize>>,
+    fields: TemplateFields,
+}
+
+/// What `write_page` sent to Notion, for read-back verification
+struct WrittenPage {
+    id: String,
+    tags: Vec<String>,
+    images: usize,
+    /// Pages of the notebook on the page, and how many of them were new or
+    /// had changed
+    pages: usize,
+    changed: usize,
+}
+
+/// Outcome of `archived_page`
+enum ArchivedLookup {
+    /// No archived page to deal with; create a new one
+    None,
+    /// The archived page was restored and can be updated
+    Restored(NotionPage),
+    /// Leave the notebook alone
+    Skip,
+}
+
+/// What `process_notebook` did with a notebook
+enum Processed {
+    Written,
+    /// No text was found; the page was skipped or archived
+    Empty,
+    /// The run was cancelled before anything was uploaded
+    Cancelled,
+    /// Writing the page failed and `ON_NOTION_ERROR` is skip
+    Skipped,
+    /// Written without text, the OCR queued for later (`DEFER_OCR`)
+    Queued,
+}
+
+/// Stable ID of a notebook: its document UUID, or its path when unknown
+fn notebook_id(notebook: &Notebook) -> &str {
+    notebook.uuid.as_deref().unwrap_or(&notebook.path)
+}
+
+/// Delete a notebook's work directory with the downloaded PDF and the
+/// rendered page images
+fn remove_work_dir(work_dir: &Path) -> Result<()> {
+    std::fs::remove_dir_all(work_dir)?;
+    Ok(())
+}
+
+/// Random (version 4) UUID
+fn random_uuid() -> String {
+    let bits = rand::random::<u128>() & !(0xf << 76) & !(0x3 << 62) | (0x4 << 76) | (0x2 << 62);
+    let hex = format!("{:032x}", bits);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// Privacy modes need every configured OCR backend to run on this machine
+fn check_local_ocr(config: &Config) -> Result<()> {
+    // apple is the only local backend
+    if !cfg!(target_os = "macos") {
+        return Err(Error::Config(
+            "PRIVACY_MODE needs a local OCR backend, and none is available on this platform (apple only runs on macOS)".to_string(),
+        ));
+    }
+    if !config.ocr_backend.is_local() {
+        return Err(Error::Config(format!(
+            "PRIVACY_MODE keeps page images on this machine, but OCR_BACKEND {:?} is a cloud service. Set OCR_BACKEND=apple.",
+            config.ocr_backend
+        )));
+    }
+    for (i, rule) in config.file.ocr_rules.iter().enumerate() {
+        if !rule.backend.is_local() {
+            return Err(Error::Config(format!(
+                "PRIVACY_MODE keeps page images on this machine, but OCR rule {} routes notebooks to {:?}, a cloud service. Change its backend to apple.",
+                i + 1,
+                rule.backend
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Whether an `--only` pattern matches the notebook's name or path
+fn matches_glob(glob: &Glob, notebook: &Notebook) -> bool {
+    glob.matches(&notebook.name) || glob.matches(&notebook.path)
+}
+
+/// Whether a `--stdin` entry is the notebook's name, path or UUID
+fn is_listed(entry: &str, notebook: &Notebook) -> bool {
+    entry.eq_ignore_ascii_case(&notebook.name)
+        || entry.eq_ignore_ascii_case(&notebook.path)
+        || notebook.uuid.as_deref() == Some(entry)
+}
+
+/// File name a page image is copied to for `--review-dir`
+fn review_image_name(image: &PageImage) -> String {
+    let extension = image
+        .path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("png");
+    format!("page-{}.{}", image.num, extension)
+}
+
+/// Tags to add ("+tag") and remove ("-tag") to bring the page's tags in line
+/// with the notebook's. Two-way tag sync keeps tags added in Notion.
+fn tag_changes(current: &[String], wanted: &[String], keep_current: bool) -> Vec<String> {
+    let added = wanted
+        .iter()
+        .filter(|tag| !current.contains(tag))
+        .map(|tag| format!("+{}", tag));
+    let removed = current
+        .iter()
+        .filter(|tag| !keep_current && !wanted.contains(tag))
+        .map(|tag| format!("-{}", tag));
+    added.chain(removed).collect()
+}
+
+/// Names of the source properties that differ between the page and the backup
+fn changed_properties<'a>(
+    page: &Page,
+    expected: &SourceProperties,
+    id_property: &'a str,
+) -> Vec<&'a str> {
+    let text = |name: &str| match page.properties.get(name) {
+        Some(Property::RichText { rich_text }) => plain_text(rich_text),
+        _ => String::new(),
+    };
+    let mut changed = Vec::new();
+    if expected
+        .uuid
+        .as_ref()
+        .is_some_and(|uuid| *uuid != text(id_property))
+    {
+        changed.push(id_property);
+    }
+    if expected.folder != text("Folder") {
+        changed.push("Folder");
+    }
+    if expected
+        .sync_hash
+        .as_ref()
+        .is_some_and(|hash| *hash != text("Sync Hash"))
+    {
+        changed.push("Sync Hash");
+    }
+    let page_count = match page.properties.get("Page Count") {
+        Some(Property::Number { number }) => *number,
+        _ => None,
+    };
+    if expected
+        .page_count
+        .is_some_and(|count| page_count != Some(count as f64))
+    {
+        changed.push("Page Count");
+    }
+    let pdf_size = match page.properties.get("PDF Size (MB)") {
+        Some(Property::Number { number }) => *number,
+        _ => None,
+    };
+    if expected
+        .pdf_size_mb
+        .is_some_and(|size| pdf_size != Some(size))
+    {
+        changed.push("PDF Size (MB)");
+    }
+    let document_type = match page.properties.get("Document Type") {
+        Some(Property::Select { select }) => select.as_ref().map(|option| option.name.as_str()),
+        _ => None,
+    };
+    if expected
+        .document_type
+        .as_deref()
+        .is_some_and(|expected| document_type != Some(expected))
+    {
+        changed.push("Document Type");
+    }
+    changed
+}
+
+/// Megabytes to two decimals, as shown in the PDF Size property
+fn pdf_size_mb(bytes: u64) -> f64 {
+    (bytes as f64 / 10_000.0).round() / 100.0
+}
+
+/// The notebook a page was made from: by Source UUID, or by title for pages
+/// that don't have one (including the dated pages of split journals)
+fn source_notebook<'a>(
+    page: &Page,
+    title: &str,
+    notebooks: &'a [Notebook],
+    id_property: &str,
+) -> Option<&'a Notebook> {
+    if let Some(Property::RichText { rich_text }) = page.properties.get(id_property) {
+        let uuid = plain_text(rich_text);
+        if !uuid.is_empty() {
+            return notebooks
+                .iter()
+                .find(|notebook| notebook.uuid.as_deref() == Some(uuid.as_str()));
+        }
+    }
+
+    notebooks
+        .iter()
+        .filter(|notebook| !notebook.is_deleted)
+        .find(|notebook| {
+            title == notebook.name
+                || title
+                    .strip_prefix(&notebook.name)
+                    .and_then(|rest| rest.strip_prefix(' '))
+                    .is_some_and(|date| chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").is_ok())
+        })
+}
+
+/// Whether the notebook is starred or tagged `priority` on the tablet
+fn is_priority(notebook: &Notebook) -> bool {
+    notebook.pinned || has_tag(notebook, PRIORITY_TAG)
+}
+
+/// Whether the notebook carries the given reMarkable tag (case-insensitive)
+fn has_tag(notebook: &Notebook, tag: &str) -> bool {
+    notebook.tags.iter().any(|t| t.eq_ignore_ascii_case(tag))
+}
+
+/// Derive a page title from the first heading-like line of OCR text.
+///
+/// A line counts as heading-like when it is short, contains letters and doesn't
+/// read like a sentence. Returns `None` when no such line starts the text.
+fn derive_title(text: &str) -> Option<String> {
+    const MAX_TITLE_LEN: usize = 60;
+    const MAX_TITLE_WORDS: usize = 8;
+
+    let line = text
+        .lines()
+        .map(|l| l.trim().trim_start_matches('#').trim())
+        .find(|l| !l.is_empty())?;
+
+    // Placeholders emitted by the OCR step when nothing was recognized
+    if line.starts_with("(No ") || line.starts_with("--- Page") {
+        return None;
+    }
+
+    let line = line.trim_end_matches(':').trim();
+    let looks_like_sentence = line.ends_with('.') || line.ends_with(',') || line.ends_with(';');
+
+    if line.chars().count() > MAX_TITLE_LEN
+        || line.split_whitespace().count() > MAX_TITLE_WORDS
+        || !line.chars().any(|c| c.is_alphabetic())
+        || looks_like_sentence
+    {
+        return None;
+    }
+
+    Some(line.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn title_from_heading_line() {
+        assert_eq!(
+            derive_title("\n# Project Kickoff\nWe discussed the plan."),
+            Some("Project Kickoff".to_string())
+        );
+        assert_eq!(
+            derive_title("Groceries:\nmilk\neggs"),
+            Some("Groceries".to_string())
+        );
+    }
+
+    #[test]
+    fn no_title_from_placeholders() {
+        assert_eq!(derive_title("(No text recognized)"), None);
+        assert_eq!(derive_title("--- Page 1 ---\nHeading"), None);
+        assert_eq!(derive_title("   \n\n"), None);
+    }
+
+    #[test]
+    fn no_title_from_sentences_or_long_lines() {
+        assert_eq!(derive_title("This went well."), None);
+        assert_eq!(derive_title("First of all,"), None);
+        assert_eq!(
+            derive_title("one two three four five six seven eight nine"),
+            None
+        );
+        assert_eq!(derive_title(&"a".repeat(61)), None);
+        assert_eq!(derive_title("2024-10-15"), None);
+    }
+    fn dates(dates: &[Option<&str>]) -> Vec<Option<String>> {
+        dates.iter().map(|date| date.map(str::to_string)).collect()
+    }
+
+    #[test]
+    fn labels_separators_with_page_dates() {
+        let text = "--- Page 1 ---\nfirst\n--- Page 2 ---\nsecond";
+        assert_eq!(
+            label_page_separators(text, &dates(&[Some("2024-05-01"), Some("2024-05-02")])),
+            "--- Page 1 · 2024-05-01 ---\nfirst\n--- Page 2 · 2024-05-02 ---\nsecond"
+        );
+    }
+
+    #[test]
+    fn keeps_separators_without_a_date() {
+        let text = "--- Page 0 ---\n--- Page 1 ---\n--- Page 2 ---\n--- Page 3 ---";
+        assert_eq!(
+            label_page_separators(text, &dates(&[Some("2024-05-01"), None])),
+            "--- Page 0 ---\n--- Page 1 · 2024-05-01 ---\n--- Page 2 ---\n--- Page 3 ---"
+        );
+        assert_eq!(label_page_separators(text, &[]), text);
+    }
+
+    #[test]
+    fn leaves_lookalike_lines_alone() {
+        let text = "--- Page one ---\n  --- Page 1 ---\n--- Page 1 ---!\nsee --- Page 1 ---";
+        assert_eq!(
+            label_page_separators(text, &dates(&[Some("2024-05-01")])),
+            text
+        );
+    }
+
+    fn page_with(properties: serde_json::Value) -> Page {
+        serde_json::from_value(serde_json::json!({ "id": "page", "properties": properties }))
+            .unwrap()
+    }
+
+    fn source_properties() -> SourceProperties {
+        SourceProperties {
+            uuid: Some("5f2b".to_string()),
+            folder: "Work/Meetings".to_string(),
+            sync_hash: Some("abc123".to_string()),
+            page_count: Some(3),
+            pdf_size_mb: Some(1.5),
+            document_type: Some("Notebook".to_string()),
+        }
+    }
+
+    fn text(parts: &[&str]) -> serde_json::Value {
+        let parts: Vec<_> = parts
+            .iter()
+            .map(|part| serde_json::json!({ "plain_text": part }))
+            .collect();
+        serde_json::json!({ "type": "rich_text", "rich_text": parts })
+    }
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn unchanged_properties_are_not_reported() {
+        let page = page_with(serde_json::json!({
+            "Source ID": text(&["5f2b"]),
+            "Folder": text(&["Work/Meetings"]),
+            "Sync Hash": text(&["abc123"]),
+            "Page Count": { "type": "number", "number": 3.0 },
+            "PDF Size (MB)": { "type": "number", "number": 1.5 },
+            "Document Type": { "type": "select", "select": { "name": "Notebook" } },
+        }));
+        assert!(changed_properties(&page, &source_properties(), "Source ID").is_empty());
+    }
+
+    #[test]
+    fn rich_text_split_across_spans_is_joined() {
+        let page = page_with(serde_json::json!({
+            "Source ID": text(&["5f", "2b"]),
+            "Folder": text(&["Work", "/", "Meetings"]),
+            "Sync Hash": text(&["abc", "123"]),
+            "Page Count": { "type": "number", "number": 3.0 },
+            "PDF Size (MB)": { "type": "number", "number": 1.5 },
+            "Document Type": { "type": "select", "select": { "name": "Notebook" } },
+        }));
+        assert!(changed_properties(&page, &source_properties(), "Source ID").is_empty());
+    }
+
+    #[test]
+    fn missing_properties_are_reported() {
+        let page = page_with(serde_json::json!({ "Folder": text(&["Work/Meetings"]) }));
+        assert_eq!(
+            changed_properties(&page, &source_properties(), "Source ID"),
+            vec![
+                "Source ID",
+                "Sync Hash",
+                "Page Count",
+                "PDF Size (MB)",
+                "Document Type"
+            ]
+        );
+
+        // Unknown values aren't compared
+        let expected = SourceProperties {
+            uuid: None,
+            folder: String::new(),
+            sync_hash: None,
+            page_count: None,
+            pdf_size_mb: None,
+            document_type: None,
+        };
+        assert!(
+            changed_properties(&page_with(serde_json::json!({})), &expected, "Source ID")
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn changed_values_are_reported() {
+        let page = page_with(serde_json::json!({
+            "Source ID": text(&["5f2b"]),
+            "Folder": text(&["Work"]),
+            "Sync Hash": text(&["def456"]),
+            "Page Count": { "type": "number", "number": 4.0 },
+            "PDF Size (MB)": { "type": "number", "number": 1.5 },
+            "Document Type": { "type": "select", "select": { "name": "PDF" } },
+        }));
+        assert_eq!(
+            changed_properties(&page, &source_properties(), "Source ID"),
+            vec!["Folder", "Sync Hash", "Page Count", "Document Type"]
+        );
+    }
+
+    #[test]
+    fn tag_order_is_not_a_change() {
+        let current = strings(&["work", "ideas", "todo"]);
+        let wanted = strings(&["todo", "work", "ideas"]);
+        assert!(tag_changes(&current, &wanted, false).is_empty());
+    }
+
+    #[test]
+    fn tag_changes_add_and_remove() {
+        let current = strings(&["work", "old"]);
+        let wanted = strings(&["new", "work"]);
+        assert_eq!(tag_changes(&current, &wanted, false), vec!["+new", "-old"]);
+        assert_eq!(tag_changes(&current, &wanted, true), vec!["+new"]);
+        assert_eq!(tag_changes(&[], &wanted, false), vec!["+new", "+work"]);
+    }
+
+    #[test]
+    fn random_uuid_is_version_4() {
+        for _ in 0..100 {
+            let uuid = random_uuid();
+            let groups: Vec<&str> = uuid.split('-').collect();
+            assert_eq!(
+                groups.iter().map(|group| group.len()).collect::<Vec<_>>(),
+                vec![8, 4, 4, 4, 12]
+            );
+            assert!(groups
+                .iter()
+                .all(|group| group.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f'))));
+            assert_eq!(uuid.chars().nth(14), Some('4'), "version of {}", uuid);
+            assert!(
+                matches!(uuid.chars().nth(19), Some('8' | '9' | 'a' | 'b')),
+                "variant of {}",
+                uuid
+            );
+        }
+    }
+
+    fn notebook(name: &str, path: &str, uuid: Option<&str>) -> Notebook {
+        serde_json::from_value(serde_json::json!({
+            "name": name,
+            "path": path,
+            "id": path,
+            "uuid": uuid,
+            "metadata": { "created_time": null, "modified_time": null, "folder_path": "" },
+            "tags": [],
+            "is_deleted": false,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn listed_by_name_or_path() {
+        let notebook = notebook("Meeting notes", "Work/Meeting notes", None);
+        assert!(is_listed("Meeting notes", &notebook));
+        assert!(is_listed("meeting NOTES", &notebook));
+        assert!(is_listed("Work/Meeting notes", &notebook));
+        assert!(is_listed("work/meeting notes", &notebook));
+        assert!(!is_listed("Work", &notebook));
+        assert!(!is_listed("Private/Meeting notes", &notebook));
+    }
+
+    #[test]
+    fn listed_by_uuid() {
+        let uuid = "0b9c4f6e-1d2a-4e8b-9c3d-5f6a7b8c9d0e";
+        let listed = notebook("Meeting notes", "Work/Meeting notes", Some(uuid));
+        assert!(is_listed(uuid, &listed));
+        assert!(!is_listed("1b9c4f6e-1d2a-4e8b-9c3d-5f6a7b8c9d0e", &listed));
+        assert!(!is_listed(uuid, &notebook("Ideas", "Ideas", None)));
+    }
+
+    fn titled(title: &str, source_id: Option<&str>) -> Page {
+        let mut properties = serde_json::json!({
+            "Name": { "type": "title", "title": [{ "plain_text": title }] },
+        });
+        if let Some(source_id) = source_id {
+            properties["Source ID"] = text(&[source_id]);
+        }
+        page_with(properties)
+    }
+
+    fn source_name(page: &Page, notebooks: &[Notebook]) -> Option<String> {
+        let title = page.title().unwrap_or_default();
+        source_notebook(page, &title, notebooks, "Source ID").map(|notebook| notebook.path.clone())
+    }
+
+    #[test]
+    fn dated_pages_belong_to_their_journal() {
+        let notebooks = vec![
+            notebook("Journal 2024", "Journal 2024", None),
+            notebook("Journal", "Daily/Journal", None),
+        ];
+        for title in ["Journal", "Journal 2024-10-15", "Journal 2024-10-16"] {
+            assert_eq!(
+                source_name(&titled(title, None), &notebooks).as_deref(),
+                Some("Daily/Journal"),
+                "{}",
+                title
+            );
+        }
+        assert_eq!(
+            source_name(&titled("Journal 2024", None), &notebooks).as_deref(),
+            Some("Journal 2024")
+        );
+        // Only a full date after a space counts
+        for title in [
+            "Journal 2024-10",
+            "Journal 2024-13-01",
+            "Journal-2024-10-15",
+        ] {
+            assert_eq!(
+                source_name(&titled(title, None), &notebooks),
+                None,
+                "{}",
+                title
+            );
+        }
+    }
+
+    #[test]
+    fn renamed_notebooks_are_found_by_source_id() {
+        let uuid = "0b9c4f6e-1d2a-4e8b-9c3d-5f6a7b8c9d0e";
+        let notebooks = vec![notebook("Team meetings", "Work/Team meetings", Some(uuid))];
+        assert_eq!(
+            source_name(&titled("Meetings", Some(uuid)), &notebooks).as_deref(),
+            Some("Work/Team meetings")
+        );
+        // Without a Source ID the old title no longer matches
+        assert_eq!(source_name(&titled("Meetings", None), &notebooks), None);
+    }
+
+    #[test]
+    fn no_source_notebook() {
+        let mut deleted = notebook("Ideas", "Ideas", None);
+        deleted.is_deleted = true;
+        let notebooks = vec![notebook("Meetings", "Meetings", None), deleted];
+        assert_eq!(source_name(&titled("Recipes", None), &notebooks), None);
+        assert_eq!(source_name(&titled("Ideas", None), &notebooks), None);
+        assert_eq!(source_name(&titled("Meetings", None), &[]), None);
+        // A Source ID that matches no notebook isn't retried by title
+        assert_eq!(
+            source_name(&titled("Meetings", Some("1b9c4f6e")), &notebooks),
+            None
+        );
+    }
 }
@@ -1,11 +1,26 @@
 use crate::error::Result;
 use crate::google_vision::GoogleVisionClient;
 use crate::notion::NotionClient;
-use crate::remarkable::RemarkableClient;
+use crate::ocr::OcrEngine;
+use crate::remarkable::ssh::{SshClient, DEFAULT_SSH_HOST};
+use crate::remarkable::{RemarkableClient, Transport};
 use std::path::{Path, PathBuf};
 use tracing::{info, warn};
 
 pub async fn test_remarkable(backup_dir: Option<PathBuf>, password: Option<String>) -> Result<()> {
+    let transport: Transport = std::env::var("REMARKABLE_TRANSPORT")
+        .ok()
+        .map(|value| value.parse())
+        .transpose()?
+        .unwrap_or_default();
+    if transport == Transport::Ssh {
+        let host = std::env::var("REMARKABLE_SSH_HOST").unwrap_or(DEFAULT_SSH_HOST.to_string());
+        info!("Testing SSH connection to {}...", host);
+        SshClient::new(host).check().await?;
+        info!("✓ Tablet reachable over SSH");
+        return Ok(());
+    }
+
     info!("Testing RemarkableSync...");
     let client = RemarkableClient::new(backup_dir, password).await?;
     client.check_installation().await?;
@@ -14,7 +29,7 @@ pub async fn test_remarkable(backup_dir: Option<PathBuf>, password: Option<Strin
     info!("⚠️  Make sure your tablet is connected via USB!");
     let notebooks = client.list_notebooks().await?;
 
-    for notebook in &notebooks {
+    for notebook in notebooks.iter().filter(|notebook| !notebook.ignored) {
         info!("  - {} (path: {})", notebook.name, notebook.path);
     }
 
@@ -28,8 +43,13 @@ pub async fn test_ocr(pdf_path: &Path) -> Result<()> {
         crate::error::Error::Config("GOOGLE_VISION_API_KEY not set in environment".to_string())
     })?;
 
-    let vision = GoogleVisionClient::new(api_key);
-    let (text, _images) = vision.extract_text_and_images_from_pdf(pdf_path).await?;
+    let quota_project = std::env::var("GOOGLE_VISION_QUOTA_PROJECT").ok();
+    let ocr = OcrEngine::new(Box::new(
+        GoogleVisionClient::new(api_key).with_quota_project(quota_project),
+    ));
+    let (text, _images) = ocr
+        .extract_text_and_images_from_pdf(pdf_path, &std::env::temp_dir())
+        .await?;
 
     info!("Extracted {} characters", text.len());
     info!("Preview: {}", &text.chars().take(200).collect::<String>());
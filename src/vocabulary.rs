@@ -0,0 +1,162 @@
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Terms shorter than this are only passed to LLM prompts; fuzzy matching them
+/// would rewrite too many ordinary words
+const MIN_FUZZY_LENGTH: usize = 5;
+
+/// Words that OCR tends to mangle: project names, jargon, names of colleagues.
+///
+/// Near misses in the OCR text are snapped to the term's spelling, and LLM
+/// correction prompts list the terms so the model keeps them intact.
+#[derive(Debug, Clone, Default)]
+pub struct Vocabulary {
+    terms: Vec<String>,
+}
+
+impl Vocabulary {
+    pub fn new(terms: impl IntoIterator<Item = String>) -> Self {
+        let mut vocabulary = Self::default();
+        vocabulary.extend(terms);
+        vocabulary
+    }
+
+    /// Add terms, skipping blanks and duplicates
+    pub fn extend(&mut self, terms: impl IntoIterator<Item = String>) {
+        for term in terms {
+            let term = term.trim().to_string();
+            if !term.is_empty() && !self.terms.contains(&term) {
+                self.terms.push(term);
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.terms.len()
+    }
+
+    /// Line for an LLM prompt naming the terms, or an empty string
+    pub fn prompt_hint(&self) -> String {
+        if self.terms.is_empty() {
+            return String::new();
+        }
+        format!(
+            "The text may contain these names and terms, spelled exactly like this: {}.",
+            self.terms.join(", ")
+        )
+    }
+
+    /// Replace words (or runs of words, for multi-word terms) that are within
+    /// a small edit distance of a term by the term itself.
+    ///
+    /// Matching is case-insensitive and never crosses a line break. Longer
+    /// terms are tried first, and each word is replaced at most once.
+    pub fn correct(&self, text: &str) -> String {
+        let mut terms: Vec<(&str, usize)> = self
+            .terms
+            .iter()
+            .filter(|term| term.chars().count() >= MIN_FUZZY_LENGTH)
+            .map(|term| (term.as_str(), term.split_whitespace().count()))
+            .collect();
+        if terms.is_empty() {
+            return text.to_string();
+        }
+        terms.sort_by_key(|(term, _)| std::cmp::Reverse(term.chars().count()));
+
+        text.split('\n')
+            .map(|line| correct_line(line, &terms))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+fn word_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"[\p{L}\p{N}][\p{L}\p{N}'’-]*").unwrap())
+}
+
+fn correct_line(line: &str, terms: &[(&str, usize)]) -> String {
+    let words: Vec<(usize, usize)> = word_re()
+        .find_iter(line)
+        .map(|m| (m.start(), m.end()))
+        .collect();
+
+    // Replacements as (start, end, term), by word index
+    let mut replaced = vec![false; words.len()];
+    let mut replacements: Vec<(usize, usize, &str)> = Vec::new();
+
+    for &(term, word_count) in terms {
+        let term_lower = term.to_lowercase();
+        for i in 0..words.len().saturating_sub(word_count - 1) {
+            let run = i..i + word_count;
+            if replaced[run.clone()].iter().any(|&r| r) {
+                continue;
+            }
+
+            // Words of a run must be separated by spaces only
+            let spaced = words[run.clone()]
+                .windows(2)
+                .all(|pair| line[pair[0].1..pair[1].0].chars().all(char::is_whitespace));
+            let (start, end) = (words[i].0, words[i + word_count - 1].1);
+            let candidate = &line[start..end];
+            if !spaced || candidate == term {
+                continue;
+            }
+
+            let joined = candidate
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+                .to_lowercase();
+            if edit_distance(&joined, &term_lower) <= max_distance(term_lower.chars().count()) {
+                replaced[run].iter_mut().for_each(|r| *r = true);
+                replacements.push((start, end, term));
+            }
+        }
+    }
+
+    if replacements.is_empty() {
+        return line.to_string();
+    }
+    replacements.sort_by_key(|(start, _, _)| *start);
+
+    let mut corrected = String::with_capacity(line.len());
+    let mut pos = 0;
+    for (start, end, term) in replacements {
+        corrected.push_str(&line[pos..start]);
+        corrected.push_str(term);
+        pos = end;
+    }
+    corrected.push_str(&line[pos..]);
+    corrected
+}
+
+/// Edits allowed for a term of the given length
+fn max_distance(length: usize) -> usize {
+    if length >= 8 {
+        2
+    } else {
+        1
+    }
+}
+
+/// Levenshtein distance between two strings, by character
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}